@@ -0,0 +1,268 @@
+//! This example program demonstrates how to capture frames from a camera
+//! using the `device` abstraction with MMAP buffers.
+//!
+//! The capture queue is configured for a raw format (`YUYV` or `RGB3`), all
+//! buffers are mapped into our address space, and frames are dequeued as they
+//! become available using `poll(2)`, along with a `Waker` that lets Ctrl+c
+//! interrupt the wait. The frame rate is reported every second,
+//! and PPM snapshots of the captured frames can optionally be written to disk.
+//! All the captured frames can also be recorded for later analysis.
+mod ppm;
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use clap::{App, Arg};
+use std::io::{self, Write};
+
+use v4l2::device::decimator::Decimator;
+use v4l2::device::poller::Waker;
+use v4l2::device::queue::*;
+use v4l2::device::recorder::Recorder;
+use v4l2::device::*;
+use v4l2::ioctl;
+use v4l2::memory::MMAP;
+use v4l2::{Error, Format, PixelFormat};
+
+/// Pixel formats we know how to turn into a PPM snapshot, in order of
+/// preference.
+const SUPPORTED_FORMATS: [&[u8; 4]; 2] = [b"YUYV", b"RGB3"];
+
+fn main() {
+    let matches = App::new("V4L2 capture example")
+        .arg(
+            Arg::with_name("device")
+                .required(true)
+                .help("Path to the camera device file"),
+        )
+        .arg(
+            Arg::with_name("num_frames")
+                .long("num_frames")
+                .short("n")
+                .takes_value(true)
+                .help("Number of frames to capture (default: until Ctrl+C is pressed)"),
+        )
+        .arg(
+            Arg::with_name("snapshot_dir")
+                .long("snapshot_dir")
+                .takes_value(true)
+                .help("Directory where to write PPM snapshots of captured frames"),
+        )
+        .arg(
+            Arg::with_name("snapshot_every")
+                .long("snapshot_every")
+                .takes_value(true)
+                .default_value("30")
+                .help("Write a snapshot every N frames"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .help("Directory where to record the raw captured frames and their index"),
+        )
+        .arg(
+            Arg::with_name("max_fps")
+                .long("max_fps")
+                .takes_value(true)
+                .help("Drop frames to keep the frame rate under this value"),
+        )
+        .get_matches();
+
+    let device_path = Path::new(matches.value_of("device").unwrap());
+    let num_frames = matches
+        .value_of("num_frames")
+        .map(|n| n.parse::<usize>().expect("Invalid number of frames"));
+    let snapshot_dir = matches.value_of("snapshot_dir").map(PathBuf::from);
+    let record_dir = matches.value_of("record").map(PathBuf::from);
+    let snapshot_every = matches
+        .value_of("snapshot_every")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Invalid snapshot interval")
+        .max(1);
+    let mut decimator = matches
+        .value_of("max_fps")
+        .map(|fps| Decimator::new(fps.parse().expect("Invalid frame rate")));
+
+    let lets_quit = Arc::new(AtomicBool::new(false));
+    let waker = Arc::new(Waker::new().expect("Failed to create waker"));
+
+    // Setup the Ctrl+c handler.
+    {
+        let lets_quit_handler = lets_quit.clone();
+        let waker_handler = Arc::clone(&waker);
+        ctrlc::set_handler(move || {
+            lets_quit_handler.store(true, Ordering::SeqCst);
+            waker_handler.wake().expect("Failed to wake capture loop");
+        })
+        .expect("Failed to set Ctrl-C handler.");
+    }
+
+    // We poll the device before dequeuing, so there is no reason to block in
+    // DQBUF.
+    let device = Device::open(device_path, DeviceConfig::new().non_blocking_dqbuf())
+        .expect("Failed to open device");
+    let caps = &device.capability;
+    println!(
+        "Opened device: {}\n\tdriver: {}\n\tbus: {}\n\tcapabilities: {}",
+        caps.card, caps.driver, caps.bus_info, caps.capabilities
+    );
+
+    let device = Arc::new(Mutex::new(device));
+
+    // Cameras typically use the single-planar API, but some use the
+    // multi-planar one.
+    let mut capture_queue = Queue::get_capture_queue(Arc::clone(&device))
+        .or_else(|_| Queue::get_capture_mplane_queue(Arc::clone(&device)))
+        .expect("Failed to obtain capture queue");
+    println!("Capture queue type: {:?}", capture_queue.get_type());
+
+    println!("Capture formats:");
+    for fmtdesc in capture_queue.format_iter() {
+        println!("\t{}", fmtdesc);
+    }
+
+    // Pick the first raw format we support.
+    let supported_formats: Vec<PixelFormat> = SUPPORTED_FORMATS.iter().map(|&f| f.into()).collect();
+    let pixelformat = capture_queue
+        .format_iter()
+        .map(|fmtdesc| fmtdesc.pixelformat)
+        .filter(|f| supported_formats.contains(f))
+        .min_by_key(|f| supported_formats.iter().position(|s| s == f))
+        .expect("None of the YUYV or RGB3 formats are supported by this device.");
+
+    let capture_format: Format = capture_queue
+        .change_format()
+        .expect("Failed to get capture format")
+        .set_size(640, 480)
+        .set_pixelformat(pixelformat)
+        .apply()
+        .expect("Failed to set capture format");
+    println!("Adjusted capture format: {:?}", capture_format);
+
+    // Segments of about 256MB are easy enough to move around.
+    let mut recorder = record_dir.map(|dir| {
+        Recorder::new(&dir, &capture_format, 256 << 20).expect("Failed to start recording")
+    });
+
+    let capture_queue = capture_queue
+        .request_buffers::<MMAP>(4)
+        .expect("Failed to allocate capture buffers");
+    println!("Using {} capture buffers.", capture_queue.num_buffers());
+
+    // Map the first plane of every buffer so we can read the captured frames.
+    let mappings: Vec<ioctl::PlaneMapping> = (0..capture_queue.num_buffers())
+        .map(|index| {
+            capture_queue
+                .map_plane(index, 0)
+                .expect("Failed to map buffer")
+        })
+        .collect();
+
+    // Give all our buffers to the driver before starting to stream.
+    while let Ok(buffer) = capture_queue.get_free_buffer() {
+        buffer.auto_queue().expect("Failed to queue capture buffer");
+    }
+    capture_queue.streamon().expect("Failed to start capture");
+
+    let start = Instant::now();
+    let mut last_report = start;
+    let mut frames_since_report = 0usize;
+    let mut cpt = 0usize;
+    let mut next_snapshot = 0usize;
+
+    while !lets_quit.load(Ordering::SeqCst) && !matches!(num_frames, Some(n) if cpt >= n) {
+        // Wait for a frame to be ready, unless Ctrl+c is pressed first.
+        let dqbuf = match capture_queue.dequeue_or_wake(&waker) {
+            Ok(dqbuf) => dqbuf,
+            Err(Error::Woken) => continue,
+            Err(e) => panic!("Failed to dequeue capture buffer: {}", e),
+        };
+        let index = dqbuf.data.index as usize;
+        let bytes_used = dqbuf.data.planes[0].bytesused as usize;
+        let keep_frame = match &mut decimator {
+            // Prefer the capture time of the frame if the driver provides it.
+            Some(decimator) => decimator.keep(match dqbuf.data.timestamp.type_ {
+                ioctl::TimestampType::Monotonic => dqbuf.data.timestamp.as_duration(),
+                _ => start.elapsed(),
+            }),
+            None => true,
+        };
+
+        if !keep_frame {
+            // Give the buffer back to the driver as soon as possible.
+            drop(dqbuf);
+            capture_queue
+                .get_buffer(index)
+                .expect("Failed to obtain capture buffer")
+                .auto_queue()
+                .expect("Failed to queue capture buffer");
+            continue;
+        }
+
+        if let Some(dir) = &snapshot_dir {
+            if cpt >= next_snapshot {
+                next_snapshot = cpt + snapshot_every;
+                let path = dir.join(format!("frame{:06}.ppm", cpt));
+                ppm::write_snapshot(
+                    &path,
+                    &capture_format,
+                    &mappings[index].as_slice()[..bytes_used],
+                )
+                .expect("Failed to write snapshot");
+            }
+        }
+
+        if let Some(recorder) = &mut recorder {
+            recorder
+                .record_dqbuf(&dqbuf.data, &[&mappings[index].as_slice()[..bytes_used]])
+                .expect("Failed to record frame");
+        }
+
+        // Return the buffer to the free pool and queue it again right away.
+        drop(dqbuf);
+        capture_queue
+            .get_buffer(index)
+            .expect("Failed to obtain capture buffer")
+            .auto_queue()
+            .expect("Failed to queue capture buffer");
+
+        cpt += 1;
+        frames_since_report += 1;
+        let elapsed = last_report.elapsed();
+        if elapsed.as_secs() >= 1 {
+            print!(
+                "\rCaptured {:#6} frames, {:#6.2} fps",
+                cpt,
+                frames_since_report as f64 / elapsed.as_secs_f64()
+            );
+            io::stdout().flush().unwrap();
+            last_report = Instant::now();
+            frames_since_report = 0;
+        }
+    }
+    println!();
+
+    capture_queue
+        .streamoff()
+        .expect("Failed to stop capture queue");
+
+    if let Some(recorder) = recorder {
+        recorder.finish().expect("Failed to finish recording");
+    }
+
+    println!(
+        "Captured {} frames in {:.2}s.",
+        cpt,
+        start.elapsed().as_secs_f64()
+    );
+
+    // The buffers must be unmapped before they can be freed.
+    drop(mappings);
+    capture_queue
+        .free_buffers()
+        .expect("Failed to release capture buffers");
+}
@@ -0,0 +1,79 @@
+//! Conversion of captured frames into PPM images.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use v4l2::Format;
+
+/// Clamp a fixed-point color component into a byte.
+fn clamp(v: i32) -> u8 {
+    v.clamp(0, 255) as u8
+}
+
+/// Convert a Y'CbCr triplet into RGB using the BT.601 limited-range matrix.
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = (y as i32 - 16) * 298;
+    let d = u as i32 - 128;
+    let e = v as i32 - 128;
+
+    [
+        clamp((c + 409 * e + 128) >> 8),
+        clamp((c - 100 * d - 208 * e + 128) >> 8),
+        clamp((c + 516 * d + 128) >> 8),
+    ]
+}
+
+/// Write the frame contained in `data`, of format `format`, as a PPM file at
+/// `path`. Only the `YUYV` and `RGB3` pixel formats are supported.
+pub fn write_snapshot(path: &Path, format: &Format, data: &[u8]) -> io::Result<()> {
+    let invalid = |error: String| io::Error::new(io::ErrorKind::InvalidInput, error);
+
+    let bytes_per_pixel = if format.pixelformat == b"RGB3".into() {
+        3
+    } else if format.pixelformat == b"YUYV".into() {
+        2
+    } else {
+        return Err(invalid(format!(
+            "Unsupported pixel format {}",
+            format.pixelformat
+        )));
+    };
+
+    let width = format.width as usize;
+    let height = format.height as usize;
+    let line_size = width * bytes_per_pixel;
+    let bytesperline = format.plane_fmt[0].bytesperline as usize;
+    if bytesperline == 0 || bytesperline < line_size {
+        return Err(invalid(format!(
+            "Invalid line size {} for a width of {}",
+            bytesperline, width
+        )));
+    }
+    // The last line does not need to be padded.
+    let frame_size = height.saturating_sub(1) * bytesperline + line_size;
+    if data.len() < frame_size {
+        return Err(invalid(format!(
+            "Truncated frame: {} bytes, expected {}",
+            data.len(),
+            frame_size
+        )));
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+
+    for line in data.chunks(bytesperline).take(height) {
+        let line = &line[..line_size];
+        if bytes_per_pixel == 3 {
+            out.write_all(line)?;
+        } else {
+            // Two pixels are packed into every 4 bytes as Y0 U Y1 V.
+            for macropixel in line.chunks_exact(4) {
+                let (y0, u, y1, v) = (macropixel[0], macropixel[1], macropixel[2], macropixel[3]);
+                out.write_all(&yuv_to_rgb(y0, u, v))?;
+                out.write_all(&yuv_to_rgb(y1, u, v))?;
+            }
+        }
+    }
+
+    out.flush()
+}
@@ -0,0 +1,160 @@
+//! This example program decodes FWHT streams, like the ones produced by the
+//! `vicodec_test` example with the `--output` option, using the `vicodec`
+//! decoder and the stateful `Decoder` abstraction.
+//!
+//! The streams are split into frames using the FWHT frame headers, and every
+//! frame is passed to the decoder. Each stream is drained once all of its
+//! frames have been submitted, so all its frames are decoded before the next
+//! stream starts.
+//!
+//! Streams can have different resolutions, in which case the decoder signals
+//! a source change when it reaches the first frame of the next stream. The
+//! CAPTURE queue is then reconfigured through
+//! `Queue::handle_source_change()`, which keeps the current buffers if they
+//! are large enough for the new format and reallocates them otherwise.
+//!
+//! Decoded frames are written as raw frames of the decoded format to the
+//! output file, if one is specified.
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use clap::{App, Arg};
+
+use v4l2::device::decoder::Decoder;
+use v4l2::device::queue::direction::Capture;
+use v4l2::device::queue::dqbuf::DQBuffer;
+use v4l2::device::queue::states::BuffersAllocated;
+use v4l2::device::queue::Queue;
+use v4l2::device::*;
+use v4l2::memory::MMAP;
+use v4l2::splitter::{FwhtHeader, FwhtSplitter};
+use v4l2::Format;
+
+fn main() {
+    let matches = App::new("FWHT decoder example")
+        .arg(
+            Arg::with_name("device")
+                .required(true)
+                .help("Path to the vicodec decoder device file"),
+        )
+        .arg(
+            Arg::with_name("input")
+                .required(true)
+                .multiple(true)
+                .help("FWHT streams to decode, one after the other"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .help("File to write the decoded frames into"),
+        )
+        .get_matches();
+
+    let device_path = Path::new(matches.value_of("device").unwrap());
+    let streams: Vec<Vec<u8>> = matches
+        .values_of("input")
+        .unwrap()
+        .map(|path| fs::read(path).expect("Failed to read input file"))
+        .collect();
+    let mut output_file = matches
+        .value_of("output")
+        .map(|path| File::create(path).expect("Failed to create output file"));
+
+    let lets_quit = Arc::new(AtomicBool::new(false));
+
+    // Setup the Ctrl+c handler.
+    {
+        let lets_quit_handler = lets_quit.clone();
+        ctrlc::set_handler(move || {
+            lets_quit_handler.store(true, Ordering::SeqCst);
+        })
+        .expect("Failed to set Ctrl-C handler.");
+    }
+
+    let device = Device::open(device_path, DeviceConfig::new()).expect("Failed to open device");
+    let caps = &device.capability;
+    println!(
+        "Opened device: {}\n\tdriver: {}\n\tbus: {}\n\tcapabilities: {}",
+        caps.card, caps.driver, caps.bus_info, caps.capabilities
+    );
+    if caps.driver != "vicodec" {
+        panic!(
+            "This device is {}, but this test is designed to work with the vicodec driver.",
+            caps.driver
+        );
+    }
+
+    let device = Arc::new(Mutex::new(device));
+
+    let on_format_change = |format: &Format| {
+        println!(
+            "\nDecoding {}x{} frames into {}.",
+            format.width, format.height, format.pixelformat
+        );
+    };
+
+    let decoded_frames = Arc::new(AtomicUsize::new(0));
+    let on_frame = {
+        let decoded_frames = Arc::clone(&decoded_frames);
+        move |dqbuf: DQBuffer<MMAP>, queue: &Queue<Capture, BuffersAllocated<MMAP>>| {
+            let index = dqbuf.data.index as usize;
+            let mut bytes_used = 0;
+            for (i, plane) in dqbuf.data.planes.iter().enumerate() {
+                // Drivers may report an offset beyond the data of an empty plane.
+                let range =
+                    plane.data_offset.min(plane.bytesused) as usize..plane.bytesused as usize;
+                bytes_used += range.len();
+                if let Some(output_file) = &mut output_file {
+                    // Only map the buffer for as long as we need it, as
+                    // mapped buffers cannot be reallocated.
+                    let mapping = queue.map_plane(index, i).expect("Failed to map buffer");
+                    output_file
+                        .write_all(&mapping.as_slice()[range])
+                        .expect("Failed to write decoded frame");
+                }
+            }
+
+            print!(
+                "\rDecoded buffer {:#5} -> {:#8} bytes",
+                dqbuf.data.sequence, bytes_used
+            );
+            io::stdout().flush().unwrap();
+            decoded_frames.fetch_add(1, Ordering::SeqCst);
+        }
+    };
+
+    let mut decoder = Decoder::new(device, b"FWHT", 2, on_format_change, on_frame)
+        .expect("Failed to create decoder");
+
+    'streams: for (i, stream) in streams.iter().enumerate() {
+        // The resolution of a stream is given by the header of its first
+        // frame.
+        let first_header = FwhtSplitter::new(stream)
+            .next()
+            .and_then(FwhtHeader::parse)
+            .expect("Input file does not start with a FWHT frame");
+        println!(
+            "\nStream {} resolution: {}x{}",
+            i, first_header.width, first_header.height
+        );
+
+        for frame in FwhtSplitter::new(stream) {
+            if lets_quit.load(Ordering::SeqCst) {
+                break 'streams;
+            }
+
+            decoder.decode(frame).expect("Failed to decode frame");
+        }
+
+        // Get all the frames of this stream before starting the next one.
+        decoder.drain().expect("Failed to drain decoder");
+    }
+    println!(
+        "\nDecoded {} frames.",
+        decoded_frames.load(Ordering::SeqCst)
+    );
+}
@@ -0,0 +1,186 @@
+//! This example program decodes a FWHT stream, like the one produced by the
+//! `vicodec_test` example with the `--output` option, using the stateless
+//! decoder of the `vicodec` driver and the `StatelessDecoder` abstraction.
+//!
+//! The stream is split into frames using the FWHT frame headers. The codec
+//! parameters of every frame are obtained from its header, and submitted
+//! along with the compressed data following the header in a media request
+//! allocated from the media device of the decoder. P-frames reference the
+//! frame decoded just before them, which is kept until the next frame has
+//! been decoded.
+//!
+//! Decoded frames are written as raw frames of the decoded format to the
+//! output file, if one is specified.
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use clap::{App, Arg};
+
+use v4l2::controls::{CompoundControl, FwhtParams};
+use v4l2::device::stateless_decoder::{DecodedFrame, StatelessDecoder, StatelessDecoderConfig};
+use v4l2::device::*;
+use v4l2::ioctl::{self, BufferFlags};
+use v4l2::splitter::{FwhtHeader, FwhtSplitter, FWHT_HEADER_SIZE};
+use v4l2::PixelFormat;
+
+fn main() {
+    let matches = App::new("FWHT stateless decoder example")
+        .arg(
+            Arg::with_name("device")
+                .required(true)
+                .help("Path to the vicodec stateless decoder device file"),
+        )
+        .arg(
+            Arg::with_name("media")
+                .required(true)
+                .help("Path to the media device file of the decoder"),
+        )
+        .arg(
+            Arg::with_name("input")
+                .required(true)
+                .help("FWHT stream to decode"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .help("File to write the decoded frames into"),
+        )
+        .get_matches();
+
+    let device_path = Path::new(matches.value_of("device").unwrap());
+    let media_path = Path::new(matches.value_of("media").unwrap());
+    let stream = fs::read(matches.value_of("input").unwrap()).expect("Failed to read input file");
+    let mut output_file = matches
+        .value_of("output")
+        .map(|path| File::create(path).expect("Failed to create output file"));
+
+    let lets_quit = Arc::new(AtomicBool::new(false));
+
+    // Setup the Ctrl+c handler.
+    {
+        let lets_quit_handler = lets_quit.clone();
+        ctrlc::set_handler(move || {
+            lets_quit_handler.store(true, Ordering::SeqCst);
+        })
+        .expect("Failed to set Ctrl-C handler.");
+    }
+
+    // The resolution of the stream is given by the header of its first frame.
+    let first_header = FwhtSplitter::new(&stream)
+        .next()
+        .and_then(FwhtHeader::parse)
+        .expect("Input file does not start with a FWHT frame");
+    println!(
+        "Stream resolution: {}x{}",
+        first_header.width, first_header.height
+    );
+
+    let device = Device::open(device_path, DeviceConfig::new()).expect("Failed to open device");
+    let caps = &device.capability;
+    println!(
+        "Opened device: {}\n\tdriver: {}\n\tbus: {}\n\tcapabilities: {}",
+        caps.card, caps.driver, caps.bus_info, caps.capabilities
+    );
+    if caps.driver != "vicodec" {
+        panic!(
+            "This device is {}, but this test is designed to work with the vicodec driver.",
+            caps.driver
+        );
+    }
+
+    // Make sure the driver uses the same version of the parameters as we do.
+    let params_ctrl =
+        ioctl::query_ext_ctrl(&device, FwhtParams::ID).expect("FWHT parameters not supported");
+    FwhtParams::check_ctrl(&params_ctrl).expect("Unexpected FWHT parameters control");
+
+    let media = File::open(media_path).expect("Failed to open media device");
+    let device = Arc::new(Mutex::new(device));
+
+    let config = StatelessDecoderConfig::new(
+        PixelFormat::FWHT_STATELESS,
+        first_header.width as usize,
+        first_header.height as usize,
+    );
+    let mut decoder =
+        StatelessDecoder::new(device, media, config).expect("Failed to create decoder");
+    println!(
+        "Decoding into {}.",
+        decoder
+            .capture_queue()
+            .get_format()
+            .expect("Failed to get capture format")
+            .pixelformat
+    );
+
+    // The last decoded frame, kept as the reference of the next one.
+    let mut reference: Option<DecodedFrame> = None;
+    let mut cpt = 0usize;
+    for frame in FwhtSplitter::new(&stream) {
+        if lets_quit.load(Ordering::SeqCst) {
+            break;
+        }
+
+        // The splitter only returns frames with a valid header.
+        let header = FwhtHeader::parse(frame).unwrap();
+        if (header.width, header.height) != (first_header.width, first_header.height) {
+            println!("\nResolution change detected, stopping.");
+            break;
+        }
+
+        let mut params = FwhtParams::from_header(&header);
+        if !params.is_i_frame() {
+            params.backward_ref_ts = match &reference {
+                Some(reference) => reference.timestamp,
+                None => panic!("Stream starts with a P-frame"),
+            };
+        }
+
+        // The driver only expects the compressed data, the header being
+        // passed as parameters.
+        decoder
+            .decode(&frame[FWHT_HEADER_SIZE..], &mut [params.to_ext_control()])
+            .expect("Failed to decode frame");
+        // Decode frame by frame, as every frame references the previous one.
+        let decoded = decoder
+            .next_frame()
+            .expect("Failed to obtain decoded frame")
+            .expect("Decoded frame not available. This is a bug.");
+
+        let data = &decoded.buffer.data;
+        if data.flags.contains(BufferFlags::ERROR) {
+            println!("\nFrame {} was not decoded properly.", cpt);
+        }
+        let mut bytes_used = 0;
+        for (i, plane) in data.planes.iter().enumerate() {
+            // Drivers may report an offset beyond the data of an empty plane.
+            let range = plane.data_offset.min(plane.bytesused) as usize..plane.bytesused as usize;
+            bytes_used += range.len();
+            if let Some(output_file) = &mut output_file {
+                let mapping = decoder
+                    .capture_queue()
+                    .map_plane(data.index as usize, i)
+                    .expect("Failed to map buffer");
+                output_file
+                    .write_all(&mapping.as_slice()[range])
+                    .expect("Failed to write decoded frame");
+            }
+        }
+
+        print!(
+            "\rDecoded buffer {:#5}, {:#6} -> {:#8} bytes",
+            data.sequence,
+            frame.len(),
+            bytes_used
+        );
+        io::stdout().flush().unwrap();
+
+        // Dropping the previous reference makes its buffer available again.
+        reference = Some(decoded);
+        cpt += 1;
+    }
+    println!("\nDecoded {} frames.", cpt);
+}
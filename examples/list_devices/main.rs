@@ -0,0 +1,76 @@
+//! This example program lists the V4L2 video nodes of the system along with
+//! the information that can be used to find them again reliably, and the
+//! ALSA cards that belong to the same hardware.
+//!
+//! With `--watch`, it then keeps running and reports video nodes being added
+//! or removed.
+use clap::{App, Arg};
+use v4l2::device::hotplug::{HotplugEvent, HotplugMonitor};
+use v4l2::device::sysfs;
+
+fn main() {
+    let matches = App::new("V4L2 device listing example")
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .help("Keep reporting devices being plugged or unplugged"),
+        )
+        .get_matches();
+
+    // Start monitoring before listing, so we don't miss any device.
+    let monitor = if matches.is_present("watch") {
+        Some(HotplugMonitor::new().expect("Failed to start hot-plug monitoring"))
+    } else {
+        None
+    };
+
+    let devices = sysfs::enumerate().expect("Failed to enumerate devices");
+    if devices.is_empty() {
+        println!("No video device found.");
+    }
+
+    for device in devices {
+        println!("{}: {}", device.dev_path.display(), device.name);
+        if let (Some(vendor_id), Some(product_id)) = (&device.vendor_id, &device.product_id) {
+            println!("\tid: {}:{}", vendor_id, product_id);
+        }
+        if let Some(serial) = &device.serial {
+            println!("\tserial: {}", serial);
+        }
+        for link in device.by_id.iter().chain(device.by_path.iter()) {
+            println!("\tlink: {}", link.display());
+        }
+        match sysfs::find_alsa_cards(&device.dev_path) {
+            Ok(cards) => {
+                for card in cards {
+                    println!("\taudio: card {} ({})", card.index, card.hw_name());
+                }
+            }
+            Err(e) => println!("\taudio: failed to look up ALSA cards: {}", e),
+        }
+    }
+
+    let monitor = match monitor {
+        Some(monitor) => monitor,
+        None => return,
+    };
+    println!("Waiting for devices to be plugged or unplugged...");
+    loop {
+        match monitor.next_event().expect("Failed to read hot-plug event") {
+            HotplugEvent::Added {
+                dev_path,
+                capability: Some(capability),
+            } => println!(
+                "{} added: {} ({})",
+                dev_path.display(),
+                capability.card,
+                capability.driver
+            ),
+            HotplugEvent::Added {
+                dev_path,
+                capability: None,
+            } => println!("{} added", dev_path.display()),
+            HotplugEvent::Removed { dev_path } => println!("{} removed", dev_path.display()),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! This example program streams from a capture device or a memory-to-memory
+//! device for a given duration, and reports the frame rate, dropped frames
+//! (gaps in the sequence numbers of dequeued buffers) and the latency of
+//! buffers (time between queueing and dequeueing) for each queue.
+//!
+//! The formats currently set on the device are used as-is. For
+//! memory-to-memory devices, OUTPUT buffers are queued with their full size
+//! used but without any meaningful content, so this is mostly useful to
+//! measure the raw throughput of a driver.
+mod stats;
+
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::{App, Arg};
+
+use stats::QueueStats;
+use v4l2::device::poller::{poll_device_with_waker, PollEvents, Waker};
+use v4l2::device::queue::direction::Direction;
+use v4l2::device::queue::states::BuffersAllocated;
+use v4l2::device::queue::*;
+use v4l2::device::*;
+use v4l2::memory::MMAP;
+use v4l2::Result;
+
+/// Interval between two reports of the frame rate.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Dequeue all the buffers that are ready from `queue` and record them into
+/// `stats`, using `queued_at` to compute their latency.
+fn dequeue_ready<D: Direction>(
+    queue: &Queue<D, BuffersAllocated<MMAP>>,
+    queued_at: &mut [Option<Instant>],
+    stats: &mut QueueStats,
+) -> Result<()> {
+    for dqbuf in queue.dequeue_all_ready()? {
+        let latency = queued_at[dqbuf.data.index as usize]
+            .take()
+            .map(|t| t.elapsed())
+            .unwrap_or_default();
+        stats.record(&dqbuf.data, latency);
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let matches = App::new("V4L2 streaming benchmark")
+        .arg(
+            Arg::with_name("device")
+                .required(true)
+                .help("Path to the capture or memory-to-memory device file"),
+        )
+        .arg(
+            Arg::with_name("duration")
+                .long("duration")
+                .short("d")
+                .takes_value(true)
+                .default_value("10")
+                .help("Duration of the measurement, in seconds"),
+        )
+        .arg(
+            Arg::with_name("num_buffers")
+                .long("num_buffers")
+                .takes_value(true)
+                .default_value("4")
+                .help("Number of buffers to allocate on each queue"),
+        )
+        .get_matches();
+
+    let device_path = Path::new(matches.value_of("device").unwrap());
+    let duration = Duration::from_secs(
+        matches
+            .value_of("duration")
+            .unwrap()
+            .parse()
+            .expect("Invalid duration"),
+    );
+    let num_buffers: u32 = matches
+        .value_of("num_buffers")
+        .unwrap()
+        .parse()
+        .expect("Invalid number of buffers");
+
+    let lets_quit = Arc::new(AtomicBool::new(false));
+    let waker = Arc::new(Waker::new().expect("Failed to create waker"));
+
+    // Setup the Ctrl+c handler.
+    {
+        let lets_quit_handler = lets_quit.clone();
+        let waker_handler = Arc::clone(&waker);
+        ctrlc::set_handler(move || {
+            lets_quit_handler.store(true, Ordering::SeqCst);
+            waker_handler
+                .wake()
+                .expect("Failed to wake the streaming loop");
+        })
+        .expect("Failed to set Ctrl-C handler.");
+    }
+
+    let device = Device::open(device_path, DeviceConfig::new().non_blocking_dqbuf())
+        .expect("Failed to open device");
+    let caps = &device.capability;
+    println!(
+        "Opened device: {}\n\tdriver: {}\n\tbus: {}\n\tcapabilities: {}",
+        caps.card, caps.driver, caps.bus_info, caps.capabilities
+    );
+
+    let device = Arc::new(Mutex::new(device));
+    // The fd remains valid for as long as the device is alive, i.e. at least
+    // as long as the queues.
+    let device_fd = device.lock().unwrap().as_raw_fd();
+
+    let capture_queue = Queue::get_capture_queue(Arc::clone(&device))
+        .or_else(|_| Queue::get_capture_mplane_queue(Arc::clone(&device)))
+        .expect("Failed to obtain capture queue");
+    // Only memory-to-memory devices have an OUTPUT queue.
+    let output_queue = Queue::get_output_queue(Arc::clone(&device))
+        .or_else(|_| Queue::get_output_mplane_queue(Arc::clone(&device)))
+        .ok();
+
+    println!(
+        "Capture format: {:?}",
+        capture_queue
+            .get_format()
+            .expect("Failed to get capture format")
+    );
+    let capture_queue = capture_queue
+        .request_buffers::<MMAP>(num_buffers)
+        .expect("Failed to allocate capture buffers");
+
+    // For OUTPUT buffers, we also need the size of each plane so we can mark
+    // it as fully used.
+    let output_queue = output_queue.map(|queue| {
+        let format = queue.get_format().expect("Failed to get output format");
+        println!("Output format: {:?}", format);
+        let plane_sizes = queue.plane_sizes().to_vec();
+        let queue = queue
+            .request_buffers::<MMAP>(num_buffers)
+            .expect("Failed to allocate output buffers");
+        (queue, plane_sizes)
+    });
+
+    println!(
+        "Using {} capture buffers{}.",
+        capture_queue.num_buffers(),
+        match &output_queue {
+            Some((queue, _)) => format!(" and {} output buffers", queue.num_buffers()),
+            None => String::new(),
+        }
+    );
+
+    let mut capture_queued_at = vec![None; capture_queue.num_buffers()];
+    let mut capture_stats = QueueStats::default();
+    let mut output_queued_at = vec![
+        None;
+        output_queue
+            .as_ref()
+            .map(|(queue, _)| queue.num_buffers())
+            .unwrap_or(0)
+    ];
+    let mut output_stats = QueueStats::default();
+
+    if let Some((queue, _)) = &output_queue {
+        queue.streamon().expect("Failed to start output queue");
+    }
+    capture_queue
+        .streamon()
+        .expect("Failed to start capture queue");
+
+    let start = Instant::now();
+    let mut last_report = start;
+    while !lets_quit.load(Ordering::SeqCst) && start.elapsed() < duration {
+        // Keep all our buffers queued.
+        while let Ok(buffer) = capture_queue.get_free_buffer() {
+            let index = buffer.index();
+            buffer.auto_queue().expect("Failed to queue capture buffer");
+            capture_queued_at[index] = Some(Instant::now());
+        }
+        if let Some((queue, plane_sizes)) = &output_queue {
+            while let Ok(mut buffer) = queue.get_free_buffer() {
+                let index = buffer.index();
+                for plane_size in plane_sizes {
+                    buffer = buffer.add_plane(qbuf::Plane::out((), *plane_size));
+                }
+                buffer.queue().expect("Failed to queue output buffer");
+                output_queued_at[index] = Some(Instant::now());
+            }
+        }
+
+        let events = match output_queue {
+            Some(_) => PollEvents::CAPTURE_READY | PollEvents::OUTPUT_READY,
+            None => PollEvents::CAPTURE_READY,
+        };
+        // Wake up in time for the next report or the end of the measurement,
+        // or as soon as Ctrl+c is pressed.
+        let timeout = duration
+            .saturating_sub(start.elapsed())
+            .min(REPORT_INTERVAL.saturating_sub(last_report.elapsed()));
+        let events = poll_device_with_waker(&device_fd, events, &waker, Some(timeout))
+            .expect("Error while polling device");
+
+        if events.contains(PollEvents::OUTPUT_READY) {
+            if let Some((queue, _)) = &output_queue {
+                dequeue_ready(queue, &mut output_queued_at, &mut output_stats)
+                    .expect("Failed to dequeue output buffer");
+            }
+        }
+        if events.contains(PollEvents::CAPTURE_READY) {
+            dequeue_ready(&capture_queue, &mut capture_queued_at, &mut capture_stats)
+                .expect("Failed to dequeue capture buffer");
+        }
+
+        if last_report.elapsed() >= REPORT_INTERVAL {
+            print!(
+                "\r{:#6} frames, {:#8.2} fps",
+                capture_stats.frames,
+                capture_stats.frames as f64 / start.elapsed().as_secs_f64()
+            );
+            io::stdout().flush().unwrap();
+            last_report = Instant::now();
+        }
+    }
+    let elapsed = start.elapsed();
+
+    capture_queue
+        .streamoff()
+        .expect("Failed to stop capture queue");
+    if let Some((queue, _)) = &output_queue {
+        queue.streamoff().expect("Failed to stop output queue");
+    }
+
+    println!(
+        "\n\nStreamed for {:.2}s, {:.2} fps",
+        elapsed.as_secs_f64(),
+        capture_stats.frames as f64 / elapsed.as_secs_f64()
+    );
+    println!("Capture: {}", capture_stats);
+    if output_queue.is_some() {
+        println!("Output: {}", output_stats);
+    }
+}
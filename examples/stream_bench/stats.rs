@@ -0,0 +1,67 @@
+//! Statistics gathered while streaming.
+use std::fmt;
+use std::time::Duration;
+
+use v4l2::ioctl::{DQBuffer, FrameSequence};
+
+/// Statistics about one queue of a streaming session.
+#[derive(Default)]
+pub struct QueueStats {
+    /// Number of buffers dequeued so far.
+    pub frames: usize,
+    /// Number of frames missing according to the sequence numbers of the
+    /// dequeued buffers.
+    pub dropped: usize,
+    /// Time spent by each dequeued buffer in the queue.
+    latencies: Vec<Duration>,
+    /// Sequence number of the last dequeued buffer.
+    last_sequence: Option<FrameSequence>,
+}
+
+impl QueueStats {
+    /// Record the dequeued buffer `dqbuf`, which has spent `latency` in the
+    /// queue.
+    pub fn record(&mut self, dqbuf: &DQBuffer, latency: Duration) {
+        let sequence = dqbuf.frame_sequence();
+        if let Some(last_sequence) = self.last_sequence {
+            self.dropped += sequence.gap_since(last_sequence) as usize;
+        }
+        self.last_sequence = Some(sequence);
+        self.frames += 1;
+        self.latencies.push(latency);
+    }
+
+    /// Returns the latency below which `percentile` percent of the buffers
+    /// fall, or `None` if no buffer has been recorded yet.
+    pub fn latency_percentile(&self, percentile: usize) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+
+        let mut latencies = self.latencies.clone();
+        latencies.sort_unstable();
+        let index = (latencies.len() - 1) * percentile.min(100) / 100;
+        Some(latencies[index])
+    }
+}
+
+impl fmt::Display for QueueStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} frames, {} dropped", self.frames, self.dropped)?;
+        if self.latencies.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, ", latency")?;
+        for percentile in &[50, 90, 99, 100] {
+            let latency = self.latency_percentile(*percentile).unwrap_or_default();
+            let name = match percentile {
+                100 => String::from("max"),
+                p => format!("p{}", p),
+            };
+            write!(f, " {}: {:.2}ms", name, latency.as_secs_f64() * 1000.0)?;
+        }
+
+        Ok(())
+    }
+}
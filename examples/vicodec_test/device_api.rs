@@ -1,4 +1,4 @@
-use super::framegen;
+use std::fs::File;
 use std::io::{self, Write};
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -6,11 +6,14 @@ use std::sync::{Arc, Mutex};
 
 use v4l2::device::queue::*;
 use v4l2::device::*;
+use v4l2::ioctl;
 use v4l2::memory::{UserPtr, MMAP};
+use v4l2::testpattern;
 
 /// Run a sample encoder on device `device_path`, which must be a `vicodec`
-/// encoder instance. `lets_quit` will turn to true when Ctrl+C is pressed.
-pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
+/// encoder instance. If `output_file` is set, the encoded stream is written
+/// into it. `lets_quit` will turn to true when Ctrl+C is pressed.
+pub fn run(device_path: &Path, mut output_file: Option<File>, lets_quit: Arc<AtomicBool>) {
     let device = Device::open(device_path, DeviceConfig::new()).expect("Failed to open device");
     let caps = &device.capability;
     println!(
@@ -95,7 +98,6 @@ pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
     println!("Adjusted capture format: {:?}", capture_format);
 
     let output_image_size = output_format.plane_fmt[0].sizeimage as usize;
-    let output_image_bytesperline = output_format.plane_fmt[0].bytesperline as usize;
 
     // Move the queues into their "allocated" state.
     let output_queue = output_queue
@@ -110,6 +112,18 @@ pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
         capture_queue.num_buffers()
     );
 
+    // Map the CAPTURE buffers if we need to read the encoded data.
+    let capture_mappings: Vec<ioctl::PlaneMapping> = match output_file {
+        None => Vec::new(),
+        Some(_) => (0..capture_queue.num_buffers())
+            .map(|index| {
+                capture_queue
+                    .map_plane(index, 0)
+                    .expect("Failed to map capture buffer")
+            })
+            .collect(),
+    };
+
     // Create backing memory for the OUTPUT buffers.
     let mut output_frame = Some(vec![0u8; output_image_size]);
 
@@ -122,16 +136,10 @@ pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
     let mut total_size = 0usize;
     // Encode generated frames until Ctrl+c is pressed.
     while !lets_quit.load(Ordering::SeqCst) {
-        let mut output_buffer_data = output_frame
+        let output_buffer_data = output_frame
             .take()
             .expect("Output buffer not available. This is a bug.");
 
-        framegen::gen_pattern(
-            &mut output_buffer_data[..],
-            output_image_bytesperline,
-            cpt as u32,
-        );
-
         // There is no information to set on MMAP capture buffers: just queue
         // them as soon as we get them.
         capture_queue
@@ -141,14 +149,23 @@ pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
             .expect("Failed to queue capture buffer");
 
         // USERPTR output buffers, on the other hand, must be set up with
-        // a user buffer and bytes_used.
+        // a user buffer and bytes_used, which is computed from the amount of
+        // data written.
         // The queue takes ownership of the buffer until the driver is done
         // with it.
-        let bytes_used = output_buffer_data.len();
+        let output_plane = qbuf::Plane::write_with(output_buffer_data, |writer| {
+            let frame = writer.remaining();
+            let frame_size = frame.len();
+            testpattern::fill(frame, &output_format, cpt as u32).expect("Failed to generate frame");
+            writer.advance(frame_size);
+            Ok(())
+        })
+        .map_err(|(e, _)| e)
+        .expect("Failed to write output frame");
         output_queue
             .get_free_buffer()
             .expect("Failed to obtain output buffer")
-            .add_plane(qbuf::Plane::out(output_buffer_data, bytes_used))
+            .add_plane(output_plane)
             .queue()
             .expect("Failed to queue output buffer");
 
@@ -169,7 +186,15 @@ pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
             .dequeue()
             .expect("Failed to dequeue capture buffer");
 
-        total_size = total_size.wrapping_add(cap_dqbuf.data.planes[0].bytesused as usize);
+        let bytes_used = cap_dqbuf.data.planes[0].bytesused as usize;
+        if let Some(output_file) = &mut output_file {
+            let mapping = &capture_mappings[cap_dqbuf.data.index as usize];
+            output_file
+                .write_all(&mapping.as_slice()[..bytes_used])
+                .expect("Failed to write encoded frame");
+        }
+
+        total_size = total_size.wrapping_add(bytes_used);
         print!(
             "\rEncoded buffer {:#5}, {:#2} -> {:#2}), bytes used:{:#6} total encoded size:{:#8}",
             cap_dqbuf.data.sequence,
@@ -189,4 +214,7 @@ pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
     output_queue
         .streamoff()
         .expect("Failed to stop output_queue");
+
+    // The buffers must be unmapped before they can be freed.
+    drop(capture_mappings);
 }
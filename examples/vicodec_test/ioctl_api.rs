@@ -1,4 +1,3 @@
-use super::framegen;
 use nix::fcntl::{open, OFlag};
 use nix::sys::stat::Mode;
 use std::collections::BTreeMap;
@@ -10,11 +9,13 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use v4l2::ioctl::*;
 use v4l2::memory::{MMAPHandle, MemoryType, UserPtrHandle};
+use v4l2::testpattern;
 use v4l2::{Format, QueueType::*};
 
 /// Run a sample encoder on device `device_path`, which must be a `vicodec`
-/// encoder instance. `lets_quit` will turn to true when Ctrl+C is pressed.
-pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
+/// encoder instance. If `output_file` is set, the encoded stream is written
+/// into it. `lets_quit` will turn to true when Ctrl+C is pressed.
+pub fn run(device_path: &Path, mut output_file: Option<File>, lets_quit: Arc<AtomicBool>) {
     let mut fd = unsafe {
         File::from_raw_fd(
             open(device_path, OFlag::O_RDWR | OFlag::O_CLOEXEC, Mode::empty())
@@ -119,11 +120,23 @@ pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
     );
 
     let output_image_size = output_format.plane_fmt[0].sizeimage as usize;
-    let output_image_bytesperline = output_format.plane_fmt[0].bytesperline as usize;
     let mut output_buffers: Vec<Vec<u8>> = std::iter::repeat(vec![0u8; output_image_size])
         .take(num_output_buffers)
         .collect();
 
+    // Map the capture buffers if we need to read the encoded data.
+    let capture_mappings: Vec<PlaneMapping> = match output_file {
+        None => Vec::new(),
+        Some(_) => (0..num_capture_buffers)
+            .map(|index| {
+                let querybuf: QueryBufferMMAP =
+                    querybuf(&fd, capture_queue, index).expect("Failed to query capture buffer");
+                let plane = &querybuf.planes[0];
+                mmap(&fd, plane.mem_offset, plane.length).expect("Failed to map capture buffer")
+            })
+            .collect(),
+    };
+
     // Start streaming.
     streamon(&mut fd, output_queue).expect("Failed to start output queue");
     streamon(&mut fd, capture_queue).expect("Failed to start capture queue");
@@ -137,11 +150,8 @@ pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
         let output_buffer = &mut output_buffers[output_buffer_index];
 
         // Generate the frame data.
-        framegen::gen_pattern(
-            &mut output_buffer[..],
-            output_image_bytesperline,
-            cpt as u32,
-        );
+        testpattern::fill(&mut output_buffer[..], &output_format, cpt as u32)
+            .expect("Failed to generate frame");
 
         // Queue the work to be encoded.
         let out_qbuf = QBuffer::<UserPtrHandle> {
@@ -174,7 +184,15 @@ pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
         let cap_dqbuf: DQBuffer =
             dqbuf(&fd, capture_queue).expect("Failed to dequeue capture buffer");
 
-        total_size = total_size.wrapping_add(cap_dqbuf.planes[0].bytesused as usize);
+        let bytes_used = cap_dqbuf.planes[0].bytesused as usize;
+        if let Some(output_file) = &mut output_file {
+            let mapping = &capture_mappings[cap_dqbuf.index as usize];
+            output_file
+                .write_all(&mapping.as_slice()[..bytes_used])
+                .expect("Failed to write encoded frame");
+        }
+
+        total_size = total_size.wrapping_add(bytes_used);
         print!(
             "\rEncoded buffer {:#5}, index: {:#2}), bytes used:{:#6} total encoded size:{:#8}",
             cap_dqbuf.sequence, cap_dqbuf.index, cap_dqbuf.planes[0].bytesused, total_size
@@ -188,7 +206,8 @@ pub fn run(device_path: &Path, lets_quit: Arc<AtomicBool>) {
     streamoff(&mut fd, capture_queue).expect("Failed to stop capture queue");
     streamoff(&mut fd, output_queue).expect("Failed to stop output queue");
 
-    // Free the buffers.
+    // Free the buffers, after unmapping them.
+    drop(capture_mappings);
     reqbufs::<(), _>(&mut fd, capture_queue, MemoryType::MMAP, 0)
         .expect("Failed to release capture buffers");
     reqbufs::<(), _>(&mut fd, output_queue, MemoryType::UserPtr, 0)
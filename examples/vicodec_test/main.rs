@@ -1,20 +1,28 @@
 //! This example program demonstrates how to use the API using the `vicodec`
 //! virtual codec driver.
 //!
-//! There are two variants doing the same thing: one using the higher-level
-//! `device` abstraction (used by default), the other using the low-level
-//! `ioctl` abstraction (used if `--use_ioctl` is specified).
+//! There are three variants doing the same thing: one using the higher-level
+//! `device` abstraction (used by default), another using the low-level
+//! `ioctl` abstraction (used if `--use_ioctl` is specified), and a last one
+//! using the `device` abstraction with non-blocking queues and the `poller`
+//! module to keep several buffers in flight (used if `--use_poll` is specified).
+//!
+//! If `--output` is specified, the encoded FWHT stream is written to the given
+//! file, which can then be decoded using the `fwht_decoder` or
+//! `fwht_stateless_decoder` examples.
 mod device_api;
-mod framegen;
 mod ioctl_api;
+mod poll_api;
 
-use ctrlc;
+use std::fs::File;
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use clap::{App, Arg};
 
+use v4l2::device::poller::Waker;
+
 fn main() {
     let matches = App::new("vicodec example")
         .arg(
@@ -22,32 +30,54 @@ fn main() {
                 .long("use_ioctl")
                 .help("Use the lower-level ioctl interface"),
         )
+        .arg(
+            Arg::with_name("use_poll")
+                .long("use_poll")
+                .conflicts_with("use_ioctl")
+                .help("Use the device interface with non-blocking queues and poll"),
+        )
         .arg(
             Arg::with_name("device")
                 .required(true)
                 .help("Path to the vicodec device file"),
         )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .help("File to write the encoded FWHT stream into"),
+        )
         .get_matches();
 
     let device_path = matches.value_of("device").unwrap_or("/dev/video0");
     let use_ioctl = matches.is_present("use_ioctl");
+    let use_poll = matches.is_present("use_poll");
+    let output_file = matches
+        .value_of("output")
+        .map(|path| File::create(path).expect("Failed to create output file"));
 
     let lets_quit = Arc::new(AtomicBool::new(false));
+    let waker = Arc::new(Waker::new().expect("Failed to create waker"));
 
     // Setup the Ctrl+c handler.
     {
         let lets_quit_handler = lets_quit.clone();
+        let waker_handler = Arc::clone(&waker);
         ctrlc::set_handler(move || {
             lets_quit_handler.store(true, Ordering::SeqCst);
+            waker_handler.wake().expect("Failed to wake the poll loop");
         })
         .expect("Failed to set Ctrl-C handler.");
     }
 
     if use_ioctl {
         println!("Using ioctl interface");
-        return ioctl_api::run(&Path::new(&device_path), lets_quit);
+        ioctl_api::run(Path::new(&device_path), output_file, lets_quit)
+    } else if use_poll {
+        println!("Using device interface with poll");
+        poll_api::run(Path::new(&device_path), output_file, lets_quit, waker)
     } else {
         println!("Using device interface");
-        return device_api::run(&Path::new(&device_path), lets_quit);
+        device_api::run(Path::new(&device_path), output_file, lets_quit)
     }
 }
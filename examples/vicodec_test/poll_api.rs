@@ -0,0 +1,222 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use v4l2::device::poller::{poll_device_with_waker, PollEvents, Waker};
+use v4l2::device::queue::*;
+use v4l2::device::*;
+use v4l2::ioctl;
+use v4l2::memory::{UserPtr, MMAP};
+use v4l2::testpattern;
+use v4l2::Error;
+
+/// Number of buffers allocated on each queue. All of them can be in flight at
+/// the same time.
+const NUM_BUFFERS: u32 = 4;
+
+/// Run a sample encoder on device `device_path`, which must be a `vicodec`
+/// encoder instance. Contrary to the other variants, this one opens the device
+/// in non-blocking mode and keeps as many buffers as possible queued on both
+/// queues, using the `poller` module to know when buffers can be dequeued.
+///
+/// If `output_file` is set, the encoded stream is written into it.
+/// `lets_quit` will turn to true when Ctrl+C is pressed, after which `waker`
+/// is woken up to interrupt the wait.
+pub fn run(
+    device_path: &Path,
+    mut output_file: Option<File>,
+    lets_quit: Arc<AtomicBool>,
+    waker: Arc<Waker>,
+) {
+    let device = Device::open(device_path, DeviceConfig::new().non_blocking_dqbuf())
+        .expect("Failed to open device");
+    let caps = &device.capability;
+    println!(
+        "Opened device: {}\n\tdriver: {}\n\tbus: {}\n\tcapabilities: {}",
+        caps.card, caps.driver, caps.bus_info, caps.capabilities
+    );
+    if caps.driver != "vicodec" {
+        panic!(
+            "This device is {}, but this test is designed to work with the vicodec driver.",
+            caps.driver
+        );
+    }
+
+    let device = Arc::new(Mutex::new(device));
+    // The fd remains valid for as long as the device is alive, i.e. at least
+    // as long as the queues.
+    let device_fd = device.lock().unwrap().as_raw_fd();
+
+    // Obtain the queues, depending on whether we are using the single or multi planar API.
+    let (mut output_queue, mut capture_queue) = if let Ok(output_queue) =
+        Queue::get_output_queue(Arc::clone(&device))
+    {
+        (
+            output_queue,
+            Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue"),
+        )
+    } else if let Ok(output_queue) = Queue::get_output_mplane_queue(Arc::clone(&device)) {
+        (
+            output_queue,
+            Queue::get_capture_mplane_queue(Arc::clone(&device))
+                .expect("Failed to obtain capture queue"),
+        )
+    } else {
+        panic!("Both single-planar and multi-planar queues are unusable.");
+    };
+
+    // Set 640x480 RGB3 format on the OUTPUT queue.
+    let output_format = output_queue
+        .change_format()
+        .expect("Failed to get output format")
+        .set_size(640, 480)
+        .set_pixelformat(b"RGB3")
+        .apply()
+        .expect("Failed to set output format");
+    if output_format.pixelformat != b"RGB3".into() {
+        panic!("RGB3 format not supported on OUTPUT queue.");
+    }
+    println!("Adjusted output format: {:?}", output_format);
+
+    // Make sure the CAPTURE queue will produce FWHT.
+    let capture_format = capture_queue
+        .change_format()
+        .expect("Failed to get capture format")
+        .set_pixelformat(b"FWHT")
+        .apply()
+        .expect("Failed to set capture format");
+    if capture_format.pixelformat != b"FWHT".into() {
+        panic!("FWHT format not supported on CAPTURE queue.");
+    }
+    println!("Adjusted capture format: {:?}", capture_format);
+
+    let output_image_size = output_format.plane_fmt[0].sizeimage as usize;
+
+    let output_queue = output_queue
+        .request_buffers::<UserPtr<Vec<u8>>>(NUM_BUFFERS)
+        .expect("Failed to allocate output buffers");
+    let capture_queue = capture_queue
+        .request_buffers::<MMAP>(NUM_BUFFERS)
+        .expect("Failed to allocate capture buffers");
+    println!(
+        "Using {} output and {} capture buffers.",
+        output_queue.num_buffers(),
+        capture_queue.num_buffers()
+    );
+
+    // Map the CAPTURE buffers if we need to read the encoded data.
+    let capture_mappings: Vec<ioctl::PlaneMapping> = match output_file {
+        None => Vec::new(),
+        Some(_) => (0..capture_queue.num_buffers())
+            .map(|index| {
+                capture_queue
+                    .map_plane(index, 0)
+                    .expect("Failed to map capture buffer")
+            })
+            .collect(),
+    };
+
+    // Backing memory for the OUTPUT buffers that are not queued.
+    let mut free_output_frames: Vec<Vec<u8>> = (0..output_queue.num_buffers())
+        .map(|_| vec![0u8; output_image_size])
+        .collect();
+
+    output_queue
+        .streamon()
+        .expect("Failed to start output queue");
+    capture_queue.streamon().expect("Failed to start capture");
+
+    let mut cpt = 0usize;
+    let mut total_size = 0usize;
+    // Encode generated frames until Ctrl+c is pressed.
+    while !lets_quit.load(Ordering::SeqCst) {
+        // Keep all the CAPTURE buffers queued.
+        while let Ok(buffer) = capture_queue.get_free_buffer() {
+            buffer.auto_queue().expect("Failed to queue capture buffer");
+        }
+
+        // Queue a new frame for every OUTPUT buffer we got back.
+        while let Some(mut frame) = free_output_frames.pop() {
+            let buffer = match output_queue.get_free_buffer() {
+                Ok(buffer) => buffer,
+                Err(_) => {
+                    free_output_frames.push(frame);
+                    break;
+                }
+            };
+
+            testpattern::fill(&mut frame[..], &output_format, cpt as u32)
+                .expect("Failed to generate frame");
+            let bytes_used = frame.len();
+            buffer
+                .add_plane(qbuf::Plane::out(frame, bytes_used))
+                .queue()
+                .expect("Failed to queue output buffer");
+            cpt = cpt.wrapping_add(1);
+        }
+
+        // Wait until a CAPTURE buffer is ready or an OUTPUT buffer has been
+        // processed, or until Ctrl+c is pressed.
+        let events = poll_device_with_waker(
+            &device_fd,
+            PollEvents::CAPTURE_READY | PollEvents::OUTPUT_READY,
+            &waker,
+            None,
+        )
+        .expect("Error while polling device");
+
+        if events.contains(PollEvents::OUTPUT_READY) {
+            // Retrieve the memory of all the processed OUTPUT buffers.
+            loop {
+                match output_queue.try_dequeue() {
+                    Ok(mut out_dqbuf) => free_output_frames.push(out_dqbuf.plane_handles.remove(0)),
+                    Err(Error::NotReady) => break,
+                    Err(e) => panic!("Failed to dequeue output buffer: {}", e),
+                }
+            }
+        }
+
+        if events.contains(PollEvents::CAPTURE_READY) {
+            loop {
+                let cap_dqbuf = match capture_queue.try_dequeue() {
+                    Ok(cap_dqbuf) => cap_dqbuf,
+                    Err(Error::NotReady) => break,
+                    Err(e) => panic!("Failed to dequeue capture buffer: {}", e),
+                };
+
+                let bytes_used = cap_dqbuf.data.planes[0].bytesused as usize;
+                if let Some(output_file) = &mut output_file {
+                    let mapping = &capture_mappings[cap_dqbuf.data.index as usize];
+                    output_file
+                        .write_all(&mapping.as_slice()[..bytes_used])
+                        .expect("Failed to write encoded frame");
+                }
+
+                total_size = total_size.wrapping_add(bytes_used);
+                print!(
+                    "\rEncoded buffer {:#5}, index: {:#2}), in flight: {}/{}, bytes used:{:#6} total encoded size:{:#8}",
+                    cap_dqbuf.data.sequence,
+                    cap_dqbuf.data.index,
+                    output_queue.num_queued_buffers(),
+                    capture_queue.num_queued_buffers(),
+                    bytes_used,
+                    total_size
+                );
+                io::stdout().flush().unwrap();
+            }
+        }
+    }
+
+    capture_queue
+        .streamoff()
+        .expect("Failed to stop capture queue");
+    output_queue
+        .streamoff()
+        .expect("Failed to stop output queue");
+
+    // The buffers must be unmapped before they can be freed.
+    drop(capture_mappings);
+}
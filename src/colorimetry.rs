@@ -0,0 +1,198 @@
+//! Colorimetry of pixel formats, i.e. how the values of the pixels map to
+//! actual colors.
+//!
+//! Each property can be left to its `Default` variant, in which case it is
+//! derived from the colorspace. Drivers of capture devices and decoders
+//! report the colorimetry of the frames they produce, while applications
+//! set it on OUTPUT queues to describe the frames they provide.
+use crate::bindings;
+
+/// Colorspace of a format, which defines its primaries and white point,
+/// as well as the default values of the other properties. Safe variant of
+/// `enum v4l2_colorspace`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u32)]
+pub enum Colorspace {
+    #[default]
+    Default = bindings::v4l2_colorspace_V4L2_COLORSPACE_DEFAULT,
+    Smpte170m = bindings::v4l2_colorspace_V4L2_COLORSPACE_SMPTE170M,
+    Smpte240m = bindings::v4l2_colorspace_V4L2_COLORSPACE_SMPTE240M,
+    Rec709 = bindings::v4l2_colorspace_V4L2_COLORSPACE_REC709,
+    Bt878 = bindings::v4l2_colorspace_V4L2_COLORSPACE_BT878,
+    System470M = bindings::v4l2_colorspace_V4L2_COLORSPACE_470_SYSTEM_M,
+    System470Bg = bindings::v4l2_colorspace_V4L2_COLORSPACE_470_SYSTEM_BG,
+    Jpeg = bindings::v4l2_colorspace_V4L2_COLORSPACE_JPEG,
+    Srgb = bindings::v4l2_colorspace_V4L2_COLORSPACE_SRGB,
+    Oprgb = bindings::v4l2_colorspace_V4L2_COLORSPACE_OPRGB,
+    Bt2020 = bindings::v4l2_colorspace_V4L2_COLORSPACE_BT2020,
+    Raw = bindings::v4l2_colorspace_V4L2_COLORSPACE_RAW,
+    DciP3 = bindings::v4l2_colorspace_V4L2_COLORSPACE_DCI_P3,
+}
+
+impl Colorspace {
+    /// Returns the variant matching the raw V4L2 value `value`, if it is
+    /// known.
+    pub fn from_v4l2(value: u32) -> Option<Self> {
+        use Colorspace::*;
+        [
+            Default,
+            Smpte170m,
+            Smpte240m,
+            Rec709,
+            Bt878,
+            System470M,
+            System470Bg,
+            Jpeg,
+            Srgb,
+            Oprgb,
+            Bt2020,
+            Raw,
+            DciP3,
+        ]
+        .iter()
+        .copied()
+        .find(|variant| *variant as u32 == value)
+    }
+}
+
+impl From<Colorspace> for u32 {
+    fn from(value: Colorspace) -> Self {
+        value as u32
+    }
+}
+
+/// Transfer function of a format. Safe variant of `enum v4l2_xfer_func`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u32)]
+pub enum XferFunc {
+    #[default]
+    Default = bindings::v4l2_xfer_func_V4L2_XFER_FUNC_DEFAULT,
+    Rec709 = bindings::v4l2_xfer_func_V4L2_XFER_FUNC_709,
+    Srgb = bindings::v4l2_xfer_func_V4L2_XFER_FUNC_SRGB,
+    Oprgb = bindings::v4l2_xfer_func_V4L2_XFER_FUNC_OPRGB,
+    Smpte240m = bindings::v4l2_xfer_func_V4L2_XFER_FUNC_SMPTE240M,
+    None = bindings::v4l2_xfer_func_V4L2_XFER_FUNC_NONE,
+    DciP3 = bindings::v4l2_xfer_func_V4L2_XFER_FUNC_DCI_P3,
+    Smpte2084 = bindings::v4l2_xfer_func_V4L2_XFER_FUNC_SMPTE2084,
+}
+
+impl XferFunc {
+    /// Returns the variant matching the raw V4L2 value `value`, if it is
+    /// known.
+    pub fn from_v4l2(value: u32) -> Option<Self> {
+        use XferFunc::*;
+        [
+            Default, Rec709, Srgb, Oprgb, Smpte240m, None, DciP3, Smpte2084,
+        ]
+        .iter()
+        .copied()
+        .find(|variant| *variant as u32 == value)
+    }
+}
+
+impl From<XferFunc> for u32 {
+    fn from(value: XferFunc) -> Self {
+        value as u32
+    }
+}
+
+/// Encoding of a YCbCr format, or of a HSV format for the `Hsv` variants
+/// which use the same field. Safe variant of `enum v4l2_ycbcr_encoding`
+/// and `enum v4l2_hsv_encoding`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u32)]
+pub enum YCbCrEncoding {
+    #[default]
+    Default = bindings::v4l2_ycbcr_encoding_V4L2_YCBCR_ENC_DEFAULT,
+    Bt601 = bindings::v4l2_ycbcr_encoding_V4L2_YCBCR_ENC_601,
+    Rec709 = bindings::v4l2_ycbcr_encoding_V4L2_YCBCR_ENC_709,
+    Xv601 = bindings::v4l2_ycbcr_encoding_V4L2_YCBCR_ENC_XV601,
+    Xv709 = bindings::v4l2_ycbcr_encoding_V4L2_YCBCR_ENC_XV709,
+    Sycc = bindings::v4l2_ycbcr_encoding_V4L2_YCBCR_ENC_SYCC,
+    Bt2020 = bindings::v4l2_ycbcr_encoding_V4L2_YCBCR_ENC_BT2020,
+    Bt2020ConstLum = bindings::v4l2_ycbcr_encoding_V4L2_YCBCR_ENC_BT2020_CONST_LUM,
+    Smpte240m = bindings::v4l2_ycbcr_encoding_V4L2_YCBCR_ENC_SMPTE240M,
+    /// Hue values are mapped to [0, 179].
+    Hsv180 = bindings::v4l2_hsv_encoding_V4L2_HSV_ENC_180,
+    /// Hue values are mapped to [0, 255].
+    Hsv256 = bindings::v4l2_hsv_encoding_V4L2_HSV_ENC_256,
+}
+
+impl YCbCrEncoding {
+    /// Returns the variant matching the raw V4L2 value `value`, if it is
+    /// known.
+    pub fn from_v4l2(value: u32) -> Option<Self> {
+        use YCbCrEncoding::*;
+        [
+            Default,
+            Bt601,
+            Rec709,
+            Xv601,
+            Xv709,
+            Sycc,
+            Bt2020,
+            Bt2020ConstLum,
+            Smpte240m,
+            Hsv180,
+            Hsv256,
+        ]
+        .iter()
+        .copied()
+        .find(|variant| *variant as u32 == value)
+    }
+}
+
+impl From<YCbCrEncoding> for u32 {
+    fn from(value: YCbCrEncoding) -> Self {
+        value as u32
+    }
+}
+
+/// Range of the values of a format. Safe variant of `enum
+/// v4l2_quantization`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u32)]
+pub enum Quantization {
+    #[default]
+    Default = bindings::v4l2_quantization_V4L2_QUANTIZATION_DEFAULT,
+    /// Values use the whole range allowed by their bit depth.
+    FullRange = bindings::v4l2_quantization_V4L2_QUANTIZATION_FULL_RANGE,
+    /// Values use a limited range, e.g. [16, 235] for 8-bit luma.
+    LimRange = bindings::v4l2_quantization_V4L2_QUANTIZATION_LIM_RANGE,
+}
+
+impl Quantization {
+    /// Returns the variant matching the raw V4L2 value `value`, if it is
+    /// known.
+    pub fn from_v4l2(value: u32) -> Option<Self> {
+        use Quantization::*;
+        [Default, FullRange, LimRange]
+            .iter()
+            .copied()
+            .find(|variant| *variant as u32 == value)
+    }
+}
+
+impl From<Quantization> for u32 {
+    fn from(value: Quantization) -> Self {
+        value as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colorimetry_conversions() {
+        assert_eq!(Colorspace::from_v4l2(3), Some(Colorspace::Rec709));
+        assert_eq!(Colorspace::from_v4l2(0xff), None);
+        assert_eq!(u32::from(XferFunc::Smpte2084), 7);
+        assert_eq!(YCbCrEncoding::from_v4l2(128), Some(YCbCrEncoding::Hsv180));
+        assert_eq!(Quantization::default(), Quantization::Default);
+    }
+}
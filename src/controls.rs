@@ -0,0 +1,94 @@
+//! Typed representations of the payloads of V4L2 compound controls.
+//!
+//! Compound controls carry a C structure instead of a single integer value.
+//! The types of this module have the same memory layout as their kernel
+//! counterparts, so they can be passed as the payload of a `struct
+//! v4l2_ext_control`.
+mod fwht;
+mod h264;
+mod hdr10;
+mod hevc;
+mod mpeg2;
+mod vp8;
+mod vp9;
+
+pub use fwht::*;
+pub use h264::*;
+pub use hdr10::*;
+pub use hevc::*;
+pub use mpeg2::*;
+pub use vp8::*;
+pub use vp9::*;
+
+use crate::ioctl::{CtrlType, ExtControl, ExtControlValue, QueryExtCtrl};
+use crate::{CtrlClass, CtrlId, Error, Result};
+use std::{mem, ptr, slice};
+
+/// Base of the stateless codec control IDs.
+const CODEC_STATELESS_BASE: u32 = CtrlClass::CodecStateless as u32 | 0x900;
+
+/// A compound control, the payload of which is a C structure.
+///
+/// # Safety
+///
+/// Implementors must have the exact memory layout of the kernel structure,
+/// i.e. be `#[repr(C)]` without implicit padding, and any bit pattern must be
+/// a valid value of the type.
+pub unsafe trait CompoundControl: Copy {
+    /// Identifier of the control.
+    const ID: CtrlId;
+    /// Type of the control, as reported by `query_ext_ctrl`.
+    const TYPE: u32;
+
+    /// Returns the payload of the control.
+    fn as_payload(&self) -> &[u8] {
+        // Safe because the trait guarantees the type has no padding.
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, mem::size_of::<Self>()) }
+    }
+
+    /// Reads the control from `payload`, or returns `None` if `payload` does
+    /// not have the size of the control.
+    fn from_payload(payload: &[u8]) -> Option<Self> {
+        if payload.len() != mem::size_of::<Self>() {
+            return None;
+        }
+
+        // Safe because the trait guarantees that any bit pattern is valid,
+        // and we checked the size of `payload`.
+        Some(unsafe { ptr::read_unaligned(payload.as_ptr() as *const Self) })
+    }
+
+    /// Checks that `ctrl`, as returned by `query_ext_ctrl`, describes this
+    /// control with a payload of the size of this structure. Returns
+    /// `Error::InvalidControlValue` otherwise, e.g. if the driver has been
+    /// built against a different version of the structure.
+    fn check_ctrl(ctrl: &QueryExtCtrl) -> Result<()> {
+        if ctrl.id != Self::ID
+            || ctrl.type_ != CtrlType::Other(Self::TYPE)
+            || ctrl.elem_size as usize != mem::size_of::<Self>()
+        {
+            return Err(Error::InvalidControlValue);
+        }
+
+        Ok(())
+    }
+
+    /// Returns an `ExtControl` setting the control to this value.
+    fn to_ext_control(&self) -> ExtControl {
+        ExtControl::new(
+            Self::ID,
+            ExtControlValue::Payload(self.as_payload().to_vec()),
+        )
+    }
+
+    /// Returns an `ExtControl` setting an array control, e.g. a dynamic
+    /// array with one element per slice, to `values`.
+    fn array_to_ext_control(values: &[Self]) -> ExtControl {
+        let payload = values
+            .iter()
+            .flat_map(|value| value.as_payload().iter().copied())
+            .collect();
+
+        ExtControl::new(Self::ID, ExtControlValue::Payload(payload))
+    }
+}
@@ -0,0 +1,116 @@
+//! FWHT stateless decoding control, used by the stateless decoder of the
+//! `vicodec` virtual driver.
+//!
+//! This structure is not part of our bindings yet, so it is defined here
+//! following `include/uapi/linux/v4l2-controls.h`.
+use super::{CompoundControl, CODEC_STATELESS_BASE};
+use crate::splitter::FwhtHeader;
+use crate::CtrlId;
+use bitflags::bitflags;
+
+/// ID of the FWHT parameters control.
+pub const V4L2_CID_STATELESS_FWHT_PARAMS: u32 = CODEC_STATELESS_BASE + 100;
+
+/// Control type of `V4L2_CID_STATELESS_FWHT_PARAMS`.
+pub const V4L2_CTRL_TYPE_FWHT_PARAMS: u32 = 0x0220;
+
+/// Current version of the FWHT bitstream.
+pub const V4L2_FWHT_VERSION: u32 = 3;
+
+bitflags! {
+    /// Flags of the `flags` field of `FwhtParams`.
+    pub struct FwhtFlags: u32 {
+        const IS_INTERLACED = 1 << 0;
+        const IS_BOTTOM_FIRST = 1 << 1;
+        const IS_ALTERNATE = 1 << 2;
+        const IS_BOTTOM_FIELD = 1 << 3;
+        const LUMA_IS_UNCOMPRESSED = 1 << 4;
+        const CB_IS_UNCOMPRESSED = 1 << 5;
+        const CR_IS_UNCOMPRESSED = 1 << 6;
+        const CHROMA_FULL_HEIGHT = 1 << 7;
+        const CHROMA_FULL_WIDTH = 1 << 8;
+        const ALPHA_IS_UNCOMPRESSED = 1 << 9;
+        const I_FRAME = 1 << 10;
+        /// Mask of the number of components minus one.
+        const COMPONENTS_NUM_MSK = 0x7 << 16;
+        /// Mask of the pixel encoding.
+        const PIXENC_MSK = 0x3 << 19;
+        const PIXENC_YUV = 1 << 19;
+        const PIXENC_RGB = 2 << 19;
+        const PIXENC_HSV = 3 << 19;
+    }
+}
+
+/// Parameters of a FWHT frame. Layout of `struct v4l2_ctrl_fwht_params`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FwhtParams {
+    /// Timestamp of the CAPTURE buffer holding the reference frame, in
+    /// nanoseconds.
+    pub backward_ref_ts: u64,
+    pub version: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Bits of `FwhtFlags`.
+    pub flags: u32,
+    pub colorspace: u32,
+    pub xfer_func: u32,
+    pub ycbcr_enc: u32,
+    pub quantization: u32,
+}
+
+impl FwhtParams {
+    /// Build the parameters of a frame from its `header`, as parsed from the
+    /// output of the `vicodec` encoder. `backward_ref_ts` is left to 0 and
+    /// must be set by the caller for P-frames.
+    pub fn from_header(header: &FwhtHeader) -> Self {
+        FwhtParams {
+            backward_ref_ts: 0,
+            version: header.version,
+            width: header.width,
+            height: header.height,
+            flags: header.flags,
+            colorspace: header.colorspace,
+            xfer_func: header.xfer_func,
+            ycbcr_enc: header.ycbcr_enc,
+            quantization: header.quantization,
+        }
+    }
+
+    /// Returns true if the frame can be decoded without a reference frame.
+    pub fn is_i_frame(&self) -> bool {
+        FwhtFlags::from_bits_truncate(self.flags).contains(FwhtFlags::I_FRAME)
+    }
+}
+
+unsafe impl CompoundControl for FwhtParams {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_FWHT_PARAMS);
+    const TYPE: u32 = V4L2_CTRL_TYPE_FWHT_PARAMS;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn fwht_layout() {
+        assert_eq!(mem::size_of::<FwhtParams>(), 40);
+        assert_eq!(V4L2_CID_STATELESS_FWHT_PARAMS, 0x00a4_0964);
+    }
+
+    #[test]
+    fn fwht_from_header() {
+        let mut frame = vec![0x4f, 0x4f, 0x4f, 0x4f, 0xff, 0xff, 0xff, 0xff];
+        let flags = FwhtFlags::I_FRAME | FwhtFlags::PIXENC_YUV;
+        for field in &[V4L2_FWHT_VERSION, 640, 480, flags.bits(), 8, 1, 1, 1, 0] {
+            frame.extend_from_slice(&field.to_be_bytes());
+        }
+
+        let params = FwhtParams::from_header(&FwhtHeader::parse(&frame).unwrap());
+        assert_eq!(params.version, V4L2_FWHT_VERSION);
+        assert_eq!((params.width, params.height), (640, 480));
+        assert_eq!(params.colorspace, 8);
+        assert!(params.is_i_frame());
+    }
+}
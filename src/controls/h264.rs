@@ -0,0 +1,351 @@
+//! H.264 stateless decoding controls.
+//!
+//! These structures are not part of our bindings yet, so they are defined
+//! here following `include/uapi/linux/v4l2-controls.h`. Fields are named
+//! after the syntax elements of the H.264 specification they carry.
+use super::{CompoundControl, CODEC_STATELESS_BASE};
+use crate::CtrlId;
+use bitflags::bitflags;
+use std::mem;
+
+/// ID of the menu control selecting whether the decoder is fed one slice or
+/// one frame per request. See `H264DecodeMode`.
+pub const V4L2_CID_STATELESS_H264_DECODE_MODE: u32 = CODEC_STATELESS_BASE;
+/// ID of the menu control selecting whether slices are prefixed with Annex B
+/// start codes. See `H264StartCode`.
+pub const V4L2_CID_STATELESS_H264_START_CODE: u32 = CODEC_STATELESS_BASE + 1;
+/// ID of the H.264 sequence parameter set control.
+pub const V4L2_CID_STATELESS_H264_SPS: u32 = CODEC_STATELESS_BASE + 2;
+/// ID of the H.264 picture parameter set control.
+pub const V4L2_CID_STATELESS_H264_PPS: u32 = CODEC_STATELESS_BASE + 3;
+/// ID of the H.264 scaling matrix control.
+pub const V4L2_CID_STATELESS_H264_SCALING_MATRIX: u32 = CODEC_STATELESS_BASE + 4;
+/// ID of the H.264 slice parameters control.
+pub const V4L2_CID_STATELESS_H264_SLICE_PARAMS: u32 = CODEC_STATELESS_BASE + 6;
+/// ID of the H.264 decode parameters control.
+pub const V4L2_CID_STATELESS_H264_DECODE_PARAMS: u32 = CODEC_STATELESS_BASE + 7;
+
+/// Control type of `V4L2_CID_STATELESS_H264_SPS`.
+pub const V4L2_CTRL_TYPE_H264_SPS: u32 = 0x0200;
+/// Control type of `V4L2_CID_STATELESS_H264_PPS`.
+pub const V4L2_CTRL_TYPE_H264_PPS: u32 = 0x0201;
+/// Control type of `V4L2_CID_STATELESS_H264_SCALING_MATRIX`.
+pub const V4L2_CTRL_TYPE_H264_SCALING_MATRIX: u32 = 0x0202;
+/// Control type of `V4L2_CID_STATELESS_H264_SLICE_PARAMS`.
+pub const V4L2_CTRL_TYPE_H264_SLICE_PARAMS: u32 = 0x0203;
+/// Control type of `V4L2_CID_STATELESS_H264_DECODE_PARAMS`.
+pub const V4L2_CTRL_TYPE_H264_DECODE_PARAMS: u32 = 0x0204;
+
+/// Number of entries of the decoded picture buffer.
+pub const V4L2_H264_NUM_DPB_ENTRIES: usize = 16;
+/// Length of the reference picture lists.
+pub const V4L2_H264_REF_LIST_LEN: usize = 2 * V4L2_H264_NUM_DPB_ENTRIES;
+
+/// Values of `V4L2_CID_STATELESS_H264_DECODE_MODE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum H264DecodeMode {
+    SliceBased = 0,
+    FrameBased = 1,
+}
+
+/// Values of `V4L2_CID_STATELESS_H264_START_CODE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum H264StartCode {
+    None = 0,
+    AnnexB = 1,
+}
+
+bitflags! {
+    /// Flags of the `constraint_set_flags` field of `H264Sps`.
+    pub struct H264SpsConstraintSetFlags: u8 {
+        const SET0 = 0x01;
+        const SET1 = 0x02;
+        const SET2 = 0x04;
+        const SET3 = 0x08;
+        const SET4 = 0x10;
+        const SET5 = 0x20;
+    }
+}
+
+bitflags! {
+    /// Flags of the `flags` field of `H264Sps`.
+    pub struct H264SpsFlags: u32 {
+        const SEPARATE_COLOUR_PLANE = 0x01;
+        const QPPRIME_Y_ZERO_TRANSFORM_BYPASS = 0x02;
+        const DELTA_PIC_ORDER_ALWAYS_ZERO = 0x04;
+        const GAPS_IN_FRAME_NUM_VALUE_ALLOWED = 0x08;
+        const FRAME_MBS_ONLY = 0x10;
+        const MB_ADAPTIVE_FRAME_FIELD = 0x20;
+        const DIRECT_8X8_INFERENCE = 0x40;
+    }
+}
+
+/// Sequence parameter set. Layout of `struct v4l2_ctrl_h264_sps`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct H264Sps {
+    pub profile_idc: u8,
+    /// Bits of `H264SpsConstraintSetFlags`.
+    pub constraint_set_flags: u8,
+    pub level_idc: u8,
+    pub seq_parameter_set_id: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub log2_max_frame_num_minus4: u8,
+    pub pic_order_cnt_type: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u8,
+    pub max_num_ref_frames: u8,
+    pub num_ref_frames_in_pic_order_cnt_cycle: u8,
+    pub offset_for_ref_frame: [i32; 255],
+    pub offset_for_non_ref_pic: i32,
+    pub offset_for_top_to_bottom_field: i32,
+    pub pic_width_in_mbs_minus1: u16,
+    pub pic_height_in_map_units_minus1: u16,
+    /// Bits of `H264SpsFlags`.
+    pub flags: u32,
+}
+
+impl Default for H264Sps {
+    fn default() -> Self {
+        // Safe because all members are integers.
+        unsafe { mem::zeroed() }
+    }
+}
+
+bitflags! {
+    /// Flags of the `flags` field of `H264Pps`.
+    pub struct H264PpsFlags: u16 {
+        const ENTROPY_CODING_MODE = 0x0001;
+        const BOTTOM_FIELD_PIC_ORDER_IN_FRAME_PRESENT = 0x0002;
+        const WEIGHTED_PRED = 0x0004;
+        const DEBLOCKING_FILTER_CONTROL_PRESENT = 0x0008;
+        const CONSTRAINED_INTRA_PRED = 0x0010;
+        const REDUNDANT_PIC_CNT_PRESENT = 0x0020;
+        const TRANSFORM_8X8_MODE = 0x0040;
+        /// Set if `V4L2_CID_STATELESS_H264_SCALING_MATRIX` is provided.
+        const SCALING_MATRIX_PRESENT = 0x0080;
+    }
+}
+
+/// Picture parameter set. Layout of `struct v4l2_ctrl_h264_pps`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct H264Pps {
+    pub pic_parameter_set_id: u8,
+    pub seq_parameter_set_id: u8,
+    pub num_slice_groups_minus1: u8,
+    pub num_ref_idx_l0_default_active_minus1: u8,
+    pub num_ref_idx_l1_default_active_minus1: u8,
+    pub weighted_bipred_idc: u8,
+    pub pic_init_qp_minus26: i8,
+    pub pic_init_qs_minus26: i8,
+    pub chroma_qp_index_offset: i8,
+    pub second_chroma_qp_index_offset: i8,
+    /// Bits of `H264PpsFlags`.
+    pub flags: u16,
+}
+
+/// Scaling matrix, in raster scan order. Layout of `struct
+/// v4l2_ctrl_h264_scaling_matrix`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct H264ScalingMatrix {
+    pub scaling_list_4x4: [[u8; 16]; 6],
+    pub scaling_list_8x8: [[u8; 64]; 6],
+}
+
+impl Default for H264ScalingMatrix {
+    /// Returns the flat scaling matrix, used when none is signaled.
+    fn default() -> Self {
+        H264ScalingMatrix {
+            scaling_list_4x4: [[16; 16]; 6],
+            scaling_list_8x8: [[16; 64]; 6],
+        }
+    }
+}
+
+/// Values of the `slice_type` field of `H264SliceParams`.
+pub const V4L2_H264_SLICE_TYPE_P: u8 = 0;
+pub const V4L2_H264_SLICE_TYPE_B: u8 = 1;
+pub const V4L2_H264_SLICE_TYPE_I: u8 = 2;
+pub const V4L2_H264_SLICE_TYPE_SP: u8 = 3;
+pub const V4L2_H264_SLICE_TYPE_SI: u8 = 4;
+
+/// Values of the `fields` field of `H264Reference` and `H264DpbEntry`.
+pub const V4L2_H264_TOP_FIELD_REF: u8 = 0x1;
+pub const V4L2_H264_BOTTOM_FIELD_REF: u8 = 0x2;
+pub const V4L2_H264_FRAME_REF: u8 = 0x3;
+
+bitflags! {
+    /// Flags of the `flags` field of `H264SliceParams`.
+    pub struct H264SliceFlags: u32 {
+        const DIRECT_SPATIAL_MV_PRED = 0x01;
+        const SP_FOR_SWITCH = 0x02;
+    }
+}
+
+/// Entry of a reference picture list. Layout of `struct
+/// v4l2_h264_reference`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct H264Reference {
+    /// Fields of the picture that are referenced, e.g.
+    /// `V4L2_H264_FRAME_REF`.
+    pub fields: u8,
+    /// Index of the picture in the `dpb` of `H264DecodeParams`.
+    pub index: u8,
+}
+
+/// Parameters of a slice. Layout of `struct v4l2_ctrl_h264_slice_params`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct H264SliceParams {
+    /// Size of the slice header, in bits.
+    pub header_bit_size: u32,
+    pub first_mb_in_slice: u32,
+    /// One of the `V4L2_H264_SLICE_TYPE_*` values.
+    pub slice_type: u8,
+    pub colour_plane_id: u8,
+    pub redundant_pic_cnt: u8,
+    pub cabac_init_idc: u8,
+    pub slice_qp_delta: i8,
+    pub slice_qs_delta: i8,
+    pub disable_deblocking_filter_idc: u8,
+    pub slice_alpha_c0_offset_div2: i8,
+    pub slice_beta_offset_div2: i8,
+    pub num_ref_idx_l0_active_minus1: u8,
+    pub num_ref_idx_l1_active_minus1: u8,
+    pub reserved: u8,
+    pub ref_pic_list0: [H264Reference; V4L2_H264_REF_LIST_LEN],
+    pub ref_pic_list1: [H264Reference; V4L2_H264_REF_LIST_LEN],
+    /// Bits of `H264SliceFlags`.
+    pub flags: u32,
+}
+
+bitflags! {
+    /// Flags of the `flags` field of `H264DpbEntry`.
+    pub struct H264DpbEntryFlags: u32 {
+        const VALID = 0x01;
+        const ACTIVE = 0x02;
+        const LONG_TERM = 0x04;
+        const FIELD = 0x08;
+    }
+}
+
+/// Entry of the decoded picture buffer. Layout of `struct
+/// v4l2_h264_dpb_entry`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct H264DpbEntry {
+    /// Timestamp of the CAPTURE buffer holding the reference picture, in
+    /// nanoseconds.
+    pub reference_ts: u64,
+    pub pic_num: u32,
+    pub frame_num: u16,
+    /// Fields of the picture that are referenced, e.g.
+    /// `V4L2_H264_FRAME_REF`.
+    pub fields: u8,
+    pub reserved: [u8; 5],
+    pub top_field_order_cnt: i32,
+    pub bottom_field_order_cnt: i32,
+    /// Bits of `H264DpbEntryFlags`.
+    pub flags: u32,
+}
+
+bitflags! {
+    /// Flags of the `flags` field of `H264DecodeParams`.
+    pub struct H264DecodeParamsFlags: u32 {
+        const IDR_PIC = 0x01;
+        const FIELD_PIC = 0x02;
+        const BOTTOM_FIELD = 0x04;
+        const PFRAME = 0x08;
+        const BFRAME = 0x10;
+    }
+}
+
+/// Parameters of the picture being decoded. Layout of `struct
+/// v4l2_ctrl_h264_decode_params`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct H264DecodeParams {
+    pub dpb: [H264DpbEntry; V4L2_H264_NUM_DPB_ENTRIES],
+    pub nal_ref_idc: u16,
+    pub frame_num: u16,
+    pub top_field_order_cnt: i32,
+    pub bottom_field_order_cnt: i32,
+    pub idr_pic_id: u16,
+    pub pic_order_cnt_lsb: u16,
+    pub delta_pic_order_cnt_bottom: i32,
+    pub delta_pic_order_cnt0: i32,
+    pub delta_pic_order_cnt1: i32,
+    /// Size of the `dec_ref_pic_marking()` syntax element, in bits.
+    pub dec_ref_pic_marking_bit_size: u32,
+    /// Size of the picture order count syntax elements, in bits.
+    pub pic_order_cnt_bit_size: u32,
+    pub slice_group_change_cycle: u32,
+    pub reserved: u32,
+    /// Bits of `H264DecodeParamsFlags`.
+    pub flags: u32,
+}
+
+unsafe impl CompoundControl for H264Sps {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_H264_SPS);
+    const TYPE: u32 = V4L2_CTRL_TYPE_H264_SPS;
+}
+
+unsafe impl CompoundControl for H264Pps {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_H264_PPS);
+    const TYPE: u32 = V4L2_CTRL_TYPE_H264_PPS;
+}
+
+unsafe impl CompoundControl for H264ScalingMatrix {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_H264_SCALING_MATRIX);
+    const TYPE: u32 = V4L2_CTRL_TYPE_H264_SCALING_MATRIX;
+}
+
+unsafe impl CompoundControl for H264SliceParams {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_H264_SLICE_PARAMS);
+    const TYPE: u32 = V4L2_CTRL_TYPE_H264_SLICE_PARAMS;
+}
+
+unsafe impl CompoundControl for H264DecodeParams {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_H264_DECODE_PARAMS);
+    const TYPE: u32 = V4L2_CTRL_TYPE_H264_DECODE_PARAMS;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ioctl::ExtControlValue;
+
+    #[test]
+    fn h264_layout() {
+        assert_eq!(mem::size_of::<H264Sps>(), 1048);
+        assert_eq!(mem::size_of::<H264Pps>(), 12);
+        assert_eq!(mem::size_of::<H264ScalingMatrix>(), 480);
+        assert_eq!(mem::size_of::<H264SliceParams>(), 152);
+        assert_eq!(mem::size_of::<H264DpbEntry>(), 32);
+        assert_eq!(mem::size_of::<H264DecodeParams>(), 560);
+        assert_eq!(V4L2_CID_STATELESS_H264_SPS, 0x00a4_0902);
+    }
+
+    #[test]
+    fn h264_payload() {
+        let pps = H264Pps {
+            pic_init_qp_minus26: -3,
+            flags: (H264PpsFlags::ENTROPY_CODING_MODE | H264PpsFlags::TRANSFORM_8X8_MODE).bits(),
+            ..Default::default()
+        };
+        let control = pps.to_ext_control();
+        assert_eq!(control.id, CtrlId(V4L2_CID_STATELESS_H264_PPS));
+        match control.value {
+            ExtControlValue::Payload(payload) => {
+                assert_eq!(payload[6], -3i8 as u8);
+                assert_eq!(H264Pps::from_payload(&payload), Some(pps));
+                assert_eq!(H264Pps::from_payload(&payload[1..]), None);
+            }
+            _ => panic!("Compound control without payload"),
+        }
+    }
+}
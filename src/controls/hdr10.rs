@@ -0,0 +1,131 @@
+//! HDR10 static metadata controls of the colorimetry class.
+//!
+//! These structures are not part of our bindings yet, so they are defined
+//! here following `include/uapi/linux/v4l2-controls.h`.
+use super::CompoundControl;
+use crate::{CtrlClass, CtrlId};
+
+/// Base of the colorimetry control IDs.
+const COLORIMETRY_CLASS_BASE: u32 = CtrlClass::Colorimetry as u32 | 0x900;
+
+/// ID of the HDR10 content light level information control.
+pub const V4L2_CID_COLORIMETRY_HDR10_CLL_INFO: u32 = COLORIMETRY_CLASS_BASE;
+/// ID of the HDR10 mastering display colour volume control.
+pub const V4L2_CID_COLORIMETRY_HDR10_MASTERING_DISPLAY: u32 = COLORIMETRY_CLASS_BASE + 1;
+
+/// Control type of `V4L2_CID_COLORIMETRY_HDR10_CLL_INFO`.
+pub const V4L2_CTRL_TYPE_HDR10_CLL_INFO: u32 = 0x0110;
+/// Control type of `V4L2_CID_COLORIMETRY_HDR10_MASTERING_DISPLAY`.
+pub const V4L2_CTRL_TYPE_HDR10_MASTERING_DISPLAY: u32 = 0x0111;
+
+/// Content light level information, as defined in CTA-861.3. Layout of
+/// `struct v4l2_ctrl_hdr10_cll_info`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hdr10CllInfo {
+    /// Maximum content light level, in cd/m².
+    pub max_content_light_level: u16,
+    /// Maximum picture-average light level, in cd/m².
+    pub max_pic_average_light_level: u16,
+}
+
+/// Mastering display colour volume, as defined in SMPTE ST 2086. Layout of
+/// `struct v4l2_ctrl_hdr10_mastering_display`.
+///
+/// Chromaticity coordinates are in units of 0.00002, and luminances in units
+/// of 0.0001 cd/m².
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hdr10MasteringDisplay {
+    /// X coordinates of the green, blue and red primaries, in that order.
+    pub display_primaries_x: [u16; 3],
+    /// Y coordinates of the green, blue and red primaries, in that order.
+    pub display_primaries_y: [u16; 3],
+    pub white_point_x: u16,
+    pub white_point_y: u16,
+    pub max_display_mastering_luminance: u32,
+    pub min_display_mastering_luminance: u32,
+}
+
+unsafe impl CompoundControl for Hdr10CllInfo {
+    const ID: CtrlId = CtrlId(V4L2_CID_COLORIMETRY_HDR10_CLL_INFO);
+    const TYPE: u32 = V4L2_CTRL_TYPE_HDR10_CLL_INFO;
+}
+
+unsafe impl CompoundControl for Hdr10MasteringDisplay {
+    const ID: CtrlId = CtrlId(V4L2_CID_COLORIMETRY_HDR10_MASTERING_DISPLAY);
+    const TYPE: u32 = V4L2_CTRL_TYPE_HDR10_MASTERING_DISPLAY;
+}
+
+impl Hdr10MasteringDisplay {
+    /// Valid range for the X coordinates of the primaries.
+    pub const PRIMARIES_X_RANGE: (u16, u16) = (5, 37000);
+    /// Valid range for the Y coordinates of the primaries.
+    pub const PRIMARIES_Y_RANGE: (u16, u16) = (5, 42000);
+    /// Valid range for `white_point_x`.
+    pub const WHITE_POINT_X_RANGE: (u16, u16) = (5, 37000);
+    /// Valid range for `white_point_y`.
+    pub const WHITE_POINT_Y_RANGE: (u16, u16) = (5, 42000);
+    /// Valid range for `max_display_mastering_luminance`.
+    pub const MAX_LUMA_RANGE: (u32, u32) = (50_000, 100_000_000);
+    /// Valid range for `min_display_mastering_luminance`.
+    pub const MIN_LUMA_RANGE: (u32, u32) = (1, 50_000);
+
+    /// Returns true if all the members are within the ranges accepted by the
+    /// kernel, which rejects the control otherwise.
+    pub fn is_valid(&self) -> bool {
+        let in_range = |(min, max): (u16, u16), c: &u16| (min..=max).contains(c);
+
+        self.display_primaries_x
+            .iter()
+            .all(|x| in_range(Self::PRIMARIES_X_RANGE, x))
+            && self
+                .display_primaries_y
+                .iter()
+                .all(|y| in_range(Self::PRIMARIES_Y_RANGE, y))
+            && in_range(Self::WHITE_POINT_X_RANGE, &self.white_point_x)
+            && in_range(Self::WHITE_POINT_Y_RANGE, &self.white_point_y)
+            && (Self::MAX_LUMA_RANGE.0..=Self::MAX_LUMA_RANGE.1)
+                .contains(&self.max_display_mastering_luminance)
+            && (Self::MIN_LUMA_RANGE.0..=Self::MIN_LUMA_RANGE.1)
+                .contains(&self.min_display_mastering_luminance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn hdr10_layout() {
+        assert_eq!(mem::size_of::<Hdr10CllInfo>(), 4);
+        assert_eq!(mem::size_of::<Hdr10MasteringDisplay>(), 24);
+        assert_eq!(V4L2_CID_COLORIMETRY_HDR10_CLL_INFO, 0x00a5_0900);
+    }
+
+    #[test]
+    fn hdr10_mastering_display_validity() {
+        // BT.2020 primaries and D65 white point on a 1000 nits display.
+        let mut display = Hdr10MasteringDisplay {
+            display_primaries_x: [8500, 6550, 35400],
+            display_primaries_y: [39850, 2300, 14600],
+            white_point_x: 15635,
+            white_point_y: 16450,
+            max_display_mastering_luminance: 10_000_000,
+            min_display_mastering_luminance: 50,
+        };
+        assert!(display.is_valid());
+
+        // Y coordinates go higher than X ones.
+        display.display_primaries_x[0] = 39850;
+        assert!(!display.is_valid());
+        display.display_primaries_x[0] = 8500;
+        display.white_point_y = 42001;
+        assert!(!display.is_valid());
+        display.white_point_y = 16450;
+
+        display.min_display_mastering_luminance = 0;
+        assert!(!display.is_valid());
+    }
+}
@@ -0,0 +1,358 @@
+//! HEVC stateless decoding controls.
+//!
+//! These structures are not part of our bindings yet, so they are defined
+//! here following `include/uapi/linux/v4l2-controls.h`. Fields are named
+//! after the syntax elements of the HEVC specification they carry.
+use super::{CompoundControl, CODEC_STATELESS_BASE};
+use crate::ioctl::{ExtControl, ExtControlValue};
+use crate::CtrlId;
+use bitflags::bitflags;
+
+/// ID of the HEVC sequence parameter set control.
+pub const V4L2_CID_STATELESS_HEVC_SPS: u32 = CODEC_STATELESS_BASE + 400;
+/// ID of the HEVC picture parameter set control.
+pub const V4L2_CID_STATELESS_HEVC_PPS: u32 = CODEC_STATELESS_BASE + 401;
+/// ID of the HEVC slice parameters control, a dynamic array with one element
+/// per slice in frame-based decoding mode.
+pub const V4L2_CID_STATELESS_HEVC_SLICE_PARAMS: u32 = CODEC_STATELESS_BASE + 402;
+/// ID of the HEVC decode parameters control.
+pub const V4L2_CID_STATELESS_HEVC_DECODE_PARAMS: u32 = CODEC_STATELESS_BASE + 404;
+/// ID of the menu control selecting whether the decoder is fed one slice or
+/// one frame per request.
+pub const V4L2_CID_STATELESS_HEVC_DECODE_MODE: u32 = CODEC_STATELESS_BASE + 405;
+/// ID of the menu control selecting whether slices are prefixed with Annex B
+/// start codes.
+pub const V4L2_CID_STATELESS_HEVC_START_CODE: u32 = CODEC_STATELESS_BASE + 406;
+/// ID of the dynamic array of `u32` entry point offsets of the slices.
+pub const V4L2_CID_STATELESS_HEVC_ENTRY_POINT_OFFSETS: u32 = CODEC_STATELESS_BASE + 407;
+
+/// Control type of `V4L2_CID_STATELESS_HEVC_SPS`.
+pub const V4L2_CTRL_TYPE_HEVC_SPS: u32 = 0x0270;
+/// Control type of `V4L2_CID_STATELESS_HEVC_PPS`.
+pub const V4L2_CTRL_TYPE_HEVC_PPS: u32 = 0x0271;
+/// Control type of `V4L2_CID_STATELESS_HEVC_SLICE_PARAMS`.
+pub const V4L2_CTRL_TYPE_HEVC_SLICE_PARAMS: u32 = 0x0272;
+/// Control type of `V4L2_CID_STATELESS_HEVC_DECODE_PARAMS`.
+pub const V4L2_CTRL_TYPE_HEVC_DECODE_PARAMS: u32 = 0x0274;
+
+/// Maximum number of entries of the decoded picture buffer.
+pub const V4L2_HEVC_DPB_ENTRIES_NUM_MAX: usize = 16;
+
+bitflags! {
+    /// Flags of the `flags` field of `HevcSps`.
+    pub struct HevcSpsFlags: u64 {
+        const SEPARATE_COLOUR_PLANE = 1 << 0;
+        const SCALING_LIST_ENABLED = 1 << 1;
+        const AMP_ENABLED = 1 << 2;
+        const SAMPLE_ADAPTIVE_OFFSET = 1 << 3;
+        const PCM_ENABLED = 1 << 4;
+        const PCM_LOOP_FILTER_DISABLED = 1 << 5;
+        const LONG_TERM_REF_PICS_PRESENT = 1 << 6;
+        const SPS_TEMPORAL_MVP_ENABLED = 1 << 7;
+        const STRONG_INTRA_SMOOTHING_ENABLED = 1 << 8;
+    }
+}
+
+/// Sequence parameter set. Layout of `struct v4l2_ctrl_hevc_sps`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HevcSps {
+    pub video_parameter_set_id: u8,
+    pub seq_parameter_set_id: u8,
+    pub pic_width_in_luma_samples: u16,
+    pub pic_height_in_luma_samples: u16,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u8,
+    pub sps_max_dec_pic_buffering_minus1: u8,
+    pub sps_max_num_reorder_pics: u8,
+    pub sps_max_latency_increase_plus1: u8,
+    pub log2_min_luma_coding_block_size_minus3: u8,
+    pub log2_diff_max_min_luma_coding_block_size: u8,
+    pub log2_min_luma_transform_block_size_minus2: u8,
+    pub log2_diff_max_min_luma_transform_block_size: u8,
+    pub max_transform_hierarchy_depth_inter: u8,
+    pub max_transform_hierarchy_depth_intra: u8,
+    pub pcm_sample_bit_depth_luma_minus1: u8,
+    pub pcm_sample_bit_depth_chroma_minus1: u8,
+    pub log2_min_pcm_luma_coding_block_size_minus3: u8,
+    pub log2_diff_max_min_pcm_luma_coding_block_size: u8,
+    pub num_short_term_ref_pic_sets: u8,
+    pub num_long_term_ref_pics_sps: u8,
+    pub chroma_format_idc: u8,
+    pub sps_max_sub_layers_minus1: u8,
+    pub reserved: [u8; 6],
+    /// Bits of `HevcSpsFlags`.
+    pub flags: u64,
+}
+
+bitflags! {
+    /// Flags of the `flags` field of `HevcPps`.
+    pub struct HevcPpsFlags: u64 {
+        const DEPENDENT_SLICE_SEGMENT_ENABLED = 1 << 0;
+        const OUTPUT_FLAG_PRESENT = 1 << 1;
+        const SIGN_DATA_HIDING_ENABLED = 1 << 2;
+        const CABAC_INIT_PRESENT = 1 << 3;
+        const CONSTRAINED_INTRA_PRED = 1 << 4;
+        const TRANSFORM_SKIP_ENABLED = 1 << 5;
+        const CU_QP_DELTA_ENABLED = 1 << 6;
+        const PPS_SLICE_CHROMA_QP_OFFSETS_PRESENT = 1 << 7;
+        const WEIGHTED_PRED = 1 << 8;
+        const WEIGHTED_BIPRED = 1 << 9;
+        const TRANSQUANT_BYPASS_ENABLED = 1 << 10;
+        const TILES_ENABLED = 1 << 11;
+        const ENTROPY_CODING_SYNC_ENABLED = 1 << 12;
+        const LOOP_FILTER_ACROSS_TILES_ENABLED = 1 << 13;
+        const PPS_LOOP_FILTER_ACROSS_SLICES_ENABLED = 1 << 14;
+        const DEBLOCKING_FILTER_OVERRIDE_ENABLED = 1 << 15;
+        const PPS_DISABLE_DEBLOCKING_FILTER = 1 << 16;
+        const LISTS_MODIFICATION_PRESENT = 1 << 17;
+        const SLICE_SEGMENT_HEADER_EXTENSION_PRESENT = 1 << 18;
+        const DEBLOCKING_FILTER_CONTROL_PRESENT = 1 << 19;
+        const UNIFORM_SPACING = 1 << 20;
+    }
+}
+
+/// Picture parameter set. Layout of `struct v4l2_ctrl_hevc_pps`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HevcPps {
+    pub pic_parameter_set_id: u8,
+    pub num_extra_slice_header_bits: u8,
+    pub num_ref_idx_l0_default_active_minus1: u8,
+    pub num_ref_idx_l1_default_active_minus1: u8,
+    pub init_qp_minus26: i8,
+    pub diff_cu_qp_delta_depth: u8,
+    pub pps_cb_qp_offset: i8,
+    pub pps_cr_qp_offset: i8,
+    pub num_tile_columns_minus1: u8,
+    pub num_tile_rows_minus1: u8,
+    pub column_width_minus1: [u8; 20],
+    pub row_height_minus1: [u8; 22],
+    pub pps_beta_offset_div2: i8,
+    pub pps_tc_offset_div2: i8,
+    pub log2_parallel_merge_level: u8,
+    pub reserved: u8,
+    /// Bits of `HevcPpsFlags`.
+    pub flags: u64,
+}
+
+/// Values of the `slice_type` field of `HevcSliceParams`.
+pub const V4L2_HEVC_SLICE_TYPE_B: u8 = 0;
+pub const V4L2_HEVC_SLICE_TYPE_P: u8 = 1;
+pub const V4L2_HEVC_SLICE_TYPE_I: u8 = 2;
+
+bitflags! {
+    /// Flags of the `flags` field of `HevcSliceParams`.
+    pub struct HevcSliceParamsFlags: u64 {
+        const SLICE_SAO_LUMA = 1 << 0;
+        const SLICE_SAO_CHROMA = 1 << 1;
+        const SLICE_TEMPORAL_MVP_ENABLED = 1 << 2;
+        const MVD_L1_ZERO = 1 << 3;
+        const CABAC_INIT = 1 << 4;
+        const COLLOCATED_FROM_L0 = 1 << 5;
+        const USE_INTEGER_MV = 1 << 6;
+        const SLICE_DEBLOCKING_FILTER_DISABLED = 1 << 7;
+        const SLICE_LOOP_FILTER_ACROSS_SLICES_ENABLED = 1 << 8;
+        const DEPENDENT_SLICE_SEGMENT = 1 << 9;
+    }
+}
+
+/// Weighted prediction parameters. Layout of `struct
+/// v4l2_hevc_pred_weight_table`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HevcPredWeightTable {
+    pub delta_luma_weight_l0: [i8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub luma_offset_l0: [i8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub delta_chroma_weight_l0: [[i8; 2]; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub chroma_offset_l0: [[i8; 2]; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub delta_luma_weight_l1: [i8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub luma_offset_l1: [i8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub delta_chroma_weight_l1: [[i8; 2]; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub chroma_offset_l1: [[i8; 2]; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub luma_log2_weight_denom: u8,
+    pub delta_chroma_log2_weight_denom: i8,
+}
+
+/// Parameters of a slice. Layout of `struct v4l2_ctrl_hevc_slice_params`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HevcSliceParams {
+    /// Size of the slice data, in bits.
+    pub bit_size: u32,
+    /// Offset of the slice data after the slice header, in bytes.
+    pub data_byte_offset: u32,
+    /// Number of entries of the slice in
+    /// `V4L2_CID_STATELESS_HEVC_ENTRY_POINT_OFFSETS`.
+    pub num_entry_point_offsets: u32,
+    pub nal_unit_type: u8,
+    pub nuh_temporal_id_plus1: u8,
+    /// One of the `V4L2_HEVC_SLICE_TYPE_*` values.
+    pub slice_type: u8,
+    pub colour_plane_id: u8,
+    pub slice_pic_order_cnt: i32,
+    pub num_ref_idx_l0_active_minus1: u8,
+    pub num_ref_idx_l1_active_minus1: u8,
+    pub collocated_ref_idx: u8,
+    pub five_minus_max_num_merge_cand: u8,
+    pub slice_qp_delta: i8,
+    pub slice_cb_qp_offset: i8,
+    pub slice_cr_qp_offset: i8,
+    pub slice_act_y_qp_offset: i8,
+    pub slice_act_cb_qp_offset: i8,
+    pub slice_act_cr_qp_offset: i8,
+    pub slice_beta_offset_div2: i8,
+    pub slice_tc_offset_div2: i8,
+    pub pic_struct: u8,
+    pub reserved0: [u8; 3],
+    pub slice_segment_addr: u32,
+    pub ref_idx_l0: [u8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub ref_idx_l1: [u8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub short_term_ref_pic_set_size: u16,
+    pub long_term_ref_pic_set_size: u16,
+    pub pred_weight_table: HevcPredWeightTable,
+    pub reserved1: [u8; 2],
+    /// Bits of `HevcSliceParamsFlags`.
+    pub flags: u64,
+}
+
+/// Returns a control setting `V4L2_CID_STATELESS_HEVC_ENTRY_POINT_OFFSETS` to
+/// `offsets`, the entry point offsets of all the slices of the request, in
+/// the order of the slices.
+///
+/// The `num_entry_point_offsets` member of each slice must tell how many of
+/// these offsets belong to it.
+pub fn hevc_entry_point_offsets(offsets: &[u32]) -> ExtControl {
+    let payload = offsets
+        .iter()
+        .flat_map(|offset| offset.to_ne_bytes().to_vec())
+        .collect();
+
+    ExtControl::new(
+        CtrlId(V4L2_CID_STATELESS_HEVC_ENTRY_POINT_OFFSETS),
+        ExtControlValue::Payload(payload),
+    )
+}
+
+/// Values of the `flags` field of `HevcDpbEntry`.
+pub const V4L2_HEVC_DPB_ENTRY_LONG_TERM_REFERENCE: u8 = 0x01;
+
+/// Entry of the decoded picture buffer. Layout of `struct
+/// v4l2_hevc_dpb_entry`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HevcDpbEntry {
+    /// Timestamp of the CAPTURE buffer holding the reference picture, in
+    /// nanoseconds.
+    pub timestamp: u64,
+    pub flags: u8,
+    pub field_pic: u8,
+    pub reserved: u16,
+    pub pic_order_cnt_val: i32,
+}
+
+bitflags! {
+    /// Flags of the `flags` field of `HevcDecodeParams`.
+    pub struct HevcDecodeParamsFlags: u64 {
+        const IRAP_PIC = 0x1;
+        const IDR_PIC = 0x2;
+        const NO_OUTPUT_OF_PRIOR = 0x4;
+    }
+}
+
+/// Parameters of the picture being decoded. Layout of `struct
+/// v4l2_ctrl_hevc_decode_params`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HevcDecodeParams {
+    pub pic_order_cnt_val: i32,
+    pub short_term_ref_pic_set_size: u16,
+    pub long_term_ref_pic_set_size: u16,
+    pub num_active_dpb_entries: u8,
+    pub num_poc_st_curr_before: u8,
+    pub num_poc_st_curr_after: u8,
+    pub num_poc_lt_curr: u8,
+    pub poc_st_curr_before: [u8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub poc_st_curr_after: [u8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub poc_lt_curr: [u8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub num_delta_pocs_of_ref_rps_idx: u8,
+    pub reserved: [u8; 3],
+    pub dpb: [HevcDpbEntry; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    /// Bits of `HevcDecodeParamsFlags`.
+    pub flags: u64,
+}
+
+unsafe impl CompoundControl for HevcSps {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_HEVC_SPS);
+    const TYPE: u32 = V4L2_CTRL_TYPE_HEVC_SPS;
+}
+
+unsafe impl CompoundControl for HevcPps {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_HEVC_PPS);
+    const TYPE: u32 = V4L2_CTRL_TYPE_HEVC_PPS;
+}
+
+unsafe impl CompoundControl for HevcSliceParams {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_HEVC_SLICE_PARAMS);
+    const TYPE: u32 = V4L2_CTRL_TYPE_HEVC_SLICE_PARAMS;
+}
+
+unsafe impl CompoundControl for HevcDecodeParams {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_HEVC_DECODE_PARAMS);
+    const TYPE: u32 = V4L2_CTRL_TYPE_HEVC_DECODE_PARAMS;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn hevc_layout() {
+        assert_eq!(mem::size_of::<HevcSps>(), 40);
+        assert_eq!(mem::size_of::<HevcPps>(), 64);
+        assert_eq!(mem::size_of::<HevcPredWeightTable>(), 194);
+        assert_eq!(mem::size_of::<HevcSliceParams>(), 280);
+        assert_eq!(mem::size_of::<HevcDpbEntry>(), 16);
+        assert_eq!(mem::size_of::<HevcDecodeParams>(), 328);
+        assert_eq!(V4L2_CID_STATELESS_HEVC_SPS, 0x00a4_0a90);
+    }
+
+    #[test]
+    fn hevc_slices() {
+        let slices = [
+            HevcSliceParams {
+                num_entry_point_offsets: 2,
+                ..Default::default()
+            },
+            HevcSliceParams {
+                num_entry_point_offsets: 1,
+                slice_type: V4L2_HEVC_SLICE_TYPE_I,
+                ..Default::default()
+            },
+        ];
+        let control = HevcSliceParams::array_to_ext_control(&slices);
+        match control.value {
+            ExtControlValue::Payload(payload) => {
+                assert_eq!(payload.len(), 2 * mem::size_of::<HevcSliceParams>());
+                let second = &payload[mem::size_of::<HevcSliceParams>()..];
+                assert_eq!(HevcSliceParams::from_payload(second), Some(slices[1]));
+            }
+            _ => panic!("Compound control without payload"),
+        }
+
+        let control = hevc_entry_point_offsets(&[16, 32, 48]);
+        assert_eq!(
+            control.id,
+            CtrlId(V4L2_CID_STATELESS_HEVC_ENTRY_POINT_OFFSETS)
+        );
+        match control.value {
+            ExtControlValue::Payload(payload) => {
+                assert_eq!(payload.len(), 12);
+                assert_eq!(&payload[4..8], &32u32.to_ne_bytes());
+            }
+            _ => panic!("Array control without payload"),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! MPEG-2 stateless decoding controls.
+//!
+//! These structures are not part of our bindings yet, so they are defined
+//! here following `include/uapi/linux/v4l2-controls.h`. Fields are named
+//! after the syntax elements of the MPEG-2 specification they carry.
+use super::{CompoundControl, CODEC_STATELESS_BASE};
+use crate::CtrlId;
+use bitflags::bitflags;
+use std::mem;
+
+/// ID of the MPEG-2 sequence header control.
+pub const V4L2_CID_STATELESS_MPEG2_SEQUENCE: u32 = CODEC_STATELESS_BASE + 220;
+/// ID of the MPEG-2 picture header control.
+pub const V4L2_CID_STATELESS_MPEG2_PICTURE: u32 = CODEC_STATELESS_BASE + 221;
+/// ID of the MPEG-2 quantisation matrices control.
+pub const V4L2_CID_STATELESS_MPEG2_QUANTISATION: u32 = CODEC_STATELESS_BASE + 222;
+
+/// Control type of `V4L2_CID_STATELESS_MPEG2_QUANTISATION`.
+pub const V4L2_CTRL_TYPE_MPEG2_QUANTISATION: u32 = 0x0250;
+/// Control type of `V4L2_CID_STATELESS_MPEG2_SEQUENCE`.
+pub const V4L2_CTRL_TYPE_MPEG2_SEQUENCE: u32 = 0x0251;
+/// Control type of `V4L2_CID_STATELESS_MPEG2_PICTURE`.
+pub const V4L2_CTRL_TYPE_MPEG2_PICTURE: u32 = 0x0252;
+
+bitflags! {
+    /// Flags of the `flags` field of `Mpeg2Sequence`.
+    pub struct Mpeg2SequenceFlags: u8 {
+        const PROGRESSIVE = 0x01;
+    }
+}
+
+/// Sequence header and sequence extension. Layout of `struct
+/// v4l2_ctrl_mpeg2_sequence`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mpeg2Sequence {
+    pub horizontal_size: u16,
+    pub vertical_size: u16,
+    /// Size of the video buffering verifier, in bytes.
+    pub vbv_buffer_size: u32,
+    pub profile_and_level_indication: u16,
+    pub chroma_format: u8,
+    /// Bits of `Mpeg2SequenceFlags`.
+    pub flags: u8,
+}
+
+/// Values of the `picture_coding_type` field of `Mpeg2Picture`.
+pub const V4L2_MPEG2_PIC_CODING_TYPE_I: u8 = 1;
+pub const V4L2_MPEG2_PIC_CODING_TYPE_P: u8 = 2;
+pub const V4L2_MPEG2_PIC_CODING_TYPE_B: u8 = 3;
+pub const V4L2_MPEG2_PIC_CODING_TYPE_D: u8 = 4;
+
+/// Values of the `picture_structure` field of `Mpeg2Picture`.
+pub const V4L2_MPEG2_PIC_TOP_FIELD: u8 = 0x1;
+pub const V4L2_MPEG2_PIC_BOTTOM_FIELD: u8 = 0x2;
+pub const V4L2_MPEG2_PIC_FRAME: u8 = 0x3;
+
+bitflags! {
+    /// Flags of the `flags` field of `Mpeg2Picture`.
+    pub struct Mpeg2PictureFlags: u32 {
+        const TOP_FIELD_FIRST = 0x0001;
+        const FRAME_PRED_DCT = 0x0002;
+        const CONCEALMENT_MV = 0x0004;
+        const INTRA_VLC = 0x0008;
+        const ALT_SCAN = 0x0010;
+        const REPEAT_FIRST = 0x0020;
+        const PROGRESSIVE = 0x0040;
+    }
+}
+
+/// Picture header and picture coding extension. Layout of `struct
+/// v4l2_ctrl_mpeg2_picture`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mpeg2Picture {
+    /// Timestamps of the CAPTURE buffers holding the reference pictures, in
+    /// nanoseconds.
+    pub backward_ref_ts: u64,
+    pub forward_ref_ts: u64,
+    /// Bits of `Mpeg2PictureFlags`.
+    pub flags: u32,
+    pub f_code: [[u8; 2]; 2],
+    /// One of the `V4L2_MPEG2_PIC_CODING_TYPE_*` values.
+    pub picture_coding_type: u8,
+    /// One of the `V4L2_MPEG2_PIC_*` structure values.
+    pub picture_structure: u8,
+    pub intra_dc_precision: u8,
+    pub reserved: [u8; 5],
+}
+
+/// Quantisation matrices, in zigzag scanning order. Layout of `struct
+/// v4l2_ctrl_mpeg2_quantisation`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mpeg2Quantisation {
+    pub intra_quantiser_matrix: [u8; 64],
+    pub non_intra_quantiser_matrix: [u8; 64],
+    pub chroma_intra_quantiser_matrix: [u8; 64],
+    pub chroma_non_intra_quantiser_matrix: [u8; 64],
+}
+
+impl Default for Mpeg2Quantisation {
+    fn default() -> Self {
+        // Safe because all members are integers.
+        unsafe { mem::zeroed() }
+    }
+}
+
+unsafe impl CompoundControl for Mpeg2Sequence {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_MPEG2_SEQUENCE);
+    const TYPE: u32 = V4L2_CTRL_TYPE_MPEG2_SEQUENCE;
+}
+
+unsafe impl CompoundControl for Mpeg2Picture {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_MPEG2_PICTURE);
+    const TYPE: u32 = V4L2_CTRL_TYPE_MPEG2_PICTURE;
+}
+
+unsafe impl CompoundControl for Mpeg2Quantisation {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_MPEG2_QUANTISATION);
+    const TYPE: u32 = V4L2_CTRL_TYPE_MPEG2_QUANTISATION;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mpeg2_layout() {
+        assert_eq!(mem::size_of::<Mpeg2Sequence>(), 12);
+        assert_eq!(mem::size_of::<Mpeg2Picture>(), 32);
+        assert_eq!(mem::size_of::<Mpeg2Quantisation>(), 256);
+        assert_eq!(V4L2_CID_STATELESS_MPEG2_SEQUENCE, 0x00a4_09dc);
+    }
+
+    #[test]
+    fn mpeg2_payload() {
+        let sequence = Mpeg2Sequence {
+            horizontal_size: 720,
+            vertical_size: 576,
+            flags: Mpeg2SequenceFlags::PROGRESSIVE.bits(),
+            ..Default::default()
+        };
+        let payload = sequence.as_payload();
+        assert_eq!(&payload[..2], &720u16.to_ne_bytes());
+        assert_eq!(payload[11], 0x01);
+        assert_eq!(Mpeg2Sequence::from_payload(payload), Some(sequence));
+    }
+}
@@ -0,0 +1,218 @@
+//! VP8 stateless decoding control.
+//!
+//! These structures are not part of our bindings yet, so they are defined
+//! here following `include/uapi/linux/v4l2-controls.h`. Fields are named
+//! after the syntax elements of the VP8 specification (RFC 6386) they carry.
+use super::{CompoundControl, CODEC_STATELESS_BASE};
+use crate::{CtrlId, Error, Result};
+use bitflags::bitflags;
+use std::mem;
+
+/// ID of the VP8 frame header control.
+pub const V4L2_CID_STATELESS_VP8_FRAME: u32 = CODEC_STATELESS_BASE + 200;
+
+/// Control type of `V4L2_CID_STATELESS_VP8_FRAME`.
+pub const V4L2_CTRL_TYPE_VP8_FRAME: u32 = 0x0240;
+
+/// Number of coefficient probabilities per context.
+pub const V4L2_VP8_COEFF_PROB_CNT: usize = 11;
+/// Number of motion vector probabilities per component.
+pub const V4L2_VP8_MV_PROB_CNT: usize = 19;
+/// Maximum number of DCT partitions of a frame.
+pub const V4L2_VP8_MAX_DCT_PARTS: usize = 8;
+
+bitflags! {
+    /// Flags of the `flags` field of `Vp8Segment`.
+    pub struct Vp8SegmentFlags: u32 {
+        const ENABLED = 0x01;
+        const UPDATE_MAP = 0x02;
+        const UPDATE_FEATURE_DATA = 0x04;
+        const DELTA_VALUE_MODE = 0x08;
+    }
+}
+
+/// Segmentation parameters. Layout of `struct v4l2_vp8_segment`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vp8Segment {
+    pub quant_update: [i8; 4],
+    pub lf_update: [i8; 4],
+    pub segment_probs: [u8; 3],
+    pub padding: u8,
+    /// Bits of `Vp8SegmentFlags`.
+    pub flags: u32,
+}
+
+bitflags! {
+    /// Flags of the `flags` field of `Vp8LoopFilter`.
+    pub struct Vp8LoopFilterFlags: u32 {
+        const ADJ_ENABLE = 0x01;
+        const DELTA_UPDATE = 0x02;
+        const FILTER_TYPE_SIMPLE = 0x04;
+    }
+}
+
+/// Loop filter parameters. Layout of `struct v4l2_vp8_loop_filter`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vp8LoopFilter {
+    pub ref_frm_delta: [i8; 4],
+    pub mb_mode_delta: [i8; 4],
+    pub sharpness_level: u8,
+    pub level: u8,
+    pub padding: u16,
+    /// Bits of `Vp8LoopFilterFlags`.
+    pub flags: u32,
+}
+
+/// Quantization indices. Layout of `struct v4l2_vp8_quantization`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vp8Quantization {
+    pub y_ac_qi: u8,
+    pub y_dc_delta: i8,
+    pub y2_dc_delta: i8,
+    pub y2_ac_delta: i8,
+    pub uv_dc_delta: i8,
+    pub uv_ac_delta: i8,
+    pub padding: u16,
+}
+
+/// Probabilities of the entropy decoder. Layout of `struct
+/// v4l2_vp8_entropy`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vp8Entropy {
+    pub coeff_probs: [[[[u8; V4L2_VP8_COEFF_PROB_CNT]; 3]; 8]; 4],
+    pub y_mode_probs: [u8; 4],
+    pub uv_mode_probs: [u8; 3],
+    pub mv_probs: [[u8; V4L2_VP8_MV_PROB_CNT]; 2],
+    pub padding: [u8; 3],
+}
+
+impl Default for Vp8Entropy {
+    fn default() -> Self {
+        // Safe because all members are integers.
+        unsafe { mem::zeroed() }
+    }
+}
+
+/// State of the boolean decoder after parsing the frame header. Layout of
+/// `struct v4l2_vp8_entropy_coder_state`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vp8EntropyCoderState {
+    pub range: u8,
+    pub value: u8,
+    pub bit_count: u8,
+    pub padding: u8,
+}
+
+bitflags! {
+    /// Flags of the `flags` field of `Vp8Frame`.
+    pub struct Vp8FrameFlags: u64 {
+        const KEY_FRAME = 0x01;
+        const EXPERIMENTAL = 0x02;
+        const SHOW_FRAME = 0x04;
+        const MB_NO_SKIP_COEFF = 0x08;
+        const SIGN_BIAS_GOLDEN = 0x10;
+        const SIGN_BIAS_ALT = 0x20;
+    }
+}
+
+/// Frame header. Layout of `struct v4l2_ctrl_vp8_frame`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vp8Frame {
+    pub segment: Vp8Segment,
+    pub lf: Vp8LoopFilter,
+    pub quant: Vp8Quantization,
+    pub entropy: Vp8Entropy,
+    pub coder_state: Vp8EntropyCoderState,
+
+    pub width: u16,
+    pub height: u16,
+
+    pub horizontal_scale: u8,
+    pub vertical_scale: u8,
+    pub version: u8,
+    pub prob_skip_false: u8,
+    pub prob_intra: u8,
+    pub prob_last: u8,
+    pub prob_gf: u8,
+    /// Number of valid entries of `dct_part_sizes`.
+    pub num_dct_parts: u8,
+
+    /// Size of the first partition, in bytes.
+    pub first_part_size: u32,
+    /// Size of the frame header within the first partition, in bits.
+    pub first_part_header_bits: u32,
+    /// Sizes of the DCT partitions, in bytes.
+    pub dct_part_sizes: [u32; V4L2_VP8_MAX_DCT_PARTS],
+
+    /// Timestamps of the CAPTURE buffers holding the reference frames, in
+    /// nanoseconds.
+    pub last_frame_ts: u64,
+    pub golden_frame_ts: u64,
+    pub alt_frame_ts: u64,
+
+    /// Bits of `Vp8FrameFlags`.
+    pub flags: u64,
+}
+
+impl Vp8Frame {
+    /// Set the sizes of the DCT partitions of the frame, and their number.
+    ///
+    /// Returns `Error::InvalidControlValue` if the number of partitions is
+    /// not one of 1, 2, 4 or 8.
+    pub fn set_dct_part_sizes(&mut self, sizes: &[u32]) -> Result<()> {
+        if !matches!(sizes.len(), 1 | 2 | 4 | 8) {
+            return Err(Error::InvalidControlValue);
+        }
+
+        self.dct_part_sizes = Default::default();
+        self.dct_part_sizes[..sizes.len()].copy_from_slice(sizes);
+        self.num_dct_parts = sizes.len() as u8;
+
+        Ok(())
+    }
+
+    /// Returns the sizes of the valid DCT partitions of the frame.
+    pub fn dct_part_sizes(&self) -> &[u32] {
+        let num_dct_parts = (self.num_dct_parts as usize).min(V4L2_VP8_MAX_DCT_PARTS);
+        &self.dct_part_sizes[..num_dct_parts]
+    }
+}
+
+unsafe impl CompoundControl for Vp8Frame {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_VP8_FRAME);
+    const TYPE: u32 = V4L2_CTRL_TYPE_VP8_FRAME;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vp8_layout() {
+        assert_eq!(mem::size_of::<Vp8Segment>(), 16);
+        assert_eq!(mem::size_of::<Vp8LoopFilter>(), 16);
+        assert_eq!(mem::size_of::<Vp8Quantization>(), 8);
+        assert_eq!(mem::size_of::<Vp8Entropy>(), 1104);
+        assert_eq!(mem::size_of::<Vp8Frame>(), 1232);
+        assert_eq!(V4L2_CID_STATELESS_VP8_FRAME, 0x00a4_09c8);
+    }
+
+    #[test]
+    fn vp8_dct_parts() {
+        let mut frame = Vp8Frame::default();
+        frame.set_dct_part_sizes(&[100, 200]).unwrap();
+        assert_eq!(frame.num_dct_parts, 2);
+        assert_eq!(frame.dct_part_sizes(), &[100, 200]);
+        assert_eq!(
+            frame.set_dct_part_sizes(&[100, 200, 300]),
+            Err(Error::InvalidControlValue)
+        );
+        assert_eq!(frame.dct_part_sizes(), &[100, 200]);
+    }
+}
@@ -0,0 +1,276 @@
+//! VP9 stateless decoding controls.
+//!
+//! These structures are not part of our bindings yet, so they are defined
+//! here following `include/uapi/linux/v4l2-controls.h`. Fields are named
+//! after the syntax elements of the VP9 specification they carry.
+use super::{CompoundControl, CODEC_STATELESS_BASE};
+use crate::{CtrlId, Error, Result};
+use bitflags::bitflags;
+use std::mem;
+
+/// ID of the VP9 frame header control.
+pub const V4L2_CID_STATELESS_VP9_FRAME: u32 = CODEC_STATELESS_BASE + 300;
+/// ID of the VP9 compressed header control.
+pub const V4L2_CID_STATELESS_VP9_COMPRESSED_HDR: u32 = CODEC_STATELESS_BASE + 301;
+
+/// Control type of `V4L2_CID_STATELESS_VP9_COMPRESSED_HDR`.
+pub const V4L2_CTRL_TYPE_VP9_COMPRESSED_HDR: u32 = 0x0260;
+/// Control type of `V4L2_CID_STATELESS_VP9_FRAME`.
+pub const V4L2_CTRL_TYPE_VP9_FRAME: u32 = 0x0261;
+
+bitflags! {
+    /// Flags of the `flags` field of `Vp9LoopFilter`.
+    pub struct Vp9LoopFilterFlags: u8 {
+        const DELTA_ENABLED = 0x1;
+        const DELTA_UPDATE = 0x2;
+    }
+}
+
+/// Loop filter parameters. Layout of `struct v4l2_vp9_loop_filter`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vp9LoopFilter {
+    pub ref_deltas: [i8; 4],
+    pub mode_deltas: [i8; 2],
+    pub level: u8,
+    pub sharpness: u8,
+    /// Bits of `Vp9LoopFilterFlags`.
+    pub flags: u8,
+    pub reserved: [u8; 7],
+}
+
+/// Quantization parameters. Layout of `struct v4l2_vp9_quantization`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vp9Quantization {
+    pub base_q_idx: u8,
+    pub delta_q_y_dc: i8,
+    pub delta_q_uv_dc: i8,
+    pub delta_q_uv_ac: i8,
+    pub reserved: [u8; 4],
+}
+
+bitflags! {
+    /// Flags of the `flags` field of `Vp9Segmentation`.
+    pub struct Vp9SegmentationFlags: u8 {
+        const ENABLED = 0x01;
+        const UPDATE_MAP = 0x02;
+        const TEMPORAL_UPDATE = 0x04;
+        const UPDATE_DATA = 0x08;
+        const ABS_OR_DELTA_UPDATE = 0x10;
+    }
+}
+
+/// Indices of the segment features in `Vp9Segmentation`.
+pub const V4L2_VP9_SEG_LVL_ALT_Q: usize = 0;
+pub const V4L2_VP9_SEG_LVL_ALT_L: usize = 1;
+pub const V4L2_VP9_SEG_LVL_REF_FRAME: usize = 2;
+pub const V4L2_VP9_SEG_LVL_SKIP: usize = 3;
+pub const V4L2_VP9_SEG_LVL_MAX: usize = 4;
+
+/// Segmentation parameters. Layout of `struct v4l2_vp9_segmentation`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vp9Segmentation {
+    pub feature_data: [[i16; V4L2_VP9_SEG_LVL_MAX]; 8],
+    /// Bitmask of the features enabled for each segment, indexed by the
+    /// `V4L2_VP9_SEG_LVL_*` values.
+    pub feature_enabled: [u8; 8],
+    pub tree_probs: [u8; 7],
+    pub pred_probs: [u8; 3],
+    /// Bits of `Vp9SegmentationFlags`.
+    pub flags: u8,
+    pub reserved: [u8; 5],
+}
+
+bitflags! {
+    /// Flags of the `flags` field of `Vp9Frame`.
+    pub struct Vp9FrameFlags: u32 {
+        const KEY_FRAME = 0x001;
+        const SHOW_FRAME = 0x002;
+        const ERROR_RESILIENT = 0x004;
+        const INTRA_ONLY = 0x008;
+        const ALLOW_HIGH_PREC_MV = 0x010;
+        const REFRESH_FRAME_CTX = 0x020;
+        const PARALLEL_DEC_MODE = 0x040;
+        const X_SUBSAMPLING = 0x080;
+        const Y_SUBSAMPLING = 0x100;
+        const COLOR_RANGE_FULL_SWING = 0x200;
+    }
+}
+
+bitflags! {
+    /// Flags of the `ref_frame_sign_bias` field of `Vp9Frame`.
+    pub struct Vp9SignBias: u8 {
+        const LAST = 0x1;
+        const GOLDEN = 0x2;
+        const ALT = 0x4;
+    }
+}
+
+/// Frame header. Layout of `struct v4l2_ctrl_vp9_frame`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vp9Frame {
+    pub lf: Vp9LoopFilter,
+    pub quant: Vp9Quantization,
+    pub seg: Vp9Segmentation,
+    /// Bits of `Vp9FrameFlags`.
+    pub flags: u32,
+    /// Size of the compressed header, in bytes.
+    pub compressed_header_size: u16,
+    /// Size of the uncompressed header, in bytes.
+    pub uncompressed_header_size: u16,
+    pub frame_width_minus_1: u16,
+    pub frame_height_minus_1: u16,
+    pub render_width_minus_1: u16,
+    pub render_height_minus_1: u16,
+    /// Timestamps of the CAPTURE buffers holding the reference frames, in
+    /// nanoseconds.
+    pub last_frame_ts: u64,
+    pub golden_frame_ts: u64,
+    pub alt_frame_ts: u64,
+    /// Bits of `Vp9SignBias`.
+    pub ref_frame_sign_bias: u8,
+    pub reset_frame_context: u8,
+    pub frame_context_idx: u8,
+    pub profile: u8,
+    pub bit_depth: u8,
+    pub interpolation_filter: u8,
+    pub tile_cols_log2: u8,
+    pub tile_rows_log2: u8,
+    pub reference_mode: u8,
+    pub reserved: [u8; 7],
+}
+
+impl Vp9Frame {
+    /// Set the frame and render sizes to `width`x`height`.
+    ///
+    /// Returns `Error::InvalidControlValue` if one of the dimensions is not
+    /// within the 1 to 65536 range supported by VP9.
+    pub fn set_frame_size(&mut self, width: u32, height: u32) -> Result<()> {
+        let minus_1 = |size: u32| match size {
+            1..=65536 => Ok((size - 1) as u16),
+            _ => Err(Error::InvalidControlValue),
+        };
+        self.frame_width_minus_1 = minus_1(width)?;
+        self.frame_height_minus_1 = minus_1(height)?;
+        self.render_width_minus_1 = self.frame_width_minus_1;
+        self.render_height_minus_1 = self.frame_height_minus_1;
+
+        Ok(())
+    }
+}
+
+/// Motion vector probabilities. Layout of `struct v4l2_vp9_mv_probs`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vp9MvProbs {
+    pub joint: [u8; 3],
+    pub sign: [u8; 2],
+    pub classes: [[u8; 10]; 2],
+    pub class0_bit: [u8; 2],
+    pub bits: [[u8; 10]; 2],
+    pub class0_fr: [[[u8; 3]; 2]; 2],
+    pub fr: [[u8; 3]; 2],
+    pub class0_hp: [u8; 2],
+    pub hp: [u8; 2],
+}
+
+/// Coefficient probabilities of one transform size, indexed by plane type,
+/// reference type, band and context.
+pub type Vp9CoefProbs = [[[[[u8; 3]; 6]; 6]; 2]; 2];
+
+/// Probability updates of the compressed header. Layout of `struct
+/// v4l2_ctrl_vp9_compressed_hdr`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vp9CompressedHdr {
+    pub tx_mode: u8,
+    pub tx8: [[u8; 1]; 2],
+    pub tx16: [[u8; 2]; 2],
+    pub tx32: [[u8; 3]; 2],
+    /// Coefficient probabilities of each transform size.
+    pub coef: [Vp9CoefProbs; 4],
+    pub skip: [u8; 3],
+    pub inter_mode: [[u8; 3]; 7],
+    pub interp_filter: [[u8; 2]; 4],
+    pub is_inter: [u8; 4],
+    pub comp_mode: [u8; 5],
+    pub single_ref: [[u8; 2]; 5],
+    pub comp_ref: [u8; 5],
+    pub y_mode: [[u8; 9]; 4],
+    pub uv_mode: [[u8; 9]; 10],
+    pub partition: [[u8; 3]; 16],
+    pub mv: Vp9MvProbs,
+}
+
+impl Default for Vp9CompressedHdr {
+    fn default() -> Self {
+        // Safe because all members are integers.
+        unsafe { mem::zeroed() }
+    }
+}
+
+unsafe impl CompoundControl for Vp9Frame {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_VP9_FRAME);
+    const TYPE: u32 = V4L2_CTRL_TYPE_VP9_FRAME;
+}
+
+unsafe impl CompoundControl for Vp9CompressedHdr {
+    const ID: CtrlId = CtrlId(V4L2_CID_STATELESS_VP9_COMPRESSED_HDR);
+    const TYPE: u32 = V4L2_CTRL_TYPE_VP9_COMPRESSED_HDR;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ioctl::{CtrlFlags, CtrlType, QueryExtCtrl};
+
+    #[test]
+    fn vp9_layout() {
+        assert_eq!(mem::size_of::<Vp9LoopFilter>(), 16);
+        assert_eq!(mem::size_of::<Vp9Quantization>(), 8);
+        assert_eq!(mem::size_of::<Vp9Segmentation>(), 88);
+        assert_eq!(mem::size_of::<Vp9Frame>(), 168);
+        assert_eq!(mem::size_of::<Vp9MvProbs>(), 69);
+        assert_eq!(mem::size_of::<Vp9CompressedHdr>(), 2040);
+        assert_eq!(V4L2_CID_STATELESS_VP9_FRAME, 0x00a4_0a2c);
+    }
+
+    #[test]
+    fn vp9_check_ctrl() {
+        let mut ctrl = QueryExtCtrl {
+            id: Vp9Frame::ID,
+            type_: CtrlType::Other(V4L2_CTRL_TYPE_VP9_FRAME),
+            name: "VP9 Frame Decode Parameters".into(),
+            minimum: 0,
+            maximum: 0,
+            step: 0,
+            default_value: 0,
+            flags: CtrlFlags::HAS_PAYLOAD,
+            elem_size: 168,
+            elems: 1,
+            dims: Vec::new(),
+        };
+        assert_eq!(Vp9Frame::check_ctrl(&ctrl), Ok(()));
+        assert_eq!(
+            Vp9CompressedHdr::check_ctrl(&ctrl),
+            Err(Error::InvalidControlValue)
+        );
+        ctrl.elem_size = 160;
+        assert_eq!(Vp9Frame::check_ctrl(&ctrl), Err(Error::InvalidControlValue));
+    }
+
+    #[test]
+    fn vp9_frame_size() {
+        let mut frame = Vp9Frame::default();
+        frame.set_frame_size(1920, 1080).unwrap();
+        assert_eq!(frame.frame_width_minus_1, 1919);
+        assert_eq!(frame.render_height_minus_1, 1079);
+        assert_eq!(
+            frame.set_frame_size(0, 1080),
+            Err(Error::InvalidControlValue)
+        );
+    }
+}
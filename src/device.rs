@@ -17,16 +17,52 @@
 //! Using this interface, the user does not have to worry about which fields of
 //! a V4L2 structure make sense - if it is relevant, then it will be visible,
 //! and if it is required, then the code won't compile unless it is provided.
+//!
+//! All the state shared between a `Queue` and its buffers is protected by
+//! mutexes, so queues, buffers being prepared for queueing (`QBuffer`) and
+//! dequeued buffers (`DQBuffer`) are all `Send`. A common pattern is to keep
+//! the queue on one thread and move dequeued buffers to worker threads for
+//! processing: dropping a `DQBuffer` from any thread makes it available for
+//! queueing again. Queues are also `Sync`, so they can be shared through an
+//! `Arc` for e.g. one thread to queue buffers while another one dequeues
+//! them.
+//!
+//! If a thread panics while updating the state of a queue, operations that
+//! depend on it fail with `Error::Poisoned` instead of panicking in turn. Such
+//! a queue must be dropped and obtained again from the `Device`.
 use super::ioctl;
-use super::ioctl::Capability;
-use super::QueueType;
+use super::ioctl::{
+    Capability, CtrlFlags, CtrlIterator, CtrlType, CtrlWhich, ExtControl, ExtControlValue,
+    QueryExtCtrl,
+};
 use super::Result;
+use super::{CtrlClass, CtrlId, QueueType};
 use std::collections::BTreeSet;
 use std::fs::File;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::path::Path;
 
+#[cfg(feature = "async")]
+mod async_io;
+pub mod capture_thread;
+pub mod decimator;
+pub mod decoder;
+pub mod deinterlacer;
+pub mod encoder_presets;
+#[cfg(feature = "mio")]
+mod event_source;
+#[cfg(feature = "async")]
+pub mod fd_waiter;
+pub mod frame_iter;
+#[cfg(feature = "async")]
+pub mod frame_stream;
+pub mod hotplug;
+pub mod poller;
 pub mod queue;
+pub mod recorder;
+pub mod reorder;
+pub mod stateless_decoder;
+pub mod sysfs;
 
 /// Options that can be specified when creating a `Device`.
 #[derive(Default)]
@@ -79,6 +115,98 @@ impl Device {
         // Safe because we are constructing a file from Fd we just opened.
         Device::new(unsafe { File::from_raw_fd(fd) })
     }
+
+    /// Perform the driver-private ioctl `I` on this device, for drivers that
+    /// extend V4L2 with their own commands.
+    pub fn custom_ioctl<I: ioctl::CustomIoctl>(&self, arg: &mut I::Arg) -> Result<i32> {
+        ioctl::custom_ioctl::<I, _>(self, arg)
+    }
+
+    /// Returns the controls of `class` supported by this device, e.g. to
+    /// present them in their own section of a user interface. The class
+    /// control describing the class itself is not included.
+    pub fn controls_in_class(&self, class: CtrlClass) -> Vec<QueryExtCtrl> {
+        CtrlIterator::new(self)
+            .in_class(class)
+            .filter(|ctrl| ctrl.type_ != CtrlType::CtrlClass)
+            .collect()
+    }
+
+    /// Set control `id` back to its default value. Returns false if the
+    /// control has no value that can be reset, i.e. if it is read-only,
+    /// volatile, write-only (e.g. buttons), disabled or grabbed.
+    pub fn reset_control(&mut self, id: CtrlId) -> Result<bool> {
+        let ctrl = ioctl::query_ext_ctrl(self, id)?;
+        if !is_resettable(&ctrl) {
+            return Ok(false);
+        }
+
+        let mut controls = [self.default_control(&ctrl)?];
+        ioctl::s_ext_ctrls(self, CtrlWhich::Current, &mut controls)?;
+
+        Ok(true)
+    }
+
+    /// Set all the controls of this device back to their default value, as
+    /// a single atomic operation: if one of them is rejected, none is reset.
+    /// Controls that cannot be reset are skipped, see `reset_control()`.
+    /// Returns the number of controls that have been reset.
+    pub fn reset_all_controls(&mut self) -> Result<usize> {
+        let ctrls: Vec<_> = CtrlIterator::new(self).filter(is_resettable).collect();
+        let mut controls = ctrls
+            .iter()
+            .map(|ctrl| self.default_control(ctrl))
+            .collect::<Result<Vec<_>>>()?;
+        if !controls.is_empty() {
+            ioctl::s_ext_ctrls(self, CtrlWhich::Current, &mut controls)?;
+        }
+
+        Ok(controls.len())
+    }
+
+    /// Returns a control setting `ctrl` to its default value.
+    fn default_control(&self, ctrl: &QueryExtCtrl) -> Result<ExtControl> {
+        let mut control = ExtControl::for_ctrl(ctrl);
+        match &mut control.value {
+            ExtControlValue::Value(value) => *value = ctrl.default_value as i32,
+            ExtControlValue::Value64(value) => *value = ctrl.default_value,
+            // The default of arrays and compound controls is not part of
+            // their description.
+            ExtControlValue::Payload(_) => {
+                ioctl::g_ext_ctrls(self, CtrlWhich::Default, std::slice::from_mut(&mut control))?
+            }
+        }
+
+        Ok(control)
+    }
+
+    /// Perform an arbitrary ioctl on this device. Prefer `custom_ioctl`, which
+    /// keeps the unsafety in the definition of the ioctl.
+    ///
+    /// # Safety
+    ///
+    /// `arg` must point to memory that is valid for the ioctl to read and
+    /// write according to `request`.
+    pub unsafe fn ioctl_raw<T>(
+        &self,
+        request: nix::sys::ioctl::ioctl_num_type,
+        arg: *mut T,
+    ) -> Result<i32> {
+        ioctl::ioctl_raw(self, request, arg)
+    }
+}
+
+/// Returns true if `ctrl` has a value that the user can set back to its
+/// default.
+fn is_resettable(ctrl: &QueryExtCtrl) -> bool {
+    let not_settable = CtrlFlags::DISABLED
+        | CtrlFlags::GRABBED
+        | CtrlFlags::READ_ONLY
+        | CtrlFlags::VOLATILE
+        | CtrlFlags::WRITE_ONLY;
+
+    !matches!(ctrl.type_, CtrlType::CtrlClass | CtrlType::Button)
+        && !ctrl.flags.intersects(not_settable)
 }
 
 impl AsRawFd for Device {
@@ -0,0 +1,110 @@
+//! Asynchronous dequeueing of buffers and events, and waiting for media
+//! requests to complete.
+//!
+//! While they wait, the futures returned by `Queue::dequeue_async`,
+//! `Device::next_event_async` and `Request::wait_async` register a duplicate
+//! of the device's (or request's) file descriptor with the reactor of the
+//! async runtime, through the `FdWaiter` given as type parameter. Using a
+//! duplicate lets several futures wait on the same device at once, e.g. one
+//! on each queue of a M2M device.
+use super::fd_waiter::FdWaiter;
+use super::poller::{poll_device, PollEvents};
+use super::queue::{direction::Direction, dqbuf::DQBuffer, states::BuffersAllocated, Queue};
+use super::Device;
+use crate::ioctl::{self, DQEvent};
+use crate::memory::Memory;
+use crate::request::Request;
+use crate::{Error, Result};
+use nix::fcntl::{fcntl, FcntlArg};
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::time::Duration;
+
+/// Condition to wait for between two attempts.
+enum Readiness {
+    Readable,
+    Writable,
+    Priority,
+}
+
+/// Call `attempt` until it returns something else than `Error::NotReady`,
+/// waiting for `readiness` on `fd` between calls.
+async fn retry_when_ready<W: FdWaiter, T>(
+    fd: RawFd,
+    readiness: Readiness,
+    mut attempt: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    // Only register with the reactor if we actually need to wait.
+    let mut waiter: Option<W> = None;
+    loop {
+        match attempt() {
+            Err(Error::NotReady) => (),
+            result => return result,
+        }
+
+        let waiter = match waiter {
+            Some(ref waiter) => waiter,
+            None => {
+                let dup = fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(0))?;
+                // Safe because we are constructing a file from the fd we just
+                // duplicated.
+                waiter.insert(W::new(unsafe { File::from_raw_fd(dup) })?)
+            }
+        };
+        match readiness {
+            Readiness::Readable => waiter.wait_readable().await?,
+            Readiness::Writable => waiter.wait_writable().await?,
+            Readiness::Priority => waiter.wait_priority().await?,
+        }
+    }
+}
+
+impl<D: Direction, M: Memory> Queue<D, BuffersAllocated<M>> {
+    /// Same as `dequeue()`, but returns a future that completes once a buffer
+    /// has been dequeued instead of blocking the calling thread. `W` is the
+    /// `FdWaiter` of the async runtime the future is polled from.
+    ///
+    /// The future only completes after the driver returns a buffer, so
+    /// another task must keep queueing buffers while it is pending.
+    pub async fn dequeue_async<W: FdWaiter>(&self) -> Result<DQBuffer<M>> {
+        let readiness = if self.get_type().is_output() {
+            Readiness::Writable
+        } else {
+            Readiness::Readable
+        };
+        retry_when_ready::<W, _>(self.device_fd(), readiness, || self.try_dequeue()).await
+    }
+}
+
+impl Device {
+    /// Returns a future that completes with the next pending event of the
+    /// device. Events must have been subscribed to with
+    /// `ioctl::subscribe_event` beforehand. `W` is the `FdWaiter` of the
+    /// async runtime the future is polled from.
+    pub async fn next_event_async<W: FdWaiter>(&self) -> Result<DQEvent> {
+        retry_when_ready::<W, _>(self.as_raw_fd(), Readiness::Priority, || {
+            let events = poll_device(self, PollEvents::EVENT, Some(Duration::from_secs(0)))?;
+            if !events.contains(PollEvents::EVENT) {
+                return Err(Error::NotReady);
+            }
+            ioctl::dqevent(self)
+        })
+        .await
+    }
+}
+
+impl Request {
+    /// Same as `wait(None)`, but returns a future that completes once the
+    /// request has completed instead of blocking the calling thread. `W` is
+    /// the `FdWaiter` of the async runtime the future is polled from.
+    pub async fn wait_async<W: FdWaiter>(&self) -> Result<()> {
+        retry_when_ready::<W, _>(self.as_raw_fd(), Readiness::Priority, || {
+            if self.wait(Some(Duration::from_secs(0)))? {
+                Ok(())
+            } else {
+                Err(Error::NotReady)
+            }
+        })
+        .await
+    }
+}
@@ -0,0 +1,270 @@
+//! Capture loop running on a dedicated thread.
+use super::poller::Waker;
+use super::queue::{direction::Capture, dqbuf::DQBuffer, states::BuffersAllocated, Queue};
+use crate::ioctl::BufferFlags;
+use crate::memory::MMAP;
+use crate::{Error, Result};
+use nix::errno::Errno;
+use nix::sched::{sched_setaffinity, CpuSet};
+use nix::unistd::Pid;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the capture thread checks whether buffers have been freed when
+/// the user holds all of them.
+const FREE_BUFFERS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+type CaptureQueue = Queue<Capture, BuffersAllocated<MMAP>>;
+
+/// Options that can be specified when starting a `CaptureThread`, for
+/// latency-sensitive applications.
+#[derive(Debug, Clone, Default)]
+pub struct CaptureThreadConfig {
+    cpus: Vec<usize>,
+    realtime_priority: Option<i32>,
+}
+
+impl CaptureThreadConfig {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only let the thread run on `cpus`, e.g. to keep it on a core isolated
+    /// from the rest of the system. The thread can run on any CPU if this is
+    /// not specified.
+    pub fn cpu_affinity(self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        CaptureThreadConfig {
+            cpus: cpus.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Run the thread with the `SCHED_FIFO` real-time policy at `priority`,
+    /// which goes from 1 (lowest) to 99 (highest) on Linux. This usually
+    /// requires the `CAP_SYS_NICE` capability, or a suitable `RLIMIT_RTPRIO`.
+    pub fn realtime_priority(self, priority: i32) -> Self {
+        CaptureThreadConfig {
+            realtime_priority: Some(priority),
+            ..self
+        }
+    }
+
+    /// Apply this configuration to the calling thread.
+    fn apply(&self) -> Result<()> {
+        if !self.cpus.is_empty() {
+            let mut cpuset = CpuSet::new();
+            for &cpu in &self.cpus {
+                cpuset.set(cpu)?;
+            }
+            // Pid 0 is the calling thread.
+            sched_setaffinity(Pid::from_raw(0), &cpuset)?;
+        }
+
+        if let Some(priority) = self.realtime_priority {
+            let param = nix::libc::sched_param {
+                sched_priority: priority,
+            };
+            // Safe because `param` is a valid structure that outlives the
+            // call, and pid 0 is the calling thread.
+            let res = unsafe { nix::libc::sched_setscheduler(0, nix::libc::SCHED_FIFO, &param) };
+            Errno::result(res)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the capture loop of a MMAP CAPTURE queue on a dedicated thread,
+/// passing every captured frame to a callback.
+///
+/// The thread queues all the free buffers, starts streaming, then dequeues
+/// frames until it is stopped, the buffer with the `LAST` flag is captured,
+/// or an error occurs. Frames are queued again once the callback (or whoever
+/// it hands them to) drops them.
+///
+/// Streaming is always stopped by the thread itself after its last dequeue,
+/// so the callback is never invoked once `stop()` returns. Waits are
+/// interrupted with a `Waker`, so stopping is immediate unless the callback
+/// is running.
+pub struct CaptureThread {
+    stop: Arc<AtomicBool>,
+    waker: Arc<Waker>,
+    thread: Option<JoinHandle<Result<CaptureQueue>>>,
+}
+
+impl CaptureThread {
+    /// Move `queue` to a new thread and start capturing, calling `on_frame`
+    /// for every captured frame.
+    pub fn start<F>(queue: CaptureQueue, on_frame: F) -> Result<Self>
+    where
+        F: FnMut(DQBuffer<MMAP>) + Send + 'static,
+    {
+        Self::start_with_config(queue, CaptureThreadConfig::new(), on_frame)
+    }
+
+    /// Same as `start()`, but the thread is set up according to `config`
+    /// before it starts capturing. If `config` cannot be applied, e.g.
+    /// because of insufficient privileges, the thread exits without
+    /// capturing and the error is returned.
+    pub fn start_with_config<F>(
+        queue: CaptureQueue,
+        config: CaptureThreadConfig,
+        on_frame: F,
+    ) -> Result<Self>
+    where
+        F: FnMut(DQBuffer<MMAP>) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let waker = Arc::new(Waker::new()?);
+        let thread_stop = Arc::clone(&stop);
+        let thread_waker = Arc::clone(&waker);
+        let (setup_sender, setup_receiver) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            if let Err(e) = config.apply() {
+                // The error is returned by `start_with_config()`, which does
+                // not look at the result of the thread in this case.
+                let _ = setup_sender.send(Err(e));
+                return Err(Error::Nix(nix::Error::Sys(Errno::ECANCELED)));
+            }
+            let _ = setup_sender.send(Ok(()));
+            run(queue, &thread_stop, &thread_waker, on_frame)
+        });
+
+        // The thread always sends its setup result before it can exit.
+        if let Ok(Err(e)) = setup_receiver.recv() {
+            let _ = thread.join();
+            return Err(e);
+        }
+
+        Ok(CaptureThread {
+            stop,
+            waker,
+            thread: Some(thread),
+        })
+    }
+
+    /// Ask the thread to stop and interrupt its current wait.
+    fn request_stop(&self) {
+        self.stop.store(true, Ordering::Release);
+        // Cannot fail with a valid eventfd. In any case the thread will
+        // notice the request after its next frame.
+        let _ = self.waker.wake();
+    }
+
+    /// Returns true if the thread has stopped capturing on its own, because
+    /// of an error or because the last frame has been captured. `stop()`
+    /// returns the reason.
+    pub fn is_finished(&self) -> bool {
+        self.thread
+            .as_ref()
+            .map_or(true, |thread| thread.is_finished())
+    }
+
+    /// Stop capturing and wait for the thread to finish. The queue is
+    /// returned, not streaming anymore, unless capture ended with an error.
+    ///
+    /// If `on_frame` panicked, the panic is propagated to the caller.
+    pub fn stop(mut self) -> Result<CaptureQueue> {
+        self.request_stop();
+        // The thread is only taken by `stop()` and `drop()`, which both
+        // consume the object.
+        let thread = self.thread.take().unwrap();
+        match thread.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl Drop for CaptureThread {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.request_stop();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn capture_loop<F>(
+    queue: &CaptureQueue,
+    stop: &AtomicBool,
+    waker: &Waker,
+    on_frame: &mut F,
+) -> Result<()>
+where
+    F: FnMut(DQBuffer<MMAP>),
+{
+    queue.queue_free_buffers()?;
+    if !queue.is_streaming() {
+        queue.streamon()?;
+    }
+
+    while !stop.load(Ordering::Acquire) {
+        queue.queue_free_buffers()?;
+        // All the buffers are held by the user, so there is nothing to wait
+        // for on the device.
+        if queue.num_queued_buffers() == 0 {
+            waker.wait_timeout(FREE_BUFFERS_POLL_INTERVAL)?;
+            continue;
+        }
+
+        match queue.dequeue_or_wake(waker) {
+            Ok(frame) => {
+                let is_last = frame.data.flags.contains(BufferFlags::LAST);
+                on_frame(frame);
+                if is_last {
+                    break;
+                }
+            }
+            Err(Error::Woken) | Err(Error::NotReady) => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+fn run<F>(
+    queue: CaptureQueue,
+    stop: &AtomicBool,
+    waker: &Waker,
+    mut on_frame: F,
+) -> Result<CaptureQueue>
+where
+    F: FnMut(DQBuffer<MMAP>),
+{
+    let result = capture_loop(&queue, stop, waker, &mut on_frame);
+    // Stop streaming even if capture failed, so the queued buffers are
+    // returned before the queue is handed back.
+    let streamoff = if queue.is_streaming() {
+        queue.streamoff().map(|_| ())
+    } else {
+        Ok(())
+    };
+
+    result.and(streamoff).map(|()| queue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sched::sched_getaffinity;
+
+    #[test]
+    fn cpu_affinity() {
+        let config = CaptureThreadConfig::new().cpu_affinity(vec![0]);
+        let cpuset = thread::spawn(move || {
+            config.apply().unwrap();
+            sched_getaffinity(Pid::from_raw(0)).unwrap()
+        })
+        .join()
+        .unwrap();
+        assert!(cpuset.is_set(0).unwrap());
+        assert!(!cpuset.is_set(1).unwrap());
+
+        let config = CaptureThreadConfig::new().cpu_affinity(vec![CpuSet::count()]);
+        assert!(config.apply().is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Crate-side frame skipping, for when the driver cannot lower its frame rate
+//! itself.
+use std::time::Duration;
+
+/// Decides which frames of a stream to keep in order to bring its frame rate
+/// down to a target value.
+///
+/// Frames are submitted with their timestamp, which can be a buffer timestamp
+/// or the time at which it has been dequeued, and are kept if enough time has
+/// passed since the last kept frame. Dropped frames should be requeued right
+/// away so the driver never runs out of buffers.
+///
+/// Timing is computed against a schedule of ideal instants rather than
+/// against the last kept frame, so the average output rate does not drift.
+/// A frame arriving up to a quarter of the target interval early is still
+/// kept, to absorb jitter in the source timestamps.
+pub struct Decimator {
+    interval: Duration,
+    next_frame: Option<Duration>,
+}
+
+impl Decimator {
+    /// Create a decimator letting at most `target_fps` frames per second
+    /// through. A `target_fps` of 0 is treated as 1.
+    pub fn new(target_fps: u32) -> Self {
+        Self::with_interval(Duration::from_secs(1) / target_fps.max(1))
+    }
+
+    /// Create a decimator keeping at most one frame every `interval`.
+    pub fn with_interval(interval: Duration) -> Self {
+        Decimator {
+            interval,
+            next_frame: None,
+        }
+    }
+
+    /// Returns true if the frame with `timestamp` should be kept, false if it
+    /// should be dropped.
+    pub fn keep(&mut self, timestamp: Duration) -> bool {
+        let next_frame = match self.next_frame {
+            // First frame, always keep it.
+            None => {
+                self.next_frame = Some(timestamp + self.interval);
+                return true;
+            }
+            Some(next_frame) => next_frame,
+        };
+
+        if timestamp + self.interval / 4 < next_frame {
+            return false;
+        }
+
+        // If we are late by more than one interval (e.g. after the source
+        // stalled), restart the schedule from this frame instead of letting
+        // several frames through in a row to catch up.
+        let next_frame = next_frame + self.interval;
+        self.next_frame = Some(if next_frame <= timestamp {
+            timestamp + self.interval
+        } else {
+            next_frame
+        });
+
+        true
+    }
+
+    /// Restart the schedule, e.g. after the stream has been restarted. The
+    /// next submitted frame will be kept.
+    pub fn reset(&mut self) {
+        self.next_frame = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decimator;
+    use std::time::Duration;
+
+    /// Returns how many frames out of `num_frames` at `source_fps` are kept
+    /// when decimating to `target_fps`.
+    fn kept_frames(source_fps: u32, target_fps: u32, num_frames: u32) -> usize {
+        let mut decimator = Decimator::new(target_fps);
+        (0..num_frames)
+            .map(|i| Duration::from_secs(1) * i / source_fps)
+            .filter(|t| decimator.keep(*t))
+            .count()
+    }
+
+    #[test]
+    fn decimate() {
+        assert_eq!(kept_frames(30, 15, 300), 150);
+        assert_eq!(kept_frames(30, 10, 300), 100);
+        assert_eq!(kept_frames(60, 25, 600), 250);
+        // Target frame rate higher than the source's: everything is kept.
+        assert_eq!(kept_frames(30, 60, 300), 300);
+    }
+
+    #[test]
+    fn decimate_after_stall() {
+        let mut decimator = Decimator::new(10);
+        assert!(decimator.keep(Duration::from_millis(0)));
+        // Source stalled for one second, then resumes at 30 fps. We should not
+        // keep several frames in a row to catch up.
+        assert!(decimator.keep(Duration::from_millis(1000)));
+        assert!(!decimator.keep(Duration::from_millis(1033)));
+        assert!(!decimator.keep(Duration::from_millis(1066)));
+        assert!(decimator.keep(Duration::from_millis(1100)));
+    }
+}
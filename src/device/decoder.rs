@@ -0,0 +1,357 @@
+//! High-level interface to stateful decoders, i.e. decoders which parse the
+//! bitstream themselves and only need to be given its data.
+//!
+//! The `Decoder` implements the sequence described in the "Memory-to-Memory
+//! Stateful Video Decoder Interface" section of the V4L2 documentation. The
+//! format of the decoded frames is only known once the decoder has parsed
+//! the beginning of the stream, and can change at any point during
+//! decoding. In both cases the decoder signals a `SOURCE_CHANGE` event, upon
+//! which the CAPTURE queue is (re)configured and the new format reported
+//! through a callback. Decoded frames are likewise delivered through a
+//! callback as soon as they are dequeued.
+use super::queue::direction::{Capture, Output};
+use super::queue::dqbuf::DQBuffer;
+use super::queue::qbuf::Plane;
+use super::queue::states::{BuffersAllocated, QueueInit};
+use super::queue::{Queue, SourceChangeError};
+use super::Device;
+use crate::ioctl::{
+    self, BufferFlags, DecoderCommand, Event, EventType, SrcChanges, SubscribeEventFlags,
+};
+use crate::memory::{UserPtr, MMAP};
+use crate::{Error, Format, PixelFormat, Result};
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Number of CAPTURE buffers allocated on top of the minimum required by the
+/// driver, so decoding can go on while the client holds a few frames.
+const EXTRA_CAPTURE_BUFFERS: u32 = 2;
+
+/// Callback invoked with the new format of the decoded frames, every time
+/// the CAPTURE queue has been reconfigured.
+pub type FormatCallback = Box<dyn FnMut(&Format) + Send>;
+
+/// Callback invoked with every decoded frame, along with the CAPTURE queue
+/// so its buffers can be mapped. The buffer is reused for decoding once the
+/// `DQBuffer` is dropped.
+pub type FrameCallback =
+    Box<dyn FnMut(DQBuffer<MMAP>, &Queue<Capture, BuffersAllocated<MMAP>>) + Send>;
+
+enum CaptureQueue {
+    /// The format of the stream is not known yet.
+    Probing(Queue<Capture, QueueInit>),
+    Decoding(Queue<Capture, BuffersAllocated<MMAP>>),
+    /// Only left if the reconfiguration of the queue panicked, after which
+    /// the decoder cannot be used anymore.
+    Broken,
+}
+
+/// A stateful decoder, using USERPTR buffers for the encoded data and MMAP
+/// buffers for the decoded frames.
+///
+/// The decoder does not need to be polled: `decode()` and `drain()` process
+/// the events and buffers reported by the driver, and only block when the
+/// decoder cannot accept more data, or while draining.
+pub struct Decoder {
+    device: Arc<Mutex<Device>>,
+    /// Fd of the device, for polling. Remains valid as long as `device` is.
+    fd: RawFd,
+    output_queue: Queue<Output, BuffersAllocated<UserPtr<Vec<u8>>>>,
+    capture_queue: CaptureQueue,
+    /// Memory of the OUTPUT buffers that have been dequeued, for reuse.
+    output_backings: Vec<Vec<u8>>,
+    on_format_change: FormatCallback,
+    on_frame: FrameCallback,
+    /// The resolution of the stream has changed, and the CAPTURE queue must
+    /// be reconfigured once its last buffer is dequeued. Remains set if the
+    /// reconfiguration fails, so it is attempted again.
+    format_change_pending: bool,
+    draining: bool,
+}
+
+impl Decoder {
+    /// Create a decoder for `coded_format` (e.g. `H264` or `FWHT`) streams
+    /// using the video device `device`, with `num_output_buffers` buffers to
+    /// hold the encoded data.
+    ///
+    /// The OUTPUT queue is streamed on right away. The CAPTURE queue is
+    /// configured once the decoder has found the format of the stream, at
+    /// which point `on_format_change` is called. `on_frame` is then called
+    /// for every decoded frame.
+    pub fn new<C, F>(
+        device: Arc<Mutex<Device>>,
+        coded_format: impl Into<PixelFormat>,
+        num_output_buffers: u32,
+        on_format_change: C,
+        on_frame: F,
+    ) -> Result<Self>
+    where
+        C: FnMut(&Format) + Send + 'static,
+        F: FnMut(DQBuffer<MMAP>, &Queue<Capture, BuffersAllocated<MMAP>>) + Send + 'static,
+    {
+        let coded_format = coded_format.into();
+        let fd = {
+            let device = lock(&device)?;
+            ioctl::subscribe_event(
+                &*device,
+                EventType::SourceChange,
+                0,
+                SubscribeEventFlags::empty(),
+            )?;
+            ioctl::subscribe_event(&*device, EventType::Eos, 0, SubscribeEventFlags::empty())?;
+            device.as_raw_fd()
+        };
+
+        let (mut output_queue, capture_queue) = match Queue::get_output_queue(Arc::clone(&device)) {
+            Ok(output_queue) => (output_queue, Queue::get_capture_queue(Arc::clone(&device))?),
+            Err(_) => (
+                Queue::get_output_mplane_queue(Arc::clone(&device))?,
+                Queue::get_capture_mplane_queue(Arc::clone(&device))?,
+            ),
+        };
+
+        let output_format = output_queue
+            .change_format()?
+            .set_pixelformat(coded_format)
+            .apply()?;
+        if output_format.pixelformat != coded_format {
+            return Err(Error::InvalidFormat);
+        }
+        let output_queue = output_queue.request_buffers(num_output_buffers)?;
+        output_queue.streamon()?;
+
+        Ok(Decoder {
+            device,
+            fd,
+            output_queue,
+            capture_queue: CaptureQueue::Probing(capture_queue),
+            output_backings: Vec::new(),
+            on_format_change: Box::new(on_format_change),
+            on_frame: Box::new(on_frame),
+            format_change_pending: false,
+            draining: false,
+        })
+    }
+
+    /// Queue `bitstream` for decoding. It does not need to be aligned on
+    /// frame boundaries.
+    ///
+    /// Frames decoded in the meantime are delivered to the frame callback
+    /// before this method returns. If all the OUTPUT buffers are in use,
+    /// this blocks until one of them is processed by the driver.
+    pub fn decode(&mut self, bitstream: &[u8]) -> Result<()> {
+        self.check_broken()?;
+        while self.output_queue.num_queued_buffers() >= self.output_queue.num_buffers() {
+            self.process(-1)?;
+        }
+
+        // Buffers must be at least as large as the plane size of the format.
+        let plane_size = self.output_queue.plane_sizes().first().copied();
+        let mut backing = self.output_backings.pop().unwrap_or_default();
+        backing.clear();
+        backing.extend_from_slice(bitstream);
+        backing.resize(plane_size.unwrap_or(0).max(bitstream.len()), 0);
+
+        let queued = self
+            .output_queue
+            .get_free_buffer()?
+            .add_plane(Plane::out(backing, bitstream.len()))
+            .queue();
+        if let Err(e) = queued {
+            self.output_backings.extend(e.plane_handles);
+            return Err(e.error);
+        }
+
+        while self.process(0)? {}
+
+        Ok(())
+    }
+
+    /// Decode all the data queued so far, and wait until the last frame has
+    /// been delivered to the frame callback. Decoding can resume afterwards.
+    pub fn drain(&mut self) -> Result<()> {
+        self.check_broken()?;
+        // No frame can be produced before the format of the stream is known.
+        if let CaptureQueue::Probing(_) = self.capture_queue {
+            return Ok(());
+        }
+
+        let stop = DecoderCommand::Stop {
+            to_black: false,
+            immediately: false,
+        };
+        ioctl::decoder_cmd(&*self.device()?, stop)?;
+        self.draining = true;
+        while self.draining {
+            self.process(-1)?;
+        }
+
+        Ok(())
+    }
+
+    fn device(&self) -> Result<MutexGuard<'_, Device>> {
+        lock(&self.device)
+    }
+
+    fn check_broken(&self) -> Result<()> {
+        match self.capture_queue {
+            CaptureQueue::Broken => Err(Error::Poisoned),
+            _ => Ok(()),
+        }
+    }
+
+    /// Wait for at most `timeout` milliseconds (or forever if negative) for
+    /// the driver to report events or buffers, and process them. Returns
+    /// false if there was nothing to process.
+    fn process(&mut self, timeout: i32) -> Result<bool> {
+        // Retry a reconfiguration that failed, e.g. because the client still
+        // held frames of the previous format. Nothing can be decoded until
+        // then.
+        let capture_streaming = match &self.capture_queue {
+            CaptureQueue::Decoding(queue) => queue.is_streaming(),
+            _ => false,
+        };
+        if self.format_change_pending && !capture_streaming {
+            self.reconfigure_capture()?;
+        }
+
+        let mut fds = [PollFd::new(
+            self.fd,
+            PollFlags::POLLIN | PollFlags::POLLOUT | PollFlags::POLLPRI,
+        )];
+        if poll(&mut fds, timeout)? == 0 {
+            return Ok(false);
+        }
+        let revents = fds[0].revents().unwrap_or_else(PollFlags::empty);
+        // The driver reports an error if it has no buffers to work with,
+        // which means that waiting any longer would never end.
+        if revents == PollFlags::POLLERR {
+            return Err(Error::Nix(nix::Error::Sys(Errno::EIO)));
+        }
+
+        let mut progress = false;
+        if revents.contains(PollFlags::POLLPRI) {
+            self.process_event()?;
+            progress = true;
+        }
+        if revents.contains(PollFlags::POLLOUT) {
+            let mut buffer = self.output_queue.dequeue()?;
+            self.output_backings.append(&mut buffer.plane_handles);
+            progress = true;
+        }
+        if revents.contains(PollFlags::POLLIN) {
+            progress |= self.process_capture_buffer()?;
+        }
+
+        if let CaptureQueue::Decoding(queue) = &self.capture_queue {
+            queue.queue_free_buffers()?;
+        }
+
+        Ok(progress)
+    }
+
+    fn process_event(&mut self) -> Result<()> {
+        let event = ioctl::dqevent(&*self.device()?)?.event;
+        match event {
+            Event::SourceChange(changes) if changes.contains(SrcChanges::RESOLUTION) => {
+                self.format_change_pending = true;
+                // Otherwise, frames of the previous resolution are still to
+                // be dequeued, the last one being marked as such.
+                if let CaptureQueue::Probing(_) = self.capture_queue {
+                    self.reconfigure_capture()?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Dequeue a CAPTURE buffer and deliver its frame. Returns false if
+    /// there was no buffer to dequeue.
+    fn process_capture_buffer(&mut self) -> Result<bool> {
+        let queue = match &self.capture_queue {
+            CaptureQueue::Decoding(queue) => queue,
+            _ => return Ok(false),
+        };
+        let buffer = match queue.dequeue() {
+            Ok(buffer) => buffer,
+            // The last buffer has already been dequeued.
+            Err(Error::Nix(nix::Error::Sys(Errno::EPIPE))) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let is_last = buffer.data.flags.contains(BufferFlags::LAST);
+        let has_data = buffer
+            .data
+            .planes
+            .iter()
+            .any(|plane| plane.bytesused > plane.data_offset);
+        if has_data {
+            (self.on_frame)(buffer, queue);
+        } else {
+            drop(buffer);
+        }
+
+        if !is_last {
+            return Ok(true);
+        }
+        if self.format_change_pending {
+            self.reconfigure_capture()?;
+        } else {
+            // End of a drain, or of the stream. Let the decoder resume.
+            self.draining = false;
+            let start = DecoderCommand::Start { mute_audio: false };
+            ioctl::decoder_cmd(&*self.device()?, start)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Configure the CAPTURE queue for the current format of the stream,
+    /// and start streaming it. The current buffers are kept if they are
+    /// large enough for the new format.
+    ///
+    /// This fails if new buffers are needed but the client still holds
+    /// mapped buffers of the previous format, in which case the
+    /// reconfiguration is attempted again the next time the decoder is used.
+    fn reconfigure_capture(&mut self) -> Result<()> {
+        let result = match mem::replace(&mut self.capture_queue, CaptureQueue::Broken) {
+            CaptureQueue::Probing(queue) => queue.handle_source_change(EXTRA_CAPTURE_BUFFERS),
+            CaptureQueue::Decoding(queue) => queue.handle_source_change(EXTRA_CAPTURE_BUFFERS),
+            CaptureQueue::Broken => return Err(Error::Poisoned),
+        };
+        // Keep the queue in whatever state the failure left it in, so the
+        // reconfiguration can be attempted again.
+        let (queue, format) = match result {
+            Ok(result) => result,
+            Err(SourceChangeError::Allocated { error, queue }) => {
+                self.capture_queue = CaptureQueue::Decoding(queue);
+                return Err(error);
+            }
+            Err(SourceChangeError::Init { error, queue }) => {
+                self.capture_queue = CaptureQueue::Probing(queue);
+                return Err(error);
+            }
+        };
+        self.format_change_pending = false;
+
+        (self.on_format_change)(&format);
+        // Keep the queue before queueing its buffers, so a failure does not
+        // leave the decoder broken. The buffers are queued again by the next
+        // call to `process()`.
+        self.capture_queue = CaptureQueue::Decoding(queue);
+        if let CaptureQueue::Decoding(queue) = &self.capture_queue {
+            queue.queue_free_buffers()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn lock(device: &Mutex<Device>) -> Result<MutexGuard<'_, Device>> {
+    device.lock().map_err(|_| Error::Poisoned)
+}
@@ -0,0 +1,304 @@
+//! High-level interface to memory-to-memory deinterlacers, i.e. devices which
+//! turn interlaced frames into progressive ones.
+//!
+//! The field order of the interlaced frames is set on the OUTPUT queue, and
+//! the CAPTURE queue is set to produce progressive (`Field::None`) frames of
+//! the same size. With the `Alternate` field order, every field is submitted
+//! in its own OUTPUT buffer marked as either `Top` or `Bottom`, and the
+//! driver needs both fields of a frame to produce it:
+//! `Deinterlacer::queue_field_pair()` submits them together, in their
+//! temporal order. The other interlaced orders hold both fields in a single
+//! buffer, which is submitted with `Deinterlacer::queue_frame()`.
+//!
+//! Depending on the driver and its algorithm, one progressive frame is
+//! produced for every field or for every pair of fields.
+use super::queue::direction::{Capture, Output};
+use super::queue::dqbuf::DQBuffer;
+use super::queue::qbuf::Plane;
+use super::queue::states::BuffersAllocated;
+use super::queue::Queue;
+use super::Device;
+use crate::ioctl::Field;
+use crate::memory::{UserPtr, MMAP};
+use crate::{Error, Format, PixelFormat, Result};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Options that can be specified when creating a `Deinterlacer`.
+pub struct DeinterlacerConfig {
+    pixelformat: PixelFormat,
+    width: usize,
+    height: usize,
+    field: Field,
+    bottom_field_first: bool,
+    num_output_buffers: u32,
+    num_capture_buffers: u32,
+}
+
+impl DeinterlacerConfig {
+    /// Create the configuration for deinterlacing `width`x`height` frames of
+    /// `pixelformat`, whose fields are laid out according to `field`, e.g.
+    /// `Field::InterlacedTB` or `Field::Alternate`. `height` is the height of
+    /// whole frames, even when the fields are submitted separately.
+    pub fn new(
+        pixelformat: impl Into<PixelFormat>,
+        width: usize,
+        height: usize,
+        field: Field,
+    ) -> Self {
+        DeinterlacerConfig {
+            pixelformat: pixelformat.into(),
+            width,
+            height,
+            field,
+            bottom_field_first: false,
+            num_output_buffers: 4,
+            num_capture_buffers: 4,
+        }
+    }
+
+    /// With the `Alternate` field order, submit the bottom field of every
+    /// frame before its top field, as e.g. for NTSC content. The other field
+    /// orders tell which field comes first by themselves.
+    pub fn bottom_field_first(self) -> Self {
+        DeinterlacerConfig {
+            bottom_field_first: true,
+            ..self
+        }
+    }
+
+    /// Number of OUTPUT buffers to allocate. At least two are allocated with
+    /// the `Alternate` field order, so both fields of a frame can be queued.
+    pub fn num_output_buffers(self, num_output_buffers: u32) -> Self {
+        DeinterlacerConfig {
+            num_output_buffers,
+            ..self
+        }
+    }
+
+    /// Number of CAPTURE buffers to allocate.
+    pub fn num_capture_buffers(self, num_capture_buffers: u32) -> Self {
+        DeinterlacerConfig {
+            num_capture_buffers,
+            ..self
+        }
+    }
+}
+
+/// A deinterlacer, using USERPTR buffers for the interlaced frames and MMAP
+/// buffers for the progressive ones.
+///
+/// Only formats with a single memory plane (e.g. `NV12`, but not `NV12M`)
+/// are supported.
+pub struct Deinterlacer {
+    output_queue: Queue<Output, BuffersAllocated<UserPtr<Vec<u8>>>>,
+    capture_queue: Queue<Capture, BuffersAllocated<MMAP>>,
+    /// Memory of the OUTPUT buffers that have been dequeued, for reuse.
+    output_backings: Vec<Vec<u8>>,
+    field: Field,
+    /// Order in which the fields of a frame are submitted with the
+    /// `Alternate` field order.
+    field_pair: [Field; 2],
+}
+
+impl Deinterlacer {
+    /// Create a deinterlacer using the m2m video device `device`.
+    ///
+    /// The formats of both queues are set according to `config` and their
+    /// buffers are allocated, after which both queues are streamed on.
+    /// `Error::InvalidFormat` is returned if the field order of `config` is
+    /// not interlaced, or if the driver does not support it or cannot
+    /// produce progressive frames of the same format.
+    pub fn new(device: Arc<Mutex<Device>>, config: DeinterlacerConfig) -> Result<Self> {
+        // Buffers of single fields would be submitted without their pair.
+        if !config.field.is_interlaced() || config.field.is_single_field() {
+            return Err(Error::InvalidFormat);
+        }
+
+        let (mut output_queue, mut capture_queue) =
+            match Queue::get_output_queue(Arc::clone(&device)) {
+                Ok(output_queue) => (output_queue, Queue::get_capture_queue(Arc::clone(&device))?),
+                Err(_) => (
+                    Queue::get_output_mplane_queue(Arc::clone(&device))?,
+                    Queue::get_capture_mplane_queue(Arc::clone(&device))?,
+                ),
+            };
+
+        // The height of the format is the height of a single field when
+        // fields are submitted separately.
+        let output_height = match config.field {
+            Field::Alternate => config.height / 2,
+            _ => config.height,
+        };
+        let output_format = output_queue
+            .change_format()?
+            .set_size(config.width, output_height)
+            .set_pixelformat(config.pixelformat)
+            .set_field(config.field)
+            .apply()?;
+        check_format(&output_format, config.pixelformat, config.field)?;
+
+        let capture_format = capture_queue
+            .change_format()?
+            .set_size(config.width, config.height)
+            .set_pixelformat(config.pixelformat)
+            .set_field(Field::None)
+            .apply()?;
+        check_format(&capture_format, config.pixelformat, Field::None)?;
+
+        let num_output_buffers = match config.field {
+            Field::Alternate => config.num_output_buffers.max(2),
+            _ => config.num_output_buffers,
+        };
+        let output_queue = output_queue.request_buffers(num_output_buffers)?;
+        let capture_queue = capture_queue.request_buffers(config.num_capture_buffers)?;
+        output_queue.streamon()?;
+        capture_queue.streamon()?;
+        capture_queue.queue_free_buffers()?;
+
+        Ok(Deinterlacer {
+            output_queue,
+            capture_queue,
+            output_backings: Vec::new(),
+            field: config.field,
+            field_pair: match config.bottom_field_first {
+                true => [Field::Bottom, Field::Top],
+                false => [Field::Top, Field::Bottom],
+            },
+        })
+    }
+
+    /// Returns the field order of the frames given to the deinterlacer.
+    pub fn field(&self) -> Field {
+        self.field
+    }
+
+    /// Returns the CAPTURE queue, e.g. to obtain the format of the
+    /// progressive frames or to map their buffers.
+    pub fn capture_queue(&self) -> &Queue<Capture, BuffersAllocated<MMAP>> {
+        &self.capture_queue
+    }
+
+    /// Submit `frame`, an interlaced frame holding both of its fields as
+    /// laid out by the field order of the deinterlacer. `timestamp` is
+    /// copied to the progressive frames produced from it.
+    ///
+    /// `Error::InvalidFormat` is returned with the `Alternate` field order,
+    /// for which `queue_field_pair()` must be used instead.
+    pub fn queue_frame(&mut self, frame: &[u8], timestamp: Duration) -> Result<()> {
+        if self.field == Field::Alternate {
+            return Err(Error::InvalidFormat);
+        }
+
+        self.wait_for_output_buffers(1)?;
+        self.queue_output(frame, None, timestamp)
+    }
+
+    /// Submit the `top` and `bottom` fields of a frame, in the order they
+    /// have been captured in, as separate buffers. `timestamp` is copied to
+    /// the progressive frames produced from them.
+    ///
+    /// Both fields are queued at once, so the driver never gets the first
+    /// field of a frame without the second one. `Error::InvalidFormat` is
+    /// returned with any other field order than `Alternate`, for which
+    /// `queue_frame()` must be used instead.
+    pub fn queue_field_pair(
+        &mut self,
+        top: &[u8],
+        bottom: &[u8],
+        timestamp: Duration,
+    ) -> Result<()> {
+        if self.field != Field::Alternate {
+            return Err(Error::InvalidFormat);
+        }
+
+        self.wait_for_output_buffers(2)?;
+        for field in self.field_pair {
+            let data = match field {
+                Field::Top => top,
+                _ => bottom,
+            };
+            self.queue_output(data, Some(field), timestamp)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next progressive frame, waiting for it to be produced if
+    /// needed. The buffer is used again for deinterlacing once dropped.
+    pub fn dequeue_frame(&mut self) -> Result<DQBuffer<MMAP>> {
+        self.capture_queue.queue_free_buffers()?;
+        self.reclaim_output_buffers()?;
+
+        self.capture_queue.dequeue()
+    }
+
+    /// Wait until `count` OUTPUT buffers are free.
+    ///
+    /// `Error::AlreadyBorrowed` is returned if the driver has no CAPTURE
+    /// buffer to write into, i.e. if they are all held by the client, as it
+    /// would never release the OUTPUT buffers then.
+    fn wait_for_output_buffers(&mut self, count: usize) -> Result<()> {
+        self.reclaim_output_buffers()?;
+        while self.output_queue.num_queued_buffers() + count > self.output_queue.num_buffers() {
+            self.capture_queue.queue_free_buffers()?;
+            if self.capture_queue.num_queued_buffers() == 0 {
+                return Err(Error::AlreadyBorrowed);
+            }
+            let mut buffer = self.output_queue.dequeue()?;
+            self.output_backings.append(&mut buffer.plane_handles);
+        }
+
+        Ok(())
+    }
+
+    /// Keep the memory of the OUTPUT buffers the driver is done with.
+    fn reclaim_output_buffers(&mut self) -> Result<()> {
+        loop {
+            match self.output_queue.try_dequeue() {
+                Ok(mut buffer) => self.output_backings.append(&mut buffer.plane_handles),
+                Err(Error::NotReady) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn queue_output(
+        &mut self,
+        data: &[u8],
+        field: Option<Field>,
+        timestamp: Duration,
+    ) -> Result<()> {
+        // Buffers must be at least as large as the plane size of the format.
+        let plane_size = self.output_queue.plane_sizes().first().copied();
+        let mut backing = self.output_backings.pop().unwrap_or_default();
+        backing.clear();
+        backing.extend_from_slice(data);
+        backing.resize(plane_size.unwrap_or(0).max(data.len()), 0);
+
+        let mut buffer = self
+            .output_queue
+            .get_free_buffer()?
+            .add_plane(Plane::out(backing, data.len()))
+            .set_timestamp(timestamp);
+        if let Some(field) = field {
+            buffer = buffer.set_field(field);
+        }
+        if let Err(e) = buffer.queue() {
+            self.output_backings.extend(e.plane_handles);
+            return Err(e.error);
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that the driver has applied `pixelformat` and `field`, and that
+/// the format has a single memory plane.
+fn check_format(format: &Format, pixelformat: PixelFormat, field: Field) -> Result<()> {
+    if format.pixelformat != pixelformat || format.field != field || format.plane_fmt.len() != 1 {
+        return Err(Error::InvalidFormat);
+    }
+
+    Ok(())
+}
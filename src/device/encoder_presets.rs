@@ -0,0 +1,264 @@
+//! Rate-control presets for encoders.
+//!
+//! A preset is a set of codec controls (bitrate mode, GOP size, B-frames and
+//! QP range) tuned for a typical use case. Drivers support different subsets
+//! of these controls and ranges, so applying a preset reports which of its
+//! controls have been set, and which ones the driver rejected and why. The
+//! bitrate itself depends on the resolution and frame rate of the stream, and
+//! is left to the user.
+use super::{is_resettable, Device};
+use crate::bindings;
+use crate::ioctl::{self, CtrlType, CtrlValue, CtrlWhich, QueryExtCtrl};
+use crate::{CtrlId, Error, Result};
+
+/// Rate-control preset of an encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateControlPreset {
+    /// Constant bitrate, short GOPs and no B-frames, so frames can be sent as
+    /// soon as they are encoded and decoders can join the stream quickly.
+    LowLatencyStreaming,
+    /// Variable bitrate, long GOPs and B-frames, with a lower QP range, for
+    /// the best quality at a given size.
+    HighQualityRecording,
+}
+
+impl RateControlPreset {
+    /// Returns the controls set by this preset, in the order they are
+    /// applied.
+    pub fn controls(self) -> Vec<(CtrlId, CtrlValue)> {
+        let (bitrate_mode, gop_size, b_frames, min_qp, max_qp) = match self {
+            RateControlPreset::LowLatencyStreaming => (
+                bindings::v4l2_mpeg_video_bitrate_mode_V4L2_MPEG_VIDEO_BITRATE_MODE_CBR,
+                30,
+                0,
+                20,
+                45,
+            ),
+            RateControlPreset::HighQualityRecording => (
+                bindings::v4l2_mpeg_video_bitrate_mode_V4L2_MPEG_VIDEO_BITRATE_MODE_VBR,
+                120,
+                2,
+                10,
+                35,
+            ),
+        };
+
+        vec![
+            (CtrlId::VIDEO_FRAME_RC_ENABLE, CtrlValue::Boolean(true)),
+            (CtrlId::VIDEO_BITRATE_MODE, CtrlValue::Menu(bitrate_mode)),
+            (CtrlId::VIDEO_GOP_SIZE, CtrlValue::Integer(gop_size)),
+            (CtrlId::VIDEO_B_FRAMES, CtrlValue::Integer(b_frames)),
+            (CtrlId::VIDEO_H264_MIN_QP, CtrlValue::Integer(min_qp)),
+            (CtrlId::VIDEO_H264_MAX_QP, CtrlValue::Integer(max_qp)),
+        ]
+    }
+}
+
+/// Why a control of a preset has not been set.
+#[derive(Debug)]
+pub enum RejectReason {
+    /// The device does not have this control.
+    Unsupported,
+    /// The control exists but cannot be set, e.g. because it is read-only,
+    /// disabled or grabbed.
+    NotSettable,
+    /// The value of the preset is of the wrong type for the control, outside
+    /// of its range, or not one of the entries of its menu.
+    InvalidValue,
+    /// The driver refused the value when setting it.
+    Refused(Error),
+}
+
+/// A control of a preset that has not been set.
+#[derive(Debug)]
+pub struct RejectedControl {
+    pub id: CtrlId,
+    /// The value the preset tried to set.
+    pub value: CtrlValue,
+    pub reason: RejectReason,
+}
+
+/// Outcome of `Device::apply_rate_control_preset()`.
+#[derive(Debug, Default)]
+pub struct PresetReport {
+    /// Controls that have been set, with the value actually set by the
+    /// driver, which may differ from the one of the preset.
+    pub applied: Vec<(CtrlId, CtrlValue)>,
+    /// Controls that have been left untouched.
+    pub rejected: Vec<RejectedControl>,
+}
+
+impl PresetReport {
+    /// Returns true if all the controls of the preset have been set.
+    pub fn is_complete(&self) -> bool {
+        self.rejected.is_empty()
+    }
+}
+
+/// Returns true if `value` is within the range of `ctrl`. Values of other
+/// kinds than integers and menus are not checked.
+fn in_range(ctrl: &QueryExtCtrl, value: &CtrlValue) -> bool {
+    let value = match value {
+        CtrlValue::Integer(value) => *value as i64,
+        CtrlValue::Integer64(value) => *value,
+        CtrlValue::Boolean(value) => *value as i64,
+        CtrlValue::Menu(index) => *index as i64,
+        _ => return true,
+    };
+    let step = match ctrl.type_ {
+        // The step of menus is always 1, and may be reported as 0.
+        CtrlType::Menu | CtrlType::IntegerMenu => 1,
+        _ => ctrl.step.max(1) as i64,
+    };
+
+    (ctrl.minimum..=ctrl.maximum).contains(&value) && (value - ctrl.minimum) % step == 0
+}
+
+impl Device {
+    /// Apply the controls of `preset` to this encoder.
+    ///
+    /// Controls the device does not have, cannot set, or whose value is not
+    /// valid for it are skipped. The remaining ones are set atomically if the
+    /// driver accepts all of them, and one by one otherwise, so one control
+    /// refused by the driver does not prevent the others from being set.
+    /// The returned report tells which controls have been set, and why the
+    /// others have not.
+    pub fn apply_rate_control_preset(&mut self, preset: RateControlPreset) -> Result<PresetReport> {
+        let mut report = PresetReport::default();
+        let mut accepted = Vec::new();
+        for (id, value) in preset.controls() {
+            let reason = match ioctl::query_ext_ctrl(self, id) {
+                Ok(ctrl) => {
+                    if !is_resettable(&ctrl) {
+                        Some(RejectReason::NotSettable)
+                    } else if value.to_ext_value(&ctrl).is_err()
+                        || !in_range(&ctrl, &value)
+                        || !self.is_menu_entry(&ctrl, &value)
+                    {
+                        Some(RejectReason::InvalidValue)
+                    } else {
+                        accepted.push((ctrl, value.clone()));
+                        None
+                    }
+                }
+                Err(Error::Nix(nix::Error::Sys(nix::errno::Errno::EINVAL))) => {
+                    Some(RejectReason::Unsupported)
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some(reason) = reason {
+                report.rejected.push(RejectedControl { id, value, reason });
+            }
+        }
+
+        if accepted.is_empty() {
+            return Ok(report);
+        }
+
+        let values: Vec<_> = accepted
+            .iter()
+            .map(|(ctrl, value)| (ctrl, value.clone()))
+            .collect();
+        if let Ok(set) = ioctl::s_ext_ctrl_values(self, CtrlWhich::Current, &values) {
+            report
+                .applied
+                .extend(accepted.iter().map(|(ctrl, _)| ctrl.id).zip(set));
+            return Ok(report);
+        }
+
+        for (ctrl, value) in accepted {
+            match ioctl::s_ext_ctrl_values(self, CtrlWhich::Current, &[(&ctrl, value.clone())]) {
+                Ok(mut set) => report.applied.push((ctrl.id, set.remove(0))),
+                Err(e) => report.rejected.push(RejectedControl {
+                    id: ctrl.id,
+                    value,
+                    reason: RejectReason::Refused(e),
+                }),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Returns false if `value` is an index that is missing from the menu of
+    /// `ctrl`. Menus can have holes, which are not visible in their range.
+    fn is_menu_entry(&self, ctrl: &QueryExtCtrl, value: &CtrlValue) -> bool {
+        match (ctrl.type_, value) {
+            (CtrlType::Menu | CtrlType::IntegerMenu, CtrlValue::Menu(index)) => {
+                ioctl::querymenu(self, ctrl.id, *index).is_ok()
+            }
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ioctl::CtrlFlags;
+
+    fn ctrl(type_: CtrlType, minimum: i64, maximum: i64, step: u64) -> QueryExtCtrl {
+        QueryExtCtrl {
+            id: CtrlId::VIDEO_GOP_SIZE,
+            type_,
+            name: String::new(),
+            minimum,
+            maximum,
+            step,
+            default_value: minimum,
+            flags: CtrlFlags::empty(),
+            elem_size: 4,
+            elems: 1,
+            dims: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let gop = ctrl(CtrlType::Integer, 1, 60, 1);
+        assert!(in_range(&gop, &CtrlValue::Integer(30)));
+        assert!(!in_range(&gop, &CtrlValue::Integer(120)));
+        assert!(!in_range(&gop, &CtrlValue::Integer(0)));
+
+        let even = ctrl(CtrlType::Integer, 0, 10, 2);
+        assert!(in_range(&even, &CtrlValue::Integer(4)));
+        assert!(!in_range(&even, &CtrlValue::Integer(5)));
+
+        let menu = ctrl(CtrlType::Menu, 0, 1, 0);
+        assert!(in_range(&menu, &CtrlValue::Menu(1)));
+        assert!(!in_range(&menu, &CtrlValue::Menu(2)));
+    }
+
+    #[test]
+    fn presets() {
+        for preset in [
+            RateControlPreset::LowLatencyStreaming,
+            RateControlPreset::HighQualityRecording,
+        ] {
+            let controls = preset.controls();
+            let get = |id| {
+                controls
+                    .iter()
+                    .find(|(ctrl_id, _)| *ctrl_id == id)
+                    .map(|(_, value)| value.clone())
+                    .unwrap()
+            };
+            match (
+                get(CtrlId::VIDEO_H264_MIN_QP),
+                get(CtrlId::VIDEO_H264_MAX_QP),
+            ) {
+                (CtrlValue::Integer(min), CtrlValue::Integer(max)) => assert!(min <= max),
+                _ => panic!("QP range is not made of integers"),
+            }
+        }
+
+        assert_eq!(
+            RateControlPreset::LowLatencyStreaming
+                .controls()
+                .iter()
+                .find(|(id, _)| *id == CtrlId::VIDEO_B_FRAMES)
+                .map(|(_, value)| value),
+            Some(&CtrlValue::Integer(0))
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Integration of devices and queues into `mio` event loops.
+//!
+//! Readable interest signals a CAPTURE buffer ready to be dequeued, writable
+//! interest an OUTPUT buffer ready to be dequeued, and priority interest a
+//! pending event, as with `poller::poll_device`.
+//!
+//! All the queues of a device share its file descriptor, so only one of the
+//! device or its queues can be registered with a given `Registry`, with the
+//! union of the interests of all of them.
+use super::queue::{direction::Direction, states::QueueState, Queue};
+use super::Device;
+use mio::event::Source;
+use mio::unix::SourceFd;
+use mio::{Interest, Registry, Token};
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+impl Source for Device {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).deregister(registry)
+    }
+}
+
+impl<D: Direction, S: QueueState> Source for Queue<D, S> {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.device_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.device_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.device_fd()).deregister(registry)
+    }
+}
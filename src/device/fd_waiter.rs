@@ -0,0 +1,127 @@
+//! Abstraction over the reactors of async runtimes, on top of which the
+//! asynchronous methods of devices and queues are built.
+//!
+//! Implementations for `tokio` and `async-io` are provided behind the
+//! features of the same name. Other runtimes can be supported by
+//! implementing `FdWaiter`.
+use crate::Result;
+use std::fs::File;
+use std::future::Future;
+
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+use crate::Error;
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+use nix::errno::Errno;
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+use std::io;
+
+#[cfg(feature = "async-io")]
+use async_io::Async;
+#[cfg(feature = "tokio")]
+use tokio::io::{unix::AsyncFd, Interest};
+
+/// Waits for readiness conditions of a V4L2 device using the reactor of an
+/// async runtime.
+///
+/// The wait methods are allowed to complete spuriously, as callers check the
+/// actual state of the device before waiting again. However they must not
+/// miss a notification that happened since the previous wait completed.
+pub trait FdWaiter: Sized + Send + Sync + 'static {
+    /// Register `fd` with the reactor. `fd` is a duplicate of the file
+    /// descriptor of the device, so its status flags (e.g. `O_NONBLOCK`) are
+    /// shared with it and must not be changed.
+    fn new(fd: File) -> Result<Self>;
+
+    /// Wait until a CAPTURE buffer can be dequeued, or an error is signaled.
+    fn wait_readable(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Wait until an OUTPUT buffer can be dequeued, or an error is signaled.
+    fn wait_writable(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Wait until an event is pending, or until a media request has
+    /// completed.
+    fn wait_priority(&self) -> impl Future<Output = Result<()>> + Send;
+}
+
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+fn io_error(e: io::Error) -> Error {
+    let errno = e.raw_os_error().unwrap_or(nix::libc::EIO);
+    Error::Nix(nix::Error::Sys(Errno::from_i32(errno)))
+}
+
+/// `FdWaiter` for the `tokio` runtime.
+///
+/// Creating it panics if this is not done from within a `tokio` runtime
+/// with IO enabled.
+#[cfg(feature = "tokio")]
+pub struct TokioWaiter(AsyncFd<File>);
+
+#[cfg(feature = "tokio")]
+impl TokioWaiter {
+    async fn wait(&self, interest: Interest) -> Result<()> {
+        // ERROR needs to be requested explicitly, but tells us that a queue
+        // stopped streaming. Clearing the readiness before the caller checks
+        // the device again ensures that no notification is missed.
+        self.0
+            .ready(interest | Interest::ERROR)
+            .await
+            .map_err(io_error)?
+            .clear_ready();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl FdWaiter for TokioWaiter {
+    fn new(fd: File) -> Result<Self> {
+        let interest =
+            Interest::READABLE | Interest::WRITABLE | Interest::PRIORITY | Interest::ERROR;
+        AsyncFd::with_interest(fd, interest)
+            .map(TokioWaiter)
+            .map_err(io_error)
+    }
+
+    async fn wait_readable(&self) -> Result<()> {
+        self.wait(Interest::READABLE).await
+    }
+
+    async fn wait_writable(&self) -> Result<()> {
+        self.wait(Interest::WRITABLE).await
+    }
+
+    async fn wait_priority(&self) -> Result<()> {
+        self.wait(Interest::PRIORITY).await
+    }
+}
+
+/// `FdWaiter` for runtimes built on `async-io`, like `smol` and `async-std`.
+///
+/// `async-io` cannot wait for priority data alone, so `wait_priority()`
+/// also completes whenever a CAPTURE buffer is ready. Waiting for an event
+/// thus busy-loops while a CAPTURE buffer is left in the queue.
+#[cfg(feature = "async-io")]
+pub struct AsyncIoWaiter(Async<File>);
+
+#[cfg(feature = "async-io")]
+impl FdWaiter for AsyncIoWaiter {
+    fn new(fd: File) -> Result<Self> {
+        // `Async::new()` would make the device non-blocking. We never perform
+        // IO through `Async`, so it does not matter if it is blocking.
+        Async::new_nonblocking(fd)
+            .map(AsyncIoWaiter)
+            .map_err(io_error)
+    }
+
+    async fn wait_readable(&self) -> Result<()> {
+        self.0.readable().await.map_err(io_error)
+    }
+
+    async fn wait_writable(&self) -> Result<()> {
+        self.0.writable().await.map_err(io_error)
+    }
+
+    async fn wait_priority(&self) -> Result<()> {
+        // Readable interest includes priority data on Linux.
+        self.wait_readable().await
+    }
+}
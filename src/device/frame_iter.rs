@@ -0,0 +1,54 @@
+//! Blocking iterator over the frames captured by a queue.
+use super::queue::{direction::Capture, dqbuf::DQBuffer, states::BuffersAllocated, Queue};
+use crate::ioctl::BufferFlags;
+use crate::memory::MMAP;
+use crate::Result;
+
+/// An `Iterator` over the frames captured by a streaming MMAP CAPTURE queue,
+/// for simple synchronous tools.
+///
+/// All the free buffers of the queue are queued before waiting for the next
+/// frame, so frames are queued again automatically once the consumer drops
+/// them. Waiting uses `Queue::dequeue()`, so the device must not have been
+/// opened in non-blocking mode, and the consumer should not hold all the
+/// buffers when asking for the next frame.
+///
+/// The iterator ends after the buffer with the `LAST` flag, or after
+/// returning an error.
+pub struct FrameIterator<'a> {
+    queue: &'a Queue<Capture, BuffersAllocated<MMAP>>,
+    ended: bool,
+}
+
+impl<'a> FrameIterator<'a> {
+    /// Create an iterator over the frames captured by `queue`, which must be
+    /// streaming before the first frame is requested.
+    pub fn new(queue: &'a Queue<Capture, BuffersAllocated<MMAP>>) -> Self {
+        FrameIterator {
+            queue,
+            ended: false,
+        }
+    }
+
+    fn next_frame(&self) -> Result<DQBuffer<MMAP>> {
+        self.queue.queue_free_buffers()?;
+        self.queue.dequeue()
+    }
+}
+
+impl<'a> Iterator for FrameIterator<'a> {
+    type Item = Result<DQBuffer<MMAP>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ended {
+            return None;
+        }
+
+        let frame = self.next_frame();
+        self.ended = match &frame {
+            Ok(buffer) => buffer.data.flags.contains(BufferFlags::LAST),
+            Err(_) => true,
+        };
+        Some(frame)
+    }
+}
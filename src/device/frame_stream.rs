@@ -0,0 +1,91 @@
+//! Asynchronous stream of the frames captured by a queue.
+use super::fd_waiter::FdWaiter;
+use super::queue::{direction::Capture, dqbuf::DQBuffer, states::BuffersAllocated, Queue};
+use crate::memory::MMAP;
+use crate::{Error, Result};
+use futures_core::Stream;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+type DequeueFuture = Pin<Box<dyn Future<Output = Result<DQBuffer<MMAP>>> + Send>>;
+
+/// A `Stream` of the frames captured by a streaming MMAP CAPTURE queue,
+/// built on `Queue::dequeue_async()` with the `FdWaiter` `W`.
+///
+/// All the free buffers of the queue are queued every time the stream is
+/// polled, so frames are queued again automatically once the consumer drops
+/// them. If the consumer holds all the buffers when the stream is polled, the
+/// driver has nothing to capture into and the stream cannot make progress.
+///
+/// The stream ends when queueing or dequeueing a buffer fails, e.g. because
+/// the queue has been stopped or after the buffer with the `LAST` flag has
+/// been dequeued. The error can be obtained with `take_error()`.
+pub struct FrameStream<W: FdWaiter> {
+    queue: Arc<Queue<Capture, BuffersAllocated<MMAP>>>,
+    dequeue: Option<DequeueFuture>,
+    error: Option<Error>,
+    ended: bool,
+    _waiter: PhantomData<fn() -> W>,
+}
+
+impl<W: FdWaiter> FrameStream<W> {
+    /// Create a stream of the frames captured by `queue`. Streaming can be
+    /// started before or after creating the stream, but the stream only
+    /// yields frames once it is.
+    pub fn new(queue: Arc<Queue<Capture, BuffersAllocated<MMAP>>>) -> Self {
+        FrameStream {
+            queue,
+            dequeue: None,
+            error: None,
+            ended: false,
+            _waiter: PhantomData,
+        }
+    }
+
+    /// Returns the queue the frames are captured from, e.g. to stop it.
+    pub fn queue(&self) -> &Arc<Queue<Capture, BuffersAllocated<MMAP>>> {
+        &self.queue
+    }
+
+    /// Returns the error that ended the stream, if any.
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
+    fn end(&mut self, error: Error) -> Poll<Option<DQBuffer<MMAP>>> {
+        self.dequeue = None;
+        self.error = Some(error);
+        self.ended = true;
+        Poll::Ready(None)
+    }
+}
+
+impl<W: FdWaiter> Stream for FrameStream<W> {
+    type Item = DQBuffer<MMAP>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.ended {
+            return Poll::Ready(None);
+        }
+        if let Err(e) = this.queue.queue_free_buffers() {
+            return this.end(e);
+        }
+
+        let queue = Arc::clone(&this.queue);
+        let dequeue = this
+            .dequeue
+            .get_or_insert_with(|| Box::pin(async move { queue.dequeue_async::<W>().await }));
+        match dequeue.as_mut().poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(buffer)) => {
+                this.dequeue = None;
+                Poll::Ready(Some(buffer))
+            }
+            Poll::Ready(Err(e)) => this.end(e),
+        }
+    }
+}
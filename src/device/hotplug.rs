@@ -0,0 +1,161 @@
+//! Monitoring of V4L2 devices being plugged and unplugged.
+//!
+//! Events are received directly from the kernel through a netlink socket,
+//! so no dependency on udev is required.
+use super::{Device, DeviceConfig};
+use crate::ioctl::Capability;
+use crate::Result;
+use nix::sys::socket::{bind, recv, MsgFlags, NetlinkAddr, SockAddr};
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::PathBuf;
+
+/// Netlink multicast group on which the kernel sends its uevents.
+const KERNEL_UEVENT_GROUP: u32 = 1;
+
+/// A video node being added or removed.
+#[derive(Debug)]
+pub enum HotplugEvent {
+    /// A video node has been added. `capability` is the result of querying
+    /// the new device, or `None` if it could not be opened. This can happen
+    /// if udev has not set the permissions of the node yet.
+    Added {
+        dev_path: PathBuf,
+        capability: Option<Capability>,
+    },
+    /// A video node has been removed.
+    Removed { dev_path: PathBuf },
+}
+
+/// Parse a kernel uevent message, which is a header followed by
+/// nul-separated `KEY=value` pairs, into the list of its properties.
+fn parse_uevent(message: &[u8]) -> HashMap<&str, &str> {
+    message
+        .split(|c| *c == b'\0')
+        .filter_map(|property| std::str::from_utf8(property).ok())
+        .filter_map(|property| {
+            let mut split = property.splitn(2, '=');
+            Some((split.next()?, split.next()?))
+        })
+        .collect()
+}
+
+/// Turn uevent `message` into a `HotplugEvent`, if it is about a video node
+/// being added or removed.
+fn event_from_uevent(message: &[u8]) -> Option<HotplugEvent> {
+    let properties = parse_uevent(message);
+    if properties.get("SUBSYSTEM") != Some(&"video4linux") {
+        return None;
+    }
+
+    let dev_name = properties.get("DEVNAME")?;
+    // Skip radio, VBI and sub-device nodes.
+    if !dev_name.starts_with("video") {
+        return None;
+    }
+    let dev_path = PathBuf::from("/dev").join(dev_name);
+
+    match *properties.get("ACTION")? {
+        "add" => {
+            let capability = Device::open(&dev_path, DeviceConfig::new())
+                .ok()
+                .map(|device| device.capability);
+            Some(HotplugEvent::Added {
+                dev_path,
+                capability,
+            })
+        }
+        "remove" => Some(HotplugEvent::Removed { dev_path }),
+        _ => None,
+    }
+}
+
+/// Watches for video nodes being added or removed.
+///
+/// `next_event()` blocks until an event is available. The monitor can be
+/// polled for readability using its file descriptor in order to integrate it
+/// into an event loop.
+pub struct HotplugMonitor {
+    socket: File,
+}
+
+impl HotplugMonitor {
+    /// Start monitoring. Only events happening after this call are reported,
+    /// so use `sysfs::enumerate()` to list the devices that are already
+    /// present.
+    pub fn new() -> Result<Self> {
+        // nix does not expose the NETLINK_KOBJECT_UEVENT protocol.
+        let fd = unsafe {
+            nix::libc::socket(
+                nix::libc::AF_NETLINK,
+                nix::libc::SOCK_DGRAM | nix::libc::SOCK_CLOEXEC,
+                nix::libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd < 0 {
+            return Err(nix::Error::last().into());
+        }
+        // Safe because we are constructing a file from a fd we just opened.
+        let socket = unsafe { File::from_raw_fd(fd) };
+
+        bind(
+            socket.as_raw_fd(),
+            &SockAddr::Netlink(NetlinkAddr::new(0, KERNEL_UEVENT_GROUP)),
+        )?;
+
+        Ok(HotplugMonitor { socket })
+    }
+
+    /// Wait for the next video node to be added or removed, and return the
+    /// corresponding event.
+    pub fn next_event(&self) -> Result<HotplugEvent> {
+        // uevents are limited to 8KB by the kernel.
+        let mut buffer = [0u8; 8192];
+
+        loop {
+            let len = recv(self.socket.as_raw_fd(), &mut buffer, MsgFlags::empty())?;
+            if let Some(event) = event_from_uevent(&buffer[..len]) {
+                return Ok(event);
+            }
+        }
+    }
+}
+
+impl AsRawFd for HotplugMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_video_uevent() {
+        let message =
+            b"remove@/devices/pci0000:00/0000:00:14.0/usb1/1-1/1-1:1.0/video4linux/video2\0\
+            ACTION=remove\0\
+            DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-1/1-1:1.0/video4linux/video2\0\
+            SUBSYSTEM=video4linux\0\
+            MAJOR=81\0\
+            MINOR=2\0\
+            DEVNAME=video2\0\
+            SEQNUM=4242\0";
+
+        let properties = parse_uevent(message);
+        assert_eq!(properties.get("ACTION"), Some(&"remove"));
+        assert_eq!(properties.get("MINOR"), Some(&"2"));
+
+        match event_from_uevent(message) {
+            Some(HotplugEvent::Removed { dev_path }) => {
+                assert_eq!(dev_path, PathBuf::from("/dev/video2"))
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        // Events about other subsystems are ignored.
+        assert!(event_from_uevent(b"add@/devices/foo\0ACTION=add\0SUBSYSTEM=usb\0").is_none());
+    }
+}
@@ -0,0 +1,209 @@
+//! Typed wrapper around `poll(2)` for V4L2 devices.
+//!
+//! A V4L2 device reports through `poll(2)` that a CAPTURE buffer is ready to
+//! be dequeued (`POLLIN`), that an OUTPUT buffer has been processed and can
+//! be dequeued (`POLLOUT`), and that an event is pending (`POLLPRI`). All the
+//! queues of a device share its file descriptor, so a single call can wait
+//! for any of them.
+//!
+//! A `Waker` can be polled alongside the device so that other threads can
+//! interrupt the wait.
+use crate::Result;
+use bitflags::bitflags;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::eventfd::{eventfd, EfdFlags};
+use nix::unistd;
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+bitflags! {
+    /// Conditions that can be waited for with `poll_device`, and that it
+    /// reports.
+    pub struct PollEvents: u32 {
+        /// A CAPTURE buffer is ready to be dequeued.
+        const CAPTURE_READY = 0b00001;
+        /// An OUTPUT buffer is ready to be dequeued.
+        const OUTPUT_READY = 0b00010;
+        /// An event is pending and can be obtained with `dqevent`.
+        const EVENT = 0b00100;
+        /// Always reported if it happens. A queue is not streaming, has no
+        /// buffer queued, or is in error.
+        const ERROR = 0b01000;
+        /// Always reported if it happens. The device has been disconnected.
+        const HANGUP = 0b10000;
+        /// Reported by `poll_device_with_waker()` if its `Waker` has been
+        /// woken up.
+        const WOKEN = 0b100000;
+    }
+}
+
+impl PollEvents {
+    fn to_poll_flags(self) -> PollFlags {
+        let mut flags = PollFlags::empty();
+        flags.set(PollFlags::POLLIN, self.contains(PollEvents::CAPTURE_READY));
+        flags.set(PollFlags::POLLOUT, self.contains(PollEvents::OUTPUT_READY));
+        flags.set(PollFlags::POLLPRI, self.contains(PollEvents::EVENT));
+        flags
+    }
+
+    fn from_poll_flags(flags: PollFlags) -> Self {
+        let mut events = PollEvents::empty();
+        events.set(
+            PollEvents::CAPTURE_READY,
+            flags.intersects(PollFlags::POLLIN | PollFlags::POLLRDNORM),
+        );
+        events.set(
+            PollEvents::OUTPUT_READY,
+            flags.intersects(PollFlags::POLLOUT | PollFlags::POLLWRNORM),
+        );
+        events.set(PollEvents::EVENT, flags.contains(PollFlags::POLLPRI));
+        events.set(PollEvents::ERROR, flags.contains(PollFlags::POLLERR));
+        events.set(PollEvents::HANGUP, flags.contains(PollFlags::POLLHUP));
+        events
+    }
+}
+
+/// Lets other threads interrupt waits on a device, e.g. to stop a capture
+/// loop promptly.
+///
+/// It is an eventfd that is polled alongside the device. A wake-up is never
+/// lost: if nobody is waiting when `wake()` is called, the next wait returns
+/// immediately. Several calls to `wake()` before a wait result in a single
+/// wake-up. Share it between threads with an `Arc`.
+pub struct Waker(File);
+
+impl Waker {
+    pub fn new() -> Result<Self> {
+        let fd = eventfd(0, EfdFlags::EFD_CLOEXEC | EfdFlags::EFD_NONBLOCK)?;
+        // Safe because we are constructing a file from the fd we just created.
+        Ok(Waker(unsafe { File::from_raw_fd(fd) }))
+    }
+
+    /// Interrupt the current or next wait using this waker.
+    pub fn wake(&self) -> Result<()> {
+        match unistd::write(self.0.as_raw_fd(), &1u64.to_ne_bytes()) {
+            // The counter would overflow, so a wake-up is pending anyway.
+            Ok(_) | Err(nix::Error::Sys(Errno::EAGAIN)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Wait at most `timeout` for a wake-up, and consume it. Returns true if
+    /// one happened.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<bool> {
+        let mut fds = [PollFd::new(self.as_raw_fd(), PollFlags::POLLIN)];
+        poll_fds(&mut fds, Some(timeout))?;
+        Ok(self.consume(&fds[0]))
+    }
+
+    /// Consume the pending wake-up if `fd`, polled for this waker, reports
+    /// one.
+    fn consume(&self, fd: &PollFd) -> bool {
+        let woken = fd
+            .revents()
+            .is_some_and(|revents| revents.contains(PollFlags::POLLIN));
+        if woken {
+            // Cannot block since the fd is non-blocking, and the only
+            // possible failure is that someone else consumed it first.
+            let _ = unistd::read(self.0.as_raw_fd(), &mut [0u8; 8]);
+        }
+        woken
+    }
+}
+
+impl AsRawFd for Waker {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// `poll(2)` `fds` until one of them is ready or `timeout` expires if it is
+/// not `None`. Calls interrupted by a signal are restarted with the remaining
+/// time.
+fn poll_fds(fds: &mut [PollFd], timeout: Option<Duration>) -> Result<()> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let timeout_ms = match deadline {
+            // Round up so we never wake up before the deadline.
+            Some(deadline) => deadline
+                .saturating_duration_since(Instant::now())
+                .as_nanos()
+                .div_ceil(1_000_000)
+                .min(i32::MAX as u128) as i32,
+            None => -1,
+        };
+        match poll(fds, timeout_ms) {
+            Ok(_) => return Ok(()),
+            Err(nix::Error::Sys(Errno::EINTR)) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Wait until one of `events` happens on the V4L2 device `fd`, or until
+/// `timeout` expires if it is not `None`.
+///
+/// Returns the conditions that are met, which is empty if the timeout
+/// expired. Calls interrupted by a signal are restarted with the remaining
+/// time.
+pub fn poll_device<F: AsRawFd>(
+    fd: &F,
+    events: PollEvents,
+    timeout: Option<Duration>,
+) -> Result<PollEvents> {
+    let mut fds = [PollFd::new(fd.as_raw_fd(), events.to_poll_flags())];
+    poll_fds(&mut fds, timeout)?;
+    let revents = fds[0].revents().unwrap_or_else(PollFlags::empty);
+    Ok(PollEvents::from_poll_flags(revents))
+}
+
+/// Same as `poll_device()`, but also returns if `waker` is woken up, in
+/// which case `WOKEN` is reported and the wake-up consumed.
+pub fn poll_device_with_waker<F: AsRawFd>(
+    fd: &F,
+    events: PollEvents,
+    waker: &Waker,
+    timeout: Option<Duration>,
+) -> Result<PollEvents> {
+    let mut fds = [
+        PollFd::new(fd.as_raw_fd(), events.to_poll_flags()),
+        PollFd::new(waker.as_raw_fd(), PollFlags::POLLIN),
+    ];
+    poll_fds(&mut fds, timeout)?;
+    let revents = fds[0].revents().unwrap_or_else(PollFlags::empty);
+    let mut events = PollEvents::from_poll_flags(revents);
+    events.set(PollEvents::WOKEN, waker.consume(&fds[1]));
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poll_events() {
+        let events = PollEvents::CAPTURE_READY | PollEvents::EVENT;
+        assert_eq!(
+            events.to_poll_flags(),
+            PollFlags::POLLIN | PollFlags::POLLPRI
+        );
+        assert_eq!(
+            PollEvents::from_poll_flags(PollFlags::POLLWRNORM | PollFlags::POLLERR),
+            PollEvents::OUTPUT_READY | PollEvents::ERROR
+        );
+    }
+
+    #[test]
+    fn waker() {
+        let waker = Waker::new().unwrap();
+        assert!(!waker.wait_timeout(Duration::from_secs(0)).unwrap());
+
+        // Wake-ups are not lost, and coalesced.
+        waker.wake().unwrap();
+        waker.wake().unwrap();
+        assert!(waker.wait_timeout(Duration::from_secs(0)).unwrap());
+        assert!(!waker.wait_timeout(Duration::from_secs(0)).unwrap());
+    }
+}
@@ -1,8 +1,12 @@
 pub mod direction;
 pub mod dqbuf;
+pub mod dump;
 pub mod qbuf;
 pub mod states;
+pub mod watermark;
+pub mod wipe;
 
+use super::poller::{poll_device, poll_device_with_waker, PollEvents, Waker};
 use super::Device;
 use crate::ioctl;
 use crate::memory::*;
@@ -10,10 +14,20 @@ use crate::*;
 use states::BufferState;
 use direction::*;
 use dqbuf::*;
+use dump::*;
 use qbuf::*;
 use states::*;
+use watermark::*;
+use wipe::*;
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Display};
+use std::ops::Range;
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::Duration;
 
 /// Contains the handles (pointers to user memory or DMABUFs) that are kept
 /// when a buffer is processed by the kernel and returned to the user upon
@@ -21,6 +35,33 @@ use std::sync::{Arc, Mutex, Weak};
 #[allow(type_alias_bounds)]
 pub type PlaneHandles<M: Memory> = Vec<M::DQBufType>;
 
+/// Convert the errors returned by the kernel when configuring a queue into
+/// their more descriptive variant.
+///
+/// `einval` is the error that `EINVAL` stands for in the context of the
+/// ioctl, and `streaming` whether the queue was streaming when `EBUSY` has
+/// been returned.
+fn setup_error(error: Error, einval: Error, streaming: bool) -> Error {
+    match error {
+        Error::Nix(nix::Error::Sys(Errno::EBUSY)) => Error::Busy { streaming },
+        Error::Nix(nix::Error::Sys(Errno::EINVAL)) => einval,
+        error => error,
+    }
+}
+
+/// Lock `mutex`, failing with `Error::Poisoned` if a thread panicked while
+/// holding it.
+fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>> {
+    mutex.lock().map_err(|_| Error::Poisoned)
+}
+
+/// Lock `mutex` even if a thread panicked while holding it. Only to be used
+/// where failing is not an option, or to access members that remain
+/// meaningful regardless of the operation that has been interrupted.
+fn lock_ignore_poison<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 /// Base values of a queue, that are always value no matter the state the queue
 /// is in. This base object remains alive as long as the queue is borrowed from
 /// the `Device`.
@@ -34,6 +75,48 @@ pub struct QueueBase {
     fd: RawFd,
     type_: QueueType,
     capabilities: ioctl::BufferCapabilities,
+    /// Size of each plane of the current format, as reported by the driver
+    /// the last time the format has been read or set. Empty if unknown.
+    plane_sizes: Vec<usize>,
+}
+
+impl QueueBase {
+    /// Record the plane layout of `format`, which has just been obtained from
+    /// or applied by the driver.
+    fn update_plane_sizes(&mut self, format: &Format) {
+        self.plane_sizes = format
+            .plane_fmt
+            .iter()
+            .map(|plane| plane.sizeimage as usize)
+            .collect();
+    }
+
+    /// Single-planar queues can only use the contiguous variant of planar
+    /// formats, so convert `format` if needed.
+    fn adapt_format(&self, format: Format) -> Format {
+        if self.type_.is_multi_planar() || !formats::is_non_contiguous(format.pixelformat) {
+            return format;
+        }
+
+        formats::to_contiguous(&format).unwrap_or(format)
+    }
+
+    /// Try `format` on the queue without applying it.
+    fn try_format(&self, format: Format) -> Result<Format> {
+        ioctl::try_fmt(self, self.type_, self.adapt_format(format))
+            .map_err(|e| setup_error(e, Error::InvalidFormat, false))
+    }
+
+    /// Set the format of the queue and record its plane layout.
+    fn set_format(&mut self, format: Format) -> Result<Format> {
+        let type_ = self.type_;
+        let format = self.adapt_format(format);
+        let format = ioctl::s_fmt(self, type_, format)
+            .map_err(|e| setup_error(e, Error::InvalidFormat, false))?;
+        self.update_plane_sizes(&format);
+
+        Ok(format)
+    }
 }
 
 impl AsRawFd for QueueBase {
@@ -46,7 +129,9 @@ impl<'a> Drop for QueueBase {
     /// Make the queue available again.
     fn drop(&mut self) {
         assert_eq!(
-            self.device.lock().unwrap().used_queues.remove(&self.type_),
+            lock_ignore_poison(&self.device)
+                .used_queues
+                .remove(&self.type_),
             true
         );
     }
@@ -78,29 +163,65 @@ where
         self.inner.type_
     }
 
+    /// File descriptor of the device, shared by all its queues.
+    #[cfg(any(feature = "mio", feature = "async"))]
+    pub(super) fn device_fd(&self) -> RawFd {
+        self.inner.fd
+    }
+
+    /// Returns true if this queue uses the multi-planar API.
+    pub fn is_multi_planar(&self) -> bool {
+        self.inner.type_.is_multi_planar()
+    }
+
     pub fn get_format(&self) -> Result<Format> {
         ioctl::g_fmt(&self.inner, self.inner.type_)
     }
 
+    /// Returns the number of planes of the current format.
+    ///
+    /// This is updated every time the format is set through this queue, so
+    /// it can be out of date if the format has been changed by other means,
+    /// e.g. a decoder adjusting its CAPTURE format.
+    pub fn num_planes(&self) -> usize {
+        self.inner.plane_sizes.len()
+    }
+
+    /// Returns the size of each plane of the current format, as reported by
+    /// the driver. The same limitations as `num_planes` apply.
+    pub fn plane_sizes(&self) -> &[usize] {
+        &self.inner.plane_sizes
+    }
+
+    /// Single-planar queues cannot use non-contiguous formats (e.g. `NV12M`),
+    /// so these are converted to their contiguous variant (e.g. `NV12`).
+    ///
     /// This method can invalidate any current format iterator, hence it requires
     /// the queue to be mutable. This way of doing is not perfect though, as setting
     /// the format on one queue can change the options available on another.
+    ///
+    /// If the queue has buffers allocated, `Error::Busy` is returned without
+    /// calling into the driver.
     pub fn set_format(&mut self, format: Format) -> Result<Format> {
-        let type_ = self.inner.type_;
-        ioctl::s_fmt(&mut self.inner, type_, format)
+        self.state.check_format_change()?;
+        self.inner.set_format(format)
     }
 
     /// Performs exactly as `set_format`, but does not actually apply `format`.
     /// Useful to check what modifications need to be done to a format before it
     /// can be used.
     pub fn try_format(&self, format: Format) -> Result<Format> {
-        ioctl::try_fmt(&self.inner, self.inner.type_, format)
+        self.inner.try_format(format)
     }
 
     /// Returns a `FormatBuilder` which is set to the currently active format
     /// and can be modified and eventually applied. The `FormatBuilder` holds
     /// a mutable reference to this `Queue`.
+    ///
+    /// If the queue has buffers allocated, `Error::Busy` is returned as the
+    /// format could not be applied anyway.
     pub fn change_format<'a>(&'a mut self) -> Result<FormatBuilder<'a>> {
+        self.state.check_format_change()?;
         FormatBuilder::new(&mut self.inner)
     }
 
@@ -108,6 +229,178 @@ where
     pub fn format_iter(&self) -> ioctl::FormatIterator<QueueBase> {
         ioctl::FormatIterator::new(&self.inner, self.inner.type_)
     }
+
+    /// Returns an iterator over the frame sizes supported for `pixel_format`.
+    pub fn frame_size_iter(
+        &self,
+        pixel_format: impl Into<PixelFormat>,
+    ) -> ioctl::FrameSizeIterator<'_, QueueBase> {
+        ioctl::FrameSizeIterator::new(&self.inner, pixel_format.into())
+    }
+
+    /// Returns an iterator over the frame intervals supported for
+    /// `pixel_format` at a size of `width`x`height`.
+    pub fn frame_interval_iter(
+        &self,
+        pixel_format: impl Into<PixelFormat>,
+        width: u32,
+        height: u32,
+    ) -> ioctl::FrameIntervalIterator<'_, QueueBase> {
+        ioctl::FrameIntervalIterator::new(&self.inner, pixel_format.into(), width, height)
+    }
+
+    /// Rank the formats and frame sizes supported by this queue against
+    /// `preferences`, using `formats::negotiate`. The first candidate, if
+    /// any, is the best match.
+    pub fn negotiate(&self, preferences: &[formats::Preference]) -> Vec<formats::Candidate> {
+        let available: Vec<_> = self
+            .format_iter()
+            .filter(|desc| {
+                preferences
+                    .iter()
+                    .any(|preference| preference.pixelformat == desc.pixelformat)
+            })
+            .map(|desc| {
+                let sizes = self.frame_size_iter(desc.pixelformat).collect();
+                (desc, sizes)
+            })
+            .collect();
+
+        formats::negotiate(preferences, &available)
+    }
+
+    /// Returns the current interval between frames, in seconds.
+    pub fn get_frame_interval(&self) -> Result<ioctl::Fraction> {
+        Ok(ioctl::g_parm(&self.inner, self.inner.type_)?.time_per_frame)
+    }
+
+    /// Set the interval between frames to `interval` seconds, e.g. 1/30 for
+    /// 30 frames per second, and returns the interval applied by the driver.
+    ///
+    /// `interval` is checked against the intervals the device enumerates for
+    /// the current format, and `Error::InvalidFrameInterval` is returned if
+    /// it is not one of them, or if the queue does not support setting the
+    /// frame interval at all. Devices that do not enumerate their intervals
+    /// are left to adjust `interval` to the nearest supported value.
+    pub fn set_frame_interval(&mut self, interval: ioctl::Fraction) -> Result<ioctl::Fraction> {
+        let type_ = self.inner.type_;
+        let parm = ioctl::g_parm(&self.inner, type_)?;
+        if !parm
+            .capabilities
+            .contains(ioctl::StreamParmCapabilities::TIMEPERFRAME)
+        {
+            return Err(Error::InvalidFrameInterval);
+        }
+
+        let format = self.get_format()?;
+        let mut intervals = self
+            .frame_interval_iter(format.pixelformat, format.width, format.height)
+            .peekable();
+        if intervals.peek().is_some() && !intervals.any(|i| i.contains(interval)) {
+            return Err(Error::InvalidFrameInterval);
+        }
+
+        let parm = ioctl::StreamParm {
+            time_per_frame: interval,
+            ..parm
+        };
+        Ok(ioctl::s_parm(&mut self.inner, type_, parm)?.time_per_frame)
+    }
+
+    /// Returns the rectangle of the selection `target`, e.g. the visible
+    /// area of the frames of a decoder's CAPTURE queue with
+    /// `SelectionTarget::Compose`.
+    ///
+    /// Multi-planar queues are handled transparently, and so are drivers
+    /// that only implement the legacy crop API, for the targets it covers.
+    pub fn get_selection(&self, target: ioctl::SelectionTarget) -> Result<ioctl::Rect> {
+        let type_ = self.inner.type_;
+        let inner = &self.inner;
+        match with_selection_type(type_, |t| ioctl::g_selection(inner, t, target)) {
+            Err(Error::Nix(nix::Error::Sys(Errno::ENOTTY))) => {
+                with_selection_type(type_, |t| get_legacy_crop(inner, t, target))
+            }
+            result => result,
+        }
+    }
+
+    /// Set the rectangle of the selection `target` to `rect`, adjusted by the
+    /// driver within the constraints of `flags`, and returns the rectangle
+    /// actually applied.
+    ///
+    /// Multi-planar queues are handled transparently, and so are drivers
+    /// that only implement the legacy crop API, for the targets it covers.
+    /// `flags` are ignored in the latter case.
+    pub fn set_selection(
+        &mut self,
+        target: ioctl::SelectionTarget,
+        rect: ioctl::Rect,
+        flags: ioctl::SelectionFlags,
+    ) -> Result<ioctl::Rect> {
+        let type_ = self.inner.type_;
+        let inner = &mut self.inner;
+        match with_selection_type(type_, |t| ioctl::s_selection(inner, t, target, rect, flags)) {
+            Err(Error::Nix(nix::Error::Sys(Errno::ENOTTY))) => {
+                with_selection_type(type_, |t| set_legacy_crop(inner, t, target, rect))
+            }
+            result => result,
+        }
+    }
+}
+
+/// Run the selection or crop ioctl `f` with the buffer type these APIs expect
+/// for `queue`, i.e. a single-planar one, even for multi-planar queues.
+/// Drivers written before the V4L2 core converted the types for them may only
+/// accept the multi-planar ones though, so these are tried as well.
+fn with_selection_type<T, F>(queue: QueueType, mut f: F) -> Result<T>
+where
+    F: FnMut(QueueType) -> Result<T>,
+{
+    let single_planar = match queue {
+        QueueType::VideoCaptureMplane => QueueType::VideoCapture,
+        QueueType::VideoOutputMplane => QueueType::VideoOutput,
+        queue => queue,
+    };
+    match f(single_planar) {
+        Err(Error::Nix(nix::Error::Sys(Errno::EINVAL))) if queue != single_planar => f(queue),
+        result => result,
+    }
+}
+
+/// Returns the legacy crop rectangle matching `target`, which is a crop
+/// target for CAPTURE queues and a compose one for OUTPUT queues. Other
+/// targets are not supported by the legacy API, and return `ENOTTY`.
+fn get_legacy_crop(
+    fd: &QueueBase,
+    queue: QueueType,
+    target: ioctl::SelectionTarget,
+) -> Result<ioctl::Rect> {
+    use ioctl::SelectionTarget::*;
+    match (target, queue.is_output()) {
+        (Crop, false) | (Compose, true) => ioctl::g_crop(fd, queue),
+        (CropDefault, false) | (ComposeDefault, true) => {
+            Ok(ioctl::cropcap(fd, queue)?.default_rect)
+        }
+        (CropBounds, false) | (ComposeBounds, true) => Ok(ioctl::cropcap(fd, queue)?.bounds),
+        _ => Err(Error::Nix(nix::Error::Sys(Errno::ENOTTY))),
+    }
+}
+
+/// Set the legacy crop rectangle matching `target`, see `get_legacy_crop`.
+fn set_legacy_crop(
+    fd: &mut QueueBase,
+    queue: QueueType,
+    target: ioctl::SelectionTarget,
+    rect: ioctl::Rect,
+) -> Result<ioctl::Rect> {
+    use ioctl::SelectionTarget::*;
+    match (target, queue.is_output()) {
+        (Crop, false) | (Compose, true) => {
+            ioctl::s_crop(fd, queue, rect)?;
+            ioctl::g_crop(fd, queue)
+        }
+        _ => Err(Error::Nix(nix::Error::Sys(Errno::ENOTTY))),
+    }
 }
 
 /// Builder for a V4L2 format. This takes a mutable reference on the queue, so
@@ -141,18 +434,25 @@ impl<'a> FormatBuilder<'a> {
         self
     }
 
+    /// Set the field order of the frames, e.g. `Field::None` for progressive
+    /// frames or one of the interlaced orders.
+    pub fn set_field(mut self, field: ioctl::Field) -> Self {
+        self.format.field = field;
+        self
+    }
+
     /// Apply the format built so far. The kernel will adjust the format to fit
     /// the driver's capabilities if needed, and the format actually applied will
     /// be returned.
     pub fn apply(self) -> Result<Format> {
-        ioctl::s_fmt(self.queue, self.queue.type_, self.format)
+        self.queue.set_format(self.format)
     }
 
     /// Try to apply the format built so far. The kernel will adjust the format
     /// to fit the driver's capabilities if needed, so make sure to check important
     /// parameters after this call.
     pub fn try_apply(&mut self) -> Result<()> {
-        let new_format = ioctl::try_fmt(self.queue, self.queue.type_, self.format.clone())?;
+        let new_format = self.queue.try_format(self.format.clone())?;
 
         self.format = new_format;
         Ok(())
@@ -167,7 +467,7 @@ impl<D: Direction> Queue<D, QueueInit> {
     /// a REQBUFS(0) is issued on the device. If it is not successful, the device is
     /// deemed to not support this kind of queue and this method will fail.
     fn create(device: Arc<Mutex<Device>>, queue_type: QueueType) -> Result<Queue<D, QueueInit>> {
-        let mut device_lock = device.lock().unwrap();
+        let mut device_lock = lock(&device)?;
 
         if device_lock.used_queues.contains(&queue_type) {
             return Err(Error::AlreadyBorrowed);
@@ -184,13 +484,20 @@ impl<D: Direction> Queue<D, QueueInit> {
 
         drop(device_lock);
 
+        let mut inner = QueueBase {
+            device,
+            fd,
+            type_: queue_type,
+            capabilities,
+            plane_sizes: Vec::new(),
+        };
+        // Queues that do not carry pixel formats have no plane layout.
+        if let Ok(format) = ioctl::g_fmt::<Format, _>(&inner, queue_type) {
+            inner.update_plane_sizes(&format);
+        }
+
         Ok(Queue::<D, QueueInit> {
-            inner: QueueBase {
-                device,
-                fd,
-                type_: queue_type,
-                capabilities,
-            },
+            inner,
             _d: std::marker::PhantomData,
             state: QueueInit {},
         })
@@ -198,24 +505,48 @@ impl<D: Direction> Queue<D, QueueInit> {
 
     /// Allocate `count` buffers for this queue and make it transition to the
     /// `BuffersAllocated` state.
-    pub fn request_buffers<M: Memory>(
+    ///
+    /// `Error::UnsupportedMemoryType` is returned if the queue does not
+    /// support the memory type `M`.
+    pub fn request_buffers<M: Memory>(self, count: u32) -> Result<Queue<D, BuffersAllocated<M>>> {
+        self.try_request_buffers(count).map_err(|(error, _)| error)
+    }
+
+    /// Same as `request_buffers()`, but gives the queue back on failure.
+    fn try_request_buffers<M: Memory>(
         mut self,
         count: u32,
-    ) -> Result<Queue<D, BuffersAllocated<M>>> {
+    ) -> std::result::Result<Queue<D, BuffersAllocated<M>>, (Error, Self)> {
         let type_ = self.inner.type_;
-        let num_buffers: usize =
-            ioctl::reqbufs(&mut self.inner, type_, M::HandleType::MEMORY_TYPE, count)?;
+        let memory_type = M::HandleType::MEMORY_TYPE;
+        if !self.inner.capabilities.supports_memory(memory_type) {
+            return Err((Error::UnsupportedMemoryType, self));
+        }
+
+        let num_buffers: usize = match ioctl::reqbufs(&mut self.inner, type_, memory_type, count) {
+            Ok(num_buffers) => num_buffers,
+            Err(e) => return Err((setup_error(e, Error::UnsupportedMemoryType, false), self)),
+        };
 
         // The buffers have been allocated, now let's get their features.
-        let querybuf: ioctl::QueryBuffer = ioctl::querybuf(&self.inner, self.inner.type_, 0)?;
+        let querybuf: ioctl::QueryBuffer = match ioctl::querybuf(&self.inner, type_, 0) {
+            Ok(querybuf) => querybuf,
+            Err(e) => {
+                // Nothing can hold these buffers yet, so freeing them is
+                // expected to succeed.
+                let _ = ioctl::reqbufs::<(), _>(&mut self.inner, type_, memory_type, 0);
+                return Err((e, self));
+            }
+        };
 
         Ok(Queue {
             inner: self.inner,
             _d: std::marker::PhantomData,
             state: BuffersAllocated {
                 num_buffers,
-                buffers_state: Arc::new(Mutex::new(BuffersManager::new(num_buffers))),
+                buffers_state: Arc::new(BuffersManager::new(num_buffers)),
                 buffer_features: querybuf,
+                exported_buffers: Mutex::new(BTreeMap::new()),
             },
         })
     }
@@ -257,8 +588,93 @@ impl Queue<Capture, QueueInit> {
     ) -> Result<Queue<Capture, QueueInit>> {
         Queue::<Capture, QueueInit>::create(device, QueueType::VideoCaptureMplane)
     }
+
+    /// Allocate buffers for the format a decoder has found in the stream, and
+    /// start streaming. This is the first half of the handling of a
+    /// `SOURCE_CHANGE` event, when the queue has no buffers yet.
+    ///
+    /// The format is read back from the driver and returned, its `width` and
+    /// `height` being the new coded resolution. The number of buffers
+    /// allocated is the minimum required by the driver, as reported by the
+    /// `MIN_BUFFERS_FOR_CAPTURE` control, plus `extra_buffers`.
+    ///
+    /// The buffers still have to be queued for the decoder to use them. On
+    /// failure, the queue is returned along with the error.
+    // The queue is given back on error, so the error is as large as it.
+    #[allow(clippy::result_large_err)]
+    pub fn handle_source_change<M: Memory>(mut self, extra_buffers: u32) -> SourceChangeResult<M> {
+        let format = match self.get_format() {
+            Ok(format) => format,
+            Err(error) => return Err(SourceChangeError::Init { error, queue: self }),
+        };
+        self.inner.update_plane_sizes(&format);
+
+        let min_buffers = min_capture_buffers(&self.inner);
+        let queue = self
+            .try_request_buffers::<M>(min_buffers + extra_buffers)
+            .map_err(|(error, queue)| SourceChangeError::Init { error, queue })?;
+        if let Err(error) = queue.streamon() {
+            return Err(SourceChangeError::Allocated { error, queue });
+        }
+
+        Ok((queue, format))
+    }
+}
+
+/// Returns the minimum number of CAPTURE buffers required by the decoder
+/// `queue` belongs to for the current stream.
+fn min_capture_buffers(queue: &QueueBase) -> u32 {
+    // Not all drivers implement this control, in which case a single buffer
+    // is the minimum.
+    ioctl::g_ctrl(queue, CtrlId::MIN_BUFFERS_FOR_CAPTURE)
+        .unwrap_or(1)
+        .max(1) as u32
+}
+
+/// Error returned by `handle_source_change()`. The CAPTURE queue is given back
+/// along with the error, in the state the failure left it in.
+pub enum SourceChangeError<M: Memory> {
+    /// The queue still has buffers allocated: either the previous ones could
+    /// not be freed, or streaming could not be started with the new ones.
+    Allocated {
+        error: Error,
+        queue: Queue<Capture, BuffersAllocated<M>>,
+    },
+    /// The queue has no buffers allocated, as the new ones could not be.
+    Init {
+        error: Error,
+        queue: Queue<Capture, QueueInit>,
+    },
+}
+
+impl<M: Memory> SourceChangeError<M> {
+    /// Returns the error that caused the failure.
+    pub fn error(&self) -> &Error {
+        match self {
+            SourceChangeError::Allocated { error, .. } => error,
+            SourceChangeError::Init { error, .. } => error,
+        }
+    }
+}
+
+impl<M: Memory> Display for SourceChangeError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self.error(), f)
+    }
 }
 
+impl<M: Memory> Debug for SourceChangeError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(self.error(), f)
+    }
+}
+
+impl<M: Memory> std::error::Error for SourceChangeError<M> {}
+
+#[allow(type_alias_bounds)]
+pub type SourceChangeResult<M: Memory> =
+    std::result::Result<(Queue<Capture, BuffersAllocated<M>>, Format), SourceChangeError<M>>;
+
 /// Represents a queued buffer which has not been processed due to `streamoff` being
 /// called on the queue.
 pub struct CanceledBuffer<M: Memory> {
@@ -274,15 +690,138 @@ impl<D: Direction, M: Memory> Queue<D, BuffersAllocated<M>> {
         self.state.num_buffers
     }
 
+    /// Allocate `count` more buffers for this queue, sized for its current
+    /// format, and returns the indices of the new buffers. The driver may
+    /// create fewer buffers than requested.
+    ///
+    /// Unlike `request_buffers()`, this can be done while streaming, e.g. for
+    /// a decoder that needs more CAPTURE buffers after a resolution change.
+    pub fn add_buffers(&mut self, count: u32) -> Result<Range<usize>> {
+        let format = self.get_format()?;
+        self.add_buffers_with_format(count, format)
+    }
+
+    /// Same as `add_buffers()`, but the new buffers are sized for `format`,
+    /// which can differ from the current format of the queue.
+    ///
+    /// All the buffers of a queue must have the same number of planes, so
+    /// `Error::InvalidFormat` is returned if `format`, as adjusted by the
+    /// driver, has a different number of planes than the current buffers.
+    ///
+    /// If buffer wiping is enabled, the new buffers are wiped before they
+    /// can be used. Should they fail to be mapped for this, the buffers are
+    /// still added but will not be wiped, and the error is returned.
+    pub fn add_buffers_with_format(&mut self, count: u32, format: Format) -> Result<Range<usize>> {
+        let format = self.inner.try_format(format)?;
+        if format.plane_fmt.len() != self.state.buffer_features.planes.len() {
+            return Err(Error::InvalidFormat);
+        }
+
+        let type_ = self.inner.type_;
+        let memory_type = M::HandleType::MEMORY_TYPE;
+        let created = ioctl::create_bufs(&mut self.inner, type_, memory_type, count, format)?;
+        let indices = created.indices();
+
+        // The buffers exist from now on, so they are tracked no matter what.
+        let wiped = self.add_to_wiper(indices.clone());
+        self.state.buffers_state.add_buffers(indices.clone());
+        self.state.num_buffers = self.state.num_buffers.max(indices.end);
+        wiped?;
+
+        Ok(indices)
+    }
+
+    /// Map the buffers of `indices` for wiping and wipe them, if wiping is
+    /// enabled.
+    fn add_to_wiper(&self, indices: Range<usize>) -> Result<()> {
+        let buffers_state = &self.state.buffers_state;
+        let mut wiper = buffers_state
+            .wiper
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let wiper = match &mut *wiper {
+            Some(wiper) => wiper,
+            None => return Ok(()),
+        };
+
+        for index in indices {
+            wiper.add_buffer(index, self.map_buffer(index)?);
+            wiper.wipe(index);
+        }
+
+        Ok(())
+    }
+
+    /// Map all the buffers for wiping, and wipe the ones that are free.
+    fn enable_wipe(&self, wipe: BufferWipe) -> Result<()> {
+        let buffers_state = &self.state.buffers_state;
+        let num_buffers = buffers_state.num_buffers();
+        let mut wiper = BufferWiper::new(wipe);
+        for index in 0..num_buffers {
+            wiper.add_buffer(index, self.map_buffer(index)?);
+        }
+        *buffers_state
+            .wiper
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(wiper);
+        buffers_state.wipe_enabled.store(true, Ordering::Release);
+
+        // Take free buffers out of the free list while they are wiped, so
+        // they cannot be used in the meantime. The ones currently used are
+        // wiped once they are returned.
+        for index in 0..num_buffers {
+            if buffers_state.take_buffer(index) {
+                buffers_state.return_buffer(index);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stop wiping buffers and drop our mappings of them. Returns how they
+    /// were wiped, if they were.
+    fn disable_wipe(&self) -> Option<BufferWipe> {
+        let buffers_state = &self.state.buffers_state;
+        buffers_state.wipe_enabled.store(false, Ordering::Release);
+        buffers_state
+            .wiper
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .map(|wiper| wiper.wipe_mode())
+    }
+
+    /// Map all the planes of MMAP buffer `index`.
+    fn map_buffer(&self, index: usize) -> Result<Vec<ioctl::PlaneMapping>> {
+        let querybuf: ioctl::QueryBufferMMAP =
+            ioctl::querybuf(&self.inner, self.inner.type_, index)?;
+        querybuf
+            .planes
+            .iter()
+            .map(|plane| ioctl::mmap(&self.inner, plane.mem_offset, plane.length))
+            .collect()
+    }
+
     /// Returns the number of buffers currently queued (i.e. being processed
     /// by the device).
     pub fn num_queued_buffers(&self) -> usize {
-        self.state.buffers_state.lock().unwrap().num_queued_buffers
+        self.state
+            .buffers_state
+            .num_queued_buffers
+            .load(Ordering::Acquire)
     }
 
     pub fn streamon(&self) -> Result<()> {
         let type_ = self.inner.type_;
-        ioctl::streamon(&self.inner, type_)
+        ioctl::streamon(&self.inner, type_)?;
+
+        // Streaming has been started explicitly, so a deferred streamon is not
+        // relevant anymore.
+        let buffers_state = &self.state.buffers_state;
+        buffers_state.deferred_streamon.store(0, Ordering::Release);
+        buffers_state.streaming.store(true, Ordering::Release);
+
+        Ok(())
     }
 
     /// Stop streaming on this queue.
@@ -294,38 +833,189 @@ impl<D: Direction, M: Memory> Queue<D, BuffersAllocated<M>> {
         let type_ = self.inner.type_;
         ioctl::streamoff(&self.inner, type_)?;
 
-        let mut buffers_state = self.state.buffers_state.lock().unwrap();
+        let buffers_state = &self.state.buffers_state;
+        buffers_state.deferred_streamon.store(0, Ordering::Release);
+        buffers_state.streaming.store(false, Ordering::Release);
+        buffers_state.paused.store(false, Ordering::Release);
 
-        let canceled_buffers: Vec<_> = buffers_state.buffers_state
-            .iter_mut()
-            .enumerate()
-            .filter_map(|(i, state)| {
+        let canceled_buffers = buffers_state
+            .buffers
+            .iter()
+            .filter_map(|(i, buffer)| {
+                let mut plane_handles = buffers_state.lock_buffer_ignore_poison(buffer);
                 // Filter entries not in queued state.
-                match *state {
-                    BufferState::Queued(_) => (),
-                    _ => return None,
-                };
-
-                // Set entry to Free state and steal its handles.
-                let old_state = std::mem::replace(state, BufferState::Free);
+                if buffer.state() != BufferState::Queued {
+                    return None;
+                }
+
+                // Steal the handles of the entry and set it to Free state.
+                let plane_handles = plane_handles.take().unwrap_or_default();
+                buffers_state
+                    .num_queued_buffers
+                    .fetch_sub(1, Ordering::AcqRel);
+                buffers_state.return_buffer(i);
                 Some(CanceledBuffer::<M> {
                     index: i as u32,
-                    plane_handles: match old_state {
-                        // We have already tested for this state above, so this
-                        // branch is guaranteed.
-                        BufferState::Queued(plane_handles) => plane_handles,
-                        _ => unreachable!("Inconsistent buffer state!"),
-                    },
+                    plane_handles,
                 })
             })
             .collect();
 
-        buffers_state.num_queued_buffers -= canceled_buffers.len();
-        for buffer in &canceled_buffers {
-            buffers_state.allocator.return_buffer(buffer.index as usize);
+        Ok(canceled_buffers)
+    }
+
+    /// Take back buffer `index`, which has been queued along with a request
+    /// that could not be queued itself. Once such a request is reinitialized,
+    /// the driver releases the buffer without it ever being dequeued, so this
+    /// makes it free again and returns the plane handles it was queued with.
+    ///
+    /// `Error::InvalidBuffer` is returned if the buffer is not queued.
+    pub fn cancel_buffer(&self, index: usize) -> Result<CanceledBuffer<M>> {
+        let buffers_state = &self.state.buffers_state;
+        let buffer = match buffers_state.buffers.get(index) {
+            Some(buffer) => buffer,
+            None => return Err(Error::InvalidBuffer),
+        };
+        let mut plane_handles = buffers_state.lock_buffer(buffer)?;
+        if buffer.state() != BufferState::Queued {
+            return Err(Error::InvalidBuffer);
         }
+        let plane_handles = plane_handles.take().unwrap_or_default();
+        buffers_state
+            .num_queued_buffers
+            .fetch_sub(1, Ordering::AcqRel);
+        buffers_state.return_buffer(index);
+
+        Ok(CanceledBuffer {
+            index: index as u32,
+            plane_handles,
+        })
+    }
 
-        Ok(canceled_buffers)
+    /// Register `callback` to be invoked when the number of free buffers of
+    /// this queue drops to `low_free_buffers` or below, or when the queue runs
+    /// out of queued buffers while streaming. See `WatermarkEvent` for
+    /// details.
+    ///
+    /// The callback is invoked from the thread that triggered the condition,
+    /// i.e. the one obtaining or dequeuing a buffer. It can use the queue, but
+    /// should return quickly. This replaces any previously registered
+    /// callback.
+    pub fn set_watermark_callback<F>(&self, low_free_buffers: usize, callback: F)
+    where
+        F: Fn(WatermarkEvent) + Send + Sync + 'static,
+    {
+        self.state
+            .buffers_state
+            .set_watermarks(Some(Watermarks::new(low_free_buffers, Arc::new(callback))));
+    }
+
+    /// Remove the watermark callback of this queue, if any.
+    pub fn clear_watermark_callback(&self) {
+        self.state.buffers_state.set_watermarks(None);
+    }
+
+    /// Returns whether the queue is currently streaming.
+    pub fn is_streaming(&self) -> bool {
+        self.state.buffers_state.streaming.load(Ordering::Acquire)
+    }
+
+    /// Pause the queue: `dequeue()` fails with `Error::Paused` until
+    /// `resume()` is called, while buffers remain allocated and queued ones
+    /// stay with the driver. Buffers can still be queued while paused.
+    ///
+    /// Decoders and encoders that support it are sent the `PAUSE` command,
+    /// which pauses the whole device. Other devices are not told to stop
+    /// processing: a capture device keeps filling the queued buffers until it
+    /// runs out of them, and resumes producing frames as soon as they are
+    /// dequeued again. `streamoff()` clears the paused state.
+    pub fn pause(&self) -> Result<()> {
+        self.codec_cmd(
+            ioctl::DecoderCommand::Pause { to_black: false },
+            ioctl::EncoderCommand::Pause,
+        )?;
+        self.state
+            .buffers_state
+            .paused
+            .store(true, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Resume a queue paused with `pause()`, sending the `RESUME` command to
+    /// decoders and encoders that support it.
+    pub fn resume(&self) -> Result<()> {
+        self.codec_cmd(ioctl::DecoderCommand::Resume, ioctl::EncoderCommand::Resume)?;
+        self.state
+            .buffers_state
+            .paused
+            .store(false, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Send `decoder` or `encoder` to the device, depending on which kind of
+    /// commands it supports. Nothing is sent to devices that support neither,
+    /// or not this specific command.
+    fn codec_cmd(
+        &self,
+        decoder: ioctl::DecoderCommand,
+        encoder: ioctl::EncoderCommand,
+    ) -> Result<()> {
+        fn unsupported(error: &Error) -> bool {
+            matches!(
+                error,
+                Error::Nix(nix::Error::Sys(Errno::ENOTTY))
+                    | Error::Nix(nix::Error::Sys(Errno::EINVAL))
+            )
+        }
+
+        match ioctl::decoder_cmd(&self.inner, decoder) {
+            Err(e) if unsupported(&e) => (),
+            result => return result,
+        }
+        match ioctl::encoder_cmd(&self.inner, encoder) {
+            Err(e) if unsupported(&e) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Returns whether a thread panicked while updating the state of this
+    /// queue. Operations that depend on the state of buffers fail with
+    /// `Error::Poisoned` on such a queue, and the only way to recover is to
+    /// drop it and obtain it again from the device. Methods that cannot fail
+    /// keep working.
+    pub fn is_poisoned(&self) -> bool {
+        self.state.buffers_state.is_poisoned()
+    }
+
+    /// Returns whether the queue is currently paused.
+    pub fn is_paused(&self) -> bool {
+        self.state.buffers_state.paused.load(Ordering::Acquire)
+    }
+
+    /// Returns a snapshot of what we know about the state of the queue and
+    /// its buffers. Useful for logging or bug reports.
+    ///
+    /// If the current format cannot be obtained from the driver, the
+    /// `format` member of the snapshot is set to `None`.
+    pub fn dump_state(&self) -> QueueStateDump {
+        let format = self.get_format().ok();
+        let buffers_state = &self.state.buffers_state;
+
+        QueueStateDump {
+            type_: self.inner.type_,
+            memory: M::HandleType::MEMORY_TYPE,
+            streaming: buffers_state.streaming.load(Ordering::Acquire),
+            pending_streamon: buffers_state.pending_streamon(),
+            num_queued_buffers: buffers_state.num_queued_buffers.load(Ordering::Acquire),
+            buffers: buffers_state
+                .buffers
+                .iter()
+                .map(|(_, buffer)| buffer.state().dump())
+                .collect(),
+            format,
+        }
     }
 
     pub fn query_buffer(&self, id: usize) -> Result<ioctl::QueryBuffer> {
@@ -336,53 +1026,62 @@ impl<D: Direction, M: Memory> Queue<D, BuffersAllocated<M>> {
     // When we get a WRBuffer, can't we have it pre-filled with the right number of planes,
     // etc from QUERY_BUF?
     pub fn get_buffer<'a>(&'a self, index: usize) -> Result<QBuffer<'a, D, M>> {
-        let mut buffers_state = self.state.buffers_state.lock().unwrap();
-        let buffer_state = &mut buffers_state.buffers_state[index];
-
-        match buffer_state {
-            BufferState::Free => (),
-            _ => return Err(Error::AlreadyBorrowed),
-        };
-
-        let num_planes = self.state.buffer_features.planes.len();
-
-        // The buffer will remain in PreQueue state until it is queued
-        // or the reference to it is lost.
-        *buffer_state = BufferState::PreQueue;
-        drop(buffer_state);
-
-        buffers_state.allocator.take_buffer(index);
-        drop(buffers_state);
+        let buffers_state = &self.state.buffers_state;
+        if buffers_state.is_poisoned() {
+            return Err(Error::Poisoned);
+        } else if index >= buffers_state.num_buffers() {
+            return Err(Error::InvalidBuffer);
+        }
 
-        let fuse = BufferStateFuse::new(Arc::downgrade(&self.state.buffers_state), index);
+        if !buffers_state.take_buffer(index) {
+            return Err(Error::AlreadyBorrowed);
+        }
 
-        Ok(QBuffer::new(self, index, num_planes, fuse))
+        self.prepare_buffer(index)
     }
 
     pub fn get_free_buffer<'a>(&'a self) -> Result<QBuffer<'a, D, M>> {
-        let mut buffers_state = self.state.buffers_state.lock().unwrap();
-        let index = match buffers_state.allocator.get_free_buffer() {
+        let buffers_state = &self.state.buffers_state;
+        if buffers_state.is_poisoned() {
+            return Err(Error::Poisoned);
+        }
+
+        let index = match buffers_state.take_free_buffer() {
             Some(index) => index,
             None => return Err(Error::AlreadyBorrowed),
         };
 
-        let buffer_state = &mut buffers_state.buffers_state[index];
-        match buffer_state {
-            BufferState::Free => (),
-            _ => panic!("Inconsistent buffer state!"),
-        }
+        self.prepare_buffer(index)
+    }
 
-        let num_planes = self.state.buffer_features.planes.len();
+    /// Wrap buffer `index`, which has just been taken from the free list and
+    /// is now in the `PreQueue` state, along with its template, and invoke
+    /// the watermark callback if needed.
+    fn prepare_buffer(&self, index: usize) -> Result<QBuffer<'_, D, M>> {
+        // The buffer will remain in PreQueue state until it is queued or the
+        // reference to it is lost.
+        let fuse = BufferStateFuse::new(Arc::downgrade(&self.state.buffers_state), index);
 
-        // The buffer remains will remain in PreQueue state until it is queued
-        // or the reference to it is lost.
-        *buffer_state = BufferState::PreQueue;
-        drop(buffer_state);
-        drop(buffers_state);
+        let num_planes = self.state.buffer_features.planes.len();
+        // The template is only missing the first time the buffer is used, or
+        // if the last `QBuffer` using it has been dropped without queueing.
+        let template = match self.state.buffers_state.buffers[index].take_template() {
+            Some(mut template) => {
+                template.clear();
+                template
+            }
+            None => Box::new(ioctl::QBufTemplate::new(
+                self.inner.type_,
+                index,
+                num_planes,
+            )?),
+        };
 
-        let fuse = BufferStateFuse::new(Arc::downgrade(&self.state.buffers_state), index);
+        if let Some((callback, event)) = self.state.buffers_state.free_buffers_changed() {
+            callback(event);
+        }
 
-        Ok(QBuffer::new(self, index, num_planes, fuse))
+        Ok(QBuffer::new(self, index, num_planes, template, fuse))
     }
 
     /// Dequeue the next processed buffer and return it.
@@ -392,29 +1091,249 @@ impl<D: Direction, M: Memory> Queue<D, BuffersAllocated<M>> {
     /// be moved into a `Rc` or `Arc` if you need to pass it to several clients.
     ///
     /// The data in the `DQBuffer` is read-only.
+    ///
+    /// If the `Requeue` empty buffer policy is set on the queue, buffers
+    /// without data are given back to the driver and the next buffer is
+    /// dequeued instead.
+    ///
+    /// `Error::Paused` is returned if the queue has been paused, and
+    /// `Error::InvalidBuffer` if the driver returned a buffer that was not
+    /// queued.
     pub fn dequeue(&self) -> Result<DQBuffer<M>> {
-        let dqbuf: ioctl::DQBuffer = ioctl::dqbuf(&self.inner, self.inner.type_)?;
-        let id = dqbuf.index as usize;
+        self.dequeue_with(|| ioctl::dqbuf(&self.inner, self.inner.type_))
+    }
+
+    /// Same as `dequeue()`, but uses `plane_data` to receive the planes of
+    /// multi-planar buffers. Reusing the same array for every call avoids
+    /// initializing a new one per frame.
+    pub fn dequeue_with_planes(&self, plane_data: &mut ioctl::PlaneData) -> Result<DQBuffer<M>> {
+        self.dequeue_with(|| ioctl::dqbuf_with_planes(&self.inner, self.inner.type_, plane_data))
+    }
+
+    /// Implementation of `dequeue()`, using `dequeue_one` to dequeue buffers
+    /// from the driver.
+    fn dequeue_with<F>(&self, mut dequeue_one: F) -> Result<DQBuffer<M>>
+    where
+        F: FnMut() -> Result<ioctl::DQBuffer>,
+    {
+        if self.is_poisoned() {
+            return Err(Error::Poisoned);
+        } else if self.is_paused() {
+            return Err(Error::Paused);
+        }
 
-        let mut buffers_state = self.state.buffers_state.lock().unwrap();
-        let buffer_state = &mut buffers_state.buffers_state[id];
+        let buffers_state = &self.state.buffers_state;
+        let (dqbuf, plane_handles) = loop {
+            let dqbuf = dequeue_one()?;
+            let id = dqbuf.index as usize;
+            // Only look up the policy for the buffers it applies to.
+            let requeue_empty =
+                match dqbuf.is_empty() && buffers_state.requeue_empty.load(Ordering::Acquire) {
+                    true => buffers_state.requeue_fn.get().copied(),
+                    false => None,
+                };
+
+            let buffer = match buffers_state.buffers.get(id) {
+                Some(buffer) => buffer,
+                None => return Err(Error::InvalidBuffer),
+            };
+            let mut buffer_handles = buffers_state.lock_buffer(buffer)?;
+            if buffer.state() != BufferState::Queued {
+                return Err(Error::InvalidBuffer);
+            }
 
-        // The buffer will remain Dequeued until our reference to it is destroyed.
-        let state = std::mem::replace(buffer_state, BufferState::Dequeued);
-        let plane_handles = match state {
-            BufferState::Queued(plane_handles) => plane_handles,
-            _ => unreachable!("Inconsistent buffer state"),
+            // The buffer will remain Dequeued until our reference to it is destroyed.
+            buffer.set_state(BufferState::Dequeued);
+            let plane_handles = buffer_handles.take().unwrap_or_default();
+
+            let requeue = match requeue_empty {
+                Some(requeue) => requeue,
+                None => break (dqbuf, plane_handles),
+            };
+            match requeue(&self.inner, id, plane_handles) {
+                Ok(plane_handles) => {
+                    *buffer_handles = Some(plane_handles);
+                    buffer.set_state(BufferState::Queued);
+                }
+                // If we cannot queue the buffer again, just return it.
+                Err(plane_handles) => break (dqbuf, plane_handles),
+            }
         };
+        let id = dqbuf.index as usize;
         let fuse = BufferStateFuse::new(Arc::downgrade(&self.state.buffers_state), id);
 
-        buffers_state.num_queued_buffers -= 1;
+        let num_queued_buffers = buffers_state
+            .num_queued_buffers
+            .fetch_sub(1, Ordering::AcqRel)
+            - 1;
+        let underrun = if num_queued_buffers == 0 && buffers_state.streaming.load(Ordering::Acquire)
+        {
+            buffers_state.underrun()
+        } else {
+            None
+        };
+
+        if let Some((callback, event)) = underrun {
+            callback(event);
+        }
 
         Ok(DQBuffer::new(plane_handles, dqbuf, fuse))
     }
 
-    pub fn free_buffers(mut self) -> Result<Queue<D, QueueInit>> {
+    /// Wait at most `timeout` for a buffer to be ready, and return whether
+    /// `dequeue()` can be called without blocking. `Error::Woken` is returned
+    /// if `waker` is woken up first.
+    fn wait_for_buffer(&self, timeout: Option<Duration>, waker: Option<&Waker>) -> Result<bool> {
+        let ready = if self.inner.type_.is_output() {
+            PollEvents::OUTPUT_READY
+        } else {
+            PollEvents::CAPTURE_READY
+        };
+        let events = match waker {
+            Some(waker) => poll_device_with_waker(&self.inner, ready, waker, timeout)?,
+            None => poll_device(&self.inner, ready, timeout)?,
+        };
+        if events.contains(PollEvents::WOKEN) {
+            return Err(Error::Woken);
+        }
+
+        // ERROR is also reported while no buffer is queued, in which case
+        // DQBUF would block. Otherwise it means the queue is in error or not
+        // streaming, and DQBUF will return the corresponding error.
+        Ok(events.contains(ready)
+            || (events.contains(PollEvents::ERROR) && self.num_queued_buffers() > 0))
+    }
+
+    /// Same as `dequeue()`, but waits at most `timeout` for a buffer to be
+    /// ready instead of blocking until the driver returns one.
+    ///
+    /// `Error::Timeout` is returned if no buffer became available in time,
+    /// which happens immediately if no buffer is queued.
+    pub fn dequeue_timeout(&self, timeout: Duration) -> Result<DQBuffer<M>> {
+        if self.is_poisoned() {
+            return Err(Error::Poisoned);
+        } else if self.is_paused() {
+            return Err(Error::Paused);
+        }
+
+        if !self.wait_for_buffer(Some(timeout), None)? {
+            return Err(Error::Timeout);
+        }
+        self.dequeue()
+    }
+
+    /// Same as `dequeue()`, but returns `Error::NotReady` instead of blocking
+    /// if no buffer is ready yet, so event loops can tell this case apart
+    /// from actual failures. This works whether or not the device has been
+    /// opened with `O_NONBLOCK`.
+    pub fn try_dequeue(&self) -> Result<DQBuffer<M>> {
+        if self.is_poisoned() {
+            return Err(Error::Poisoned);
+        } else if self.is_paused() {
+            return Err(Error::Paused);
+        }
+
+        if !self.wait_for_buffer(Some(Duration::from_secs(0)), None)? {
+            return Err(Error::NotReady);
+        }
+        self.dequeue().map_err(|e| match e {
+            Error::Nix(nix::Error::Sys(Errno::EAGAIN)) => Error::NotReady,
+            e => e,
+        })
+    }
+
+    /// Same as `dequeue()`, but returns `Error::Woken` if `waker` is woken up
+    /// before a buffer is ready, so another thread can interrupt the wait.
+    ///
+    /// `Error::NotReady` is returned if no buffer is queued, since none can
+    /// become ready while we wait.
+    pub fn dequeue_or_wake(&self, waker: &Waker) -> Result<DQBuffer<M>> {
+        if self.is_poisoned() {
+            return Err(Error::Poisoned);
+        } else if self.is_paused() {
+            return Err(Error::Paused);
+        }
+
+        if !self.wait_for_buffer(None, Some(waker))? {
+            return Err(Error::NotReady);
+        }
+        self.dequeue()
+    }
+
+    /// Dequeue all the buffers that are ready without blocking, in the order
+    /// the driver returns them. The returned `Vec` is empty if no buffer is
+    /// ready.
+    ///
+    /// If the device has been opened with `O_NONBLOCK` (see
+    /// `DeviceConfig::non_blocking_dqbuf()`), this only costs one DQBUF per
+    /// buffer plus the one failing with `EAGAIN`, instead of a poll and a
+    /// DQBUF per buffer. This helps with devices returning many buffers at
+    /// once. Otherwise the device is polled before each DQBUF so we never
+    /// block.
+    ///
+    /// Dequeueing stops after a buffer with the `LAST` flag. If an error
+    /// occurs after some buffers have been dequeued, these buffers are
+    /// returned and the error is dropped. Persistent errors, e.g. the queue
+    /// not streaming anymore, are then reported by the next call.
+    pub fn dequeue_all_ready(&self) -> Result<Vec<DQBuffer<M>>> {
+        let flags = OFlag::from_bits_truncate(fcntl(self.inner.fd, FcntlArg::F_GETFL)?);
+        let non_blocking = flags.contains(OFlag::O_NONBLOCK);
+
+        let mut buffers = Vec::new();
+        while self.num_queued_buffers() > 0 {
+            let result = if non_blocking {
+                self.dequeue()
+            } else {
+                self.try_dequeue()
+            };
+            match result {
+                Ok(buffer) => {
+                    let is_last = buffer.data.flags.contains(ioctl::BufferFlags::LAST);
+                    buffers.push(buffer);
+                    if is_last {
+                        break;
+                    }
+                }
+                Err(Error::NotReady) | Err(Error::Nix(nix::Error::Sys(Errno::EAGAIN))) => break,
+                Err(_) if !buffers.is_empty() => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(buffers)
+    }
+
+    /// Free all the buffers of this queue and make it transition back to the
+    /// `QueueInit` state.
+    ///
+    /// This fails with `Error::Busy` if the queue is still streaming, or if
+    /// some MMAP buffers are still mapped.
+    pub fn free_buffers(self) -> Result<Queue<D, QueueInit>> {
+        self.try_free_buffers().map_err(|(error, _)| error)
+    }
+
+    /// Same as `free_buffers()`, but gives the queue back on failure.
+    // The queue is given back on error, so the error is as large as it.
+    #[allow(clippy::result_large_err)]
+    fn try_free_buffers(mut self) -> std::result::Result<Queue<D, QueueInit>, (Error, Self)> {
+        if self.is_streaming() {
+            return Err((Error::Busy { streaming: true }, self));
+        }
+
+        // Our own mappings would keep the buffers from being freed.
+        let wipe = self.disable_wipe();
+
         let type_ = self.inner.type_;
-        ioctl::reqbufs(&mut self.inner, type_, M::HandleType::MEMORY_TYPE, 0)?;
+        if let Err(e) =
+            ioctl::reqbufs::<(), _>(&mut self.inner, type_, M::HandleType::MEMORY_TYPE, 0)
+        {
+            // The buffers are still there, and may have been returned without
+            // being wiped in the meantime.
+            if let Some(wipe) = wipe {
+                let _ = self.enable_wipe(wipe);
+            }
+            return Err((setup_error(e, Error::UnsupportedMemoryType, false), self));
+        }
 
         Ok(Queue {
             inner: self.inner,
@@ -424,18 +1343,424 @@ impl<D: Direction, M: Memory> Queue<D, BuffersAllocated<M>> {
     }
 }
 
+impl<D: Direction> Queue<D, BuffersAllocated<MMAP>> {
+    /// Query the length and mmap offset of each plane of MMAP buffer `id`,
+    /// which works the same for single-planar and multi-planar queues.
+    pub fn query_mmap_buffer(&self, id: usize) -> Result<ioctl::QueryBufferMMAP> {
+        ioctl::querybuf(&self.inner, self.inner.type_, id)
+    }
+
+    /// Map plane `plane` of MMAP buffer `id` into memory, so its content can be
+    /// read after it is dequeued or written before it is queued.
+    pub fn map_plane(&self, id: usize, plane: usize) -> Result<ioctl::PlaneMapping> {
+        let querybuf = self.query_mmap_buffer(id)?;
+        let plane = querybuf.planes.get(plane).ok_or(Error::InvalidBuffer)?;
+
+        ioctl::mmap(&self.inner, plane.mem_offset, plane.length)
+    }
+
+    /// Export plane `plane` of MMAP buffer `id` as a DMABUF, e.g. to share it
+    /// with a display or GPU. The returned object records the access mode
+    /// requested in `flags`.
+    pub fn export_buffer(
+        &self,
+        id: usize,
+        plane: usize,
+        flags: ioctl::ExportFlags,
+    ) -> Result<ioctl::ExportedBuffer> {
+        ioctl::expbuf(&self.inner, self.inner.type_, id, plane, flags)
+    }
+
+    /// Export all the planes of MMAP buffer `id` as DMABUFs, e.g. to hand
+    /// captured frames to another device without copying them.
+    ///
+    /// The exported DMABUFs are kept by the queue, so calling this method
+    /// again for the same buffer returns the same DMABUFs, no matter how many
+    /// times the buffer has been queued in between. This lets importers
+    /// recognize the buffers they already know. Buffers are exported again
+    /// if `flags` differ from the ones used previously. The queue drops its
+    /// references when its buffers are freed, but the DMABUFs remain valid
+    /// for as long as someone holds them.
+    pub fn export_dmabufs(
+        &self,
+        id: usize,
+        flags: ioctl::ExportFlags,
+    ) -> Result<Vec<Arc<ioctl::ExportedBuffer>>> {
+        if id >= self.state.num_buffers {
+            return Err(Error::InvalidBuffer);
+        }
+
+        let mut exported_buffers = lock(&self.state.exported_buffers)?;
+        if let Some(exported) = exported_buffers.get(&id) {
+            if exported.iter().all(|buffer| buffer.flags == flags) {
+                return Ok(exported.clone());
+            }
+        }
+
+        let exported = (0..self.state.buffer_features.planes.len())
+            .map(|plane| self.export_buffer(id, plane, flags).map(Arc::new))
+            .collect::<Result<Vec<_>>>()?;
+        exported_buffers.insert(id, exported.clone());
+
+        Ok(exported)
+    }
+
+    /// Export plane `plane` of MMAP buffer `id` as a DMABUF, which is kept by
+    /// the queue like the ones returned by `export_dmabufs()`.
+    pub fn export_dmabuf(
+        &self,
+        id: usize,
+        plane: usize,
+        flags: ioctl::ExportFlags,
+    ) -> Result<Arc<ioctl::ExportedBuffer>> {
+        self.export_dmabufs(id, flags)?
+            .into_iter()
+            .nth(plane)
+            .ok_or(Error::InvalidBuffer)
+    }
+}
+
+impl<D: Direction> Queue<D, BuffersAllocated<MMAP>> {
+    /// Fill the memory of the buffers of this queue according to `wipe`
+    /// every time they return to the free list, i.e. when a `DQBuffer` or a
+    /// `QBuffer` that has not been queued is dropped, or when the queue is
+    /// streamed off. This keeps the content of a frame from leaking to the
+    /// next user of its buffer, e.g. in privacy-sensitive contexts. Wiping
+    /// is stopped if `wipe` is `None`.
+    ///
+    /// The buffers that are free are wiped right away, so enabling wiping
+    /// right after the buffers are allocated covers them from the start.
+    /// Buffers added later with `add_buffers()` are wiped as well.
+    ///
+    /// The queue keeps all its buffers mapped while wiping is enabled, and
+    /// stops wiping them when they are freed.
+    pub fn set_buffer_wipe(&self, wipe: Option<BufferWipe>) -> Result<()> {
+        self.disable_wipe();
+        match wipe {
+            Some(wipe) => self.enable_wipe(wipe),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Queue<Capture, BuffersAllocated<MMAP>> {
+    /// Queue all the free buffers, so the driver can fill them, and return
+    /// how many were queued. Stops once no free buffer is left, and fails on
+    /// any other error.
+    pub fn queue_free_buffers(&self) -> Result<usize> {
+        let mut queued = 0;
+        loop {
+            let buffer = match self.get_free_buffer() {
+                Ok(buffer) => buffer,
+                Err(Error::AlreadyBorrowed) => return Ok(queued),
+                Err(e) => return Err(e),
+            };
+            buffer.auto_queue().map_err(|e| e.error)?;
+            queued += 1;
+        }
+    }
+}
+
+impl<M: Memory> Queue<Capture, BuffersAllocated<M>> {
+    /// Start streaming as soon as at least `min_queued_buffers` buffers are
+    /// queued, instead of right now.
+    ///
+    /// Many drivers cannot start producing frames until a given number of
+    /// CAPTURE buffers are available, and some will stall if streaming is
+    /// started with none. With this method the `streamon` is performed by
+    /// `QBuffer::queue()` once the required number of buffers is reached, or
+    /// immediately if enough buffers are already queued.
+    ///
+    /// `min_queued_buffers` is capped to the number of allocated buffers. A
+    /// pending deferred streamon is cancelled by `streamon()` and `streamoff()`.
+    pub fn streamon_deferred(&self, min_queued_buffers: usize) -> Result<()> {
+        let min_queued_buffers = min_queued_buffers.min(self.state.num_buffers);
+        let buffers_state = &self.state.buffers_state;
+
+        if buffers_state.num_queued_buffers.load(Ordering::Acquire) >= min_queued_buffers {
+            return self.streamon();
+        }
+
+        buffers_state
+            .deferred_streamon
+            .store(min_queued_buffers, Ordering::Release);
+        // Buffers may have been queued by another thread since we checked.
+        if buffers_state.trigger_deferred_streamon(min_queued_buffers) {
+            self.streamon()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of buffers that still need to be queued before a
+    /// deferred streamon takes place, or `None` if no streamon is pending.
+    pub fn pending_streamon(&self) -> Option<usize> {
+        self.state.buffers_state.pending_streamon()
+    }
+
+    /// React to a `SOURCE_CHANGE` event signaling a resolution change, once
+    /// the buffer with the `LAST` flag has been dequeued: stop streaming,
+    /// make sure the buffers fit the new format of the stream, and stream
+    /// again. See the `QueueInit` version of this method for the details, and
+    /// for the returned format.
+    ///
+    /// If the current buffers are numerous and large enough for the new
+    /// format, they are kept and only the plane sizes of the queue are
+    /// updated, which makes e.g. a change to a lower resolution quicker.
+    /// Otherwise they are freed, and new ones allocated.
+    ///
+    /// Freeing fails with `Error::Busy` if some MMAP buffers are still mapped,
+    /// e.g. because `DQBuffer`s of the previous resolution are still held, in
+    /// which case the queue is given back with its buffers so the operation
+    /// can be retried once they are released. Drivers that support it also
+    /// allow to keep the current buffers and add larger ones with
+    /// `add_buffers()` instead, but reallocating is the sequence all stateful
+    /// decoders support.
+    // The queue is given back on error, so the error is as large as it.
+    #[allow(clippy::result_large_err)]
+    pub fn handle_source_change(mut self, extra_buffers: u32) -> SourceChangeResult<M> {
+        if let Err(error) = self.streamoff() {
+            return Err(SourceChangeError::Allocated { error, queue: self });
+        }
+
+        match self.fitting_format(extra_buffers) {
+            Ok(Some(format)) => {
+                self.inner.update_plane_sizes(&format);
+                if let Err(error) = self.streamon() {
+                    return Err(SourceChangeError::Allocated { error, queue: self });
+                }
+                return Ok((self, format));
+            }
+            Ok(None) => (),
+            Err(error) => return Err(SourceChangeError::Allocated { error, queue: self }),
+        }
+
+        self.try_free_buffers()
+            .map_err(|(error, queue)| SourceChangeError::Allocated { error, queue })?
+            .handle_source_change(extra_buffers)
+    }
+
+    /// Returns the new format of the stream if the current buffers of this
+    /// queue can be used for it, i.e. if there are at least as many of them
+    /// as `handle_source_change()` would allocate and all their planes are
+    /// large enough.
+    fn fitting_format(&self, extra_buffers: u32) -> Result<Option<Format>> {
+        let format = self.get_format()?;
+        if self.num_buffers() < (min_capture_buffers(&self.inner) + extra_buffers) as usize {
+            return Ok(None);
+        }
+
+        for index in 0..self.num_buffers() {
+            let querybuf: ioctl::QueryBuffer =
+                ioctl::querybuf(&self.inner, self.inner.type_, index)?;
+            if querybuf.planes.len() != format.plane_fmt.len() {
+                return Ok(None);
+            }
+            let too_small = querybuf
+                .planes
+                .iter()
+                .zip(format.plane_fmt.iter())
+                .any(|(plane, plane_fmt)| plane.length < plane_fmt.sizeimage);
+            if too_small {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(format))
+    }
+
+    /// Drain the memory-to-memory device this CAPTURE queue belongs to: send
+    /// it the STOP command for `device`, and dequeue buffers until the one
+    /// with the `LAST` flag is returned.
+    ///
+    /// `timeout` is the longest time to wait for each buffer, or `None` to
+    /// wait as long as needed. The OUTPUT queue must keep being serviced
+    /// meanwhile (e.g. from another thread), or the device may never finish
+    /// processing its pending data.
+    ///
+    /// The dequeued buffers are kept until the drain completes, so the
+    /// driver may run out of CAPTURE buffers before it can return the last
+    /// one. The drain then stops with `DrainStatus::OutOfBuffers`, and can be
+    /// resumed with `finish_drain()` once buffers have been queued again. The
+    /// same goes for `DrainStatus::TimedOut`.
+    ///
+    /// Once drained, the device can be restarted with the START command or by
+    /// a `streamoff()`/`streamon()` of this queue.
+    pub fn drain(&self, device: M2mDevice, timeout: Option<Duration>) -> Result<DrainStatus<M>> {
+        if !self.is_streaming() {
+            return Ok(DrainStatus::NotStreaming);
+        }
+
+        match device {
+            M2mDevice::Decoder => ioctl::decoder_cmd(
+                &self.inner,
+                ioctl::DecoderCommand::Stop {
+                    to_black: false,
+                    immediately: false,
+                },
+            )?,
+            M2mDevice::Encoder => ioctl::encoder_cmd(
+                &self.inner,
+                ioctl::EncoderCommand::Stop { at_gop_end: false },
+            )?,
+        }
+
+        self.finish_drain(timeout)
+    }
+
+    /// Keep dequeuing the buffers of a drain started by `drain()` that
+    /// stopped before the buffer with the `LAST` flag, e.g. after buffers
+    /// have been queued again following `DrainStatus::OutOfBuffers`. The
+    /// STOP command is not sent again, since devices refuse it while they
+    /// are draining.
+    pub fn finish_drain(&self, timeout: Option<Duration>) -> Result<DrainStatus<M>> {
+        if !self.is_streaming() {
+            return Ok(DrainStatus::NotStreaming);
+        }
+
+        let mut buffers = Vec::new();
+        loop {
+            // Without queued buffers, only a last buffer that has already
+            // been dequeued can be reported, so do not wait for it.
+            let out_of_buffers = self.num_queued_buffers() == 0;
+            let wait = match out_of_buffers {
+                true => Some(Duration::from_secs(0)),
+                false => timeout,
+            };
+            if !self.wait_for_buffer(wait, None)? {
+                return Ok(match out_of_buffers {
+                    true => DrainStatus::OutOfBuffers(buffers),
+                    false => DrainStatus::TimedOut(buffers),
+                });
+            }
+
+            match self.dequeue() {
+                Ok(buffer) => {
+                    let is_last = buffer.data.flags.contains(ioctl::BufferFlags::LAST);
+                    buffers.push(buffer);
+                    if is_last {
+                        return Ok(DrainStatus::Completed(buffers));
+                    }
+                }
+                // Another thread dequeued the buffer first.
+                Err(Error::Nix(nix::Error::Sys(Errno::EAGAIN))) => (),
+                // The last buffer had already been dequeued.
+                Err(Error::Nix(nix::Error::Sys(Errno::EPIPE))) if buffers.is_empty() => {
+                    return Ok(DrainStatus::AlreadyDrained)
+                }
+                Err(Error::Nix(nix::Error::Sys(Errno::EPIPE))) => {
+                    return Ok(DrainStatus::Completed(buffers))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Kind of memory-to-memory device, which decides the command used to drain
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum M2mDevice {
+    Decoder,
+    Encoder,
+}
+
+/// Outcome of `Queue::drain()`.
+pub enum DrainStatus<M: Memory> {
+    /// The device has been drained. Contains the buffers dequeued in the
+    /// process, the last of which has the `LAST` flag and may be empty.
+    Completed(Vec<DQBuffer<M>>),
+    /// The last buffer had already been dequeued before the drain, so there
+    /// was nothing left to drain.
+    AlreadyDrained,
+    /// All the CAPTURE buffers have been dequeued before the last one, so
+    /// the driver has nowhere to write the remaining data. Contains the
+    /// buffers dequeued so far. Buffers must be queued again before calling
+    /// `Queue::finish_drain()`.
+    OutOfBuffers(Vec<DQBuffer<M>>),
+    /// No buffer has been returned within the timeout. Contains the buffers
+    /// dequeued so far. `Queue::finish_drain()` keeps waiting for the
+    /// remaining ones.
+    TimedOut(Vec<DQBuffer<M>>),
+    /// The queue is not streaming, so there was nothing to drain.
+    NotStreaming,
+}
+
+/// What to do with CAPTURE buffers that are dequeued without any data.
+///
+/// Some drivers, e.g. decoders during their startup phase, return such
+/// buffers. They need to be queued again without being processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyBufferPolicy {
+    /// Return empty buffers from `dequeue()` like any other buffer. This is
+    /// the default.
+    Deliver,
+    /// Queue empty buffers again and dequeue the next buffer instead. Buffers
+    /// with the `LAST` flag are always delivered.
+    Requeue,
+}
+
+/// Queue buffer `index` again with the `plane_handles` it has just been
+/// dequeued with.
+fn requeue_buffer<M: Memory>(
+    queue: &QueueBase,
+    index: usize,
+    plane_handles: PlaneHandles<M>,
+) -> std::result::Result<PlaneHandles<M>, PlaneHandles<M>>
+where
+    M::DQBufType: Into<M::QBufType>,
+{
+    let backings: Vec<M::QBufType> = plane_handles.into_iter().map(Into::into).collect();
+    let qbuffer = ioctl::QBuffer::<M::HandleType> {
+        planes: backings
+            .iter()
+            // Safe because the backings are kept as long as the buffer is
+            // queued.
+            .map(|backing| ioctl::QBufPlane::new(unsafe { M::build_handle(backing) }, 0))
+            .collect(),
+        ..Default::default()
+    };
+
+    let result = ioctl::qbuf(queue, queue.type_, index, qbuffer);
+    let plane_handles = backings.into_iter().map(M::build_dqbuftype).collect();
+    match result {
+        Ok(()) => Ok(plane_handles),
+        Err(_) => Err(plane_handles),
+    }
+}
+
+impl<M: Memory> Queue<Capture, BuffersAllocated<M>>
+where
+    M::DQBufType: Into<M::QBufType>,
+{
+    /// Set what `dequeue()` does with buffers the driver returned without any
+    /// data. See `EmptyBufferPolicy`.
+    pub fn set_empty_buffer_policy(&self, policy: EmptyBufferPolicy) {
+        let buffers_state = &self.state.buffers_state;
+        let requeue_empty: Option<RequeueFn<M>> = match policy {
+            EmptyBufferPolicy::Deliver => None,
+            EmptyBufferPolicy::Requeue => Some(requeue_buffer::<M>),
+        };
+        if let Some(requeue_empty) = requeue_empty {
+            buffers_state.requeue_fn.get_or_init(|| requeue_empty);
+        }
+        buffers_state
+            .requeue_empty
+            .store(requeue_empty.is_some(), Ordering::Release);
+    }
+}
+
 /// A fuse that will return the buffer to the Free state when destroyed, unless
 /// it has been disarmed.
-// TODO Use Arc::Weak<Mutex<BufferState>> here to make DQBuffer passable across threads?
 struct BufferStateFuse<M: Memory> {
-    buffers_manager: Weak<Mutex<BuffersManager<M>>>,
+    buffers_manager: Weak<BuffersManager<M>>,
     index: usize,
 }
 
 impl<M: Memory> BufferStateFuse<M> {
-    /// Create a new fuse that will set `state` to `BufferState::Free` if
-    /// destroyed before `disarm()` has been called.
-    fn new(buffers_manager: Weak<Mutex<BuffersManager<M>>>, index: usize) -> Self {
+    /// Create a new fuse that will return buffer `index` to the
+    /// `BufferState::Free` state if destroyed before `disarm()` has been
+    /// called.
+    fn new(buffers_manager: Weak<BuffersManager<M>>, index: usize) -> Self {
         BufferStateFuse {
             buffers_manager,
             index,
@@ -455,10 +1780,109 @@ impl<M: Memory> Drop for BufferStateFuse<M> {
         match self.buffers_manager.upgrade() {
             None => (),
             Some(buffers_manager) => {
-                let mut buffers_manager = buffers_manager.lock().unwrap();
-                buffers_manager.buffers_state[self.index] = BufferState::Free;
-                buffers_manager.allocator.return_buffer(self.index);
+                // We cannot fail here, and the buffer would be lost if we
+                // didn't return it.
+                buffers_manager.return_buffer(self.index);
             }
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    fn assert_memory_send_sync<M: Memory + 'static>()
+    where
+        M::HandleType: Send + Sync,
+    {
+        assert_send::<Queue<Capture, BuffersAllocated<M>>>();
+        assert_sync::<Queue<Capture, BuffersAllocated<M>>>();
+        assert_send::<Queue<Output, BuffersAllocated<M>>>();
+        assert_sync::<Queue<Output, BuffersAllocated<M>>>();
+        assert_send::<QBuffer<'static, Capture, M>>();
+        assert_send::<QBuffer<'static, Output, M>>();
+        assert_send::<DQBuffer<M>>();
+        assert_send::<CanceledBuffer<M>>();
+    }
+
+    #[test]
+    fn send_sync() {
+        assert_send::<Queue<Capture, QueueInit>>();
+        assert_sync::<Queue<Output, QueueInit>>();
+
+        assert_memory_send_sync::<MMAP>();
+        assert_memory_send_sync::<UserPtr<Vec<u8>>>();
+        assert_memory_send_sync::<DMABuf>();
+        assert_send::<qbuf::Plane<Output, UserPtr<Vec<u8>>>>();
+    }
+
+    #[test]
+    fn poisoned_lock() {
+        let mutex = Arc::new(Mutex::new(0u32));
+        let poisoner = Arc::clone(&mutex);
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("poisoning the lock");
+        })
+        .join();
+
+        assert!(matches!(lock(&mutex), Err(Error::Poisoned)));
+        *lock_ignore_poison(&mutex) += 1;
+        assert_eq!(*lock_ignore_poison(&mutex), 1);
+    }
+
+    #[test]
+    fn poisoned_buffer_state() {
+        let manager = Arc::new(BuffersManager::<MMAP>::new(2));
+        assert!(!manager.is_poisoned());
+
+        let poisoner = Arc::clone(&manager);
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.lock_buffer(&poisoner.buffers[1]).unwrap();
+            panic!("poisoning the buffer");
+        })
+        .join();
+
+        assert!(manager.is_poisoned());
+        let buffers = &manager.buffers;
+        assert!(manager.lock_buffer(&buffers[0]).is_ok());
+        assert!(matches!(
+            manager.lock_buffer(&buffers[1]),
+            Err(Error::Poisoned)
+        ));
+        assert!(manager.lock_buffer_ignore_poison(&buffers[1]).is_none());
+        assert_eq!(buffers[1].state(), BufferState::Free);
+    }
+
+    #[test]
+    fn buffer_slots() {
+        assert_eq!(BufferSlots::<MMAP>::locate(0), (0, 0));
+        assert_eq!(BufferSlots::<MMAP>::locate(31), (0, 31));
+        assert_eq!(BufferSlots::<MMAP>::locate(32), (1, 0));
+        assert_eq!(BufferSlots::<MMAP>::locate(95), (1, 63));
+        assert_eq!(BufferSlots::<MMAP>::locate(96), (2, 0));
+
+        let manager = BuffersManager::<MMAP>::new(2);
+        assert_eq!(manager.take_free_buffer(), Some(0));
+        assert_eq!(manager.take_free_buffer(), Some(1));
+        assert_eq!(manager.take_free_buffer(), None);
+        assert!(!manager.take_buffer(1));
+
+        // Free buffers are handed out in the order they have been freed,
+        // including the ones added later.
+        manager.return_buffer(1);
+        manager.return_buffer(0);
+        manager.add_buffers(2..40);
+        assert_eq!(manager.num_buffers(), 40);
+        assert_eq!(manager.take_free_buffer(), Some(1));
+        assert_eq!(manager.take_free_buffer(), Some(0));
+        assert!(manager.take_buffer(39));
+        assert_eq!(manager.take_free_buffer(), Some(2));
+        assert_eq!(manager.buffers[39].state(), BufferState::PreQueue);
+        assert!(manager.buffers.get(40).is_none());
+    }
+}
@@ -0,0 +1,126 @@
+//! Provides a snapshot of the state of a `Queue`, for debugging purposes.
+use crate::memory::MemoryType;
+use crate::{Format, QueueType};
+use std::fmt::{self, Display};
+
+/// State of a single buffer, as tracked by the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferStateDump {
+    /// The buffer can be obtained and queued.
+    Free,
+    /// The buffer has been obtained but is not queued yet.
+    PreQueue,
+    /// The buffer is queued and waiting to be dequeued.
+    Queued,
+    /// The buffer has been dequeued and is still in use by the client.
+    Dequeued,
+}
+
+impl BufferStateDump {
+    /// One-letter representation of the state, used by the compact `Display`
+    /// of `QueueStateDump`.
+    fn as_char(self) -> char {
+        match self {
+            BufferStateDump::Free => 'F',
+            BufferStateDump::PreQueue => 'P',
+            BufferStateDump::Queued => 'Q',
+            BufferStateDump::Dequeued => 'D',
+        }
+    }
+}
+
+/// Snapshot of what the crate believes the state of a queue is, as returned
+/// by `Queue::dump_state()`. Its `Display` implementation is compact enough to
+/// be included in logs and bug reports.
+#[derive(Debug, Clone)]
+pub struct QueueStateDump {
+    pub type_: QueueType,
+    pub memory: MemoryType,
+    /// Whether the queue has been streamed on.
+    pub streaming: bool,
+    /// Number of buffers still to be queued before a deferred streamon takes
+    /// place, if one is pending.
+    pub pending_streamon: Option<usize>,
+    pub num_queued_buffers: usize,
+    /// State of each buffer, by index.
+    pub buffers: Vec<BufferStateDump>,
+    /// Format currently set on the queue, or `None` if it could not be
+    /// obtained.
+    pub format: Option<Format>,
+}
+
+impl QueueStateDump {
+    /// Returns the number of buffers currently in state `state`.
+    pub fn count(&self, state: BufferStateDump) -> usize {
+        self.buffers.iter().filter(|s| **s == state).count()
+    }
+}
+
+impl Display for QueueStateDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?}/{:?} {}",
+            self.type_,
+            self.memory,
+            if self.streaming {
+                "streaming"
+            } else {
+                "stopped"
+            }
+        )?;
+        if let Some(pending) = self.pending_streamon {
+            write!(f, " (streamon in {})", pending)?;
+        }
+        write!(
+            f,
+            ", {}/{} queued [",
+            self.num_queued_buffers,
+            self.buffers.len()
+        )?;
+        for state in &self.buffers {
+            write!(f, "{}", state.as_char())?;
+        }
+        write!(f, "]")?;
+        match &self.format {
+            Some(format) => write!(
+                f,
+                ", {} {}x{} {} plane(s)",
+                format.pixelformat,
+                format.width,
+                format.height,
+                format.plane_fmt.len()
+            ),
+            None => write!(f, ", unknown format"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_queue_state_dump() {
+        let dump = QueueStateDump {
+            type_: QueueType::VideoCaptureMplane,
+            memory: MemoryType::MMAP,
+            streaming: true,
+            pending_streamon: None,
+            num_queued_buffers: 2,
+            buffers: vec![
+                BufferStateDump::Queued,
+                BufferStateDump::Dequeued,
+                BufferStateDump::Queued,
+                BufferStateDump::Free,
+            ],
+            format: Some(Format::from((b"NV12", (640, 480)))),
+        };
+
+        assert_eq!(dump.count(BufferStateDump::Queued), 2);
+        assert_eq!(
+            dump.to_string(),
+            "VideoCaptureMplane/MMAP streaming, 2/4 queued [QDQF], NV12 640x480 0 plane(s)"
+        );
+    }
+}
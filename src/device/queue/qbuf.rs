@@ -3,9 +3,14 @@ use super::{BufferState, BufferStateFuse, BuffersAllocated, PlaneHandles, Queue}
 use super::{Capture, Direction, Output};
 use crate::ioctl;
 use crate::memory::*;
-use crate::Error;
+use crate::request::Request;
+use crate::{Error, Result};
 use std::cmp::Ordering;
 use std::fmt::{self, Debug, Display};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic;
+use std::time::Duration;
 
 /// Error that can occur when queuing a buffer. It wraps a regular error and also
 /// returns the plane handles back to the user.
@@ -63,7 +68,13 @@ pub struct QBuffer<'a, D: Direction, M: Memory> {
     queue: &'a Queue<D, BuffersAllocated<M>>,
     index: usize,
     num_planes: usize,
-    qbuffer: ioctl::QBuffer<M::HandleType>,
+    /// Template of the buffer, borrowed from its state, into which the
+    /// planes and options are written as they are specified.
+    template: Box<ioctl::QBufTemplate<M::HandleType>>,
+    num_set_planes: usize,
+    /// Set if a plane has been given a data offset the queue does not
+    /// support.
+    data_offset_not_supported: bool,
     plane_handles: PlaneHandles<M>,
     fuse: BufferStateFuse<M>,
 }
@@ -73,14 +84,17 @@ impl<'a, D: Direction, M: Memory> QBuffer<'a, D, M> {
         queue: &'a Queue<D, BuffersAllocated<M>>,
         index: usize,
         num_planes: usize,
+        template: Box<ioctl::QBufTemplate<M::HandleType>>,
         fuse: BufferStateFuse<M>,
     ) -> Self {
         QBuffer {
             queue,
             index,
             num_planes,
-            qbuffer: Default::default(),
-            plane_handles: Vec::new(),
+            template,
+            num_set_planes: 0,
+            data_offset_not_supported: false,
+            plane_handles: Vec::with_capacity(num_planes),
             fuse,
         }
     }
@@ -96,71 +110,163 @@ impl<'a, D: Direction, M: Memory> QBuffer<'a, D, M> {
         self.num_planes
     }
 
+    /// Returns the size of each plane of the current format, which can be
+    /// used to allocate the backing memory of the planes to add.
+    pub fn plane_sizes(&self) -> &[usize] {
+        self.queue.plane_sizes()
+    }
+
     /// Returns the number of planes that have been specified so far.
     pub fn num_set_planes(&self) -> usize {
-        self.qbuffer.planes.len()
+        self.num_set_planes
     }
 
     /// Specify the next plane of this buffer.
     pub fn add_plane(mut self, plane: Plane<D, M>) -> Self {
-        self.qbuffer.planes.push(plane.plane);
+        let index = self.num_set_planes;
+        // Planes in excess are reported when queueing.
+        if index < self.num_planes {
+            let ioctl::QBufPlane {
+                bytesused,
+                data_offset,
+                handle,
+            } = &plane.plane;
+            // Cannot fail since the plane exists.
+            let _ = self.template.set_plane(index, *bytesused, handle);
+            if self.template.set_data_offset(index, *data_offset).is_err() {
+                self.data_offset_not_supported = true;
+            }
+        }
+        self.num_set_planes += 1;
         self.plane_handles.push(M::build_dqbuftype(plane.backing));
         self
     }
 
+    /// Check that the planes have been specified correctly.
+    fn check_planes(&self) -> Result<()> {
+        match self.num_set_planes.cmp(&self.num_planes) {
+            Ordering::Less => return Err(Error::NotEnoughPlanes),
+            Ordering::Greater => return Err(Error::TooManyPlanes),
+            Ordering::Equal => (),
+        };
+        if self.data_offset_not_supported {
+            return Err(Error::DataOffsetNotSupported);
+        }
+
+        Ok(())
+    }
+
+    /// Let the driver prepare the buffer for queueing (e.g. validate it and
+    /// map its memory), so the following `queue()` is faster. All the planes
+    /// must have been specified, and cannot be changed afterwards.
+    ///
+    /// This is useful to move work out of the streaming loop, e.g. by
+    /// preparing the next buffer while the previous one is being processed.
+    pub fn prepare(&self) -> Result<()> {
+        self.check_planes()?;
+
+        ioctl::prepare_buf_template(&self.queue.inner, &self.template)
+    }
+
     /// Queue the buffer. The QBuffer object is consumed and the buffer won't
     /// be available again until it has been dequeued and dropped, or a
     /// `streamoff()` is performed.
     pub fn queue(mut self) -> QueueResult<M, ()> {
+        // First check that the planes are what we expect, and fail while we
+        // can still return the plane handles: once the buffer is queued we
+        // must keep them until it is dequeued.
+        let checked = self
+            .check_planes()
+            .and_then(|()| match self.queue.is_poisoned() {
+                true => Err(Error::Poisoned),
+                false => Ok(()),
+            });
         let plane_handles = self.plane_handles;
+        let buffers_state = &self.queue.state.buffers_state;
+        let buffer = &buffers_state.buffers[self.index];
+        if let Err(error) = checked {
+            buffer.return_template(self.template);
+            return Err(QueueError {
+                error,
+                plane_handles,
+            });
+        }
 
-        // First check that the number of provided planes is what we expect.
-        match self.qbuffer.planes.len().cmp(&self.num_planes) {
-            Ordering::Less => {
-                return Err(QueueError {
-                    error: Error::NotEnoughPlanes,
-                    plane_handles,
-                })
-            }
-            Ordering::Greater => {
-                return Err(QueueError {
-                    error: Error::TooManyPlanes,
-                    plane_handles,
-                })
-            }
-            Ordering::Equal => (),
-        };
+        // Hold the lock of the buffer while it is queued, so a concurrent
+        // `dequeue()` cannot see it before its state is updated.
+        let mut buffer_handles = buffers_state.lock_buffer_ignore_poison(buffer);
 
-        match ioctl::qbuf(
-            &self.queue.inner,
-            self.queue.inner.type_,
-            self.index,
-            self.qbuffer,
-        ) {
-            Ok(_) => (),
-            Err(error) => {
-                return Err(QueueError {
-                    error,
-                    plane_handles,
-                })
-            }
-        };
+        let result = ioctl::qbuf_template(&self.queue.inner, &self.template);
+        buffer.return_template(self.template);
+        if let Err(error) = result {
+            return Err(QueueError {
+                error,
+                plane_handles,
+            });
+        }
 
         // We got this now.
         self.fuse.disarm();
 
-        let mut buffers_state = self.queue.state.buffers_state.lock().unwrap();
-        std::mem::replace(&mut buffers_state.buffers_state[self.index], BufferState::Queued(plane_handles));
-        // TODO this indicates that we should probably use treemaps for each buffer state
-        // (or bitmaps for simple state and a treemap for the queued one) instead of a global
-        // array?
-        buffers_state.num_queued_buffers += 1;
-        drop(buffers_state);
+        *buffer_handles = Some(plane_handles);
+        buffer.set_state(BufferState::Queued);
+        buffers_state
+            .num_queued_buffers
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        drop(buffer_handles);
+
+        let min_queued_buffers = buffers_state
+            .deferred_streamon
+            .load(atomic::Ordering::Acquire);
+        let start_streaming =
+            min_queued_buffers != 0 && buffers_state.trigger_deferred_streamon(min_queued_buffers);
+
+        // We have reached the number of buffers required to start streaming.
+        // The buffer itself is queued at this point, so no plane handle is
+        // returned on error.
+        if start_streaming {
+            self.queue.streamon().map_err(|error| QueueError {
+                error,
+                plane_handles: Vec::new(),
+            })?;
+        }
 
         Ok(())
     }
 }
 
+impl<'a, M: Memory> QBuffer<'a, Output, M> {
+    /// Set the timestamp of this buffer, which the driver copies to the
+    /// CAPTURE buffer it produces from it. Codecs use it to match their
+    /// input and output buffers, and stateless decoders to identify
+    /// reference frames.
+    ///
+    /// V4L2 timestamps have a microsecond resolution, so anything below is
+    /// ignored.
+    pub fn set_timestamp(mut self, timestamp: Duration) -> Self {
+        self.template.set_timestamp(timestamp);
+        self
+    }
+
+    /// Queue this buffer as part of `request` instead of immediately. The
+    /// buffer will only be processed once `request` is itself queued.
+    ///
+    /// The request does not need to outlive this object, as the kernel keeps
+    /// its own reference to it after `queue()`.
+    pub fn set_request(mut self, request: &Request) -> Self {
+        self.template.set_request(Some(request.as_raw_fd()));
+        self
+    }
+
+    /// Set the field contained in this buffer. This is required when the
+    /// field order of the format is `Field::Alternate`, in which case every
+    /// buffer holds a single field, either `Field::Top` or `Field::Bottom`.
+    pub fn set_field(mut self, field: ioctl::Field) -> Self {
+        self.template.set_field(field as u32);
+        self
+    }
+}
+
 impl<'a> QBuffer<'a, Capture, MMAP> {
     /// For Capture MMAP buffers, there is no point requesting the user to
     /// provide as many empty handles as there are planes in the buffer. This
@@ -231,3 +337,126 @@ impl<M: Memory> Plane<Output, M> {
         self
     }
 }
+
+impl<T: AsRef<[u8]> + AsMut<[u8]> + Send> Plane<Output, UserPtr<T>> {
+    /// Creates a new output plane backed by `backing`, which is filled by
+    /// `write`. The number of bytes used is the number of bytes written, so
+    /// it does not need to be computed separately.
+    ///
+    /// If `write` fails, `backing` is returned along with the error so it can
+    /// be reused.
+    pub fn write_with<F>(mut backing: T, write: F) -> std::result::Result<Self, (io::Error, T)>
+    where
+        F: FnOnce(&mut PlaneWriter) -> io::Result<()>,
+    {
+        let mut writer = PlaneWriter::new(backing.as_mut());
+        let result = write(&mut writer).map(|()| writer.bytes_written());
+
+        match result {
+            Ok(bytes_used) => Ok(Self::out(backing, bytes_used)),
+            Err(e) => Err((e, backing)),
+        }
+    }
+}
+
+impl Plane<Output, MMAP> {
+    /// Creates a new output plane by letting `write` fill `mapping`, which
+    /// must be the mapping of the corresponding plane of the buffer being
+    /// queued. The number of bytes used is the number of bytes written, so it
+    /// does not need to be computed separately.
+    pub fn write_mapped<F>(mapping: &mut [u8], write: F) -> io::Result<Self>
+    where
+        F: FnOnce(&mut PlaneWriter) -> io::Result<()>,
+    {
+        let mut writer = PlaneWriter::new(mapping);
+        write(&mut writer)?;
+        let bytes_used = writer.bytes_written();
+
+        Ok(Self::out((), bytes_used))
+    }
+}
+
+/// Writes data sequentially into the memory of an output plane, keeping track
+/// of how many bytes have been written. Used by `Plane::write_with()` and
+/// `Plane::write_mapped()`.
+///
+/// Writing past the end of the plane fails with `ErrorKind::WriteZero` when
+/// using `write_all()`.
+pub struct PlaneWriter<'a> {
+    data: &'a mut [u8],
+    written: usize,
+}
+
+impl<'a> PlaneWriter<'a> {
+    /// Create a writer that starts writing at the beginning of `data`.
+    pub fn new(data: &'a mut [u8]) -> Self {
+        PlaneWriter { data, written: 0 }
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn bytes_written(&self) -> usize {
+        self.written
+    }
+
+    /// Returns the part of the plane that has not been written yet. Use
+    /// `advance()` after writing into it directly.
+    pub fn remaining(&mut self) -> &mut [u8] {
+        &mut self.data[self.written..]
+    }
+
+    /// Record that `len` bytes have been written through `remaining()`.
+    /// `len` is capped to the size of the plane.
+    pub fn advance(&mut self, len: usize) {
+        self.written = (self.written + len).min(self.data.len());
+    }
+}
+
+impl<'a> io::Write for PlaneWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let remaining = self.remaining();
+        let len = buf.len().min(remaining.len());
+        remaining[..len].copy_from_slice(&buf[..len]);
+        self.written += len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn write_with() {
+        let plane = Plane::<Output, UserPtr<Vec<u8>>>::write_with(vec![0u8; 8], |writer| {
+            writer.write_all(b"abc")?;
+            writer.remaining()[..2].copy_from_slice(b"de");
+            writer.advance(2);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(plane.plane.bytesused, 5);
+        assert_eq!(&plane.backing[..6], b"abcde\0");
+
+        let (error, backing) =
+            Plane::<Output, UserPtr<Vec<u8>>>::write_with(vec![0u8; 4], |writer| {
+                writer.write_all(b"too long")
+            })
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+        assert_eq!(backing.len(), 4);
+
+        let mut mapping = [0u8; 4];
+        let error = Plane::<Output, MMAP>::write_mapped(&mut mapping, |writer| {
+            writer.write_all(b"too long")
+        })
+        .err()
+        .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+    }
+}
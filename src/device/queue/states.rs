@@ -1,14 +1,28 @@
-use super::PlaneHandles;
+use super::dump::BufferStateDump;
+use super::watermark::*;
+use super::wipe::BufferWiper;
+use super::{lock_ignore_poison, PlaneHandles, QueueBase};
 use crate::ioctl;
 use crate::memory::Memory;
-use std::collections::VecDeque;
+use crate::{Error, Result};
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut, Index, Range};
 
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, RwLock};
+use std::thread;
 
 /// Trait for the different states a queue can be in. This allows us to limit
 /// the available queue methods to the one that make sense at a given point of
 /// the queue's lifecycle.
-pub trait QueueState {}
+pub trait QueueState {
+    /// Check whether the format of a queue in this state can be changed, and
+    /// return the error to report if not.
+    #[doc(hidden)]
+    fn check_format_change(&self) -> Result<()> {
+        Ok(())
+    }
+}
 
 /// Initial state of the queue when created. Streaming and queuing are not
 /// supported since buffers have not been allocated yet.
@@ -16,75 +30,486 @@ pub trait QueueState {}
 pub struct QueueInit;
 impl QueueState for QueueInit {}
 
-pub(super) trait BufferAllocator {
-    fn get_free_buffer(&mut self) -> Option<usize>;
-    fn take_buffer(&mut self, index: usize);
-    fn return_buffer(&mut self, index: usize);
+/// Function queueing a buffer again with the plane handles it has just been
+/// dequeued with. Returns the plane handles to keep while the buffer is
+/// queued, or gives them back if the buffer could not be queued.
+pub(super) type RequeueFn<M> =
+    fn(&QueueBase, usize, PlaneHandles<M>) -> std::result::Result<PlaneHandles<M>, PlaneHandles<M>>;
+
+/// Represents the current state of an allocated buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(super) enum BufferState {
+    /// The buffer can be obtained via `get_buffer()` and be queued.
+    Free,
+    /// The buffer has been requested via `get_buffer()` but is not queued yet.
+    PreQueue,
+    /// The buffer is queued and waiting to be dequeued.
+    Queued,
+    /// The buffer has been dequeued and the client is still using it. The buffer
+    /// will go back to the `Free` state once the reference is dropped.
+    Dequeued,
 }
 
-pub(super) struct FifoBufferAllocator {
-    queue: VecDeque<usize>,
+impl BufferState {
+    fn from_u8(state: u8) -> Self {
+        match state {
+            0 => BufferState::Free,
+            1 => BufferState::PreQueue,
+            2 => BufferState::Queued,
+            _ => BufferState::Dequeued,
+        }
+    }
+
+    pub(super) fn dump(self) -> BufferStateDump {
+        match self {
+            BufferState::Free => BufferStateDump::Free,
+            BufferState::PreQueue => BufferStateDump::PreQueue,
+            BufferState::Queued => BufferStateDump::Queued,
+            BufferState::Dequeued => BufferStateDump::Dequeued,
+        }
+    }
 }
 
-impl FifoBufferAllocator {
-    fn new(nb_buffers: usize) -> Self {
-        FifoBufferAllocator {
-            queue: (0..nb_buffers).collect(),
+/// A buffer of a queue.
+///
+/// Its state is atomic, so free buffers can be found and taken without
+/// locking anything. A buffer only leaves the `Queued` state, and only enters
+/// it, with the lock on its plane handles held. This lock, like the one on
+/// its template, is only taken by the thread owning the buffer, i.e. the one
+/// obtaining, queueing, dequeueing or canceling it, so it is not contended.
+pub(super) struct BufferSlot<M: Memory> {
+    state: AtomicU8,
+    /// Value of `BuffersManager::free_counter` when the buffer was last freed,
+    /// so free buffers are handed out in the order they have been freed.
+    freed_at: AtomicU64,
+    /// Plane handles of the buffer while it is queued.
+    plane_handles: Mutex<Option<PlaneHandles<M>>>,
+    /// Information the buffer is queued with, created the first time the
+    /// buffer is obtained and reused for every frame. Lent to the `QBuffer`
+    /// preparing the buffer, which gives it back when queueing it.
+    template: Mutex<Option<Box<ioctl::QBufTemplate<M::HandleType>>>>,
+}
+
+impl<M: Memory> BufferSlot<M> {
+    fn new() -> Self {
+        BufferSlot {
+            state: AtomicU8::new(BufferState::Free as u8),
+            freed_at: AtomicU64::new(0),
+            plane_handles: Mutex::new(None),
+            template: Mutex::new(None),
         }
     }
+
+    pub(super) fn state(&self) -> BufferState {
+        BufferState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    /// Set the state of a buffer owned by the caller. Moving a buffer to or
+    /// from `Queued` must be done with the lock on its plane handles held.
+    pub(super) fn set_state(&self, state: BufferState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
+    /// Borrow the template of the buffer, if it has one.
+    pub(super) fn take_template(&self) -> Option<Box<ioctl::QBufTemplate<M::HandleType>>> {
+        lock_ignore_poison(&self.template).take()
+    }
+
+    /// Give back the template borrowed with `take_template()`.
+    pub(super) fn return_template(&self, template: Box<ioctl::QBufTemplate<M::HandleType>>) {
+        *lock_ignore_poison(&self.template) = Some(template);
+    }
+
+    /// Move the buffer from `Free` to `PreQueue`, and return whether it was
+    /// free.
+    fn take(&self) -> bool {
+        self.state
+            .compare_exchange(
+                BufferState::Free as u8,
+                BufferState::PreQueue as u8,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+    }
 }
 
-impl BufferAllocator for FifoBufferAllocator {
-    fn get_free_buffer(&mut self) -> Option<usize> {
-        self.queue.pop_front()
+/// Number of buffers of the first segment of `BufferSlots`. Each following
+/// segment is twice as large as the previous one.
+const FIRST_SEGMENT_LEN: usize = 32;
+/// Enough segments for 131040 buffers, far more than drivers support.
+const NUM_SEGMENTS: usize = 12;
+
+/// Array of buffers that can grow while being read, without locking. The
+/// buffers are stored in segments that are allocated as needed and never
+/// move.
+pub(super) struct BufferSlots<M: Memory> {
+    segments: [OnceLock<Box<[BufferSlot<M>]>>; NUM_SEGMENTS],
+    /// Number of buffers, which only grows.
+    len: AtomicUsize,
+}
+
+impl<M: Memory> BufferSlots<M> {
+    fn new() -> Self {
+        BufferSlots {
+            segments: Default::default(),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the segment of buffer `index`, and its position in it.
+    pub(super) fn locate(index: usize) -> (usize, usize) {
+        let units = index / FIRST_SEGMENT_LEN + 1;
+        let segment = (usize::BITS - 1 - units.leading_zeros()) as usize;
+        (segment, index - FIRST_SEGMENT_LEN * ((1 << segment) - 1))
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
     }
 
-    fn take_buffer(&mut self, index: usize) {
-        self.queue.retain(|i| *i != index);
+    pub(super) fn get(&self, index: usize) -> Option<&BufferSlot<M>> {
+        if index >= self.len() {
+            return None;
+        }
+        let (segment, offset) = Self::locate(index);
+        self.segments.get(segment)?.get()?.get(offset)
     }
 
-    fn return_buffer(&mut self, index: usize) {
-        self.queue.push_back(index);
+    pub(super) fn iter(&self) -> impl Iterator<Item = (usize, &BufferSlot<M>)> {
+        (0..self.len()).filter_map(move |index| Some((index, self.get(index)?)))
+    }
+
+    /// Allocate the segments holding the buffers up to `index`, which are
+    /// not visible until `publish()` is called.
+    fn reserve(&self, index: usize) -> Option<&BufferSlot<M>> {
+        let (last_segment, offset) = Self::locate(index);
+        for segment in 0..=last_segment.min(NUM_SEGMENTS - 1) {
+            self.segments[segment].get_or_init(|| {
+                std::iter::repeat_with(BufferSlot::new)
+                    .take(FIRST_SEGMENT_LEN << segment)
+                    .collect()
+            });
+        }
+        self.segments.get(last_segment)?.get()?.get(offset)
+    }
+
+    /// Make the buffers up to `len` visible.
+    fn publish(&self, len: usize) {
+        self.len.fetch_max(len, Ordering::AcqRel);
     }
 }
 
-/// Represents the current state of an allocated buffer.
-pub(super) enum BufferState<M: Memory> {
-    /// The buffer can be obtained via `get_buffer()` and be queued.
-    Free,
-    /// The buffer has been requested via `get_buffer()` but is not queued yet.
-    PreQueue,
-    /// The buffer is queued and waiting to be dequeued.
-    Queued(PlaneHandles<M>),
-    /// The buffer has been dequeued and the client is still using it. The buffer
-    /// will go back to the `Free` state once the reference is dropped.
-    Dequeued,
+impl<M: Memory> Index<usize> for BufferSlots<M> {
+    type Output = BufferSlot<M>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.get(index).expect("Invalid buffer index")
+    }
 }
 
+/// Tracks the state of the buffers of a queue.
+///
+/// The states of the buffers and the counters are atomic, and there is no
+/// global lock involved in obtaining, queueing or dequeueing buffers, so
+/// these operations do not contend when done from different threads. Only
+/// the optional watermarks and wiping use a read-write lock, which is only
+/// read-locked on these paths and skipped entirely when not enabled.
 pub(super) struct BuffersManager<M: Memory> {
-    pub(super) allocator: FifoBufferAllocator,
-    pub(super) buffers_state: Vec<BufferState<M>>,
-    pub(super) num_queued_buffers: usize,
+    pub(super) buffers: BufferSlots<M>,
+    /// Number of buffers in the `Free` state. Incremented before a buffer
+    /// becomes `Free` and decremented after it has been taken, so that it
+    /// never goes below zero.
+    num_free_buffers: AtomicUsize,
+    /// Source of `BufferSlot::freed_at`.
+    free_counter: AtomicU64,
+    /// Updated while holding the lock of the queued or dequeued buffer, so
+    /// that it never goes below zero.
+    pub(super) num_queued_buffers: AtomicUsize,
+    /// If non-zero, `streamon` will be performed as soon as this number of
+    /// buffers are queued.
+    pub(super) deferred_streamon: AtomicUsize,
+    /// Whether the queue is currently streaming.
+    pub(super) streaming: AtomicBool,
+    /// Whether dequeuing has been paused by the user.
+    pub(super) paused: AtomicBool,
+    /// Whether empty buffers are given back to the driver using `requeue_fn`
+    /// instead of being returned by `dequeue()`.
+    pub(super) requeue_empty: AtomicBool,
+    /// Set when the policy is first selected, as only queues whose memory
+    /// type allows requeueing buffers can provide this function.
+    pub(super) requeue_fn: OnceLock<RequeueFn<M>>,
+    /// Buffer levels to watch and callback to invoke when they are reached.
+    /// `watermarks_set` lets us skip the lock when there are none.
+    watermarks: RwLock<Option<Watermarks>>,
+    watermarks_set: AtomicBool,
+    /// Mappings used to wipe buffers when they return to the free list, if
+    /// enabled. `wipe_enabled` lets us skip the lock when it is not.
+    pub(super) wiper: RwLock<Option<BufferWiper>>,
+    pub(super) wipe_enabled: AtomicBool,
+    /// Set if a thread panicked while holding the lock of a buffer, so we
+    /// don't have to go through all of them to find out.
+    poisoned: AtomicBool,
 }
 
+/// Lock on the plane handles of a buffer, which marks its `BuffersManager`
+/// as poisoned if the thread panics while holding it.
+pub(super) struct BufferGuard<'a, M: Memory> {
+    guard: MutexGuard<'a, Option<PlaneHandles<M>>>,
+    poisoned: &'a AtomicBool,
+}
+
+impl<'a, M: Memory> Deref for BufferGuard<'a, M> {
+    type Target = Option<PlaneHandles<M>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, M: Memory> DerefMut for BufferGuard<'a, M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, M: Memory> Drop for BufferGuard<'a, M> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.poisoned.store(true, Ordering::Release);
+        }
+    }
+}
 
 impl<M: Memory> BuffersManager<M> {
     pub(super) fn new(num_buffers: usize) -> Self {
-        BuffersManager {
-            allocator: FifoBufferAllocator::new(num_buffers),
-            buffers_state: std::iter::repeat_with(|| BufferState::Free)
-                .take(num_buffers)
-                .collect(),
-            num_queued_buffers: 0,
+        let manager = BuffersManager {
+            buffers: BufferSlots::new(),
+            num_free_buffers: AtomicUsize::new(0),
+            free_counter: AtomicU64::new(0),
+            num_queued_buffers: AtomicUsize::new(0),
+            deferred_streamon: AtomicUsize::new(0),
+            streaming: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            requeue_empty: AtomicBool::new(false),
+            requeue_fn: OnceLock::new(),
+            watermarks: RwLock::new(None),
+            watermarks_set: AtomicBool::new(false),
+            wiper: RwLock::new(None),
+            wipe_enabled: AtomicBool::new(false),
+            poisoned: AtomicBool::new(false),
+        };
+        manager.add_buffers(0..num_buffers);
+        manager
+    }
+
+    /// Returns the number of buffers tracked.
+    pub(super) fn num_buffers(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Lock the plane handles of `buffer`, which must be one of our buffers,
+    /// failing with `Error::Poisoned` if a thread panicked while holding
+    /// them.
+    pub(super) fn lock_buffer<'a>(
+        &'a self,
+        buffer: &'a BufferSlot<M>,
+    ) -> Result<BufferGuard<'a, M>> {
+        match buffer.plane_handles.lock() {
+            Ok(guard) => Ok(BufferGuard {
+                guard,
+                poisoned: &self.poisoned,
+            }),
+            Err(_) => {
+                self.poisoned.store(true, Ordering::Release);
+                Err(Error::Poisoned)
+            }
+        }
+    }
+
+    /// Same as `lock_buffer()`, but never fails.
+    pub(super) fn lock_buffer_ignore_poison<'a>(
+        &'a self,
+        buffer: &'a BufferSlot<M>,
+    ) -> BufferGuard<'a, M> {
+        BufferGuard {
+            guard: buffer
+                .plane_handles
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            poisoned: &self.poisoned,
+        }
+    }
+
+    /// Returns whether a thread panicked while holding one of our locks.
+    pub(super) fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
+    /// Start tracking the buffers of `indices`, which have just been created,
+    /// as free. This cannot fail, so the buffers are accounted for even if the
+    /// queue has been poisoned, as only growing the states is always valid.
+    pub(super) fn add_buffers(&self, indices: Range<usize>) {
+        let mut end = indices.start;
+        for index in indices {
+            let buffer = match self.buffers.reserve(index) {
+                Some(buffer) => buffer,
+                None => break,
+            };
+            let freed_at = self.free_counter.fetch_add(1, Ordering::AcqRel);
+            buffer.freed_at.store(freed_at, Ordering::Release);
+            end = index + 1;
+        }
+        self.num_free_buffers
+            .fetch_add(end.saturating_sub(self.buffers.len()), Ordering::AcqRel);
+        self.buffers.publish(end);
+        // The number of free buffers can only go up, so no event to report.
+        self.free_buffers_changed();
+    }
+
+    /// Take the free buffer that has been freed the longest ago, moving it to
+    /// the `PreQueue` state. Returns its index, or `None` if no buffer is
+    /// free.
+    pub(super) fn take_free_buffer(&self) -> Option<usize> {
+        loop {
+            let (index, buffer) = self
+                .buffers
+                .iter()
+                .filter(|(_, buffer)| buffer.state() == BufferState::Free)
+                .min_by_key(|(_, buffer)| buffer.freed_at.load(Ordering::Acquire))?;
+            // Another thread may take it first, in which case we look again.
+            if buffer.take() {
+                self.num_free_buffers.fetch_sub(1, Ordering::AcqRel);
+                return Some(index);
+            }
         }
     }
+
+    /// Take buffer `index` if it is free, moving it to the `PreQueue` state,
+    /// and return whether it was.
+    pub(super) fn take_buffer(&self, index: usize) -> bool {
+        match self.buffers.get(index) {
+            Some(buffer) if buffer.take() => {
+                self.num_free_buffers.fetch_sub(1, Ordering::AcqRel);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Wipe the memory of buffer `index`, which must not be queued, if
+    /// wiping is enabled.
+    pub(super) fn wipe_buffer(&self, index: usize) {
+        if !self.wipe_enabled.load(Ordering::Acquire) {
+            return;
+        }
+        let wiper = self.wiper.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(wiper) = &*wiper {
+            wiper.wipe(index);
+        }
+    }
+
+    /// Return buffer `index`, which is owned by the caller and not queued, to
+    /// the `Free` state. The buffer is wiped first if wiping is enabled.
+    pub(super) fn return_buffer(&self, index: usize) {
+        let buffer = match self.buffers.get(index) {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        self.wipe_buffer(index);
+        let freed_at = self.free_counter.fetch_add(1, Ordering::AcqRel);
+        buffer.freed_at.store(freed_at, Ordering::Release);
+        self.num_free_buffers.fetch_add(1, Ordering::AcqRel);
+        buffer.set_state(BufferState::Free);
+        // The number of free buffers can only go up, so no event to report.
+        self.free_buffers_changed();
+    }
+
+    /// Must be called after the number of free buffers has changed. Returns
+    /// the watermark callback to invoke and its event, if any. The callback
+    /// is not invoked directly, so callers can do it once they are done with
+    /// the buffer.
+    pub(super) fn free_buffers_changed(&self) -> Option<(WatermarkCallback, WatermarkEvent)> {
+        if !self.watermarks_set.load(Ordering::Acquire) {
+            return None;
+        }
+        let free = self.num_free_buffers.load(Ordering::Acquire);
+        self.watermarks
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .and_then(|watermarks| watermarks.free_buffers_changed(free))
+    }
+
+    /// Returns the watermark callback to invoke to report an underrun, if
+    /// any.
+    pub(super) fn underrun(&self) -> Option<(WatermarkCallback, WatermarkEvent)> {
+        if !self.watermarks_set.load(Ordering::Acquire) {
+            return None;
+        }
+        self.watermarks
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(Watermarks::underrun)
+    }
+
+    /// Replace the watermarks to watch. Their detection state is initialized
+    /// from the current level, without reporting anything.
+    pub(super) fn set_watermarks(&self, watermarks: Option<Watermarks>) {
+        let set = watermarks.is_some();
+        *self
+            .watermarks
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = watermarks;
+        self.watermarks_set.store(set, Ordering::Release);
+        self.free_buffers_changed();
+    }
+
+    /// Returns the number of buffers that still need to be queued before a
+    /// deferred streamon takes place, or `None` if no streamon is pending.
+    pub(super) fn pending_streamon(&self) -> Option<usize> {
+        match self.deferred_streamon.load(Ordering::Acquire) {
+            0 => None,
+            min => Some(min.saturating_sub(self.num_queued_buffers.load(Ordering::Acquire))),
+        }
+    }
+
+    /// Clear the deferred streamon if it expected `min_queued_buffers` and at
+    /// least that many buffers are queued. Returns true if the caller must
+    /// now perform the streamon, which will mark the queue as streaming once
+    /// it succeeds.
+    pub(super) fn trigger_deferred_streamon(&self, min_queued_buffers: usize) -> bool {
+        if self.num_queued_buffers.load(Ordering::Acquire) < min_queued_buffers {
+            return false;
+        }
+        // Only one thread can win, even if several are queueing buffers.
+        self.deferred_streamon
+            .compare_exchange(min_queued_buffers, 0, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
 }
 
 /// Allocated state for a queue. A queue with its buffers allocated can be
 /// streamed on and off, and buffers can be queued and dequeued.
 pub struct BuffersAllocated<M: Memory> {
     pub(super) num_buffers: usize,
-    pub(super) buffers_state: Arc<Mutex<BuffersManager<M>>>,
+    pub(super) buffers_state: Arc<BuffersManager<M>>,
     pub(super) buffer_features: ioctl::QueryBuffer,
+    /// DMABUFs exported from the planes of each buffer, along with the flags
+    /// they have been exported with. Only used with MMAP buffers.
+    pub(super) exported_buffers: Mutex<BTreeMap<usize, Vec<Arc<ioctl::ExportedBuffer>>>>,
+}
+impl<M: Memory> QueueState for BuffersAllocated<M> {
+    /// Drivers refuse format changes as long as buffers are allocated.
+    fn check_format_change(&self) -> Result<()> {
+        if self.num_buffers == 0 {
+            return Ok(());
+        }
+
+        Err(Error::Busy {
+            streaming: self.buffers_state.streaming.load(Ordering::Acquire),
+        })
+    }
 }
-impl<M: Memory> QueueState for BuffersAllocated<M> {}
@@ -0,0 +1,66 @@
+//! Notifications about the buffer levels of a queue, to let producers adapt
+//! before frames are dropped.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Condition reported to the watermark callback of a queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatermarkEvent {
+    /// The number of free buffers (i.e. buffers that can be obtained with
+    /// `get_free_buffer()`) has dropped to `free`, which is at or below the
+    /// low watermark. Reported once until the level rises above the
+    /// watermark again.
+    LowFreeBuffers { free: usize },
+    /// The last queued buffer has been dequeued while the queue is streaming,
+    /// so the driver has no buffer to work with. For a CAPTURE queue this
+    /// means that the next frames will be dropped.
+    Underrun,
+}
+
+/// Callback invoked when a watermark condition is detected.
+pub type WatermarkCallback = Arc<dyn Fn(WatermarkEvent) + Send + Sync>;
+
+/// Watermark configuration and edge detection state of a queue.
+pub(super) struct Watermarks {
+    low_free_buffers: usize,
+    callback: WatermarkCallback,
+    /// Whether we are currently below the low watermark, so the event is
+    /// only reported once.
+    below_low: AtomicBool,
+}
+
+impl Watermarks {
+    pub(super) fn new(low_free_buffers: usize, callback: WatermarkCallback) -> Self {
+        Watermarks {
+            low_free_buffers,
+            callback,
+            below_low: AtomicBool::new(false),
+        }
+    }
+
+    /// Update the detection state after the number of free buffers changed to
+    /// `free`. Returns the callback to invoke and the event to report, if any.
+    pub(super) fn free_buffers_changed(
+        &self,
+        free: usize,
+    ) -> Option<(WatermarkCallback, WatermarkEvent)> {
+        if free > self.low_free_buffers {
+            self.below_low.store(false, Ordering::Release);
+            return None;
+        }
+
+        // Only the thread crossing the watermark reports it.
+        if self.below_low.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        Some((
+            Arc::clone(&self.callback),
+            WatermarkEvent::LowFreeBuffers { free },
+        ))
+    }
+
+    /// Returns the callback to invoke to report an underrun.
+    pub(super) fn underrun(&self) -> (WatermarkCallback, WatermarkEvent) {
+        (Arc::clone(&self.callback), WatermarkEvent::Underrun)
+    }
+}
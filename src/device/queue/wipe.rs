@@ -0,0 +1,100 @@
+//! Wiping of the memory of MMAP buffers, so the content of a frame does not
+//! leak from one user of a buffer to the next.
+use super::lock_ignore_poison;
+use crate::ioctl::PlaneMapping;
+use std::sync::Mutex;
+
+/// Content written into the memory of buffers, see
+/// `Queue::set_buffer_wipe()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferWipe {
+    /// Fill buffers with zeroes.
+    Zero,
+    /// Fill every byte of the buffers with this value, e.g. to make stale
+    /// content easy to spot.
+    Pattern(u8),
+}
+
+impl BufferWipe {
+    fn byte(self) -> u8 {
+        match self {
+            BufferWipe::Zero => 0,
+            BufferWipe::Pattern(byte) => byte,
+        }
+    }
+}
+
+/// Mappings of the planes of all the buffers of a queue, kept for as long as
+/// their wiping is enabled so buffers are not mapped again every time they
+/// are wiped.
+pub(super) struct BufferWiper {
+    wipe: BufferWipe,
+    /// Mappings of the planes of each buffer. A buffer is only wiped by the
+    /// thread returning it to the free list, so its lock is not contended.
+    buffers: Vec<Mutex<Vec<PlaneMapping>>>,
+}
+
+impl BufferWiper {
+    pub(super) fn new(wipe: BufferWipe) -> Self {
+        BufferWiper {
+            wipe,
+            buffers: Vec::new(),
+        }
+    }
+
+    pub(super) fn wipe_mode(&self) -> BufferWipe {
+        self.wipe
+    }
+
+    /// Keep `planes`, the mappings of the planes of buffer `index`.
+    pub(super) fn add_buffer(&mut self, index: usize, planes: Vec<PlaneMapping>) {
+        if self.buffers.len() <= index {
+            self.buffers.resize_with(index + 1, Default::default);
+        }
+        self.buffers[index] = Mutex::new(planes);
+    }
+
+    /// Fill the memory of buffer `index`, which must not be queued.
+    pub(super) fn wipe(&self, index: usize) {
+        if let Some(planes) = self.buffers.get(index) {
+            for plane in lock_ignore_poison(planes).iter_mut() {
+                plane.as_mut_slice().fill(self.wipe.byte());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ioctl;
+    use std::fs::{self, OpenOptions};
+
+    #[test]
+    fn wipe_buffers() {
+        let path = std::env::temp_dir().join(format!("v4l2-wipe-{}", std::process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(8192).unwrap();
+
+        let mut wiper = BufferWiper::new(BufferWipe::Pattern(0xa5));
+        for index in 0..2 {
+            let mut plane = ioctl::mmap(&file, index * 4096, 16).unwrap();
+            plane.as_mut_slice().copy_from_slice(b"previous content");
+            wiper.add_buffer(index as usize, vec![plane]);
+        }
+        // Not a buffer of ours.
+        wiper.wipe(2);
+
+        wiper.wipe(1);
+        let check = ioctl::mmap(&file, 0, 8192).unwrap();
+        assert_eq!(&check.as_slice()[..16], b"previous content");
+        assert!(check.as_slice()[4096..4112].iter().all(|&b| b == 0xa5));
+        fs::remove_file(&path).unwrap();
+    }
+}
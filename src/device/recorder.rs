@@ -0,0 +1,233 @@
+//! Recording of captured frames to disk, for analyzing them later.
+//!
+//! A recording is a directory containing numbered segments. Each segment is
+//! made of a data file (`segment-NNNNN.raw`), which is the concatenation of
+//! the raw frames, and an index file (`segment-NNNNN.idx`), a text file with
+//! one line per frame:
+//!
+//! ```text
+//! # NV12 640x480
+//! <sequence> <timestamp in µs> <flags> <offset in data file> <size>
+//! ```
+//!
+//! A new segment is started when the current one would exceed the maximum
+//! segment size, so recordings can be copied or deleted piece by piece.
+use crate::ioctl::DQBuffer;
+use crate::Format;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Entry of a segment index, describing one recorded frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub sequence: u32,
+    pub timestamp: Duration,
+    /// Raw V4L2 buffer flags.
+    pub flags: u32,
+    /// Offset of the frame in the data file of its segment.
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl IndexEntry {
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{} {} 0x{:08x} {} {}",
+            self.sequence,
+            self.timestamp.as_micros(),
+            self.flags,
+            self.offset,
+            self.size
+        )
+    }
+}
+
+impl FromStr for IndexEntry {
+    type Err = io::Error;
+
+    fn from_str(line: &str) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid index entry");
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(invalid());
+        }
+
+        Ok(IndexEntry {
+            sequence: fields[0].parse().map_err(|_| invalid())?,
+            timestamp: Duration::from_micros(fields[1].parse().map_err(|_| invalid())?),
+            flags: u32::from_str_radix(fields[2].trim_start_matches("0x"), 16)
+                .map_err(|_| invalid())?,
+            offset: fields[3].parse().map_err(|_| invalid())?,
+            size: fields[4].parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Returns the paths of the data and index files of segment `segment`.
+pub fn segment_paths(dir: &Path, segment: u32) -> (PathBuf, PathBuf) {
+    (
+        dir.join(format!("segment-{:05}.raw", segment)),
+        dir.join(format!("segment-{:05}.idx", segment)),
+    )
+}
+
+/// Read the index of segment `segment` of the recording in `dir`.
+pub fn read_index(dir: &Path, segment: u32) -> io::Result<Vec<IndexEntry>> {
+    let (_, index_path) = segment_paths(dir, segment);
+    BufReader::new(File::open(index_path)?)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.starts_with('#') || line.is_empty()))
+        .map(|line| line?.parse())
+        .collect()
+}
+
+/// Writes frames and their metadata into a segmented recording.
+pub struct Recorder {
+    dir: PathBuf,
+    header: String,
+    max_segment_size: u64,
+    segment: u32,
+    data: BufWriter<File>,
+    index: BufWriter<File>,
+    segment_size: u64,
+}
+
+impl Recorder {
+    /// Start a recording of frames with format `format` into `dir`, which is
+    /// created if needed. Segments are limited to `max_segment_size` bytes of
+    /// frame data, unless a single frame is larger than that.
+    pub fn new(dir: &Path, format: &Format, max_segment_size: u64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let header = format!(
+            "# {} {}x{}",
+            format.pixelformat, format.width, format.height
+        );
+        let (data, index) = Self::open_segment(dir, &header, 0)?;
+
+        Ok(Recorder {
+            dir: dir.to_path_buf(),
+            header,
+            max_segment_size,
+            segment: 0,
+            data,
+            index,
+            segment_size: 0,
+        })
+    }
+
+    fn open_segment(
+        dir: &Path,
+        header: &str,
+        segment: u32,
+    ) -> io::Result<(BufWriter<File>, BufWriter<File>)> {
+        let (data_path, index_path) = segment_paths(dir, segment);
+        let data = BufWriter::new(File::create(data_path)?);
+        let mut index = BufWriter::new(File::create(index_path)?);
+        writeln!(index, "{}", header)?;
+
+        Ok((data, index))
+    }
+
+    /// Returns the number of the segment currently being written.
+    pub fn segment(&self) -> u32 {
+        self.segment
+    }
+
+    /// Record a frame made of `planes`, with the given metadata.
+    pub fn record(
+        &mut self,
+        sequence: u32,
+        timestamp: Duration,
+        flags: u32,
+        planes: &[&[u8]],
+    ) -> io::Result<()> {
+        let size: u64 = planes.iter().map(|plane| plane.len() as u64).sum();
+        if self.segment_size > 0 && self.segment_size + size > self.max_segment_size {
+            self.data.flush()?;
+            self.index.flush()?;
+            self.segment += 1;
+            let (data, index) = Self::open_segment(&self.dir, &self.header, self.segment)?;
+            self.data = data;
+            self.index = index;
+            self.segment_size = 0;
+        }
+
+        for plane in planes {
+            self.data.write_all(plane)?;
+        }
+        IndexEntry {
+            sequence,
+            timestamp,
+            flags,
+            offset: self.segment_size,
+            size,
+        }
+        .write(&mut self.index)?;
+        self.segment_size += size;
+
+        Ok(())
+    }
+
+    /// Record the frame of dequeued buffer `dqbuf`, whose used data is
+    /// `planes`.
+    pub fn record_dqbuf(&mut self, dqbuf: &DQBuffer, planes: &[&[u8]]) -> io::Result<()> {
+        self.record(
+            dqbuf.sequence,
+            dqbuf.timestamp.as_duration(),
+            dqbuf.flags.bits(),
+            planes,
+        )
+    }
+
+    /// Flush all the recorded data to disk.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.data.flush()?;
+        self.index.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_segments() {
+        let dir = std::env::temp_dir().join(format!("v4l2-recorder-{}", std::process::id()));
+        let format = (b"GREY", (4, 2)).into();
+        let mut recorder = Recorder::new(&dir, &format, 16).unwrap();
+        for sequence in 0..3u32 {
+            let frame = [sequence as u8; 8];
+            let timestamp = Duration::from_millis(33 * sequence as u64);
+            recorder
+                .record(sequence, timestamp, 0x4000, &[&frame[..4], &frame[4..]])
+                .unwrap();
+        }
+        assert_eq!(recorder.segment(), 1);
+        recorder.finish().unwrap();
+
+        let first = read_index(&dir, 0).unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(
+            first[1],
+            IndexEntry {
+                sequence: 1,
+                timestamp: Duration::from_millis(33),
+                flags: 0x4000,
+                offset: 8,
+                size: 8,
+            }
+        );
+        assert_eq!(read_index(&dir, 1).unwrap()[0].offset, 0);
+        let (data_path, index_path) = segment_paths(&dir, 0);
+        assert_eq!(fs::read(data_path).unwrap(), [[0u8; 8], [1u8; 8]].concat());
+        assert!(fs::read_to_string(index_path)
+            .unwrap()
+            .starts_with("# GREY 4x2\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
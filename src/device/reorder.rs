@@ -0,0 +1,143 @@
+//! Delivery of dequeued frames in sequence order, for drivers that may
+//! complete buffers out of order.
+use crate::ioctl::FrameSequence;
+
+/// Buffers items (typically dequeued buffers) that arrive out of order, and
+/// returns them strictly in the order of their sequence number.
+///
+/// The first item pushed defines the start of the sequence. When the item
+/// with the next expected sequence number is missing, up to `depth` later
+/// items are held back waiting for it. Past that, the missing item is
+/// considered lost and the earliest held item is released. Items arriving
+/// after a later one has been released are rejected.
+///
+/// Held items keep their buffers away from the driver, so `depth` must be
+/// lower than the number of buffers of the queue for streaming to continue.
+pub struct ReorderBuffer<T> {
+    depth: usize,
+    next_sequence: Option<FrameSequence>,
+    pending: Vec<(u32, T)>,
+}
+
+impl<T> ReorderBuffer<T> {
+    /// Create a reorder buffer holding at most `depth` items while waiting for
+    /// a missing one.
+    pub fn new(depth: usize) -> Self {
+        ReorderBuffer {
+            depth,
+            next_sequence: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Distance of `sequence` from the next expected sequence number, or
+    /// `None` if `sequence` is before it.
+    fn distance(&self, sequence: u32) -> Option<u32> {
+        match self.next_sequence {
+            Some(next_sequence) => FrameSequence(sequence).distance_since(next_sequence),
+            None => Some(0),
+        }
+    }
+
+    /// Add `item` with sequence number `sequence`. The item is given back if
+    /// it arrives too late, i.e. after a later item has already been
+    /// returned, or if an item with the same sequence number is pending.
+    pub fn push(&mut self, sequence: u32, item: T) -> std::result::Result<(), T> {
+        if self.distance(sequence).is_none() || self.pending.iter().any(|(s, _)| *s == sequence) {
+            return Err(item);
+        }
+
+        if self.next_sequence.is_none() {
+            self.next_sequence = Some(FrameSequence(sequence));
+        }
+        self.pending.push((sequence, item));
+        Ok(())
+    }
+
+    /// Returns the next item in sequence order, if it is available or if
+    /// enough items are held to consider the missing ones lost.
+    pub fn pop(&mut self) -> Option<T> {
+        let earliest = self.earliest()?;
+        if self.distance(self.pending[earliest].0) == Some(0) || self.pending.len() > self.depth {
+            Some(self.take(earliest))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the earliest held item regardless of missing ones, e.g. to
+    /// drain the buffer at the end of a stream.
+    pub fn flush(&mut self) -> Option<T> {
+        let earliest = self.earliest()?;
+        Some(self.take(earliest))
+    }
+
+    /// Returns the number of items currently held.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns true if no item is currently held.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Drop all held items and restart the sequence with the next pushed
+    /// item, e.g. after a `streamoff()`.
+    pub fn reset(&mut self) {
+        self.next_sequence = None;
+        self.pending.clear();
+    }
+
+    fn earliest(&self) -> Option<usize> {
+        self.pending
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (sequence, _))| self.distance(*sequence))
+            .map(|(index, _)| index)
+    }
+
+    fn take(&mut self, index: usize) -> T {
+        let (sequence, item) = self.pending.swap_remove(index);
+        self.next_sequence = Some(FrameSequence(sequence).next());
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReorderBuffer;
+
+    #[test]
+    fn reorder() {
+        let mut reorder = ReorderBuffer::new(2);
+        let mut out = Vec::new();
+        for &sequence in &[10u32, 12, 11, 13] {
+            reorder.push(sequence, sequence).unwrap();
+            while let Some(item) = reorder.pop() {
+                out.push(item);
+            }
+        }
+        assert_eq!(out, vec![10, 11, 12, 13]);
+
+        // 14 is lost: 15 and 16 are held, then released once 17 arrives.
+        for &sequence in &[15u32, 16] {
+            reorder.push(sequence, sequence).unwrap();
+            assert_eq!(reorder.pop(), None);
+        }
+        reorder.push(17, 17).unwrap();
+        assert_eq!(reorder.pop(), Some(15));
+        assert_eq!(reorder.pop(), Some(16));
+        assert_eq!(reorder.pop(), Some(17));
+        assert_eq!(reorder.push(14, 14), Err(14));
+
+        // Wrapping sequence numbers, and draining.
+        reorder.reset();
+        reorder.push(u32::MAX, 1).unwrap();
+        reorder.push(1, 3).unwrap();
+        assert_eq!(reorder.pop(), Some(1));
+        assert_eq!(reorder.pop(), None);
+        assert_eq!(reorder.flush(), Some(3));
+        assert!(reorder.is_empty());
+    }
+}
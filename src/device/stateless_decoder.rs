@@ -0,0 +1,332 @@
+//! High-level interface to stateless decoders, i.e. decoders which are given
+//! every frame along with the codec parameters needed to decode it, which the
+//! user obtains by parsing the bitstream.
+//!
+//! Each frame is submitted as a job made of a media request, the controls
+//! holding its parameters and an OUTPUT buffer holding its data. The decoder
+//! takes care of allocating and recycling requests and buffers, and of
+//! waiting for jobs to complete, so decoding a stream boils down to calling
+//! `StatelessDecoder::decode()` for every frame and
+//! `StatelessDecoder::next_frame()` to obtain the decoded frames.
+use super::queue::direction::{Capture, Output};
+use super::queue::dqbuf::DQBuffer;
+use super::queue::qbuf::Plane;
+use super::queue::states::BuffersAllocated;
+use super::queue::Queue;
+use super::Device;
+use crate::ioctl::{query_ext_ctrl, CtrlReading, ExtControl, QueryExtCtrl};
+use crate::memory::{UserPtr, MMAP};
+use crate::request::Request;
+use crate::{CtrlId, Error, PixelFormat, Result};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Options that can be specified when creating a `StatelessDecoder`.
+pub struct StatelessDecoderConfig {
+    coded_format: PixelFormat,
+    width: usize,
+    height: usize,
+    decoded_format: Option<PixelFormat>,
+    num_output_buffers: u32,
+    num_capture_buffers: u32,
+    readback_ctrls: Vec<CtrlId>,
+}
+
+impl StatelessDecoderConfig {
+    /// Create the configuration for decoding a stream of `coded_format` (e.g.
+    /// `S264` or `FWHC`), with a coded resolution of `width`x`height`.
+    pub fn new(coded_format: impl Into<PixelFormat>, width: usize, height: usize) -> Self {
+        StatelessDecoderConfig {
+            coded_format: coded_format.into(),
+            width,
+            height,
+            decoded_format: None,
+            num_output_buffers: 4,
+            num_capture_buffers: 8,
+            readback_ctrls: Vec::new(),
+        }
+    }
+
+    /// Format to decode frames into. The format chosen by the driver is used
+    /// if this is not specified.
+    pub fn decoded_format(self, decoded_format: impl Into<PixelFormat>) -> Self {
+        StatelessDecoderConfig {
+            decoded_format: Some(decoded_format.into()),
+            ..self
+        }
+    }
+
+    /// Number of OUTPUT buffers to allocate, which is the number of jobs
+    /// that can be pending at the same time.
+    pub fn num_output_buffers(self, num_output_buffers: u32) -> Self {
+        StatelessDecoderConfig {
+            num_output_buffers,
+            ..self
+        }
+    }
+
+    /// Number of CAPTURE buffers to allocate. This must be large enough to
+    /// hold all the reference frames of the stream, plus the frames being
+    /// decoded.
+    pub fn num_capture_buffers(self, num_capture_buffers: u32) -> Self {
+        StatelessDecoderConfig {
+            num_capture_buffers,
+            ..self
+        }
+    }
+
+    /// Controls to read back from the request of every frame once it has
+    /// been decoded, e.g. the decoding status reported by the driver. Their
+    /// values are returned in `DecodedFrame::controls`.
+    pub fn readback_ctrls(self, readback_ctrls: Vec<CtrlId>) -> Self {
+        StatelessDecoderConfig {
+            readback_ctrls,
+            ..self
+        }
+    }
+}
+
+/// A frame that has been decoded.
+pub struct DecodedFrame {
+    /// Timestamp of the frame, in nanoseconds. This is the value to pass in
+    /// the codec parameters of later frames to use this one as reference.
+    pub timestamp: u64,
+    /// The CAPTURE buffer holding the frame. It is not used for decoding
+    /// again until dropped, so it must be kept for as long as the frame is
+    /// used as a reference.
+    pub buffer: DQBuffer<MMAP>,
+    /// Values of the controls given to `StatelessDecoderConfig::readback_ctrls`,
+    /// as read from the request of the frame after it completed.
+    pub controls: Vec<CtrlReading>,
+}
+
+/// A frame submitted to the decoder, waiting for its request to complete.
+struct Job {
+    request: Request,
+    timestamp: u64,
+}
+
+/// A stateless decoder, using USERPTR buffers for the encoded data and MMAP
+/// buffers for the decoded frames.
+pub struct StatelessDecoder {
+    device: Arc<Mutex<Device>>,
+    /// The media device requests are allocated from.
+    media: File,
+    /// The controls to read back from completed requests.
+    readback_ctrls: Vec<QueryExtCtrl>,
+    output_queue: Queue<Output, BuffersAllocated<UserPtr<Vec<u8>>>>,
+    capture_queue: Queue<Capture, BuffersAllocated<MMAP>>,
+    /// Memory of the OUTPUT buffers that have been dequeued, for reuse.
+    output_backings: Vec<Vec<u8>>,
+    free_requests: Vec<Request>,
+    pending_jobs: VecDeque<Job>,
+    ready_frames: VecDeque<DecodedFrame>,
+    next_timestamp: u64,
+}
+
+impl StatelessDecoder {
+    /// Create a decoder using the video device `device`, and `media`, the
+    /// opened media device it belongs to.
+    ///
+    /// The format of both queues is set according to `config` and their
+    /// buffers are allocated, after which both queues are streamed on.
+    /// `Error::InvalidFormat` is returned if the driver does not support the
+    /// requested formats.
+    pub fn new(
+        device: Arc<Mutex<Device>>,
+        media: File,
+        config: StatelessDecoderConfig,
+    ) -> Result<Self> {
+        let (mut output_queue, mut capture_queue) =
+            match Queue::get_output_queue(Arc::clone(&device)) {
+                Ok(output_queue) => (output_queue, Queue::get_capture_queue(Arc::clone(&device))?),
+                Err(_) => (
+                    Queue::get_output_mplane_queue(Arc::clone(&device))?,
+                    Queue::get_capture_mplane_queue(Arc::clone(&device))?,
+                ),
+            };
+
+        // Setting the coded format may change the decoded format, so it
+        // must be done first.
+        let output_format = output_queue
+            .change_format()?
+            .set_size(config.width, config.height)
+            .set_pixelformat(config.coded_format)
+            .apply()?;
+        if output_format.pixelformat != config.coded_format {
+            return Err(Error::InvalidFormat);
+        }
+
+        if let Some(decoded_format) = config.decoded_format {
+            let capture_format = capture_queue
+                .change_format()?
+                .set_pixelformat(decoded_format)
+                .apply()?;
+            if capture_format.pixelformat != decoded_format {
+                return Err(Error::InvalidFormat);
+            }
+        }
+
+        let readback_ctrls = {
+            let device = device.lock().map_err(|_| Error::Poisoned)?;
+            config
+                .readback_ctrls
+                .iter()
+                .map(|&id| query_ext_ctrl(&*device, id))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        let output_queue = output_queue.request_buffers(config.num_output_buffers)?;
+        let capture_queue = capture_queue.request_buffers(config.num_capture_buffers)?;
+        output_queue.streamon()?;
+        capture_queue.streamon()?;
+
+        Ok(StatelessDecoder {
+            device,
+            media,
+            readback_ctrls,
+            output_queue,
+            capture_queue,
+            output_backings: Vec::new(),
+            free_requests: Vec::new(),
+            pending_jobs: VecDeque::new(),
+            ready_frames: VecDeque::new(),
+            next_timestamp: 0,
+        })
+    }
+
+    /// Returns the CAPTURE queue, e.g. to obtain the decoded format or to
+    /// map the buffers of decoded frames.
+    pub fn capture_queue(&self) -> &Queue<Capture, BuffersAllocated<MMAP>> {
+        &self.capture_queue
+    }
+
+    /// Returns the number of frames that have been submitted, but have not
+    /// been returned by `next_frame()` yet.
+    pub fn num_pending_frames(&self) -> usize {
+        self.pending_jobs.len() + self.ready_frames.len()
+    }
+
+    /// Submit the frame made of `bitstream` and decoded using the codec
+    /// parameters in `controls`. Returns the timestamp of the frame, which
+    /// identifies it when it is used as a reference.
+    ///
+    /// If all the OUTPUT buffers are in use, this waits for the oldest
+    /// pending frame to be decoded. `Error::AlreadyBorrowed` is returned if
+    /// no CAPTURE buffer can receive the frame, i.e. if they are all held by
+    /// `DecodedFrame`s or pending frames.
+    pub fn decode(&mut self, bitstream: &[u8], controls: &mut [ExtControl]) -> Result<u64> {
+        if self.pending_jobs.len() >= self.output_queue.num_buffers() {
+            self.complete_job()?;
+        }
+
+        self.capture_queue.queue_free_buffers()?;
+        if self.capture_queue.num_queued_buffers() <= self.pending_jobs.len() {
+            return Err(Error::AlreadyBorrowed);
+        }
+
+        let request = match self.free_requests.pop() {
+            Some(request) => request,
+            None => Request::alloc(&self.media)?,
+        };
+        let mut device = self.device.lock().map_err(|_| Error::Poisoned)?;
+        let result = request.set_ctrls(&mut *device, controls);
+        drop(device);
+        if let Err(e) = result {
+            self.recycle_request(request)?;
+            return Err(e);
+        }
+
+        // Buffers must be at least as large as the plane size of the format.
+        let plane_size = self.output_queue.plane_sizes().first().copied();
+        let mut backing = self.output_backings.pop().unwrap_or_default();
+        backing.clear();
+        backing.extend_from_slice(bitstream);
+        backing.resize(plane_size.unwrap_or(0).max(bitstream.len()), 0);
+
+        let timestamp = Duration::from_micros(self.next_timestamp);
+        self.next_timestamp += 1;
+
+        let buffer = self.output_queue.get_free_buffer()?;
+        let index = buffer.index();
+        let queued = buffer
+            .add_plane(Plane::out(backing, bitstream.len()))
+            .set_timestamp(timestamp)
+            .set_request(&request)
+            .queue();
+        if let Err(e) = queued {
+            self.output_backings.extend(e.plane_handles);
+            self.recycle_request(request)?;
+            return Err(e.error);
+        }
+        if let Err(e) = request.queue() {
+            // Reinitializing the request makes the driver release the OUTPUT
+            // buffer, which can then be reused.
+            self.recycle_request(request)?;
+            let canceled = self.output_queue.cancel_buffer(index)?;
+            self.output_backings.extend(canceled.plane_handles);
+            return Err(e);
+        }
+
+        let timestamp = timestamp.as_nanos() as u64;
+        self.pending_jobs.push_back(Job { request, timestamp });
+
+        Ok(timestamp)
+    }
+
+    /// Returns the next decoded frame, in submission order, waiting for it
+    /// to be decoded if needed. Returns `None` if there are no pending
+    /// frames.
+    pub fn next_frame(&mut self) -> Result<Option<DecodedFrame>> {
+        if self.ready_frames.is_empty() && !self.pending_jobs.is_empty() {
+            self.complete_job()?;
+        }
+
+        Ok(self.ready_frames.pop_front())
+    }
+
+    /// Wait for the oldest pending job to complete, and move its frame to
+    /// the ready frames.
+    fn complete_job(&mut self) -> Result<()> {
+        let controls = match self.pending_jobs.front() {
+            Some(job) => {
+                job.request.wait(None)?;
+                if self.readback_ctrls.is_empty() {
+                    Vec::new()
+                } else {
+                    // Read back the controls before anything else, so the job
+                    // is still pending if this fails.
+                    let device = self.device.lock().map_err(|_| Error::Poisoned)?;
+                    job.request
+                        .get_ctrl_values(&*device, &self.readback_ctrls)?
+                }
+            }
+            None => return Ok(()),
+        };
+        // Safe to unwrap as we have just checked that there is a job.
+        let job = self.pending_jobs.pop_front().unwrap();
+
+        // We don't need the encoded data back, but can reuse its memory.
+        let mut output_buffer = self.output_queue.dequeue()?;
+        self.output_backings
+            .append(&mut output_buffer.plane_handles);
+        let buffer = self.capture_queue.dequeue()?;
+
+        self.ready_frames.push_back(DecodedFrame {
+            timestamp: job.timestamp,
+            buffer,
+            controls,
+        });
+        self.recycle_request(job.request)
+    }
+
+    /// Make `request` available for another job.
+    fn recycle_request(&mut self, request: Request) -> Result<()> {
+        request.reinit()?;
+        self.free_requests.push(request);
+
+        Ok(())
+    }
+}
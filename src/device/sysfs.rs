@@ -0,0 +1,314 @@
+//! Helpers to look up information about V4L2 devices in sysfs.
+//!
+//! Device nodes are matched to their sysfs entries using their major and
+//! minor numbers, so these helpers work with any path to a device node,
+//! including symbolic links like the ones in `/dev/v4l/by-id`.
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// Returns the canonical sysfs directory of the character device node at
+/// `dev_path`, e.g. `/sys/devices/.../video4linux/video0`.
+pub fn sysfs_path(dev_path: &Path) -> io::Result<PathBuf> {
+    let metadata = fs::metadata(dev_path)?;
+    if !metadata.file_type().is_char_device() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a character device", dev_path.display()),
+        ));
+    }
+
+    let rdev = metadata.rdev();
+    fs::canonicalize(format!(
+        "/sys/dev/char/{}:{}",
+        nix::sys::stat::major(rdev),
+        nix::sys::stat::minor(rdev)
+    ))
+}
+
+/// Returns the canonical sysfs directory of the hardware device behind the
+/// device node at `dev_path`, e.g. the USB interface of a webcam.
+pub fn hardware_path(dev_path: &Path) -> io::Result<PathBuf> {
+    fs::canonicalize(sysfs_path(dev_path)?.join("device"))
+}
+
+/// Read the sysfs attribute `name` in directory `dir`, without its trailing
+/// newline. Returns `None` if the attribute does not exist or is unreadable.
+fn read_attribute(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|value| value.trim_end().to_string())
+}
+
+/// Identity of a V4L2 device node, i.e. information that allows to find "the
+/// same device" again after a reboot or after it has been plugged into
+/// another port, when its `/dev/videoN` node may have changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    /// Path to the device node, e.g. `/dev/video0`.
+    pub dev_path: PathBuf,
+    /// Name of the device as reported by the driver.
+    pub name: String,
+    /// Vendor ID of the USB or PCI device, as a hexadecimal string.
+    pub vendor_id: Option<String>,
+    /// Product ID of the USB or PCI device, as a hexadecimal string.
+    pub product_id: Option<String>,
+    /// Serial number of the USB device, if it has one.
+    pub serial: Option<String>,
+    /// Links to the node in `/dev/v4l/by-id`. They are stable across
+    /// reboots and ports, as long as the device has a serial number.
+    pub by_id: Vec<PathBuf>,
+    /// Links to the node in `/dev/v4l/by-path`. They are stable across
+    /// reboots as long as the device remains on the same port.
+    pub by_path: Vec<PathBuf>,
+}
+
+/// Returns the links in `dir` that point to `dev_path`, which must be
+/// canonical.
+fn links_to(dir: &str, dev_path: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // No udev, or no device ever plugged.
+        Err(_) => return Vec::new(),
+    };
+
+    let mut links: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|link| matches!(fs::canonicalize(link), Ok(target) if target == dev_path))
+        .collect();
+    links.sort();
+    links
+}
+
+/// Returns the identity of the device node at `dev_path`.
+pub fn identity(dev_path: &Path) -> io::Result<DeviceIdentity> {
+    let dev_path = fs::canonicalize(dev_path)?;
+    let sysfs_path = sysfs_path(&dev_path)?;
+    let mut identity = DeviceIdentity {
+        name: read_attribute(&sysfs_path, "name").unwrap_or_default(),
+        by_id: links_to("/dev/v4l/by-id", &dev_path),
+        by_path: links_to("/dev/v4l/by-path", &dev_path),
+        dev_path,
+        ..Default::default()
+    };
+
+    // Look for the USB or PCI device the node belongs to. For USB devices,
+    // the node is attached to an interface, so we need to go up one level.
+    let hardware_path = match fs::canonicalize(sysfs_path.join("device")) {
+        Ok(path) => path,
+        // Virtual devices have no hardware.
+        Err(_) => return Ok(identity),
+    };
+    for dir in hardware_path.ancestors().take(2) {
+        if let Some(vendor_id) = read_attribute(dir, "idVendor") {
+            identity.vendor_id = Some(vendor_id);
+            identity.product_id = read_attribute(dir, "idProduct");
+            identity.serial = read_attribute(dir, "serial");
+            break;
+        }
+        if let Some(vendor_id) = read_attribute(dir, "vendor") {
+            identity.vendor_id = Some(vendor_id.trim_start_matches("0x").to_string());
+            identity.product_id =
+                read_attribute(dir, "device").map(|id| id.trim_start_matches("0x").to_string());
+            break;
+        }
+    }
+
+    Ok(identity)
+}
+
+/// Returns the identities of all the V4L2 video nodes of the system, sorted by
+/// device node.
+pub fn enumerate() -> io::Result<Vec<DeviceIdentity>> {
+    let mut devices = Vec::new();
+    let entries = match fs::read_dir("/sys/class/video4linux") {
+        Ok(entries) => entries,
+        // The V4L2 core is not loaded, so there cannot be any device.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(devices),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let name = entry?.file_name();
+        let name = match name.to_str() {
+            Some(name) if name.starts_with("video") => name,
+            // Radio, VBI, sub-devices...
+            _ => continue,
+        };
+
+        match identity(&Path::new("/dev").join(name)) {
+            Ok(identity) => devices.push(identity),
+            // The device may have been unplugged, or its node not have been
+            // created by udev yet.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    devices.sort_by(|a, b| a.dev_path.cmp(&b.dev_path));
+    Ok(devices)
+}
+
+/// Returns the device node that `/dev/v4l/by-id/<id>` points to.
+pub fn find_by_id(id: &str) -> io::Result<PathBuf> {
+    fs::canonicalize(Path::new("/dev/v4l/by-id").join(id))
+}
+
+/// Returns the device node that `/dev/v4l/by-path/<path>` points to.
+pub fn find_by_path(path: &str) -> io::Result<PathBuf> {
+    fs::canonicalize(Path::new("/dev/v4l/by-path").join(path))
+}
+
+/// An ALSA sound card, as listed in `/sys/class/sound`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlsaCard {
+    /// Index of the card, i.e. `N` in `cardN`.
+    pub index: u32,
+    /// Identifier of the card, which is stable across reboots contrary to
+    /// its index.
+    pub id: String,
+    /// Canonical sysfs directory of the hardware device of the card.
+    pub hardware_path: PathBuf,
+}
+
+impl AlsaCard {
+    /// Returns the ALSA device name to use to open this card, e.g.
+    /// `hw:CARD=U0x46d0x825`.
+    pub fn hw_name(&self) -> String {
+        format!("hw:CARD={}", self.id)
+    }
+}
+
+/// Returns all the ALSA cards of the system. Cards that are not backed by a
+/// hardware device, e.g. some virtual ones, are skipped.
+pub fn alsa_cards() -> io::Result<Vec<AlsaCard>> {
+    let mut cards = Vec::new();
+    let entries = match fs::read_dir("/sys/class/sound") {
+        Ok(entries) => entries,
+        // The ALSA core is not loaded, so there cannot be any card.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(cards),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let index = match name
+            .to_str()
+            .and_then(|name| name.strip_prefix("card"))
+            .and_then(|index| index.parse().ok())
+        {
+            Some(index) => index,
+            // Not a card, but one of its PCM or control devices.
+            None => continue,
+        };
+
+        let path = entry.path();
+        let hardware_path = match fs::canonicalize(path.join("device")) {
+            Ok(hardware_path) => hardware_path,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        cards.push(AlsaCard {
+            index,
+            id: read_attribute(&path, "id").unwrap_or_default(),
+            hardware_path,
+        });
+    }
+
+    cards.sort_by_key(|card| card.index);
+    Ok(cards)
+}
+
+/// Returns the ALSA cards that belong to the same hardware as the V4L2 device
+/// node at `dev_path`, e.g. the microphone of a webcam or the audio input of
+/// a capture card.
+///
+/// Cards are first looked up on the hardware device of the node itself, then
+/// on the other interfaces of the same USB device, or the other functions of
+/// the same PCI device, which is how audio and video functions of a device
+/// are grouped. An empty vector is returned if no card is found.
+pub fn find_alsa_cards(dev_path: &Path) -> io::Result<Vec<AlsaCard>> {
+    Ok(cards_of(&hardware_path(dev_path)?, alsa_cards()?))
+}
+
+/// Returns the cards among `cards` that belong to `hardware_path`, as
+/// described in `find_alsa_cards()`.
+fn cards_of(hardware_path: &Path, cards: Vec<AlsaCard>) -> Vec<AlsaCard> {
+    let (same_device, others): (Vec<_>, Vec<_>) = cards
+        .into_iter()
+        .partition(|card| card.hardware_path == hardware_path);
+    if !same_device.is_empty() {
+        return same_device;
+    }
+
+    let group = match function_group(hardware_path) {
+        Some(group) => group,
+        None => return Vec::new(),
+    };
+    others
+        .into_iter()
+        .filter(|card| function_group(&card.hardware_path) == Some(group))
+        .collect()
+}
+
+/// Returns what identifies the device `path` is a function of: its parent
+/// directory, and its name without the function number. USB interfaces are
+/// named `<port>:<config>.<interface>` and PCI functions
+/// `<domain>:<bus>:<slot>.<function>`, below the same parent directory.
+///
+/// Paths are compared exactly, as all the devices below e.g. a PCI root
+/// bridge share its path as a prefix.
+fn function_group(path: &Path) -> Option<(&Path, &str)> {
+    let name = path.file_name()?.to_str()?;
+    let device = &name[..name.rfind('.')?];
+
+    Some((path.parent()?, device))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(index: u32, hardware_path: &str) -> AlsaCard {
+        AlsaCard {
+            index,
+            id: format!("Card{}", index),
+            hardware_path: PathBuf::from(hardware_path),
+        }
+    }
+
+    #[test]
+    fn cards_of_device() {
+        let cards = vec![
+            card(0, "/sys/devices/pci0000:00/0000:00:1f.3"),
+            card(3, "/sys/devices/pci0000:00/0000:00:1c.0/0000:03:00.1"),
+            card(1, "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-1/1-1:1.2"),
+            card(2, "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.2"),
+        ];
+
+        // USB webcam, whose microphone is another interface of the device.
+        let webcam = Path::new("/sys/devices/pci0000:00/0000:00:14.0/usb1/1-1/1-1:1.0");
+        let found = cards_of(webcam, cards.clone());
+        assert_eq!(found.iter().map(|c| c.index).collect::<Vec<_>>(), vec![1]);
+
+        // Card on the same device as the video node.
+        let capture_card = Path::new("/sys/devices/pci0000:00/0000:00:1f.3");
+        let found = cards_of(capture_card, cards.clone());
+        assert_eq!(found.iter().map(|c| c.index).collect::<Vec<_>>(), vec![0]);
+
+        // Audio function of a PCI capture card.
+        let pci_card = Path::new("/sys/devices/pci0000:00/0000:00:1c.0/0000:03:00.0");
+        let found = cards_of(pci_card, cards.clone());
+        assert_eq!(found.iter().map(|c| c.index).collect::<Vec<_>>(), vec![3]);
+
+        // A device directly below the root bridge must not match the other
+        // cards below it.
+        let gpu = Path::new("/sys/devices/pci0000:00/0000:00:02.0");
+        assert!(cards_of(gpu, cards.clone()).is_empty());
+        let platform = Path::new("/sys/devices/platform/vivid.0");
+        assert!(cards_of(platform, cards).is_empty());
+    }
+}
@@ -0,0 +1,297 @@
+//! Information about pixel formats that V4L2 only describes in its
+//! documentation.
+//!
+//! Many planar formats come in two variants: a contiguous one (e.g. `NV12`)
+//! where all the color planes are stored in a single memory plane, and a
+//! non-contiguous one (e.g. `NV12M`) where each color plane has its own
+//! memory plane. Drivers typically only support one of them, while other
+//! components (GPUs, displays) may expect the other, so this module helps
+//! picking a variant and converting formats and plane offsets between them.
+//!
+//! The `drm` submodule maps V4L2 formats to the DRM formats used to share
+//! buffers with displays and GPUs, the `info` submodule describes the layout
+//! of common formats, and the `negotiate` submodule picks the best format and
+//! frame size supported by a device.
+mod drm;
+mod info;
+mod negotiate;
+
+pub use drm::*;
+pub use info::*;
+pub use negotiate::*;
+
+use crate::{Format, PixelFormat, PlanePixFormat};
+
+/// Layout of one color plane of a planar format, relative to the first one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ColorPlane {
+    /// Divider to apply to the bytes per line of the first plane.
+    bytesperline_div: u32,
+    /// Divider to apply to the height of the first plane.
+    height_div: u32,
+}
+
+const fn plane(bytesperline_div: u32, height_div: u32) -> ColorPlane {
+    ColorPlane {
+        bytesperline_div,
+        height_div,
+    }
+}
+
+const LUMA: ColorPlane = plane(1, 1);
+
+/// A planar layout available in both variants.
+struct PlanarLayout {
+    contiguous: &'static [u8; 4],
+    non_contiguous: &'static [u8; 4],
+    planes: &'static [ColorPlane],
+}
+
+const PLANAR_LAYOUTS: &[PlanarLayout] = &[
+    PlanarLayout {
+        contiguous: b"NV12",
+        non_contiguous: b"NM12",
+        planes: &[LUMA, plane(1, 2)],
+    },
+    PlanarLayout {
+        contiguous: b"NV21",
+        non_contiguous: b"NM21",
+        planes: &[LUMA, plane(1, 2)],
+    },
+    PlanarLayout {
+        contiguous: b"NV16",
+        non_contiguous: b"NM16",
+        planes: &[LUMA, plane(1, 1)],
+    },
+    PlanarLayout {
+        contiguous: b"NV61",
+        non_contiguous: b"NM61",
+        planes: &[LUMA, plane(1, 1)],
+    },
+    PlanarLayout {
+        contiguous: b"YU12",
+        non_contiguous: b"YM12",
+        planes: &[LUMA, plane(2, 2), plane(2, 2)],
+    },
+    PlanarLayout {
+        contiguous: b"YV12",
+        non_contiguous: b"YM21",
+        planes: &[LUMA, plane(2, 2), plane(2, 2)],
+    },
+    PlanarLayout {
+        contiguous: b"422P",
+        non_contiguous: b"YM16",
+        planes: &[LUMA, plane(2, 1), plane(2, 1)],
+    },
+];
+
+fn find_layout(format: PixelFormat) -> Option<&'static PlanarLayout> {
+    PLANAR_LAYOUTS.iter().find(|layout| {
+        format == PixelFormat::from(layout.contiguous)
+            || format == PixelFormat::from(layout.non_contiguous)
+    })
+}
+
+/// Returns true if `format` is the non-contiguous variant of a planar format,
+/// i.e. uses one memory plane per color plane.
+pub fn is_non_contiguous(format: PixelFormat) -> bool {
+    matches!(find_layout(format), Some(layout) if format == layout.non_contiguous.into())
+}
+
+/// Returns the contiguous variant of `format`, which can be `format` itself.
+/// Returns `None` if `format` is not a known planar format.
+pub fn contiguous_variant(format: PixelFormat) -> Option<PixelFormat> {
+    find_layout(format).map(|layout| layout.contiguous.into())
+}
+
+/// Returns the non-contiguous variant of `format`, which can be `format`
+/// itself. Returns `None` if `format` is not a known planar format.
+pub fn non_contiguous_variant(format: PixelFormat) -> Option<PixelFormat> {
+    find_layout(format).map(|layout| layout.non_contiguous.into())
+}
+
+/// Returns the number of color planes of `format`, or `None` if it is not a
+/// known planar format.
+pub fn num_color_planes(format: PixelFormat) -> Option<usize> {
+    find_layout(format).map(|layout| layout.planes.len())
+}
+
+/// Choose which variant of `wanted` to use among the `available` formats,
+/// typically the ones returned by `Queue::format_iter()`.
+///
+/// `wanted` is returned if it is available, otherwise its other variant if
+/// that one is. `None` is returned if neither is available.
+pub fn choose_variant<I>(wanted: PixelFormat, available: I) -> Option<PixelFormat>
+where
+    I: IntoIterator<Item = PixelFormat>,
+{
+    let other = find_layout(wanted).map(|layout| {
+        if wanted == layout.contiguous.into() {
+            layout.non_contiguous.into()
+        } else {
+            layout.contiguous.into()
+        }
+    });
+
+    let mut found_other = None;
+    for format in available {
+        if format == wanted {
+            return Some(wanted);
+        } else if Some(format) == other {
+            found_other = Some(format);
+        }
+    }
+
+    found_other
+}
+
+/// Compute the offset of each color plane within the single memory plane of
+/// a contiguous `format`, using the bytes per line of its first plane.
+///
+/// Returns `None` if `format` is not the contiguous variant of a planar
+/// format, or has no plane information.
+pub fn contiguous_plane_offsets(format: &Format) -> Option<Vec<usize>> {
+    let layout = find_layout(format.pixelformat)?;
+    if format.pixelformat != layout.contiguous.into() {
+        return None;
+    }
+    let bytesperline = format.plane_fmt.first()?.bytesperline;
+
+    let mut offset = 0;
+    Some(
+        layout
+            .planes
+            .iter()
+            .map(|plane| {
+                let plane_offset = offset;
+                offset += color_plane_size(plane, bytesperline, format.height);
+                plane_offset
+            })
+            .collect(),
+    )
+}
+
+fn color_plane_size(plane: &ColorPlane, bytesperline: u32, height: u32) -> usize {
+    let height = height.div_ceil(plane.height_div);
+    (bytesperline / plane.bytesperline_div) as usize * height as usize
+}
+
+/// Convert `format` into its contiguous variant, merging its memory planes
+/// into a single one. Formats that are already contiguous are returned as-is.
+///
+/// Returns `None` if `format` is not a known planar format.
+pub fn to_contiguous(format: &Format) -> Option<Format> {
+    let layout = find_layout(format.pixelformat)?;
+    if format.pixelformat == layout.contiguous.into() {
+        return Some(format.clone());
+    }
+
+    let bytesperline = format.plane_fmt.first()?.bytesperline;
+    Some(Format {
+        pixelformat: layout.contiguous.into(),
+        plane_fmt: vec![PlanePixFormat {
+            sizeimage: format.plane_fmt.iter().map(|plane| plane.sizeimage).sum(),
+            bytesperline,
+        }],
+        ..format.clone()
+    })
+}
+
+/// Convert `format` into its non-contiguous variant, splitting its memory
+/// plane into one per color plane. Formats that are already non-contiguous
+/// are returned as-is.
+///
+/// Returns `None` if `format` is not a known planar format.
+pub fn to_non_contiguous(format: &Format) -> Option<Format> {
+    let layout = find_layout(format.pixelformat)?;
+    if format.pixelformat == layout.non_contiguous.into() {
+        return Some(format.clone());
+    }
+
+    let bytesperline = format.plane_fmt.first()?.bytesperline;
+    Some(Format {
+        pixelformat: layout.non_contiguous.into(),
+        plane_fmt: layout
+            .planes
+            .iter()
+            .map(|plane| PlanePixFormat {
+                sizeimage: color_plane_size(plane, bytesperline, format.height) as u32,
+                bytesperline: bytesperline / plane.bytesperline_div,
+            })
+            .collect(),
+        ..format.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nv12_640x480() -> Format {
+        Format {
+            width: 640,
+            height: 480,
+            pixelformat: b"NV12".into(),
+            plane_fmt: vec![PlanePixFormat {
+                sizeimage: 640 * 480 * 3 / 2,
+                bytesperline: 640,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn variants() {
+        assert!(is_non_contiguous(b"NM12".into()));
+        assert!(!is_non_contiguous(b"NV12".into()));
+        assert!(!is_non_contiguous(b"YUYV".into()));
+        assert_eq!(contiguous_variant(b"YM12".into()), Some(b"YU12".into()));
+        assert_eq!(non_contiguous_variant(b"NV12".into()), Some(b"NM12".into()));
+        assert_eq!(non_contiguous_variant(b"RGB3".into()), None);
+        assert_eq!(num_color_planes(b"YV12".into()), Some(3));
+
+        let available: Vec<PixelFormat> = vec![b"YUYV".into(), b"NM12".into()];
+        assert_eq!(
+            choose_variant(b"NV12".into(), available.iter().copied()),
+            Some(b"NM12".into())
+        );
+        assert_eq!(
+            choose_variant(b"YUYV".into(), available.iter().copied()),
+            Some(b"YUYV".into())
+        );
+        assert_eq!(choose_variant(b"YU12".into(), available), None);
+    }
+
+    #[test]
+    fn conversions() {
+        let nv12 = nv12_640x480();
+        assert_eq!(contiguous_plane_offsets(&nv12), Some(vec![0, 640 * 480]));
+
+        let nm12 = to_non_contiguous(&nv12).unwrap();
+        assert_eq!(nm12.pixelformat, b"NM12".into());
+        assert_eq!(
+            nm12.plane_fmt,
+            vec![
+                PlanePixFormat {
+                    sizeimage: 640 * 480,
+                    bytesperline: 640
+                },
+                PlanePixFormat {
+                    sizeimage: 640 * 240,
+                    bytesperline: 640
+                },
+            ]
+        );
+        assert_eq!(contiguous_plane_offsets(&nm12), None);
+        assert_eq!(to_contiguous(&nm12), Some(nv12));
+
+        let yu12 = Format {
+            pixelformat: b"YU12".into(),
+            ..nv12_640x480()
+        };
+        assert_eq!(
+            contiguous_plane_offsets(&yu12),
+            Some(vec![0, 640 * 480, 640 * 480 + 320 * 240])
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Mapping between V4L2 pixel formats and DRM formats, as used by the DRM,
+//! EGL and Vulkan interfaces to import buffers.
+//!
+//! Both APIs identify formats by fourcc, but do not always use the same
+//! fourcc for the same layout, and some layouts are described by a pair of
+//! fourcc and modifier on the DRM side.
+use crate::PixelFormat;
+use std::fmt;
+
+/// Modifier for buffers laid out linearly, i.e. without tiling or
+/// compression.
+pub const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+/// Modifier meaning that the layout of a buffer is unknown or implicit.
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+/// A DRM format: a fourcc from `drm_fourcc.h` and the modifier describing the
+/// layout of the buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrmFormat {
+    pub fourcc: PixelFormat,
+    pub modifier: u64,
+}
+
+impl DrmFormat {
+    /// A linear DRM format with fourcc `fourcc`.
+    pub fn linear(fourcc: impl Into<PixelFormat>) -> Self {
+        DrmFormat {
+            fourcc: fourcc.into(),
+            modifier: DRM_FORMAT_MOD_LINEAR,
+        }
+    }
+}
+
+impl fmt::Display for DrmFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:0x{:016x}", self.fourcc, self.modifier)
+    }
+}
+
+/// Equivalences between V4L2 and DRM formats. Several V4L2 formats can map to
+/// the same DRM format, in which case the first entry is the preferred one.
+const DRM_FORMATS: &[(&[u8; 4], &[u8; 4], u64)] = &[
+    // Semi-planar YUV.
+    (b"NV12", b"NV12", DRM_FORMAT_MOD_LINEAR),
+    (b"NM12", b"NV12", DRM_FORMAT_MOD_LINEAR),
+    (b"NV21", b"NV21", DRM_FORMAT_MOD_LINEAR),
+    (b"NM21", b"NV21", DRM_FORMAT_MOD_LINEAR),
+    (b"NV16", b"NV16", DRM_FORMAT_MOD_LINEAR),
+    (b"NM16", b"NV16", DRM_FORMAT_MOD_LINEAR),
+    (b"NV61", b"NV61", DRM_FORMAT_MOD_LINEAR),
+    (b"NM61", b"NV61", DRM_FORMAT_MOD_LINEAR),
+    (b"P010", b"P010", DRM_FORMAT_MOD_LINEAR),
+    // Planar YUV.
+    (b"YU12", b"YU12", DRM_FORMAT_MOD_LINEAR),
+    (b"YM12", b"YU12", DRM_FORMAT_MOD_LINEAR),
+    (b"YV12", b"YV12", DRM_FORMAT_MOD_LINEAR),
+    (b"YM21", b"YV12", DRM_FORMAT_MOD_LINEAR),
+    (b"422P", b"YU16", DRM_FORMAT_MOD_LINEAR),
+    (b"YM16", b"YU16", DRM_FORMAT_MOD_LINEAR),
+    // Packed YUV.
+    (b"YUYV", b"YUYV", DRM_FORMAT_MOD_LINEAR),
+    (b"YVYU", b"YVYU", DRM_FORMAT_MOD_LINEAR),
+    (b"UYVY", b"UYVY", DRM_FORMAT_MOD_LINEAR),
+    (b"VYUY", b"VYUY", DRM_FORMAT_MOD_LINEAR),
+    // RGB. V4L2 names the components in memory order, while DRM names them
+    // from the most significant bits of a little-endian word.
+    (b"RGB3", b"BG24", DRM_FORMAT_MOD_LINEAR),
+    (b"BGR3", b"RG24", DRM_FORMAT_MOD_LINEAR),
+    (b"XR24", b"XR24", DRM_FORMAT_MOD_LINEAR),
+    (b"AR24", b"AR24", DRM_FORMAT_MOD_LINEAR),
+    (b"XB24", b"XB24", DRM_FORMAT_MOD_LINEAR),
+    (b"AB24", b"AB24", DRM_FORMAT_MOD_LINEAR),
+    (b"RGBP", b"RG16", DRM_FORMAT_MOD_LINEAR),
+    (b"GREY", b"R8  ", DRM_FORMAT_MOD_LINEAR),
+];
+
+/// Returns the DRM format matching the V4L2 pixel format `format`, or `None`
+/// if there is no known equivalent.
+pub fn to_drm_format(format: PixelFormat) -> Option<DrmFormat> {
+    DRM_FORMATS
+        .iter()
+        .find(|(v4l2, _, _)| format == PixelFormat::from(*v4l2))
+        .map(|(_, drm, modifier)| DrmFormat {
+            fourcc: (*drm).into(),
+            modifier: *modifier,
+        })
+}
+
+/// Returns all the V4L2 pixel formats matching `format`, preferred one first.
+/// `DRM_FORMAT_MOD_INVALID` matches formats with any modifier.
+pub fn from_drm_format(format: DrmFormat) -> Vec<PixelFormat> {
+    DRM_FORMATS
+        .iter()
+        .filter(|(_, drm, modifier)| {
+            format.fourcc == PixelFormat::from(*drm)
+                && (format.modifier == DRM_FORMAT_MOD_INVALID || format.modifier == *modifier)
+        })
+        .map(|(v4l2, _, _)| PixelFormat::from(*v4l2))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drm_formats() {
+        assert_eq!(
+            to_drm_format(b"NM12".into()),
+            Some(DrmFormat::linear(b"NV12"))
+        );
+        assert_eq!(
+            to_drm_format(b"RGB3".into()),
+            Some(DrmFormat::linear(b"BG24"))
+        );
+        assert_eq!(to_drm_format(b"FWHT".into()), None);
+
+        assert_eq!(
+            from_drm_format(DrmFormat::linear(b"NV12")),
+            vec![PixelFormat::from(b"NV12"), PixelFormat::from(b"NM12")]
+        );
+        let any_modifier = DrmFormat {
+            fourcc: b"YU16".into(),
+            modifier: DRM_FORMAT_MOD_INVALID,
+        };
+        assert_eq!(from_drm_format(any_modifier).len(), 2);
+        let tiled = DrmFormat {
+            fourcc: b"NV12".into(),
+            modifier: 0x0100_0000_0000_0001,
+        };
+        assert!(from_drm_format(tiled).is_empty());
+
+        for &(v4l2, _, _) in DRM_FORMATS {
+            let drm = to_drm_format(v4l2.into()).unwrap();
+            assert!(from_drm_format(drm).contains(&v4l2.into()));
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Static description of the layout of common pixel formats, which lets
+//! applications compute buffer sizes and check plane counts without having
+//! to know about each format.
+use crate::{Error, Format, PixelFormat, PlanePixFormat, Result};
+
+/// Layout of a pixel format, similar to the kernel's internal `struct
+/// v4l2_format_info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelFormatInfo {
+    pub pixelformat: PixelFormat,
+    /// Number of memory planes, i.e. of planes in `Format::plane_fmt` when
+    /// using the multi-planar API.
+    pub mem_planes: usize,
+    /// Bits used by each color plane for one of its pixels. The chroma
+    /// planes have fewer pixels than the first plane if they are
+    /// subsampled. Empty for compressed formats.
+    pub bits_per_pixel: &'static [u32],
+    /// Horizontal subsampling of the chroma, i.e. number of pixels of the
+    /// image sharing the same chroma samples along a line.
+    pub hsub: u32,
+    /// Vertical subsampling of the chroma.
+    pub vsub: u32,
+    /// Whether this is a compressed format, for which the size of a frame
+    /// cannot be deduced from its resolution.
+    pub compressed: bool,
+}
+
+impl PixelFormatInfo {
+    /// Number of color planes. Packed formats have a single color plane,
+    /// and compressed formats none.
+    pub fn color_planes(&self) -> usize {
+        self.bits_per_pixel.len()
+    }
+
+    /// Average number of bits used per pixel of the image, over all the
+    /// color planes. Returns `None` for compressed formats.
+    pub fn average_bits_per_pixel(&self) -> Option<u32> {
+        let (first, chroma) = self.bits_per_pixel.split_first()?;
+        Some(first + chroma.iter().sum::<u32>() / (self.hsub * self.vsub))
+    }
+
+    /// Returns the bytes per line and size of each color plane of a
+    /// `width`x`height` image whose first plane uses `bytesperline` bytes per
+    /// line, or the minimum if `bytesperline` is smaller.
+    ///
+    /// The bytes per line of the chroma planes are derived from the ones of
+    /// the first plane, as done by V4L2 for formats using a single memory
+    /// plane.
+    fn color_plane_formats(
+        &self,
+        width: u32,
+        height: u32,
+        bytesperline: u32,
+    ) -> Vec<PlanePixFormat> {
+        let bits_per_line = |width: u32, bpp: u32| (width * bpp).div_ceil(8);
+        let first_bpp = match self.bits_per_pixel.first() {
+            Some(bpp) => *bpp,
+            None => return Vec::new(),
+        };
+        let bytesperline = bytesperline.max(bits_per_line(width, first_bpp));
+
+        self.bits_per_pixel
+            .iter()
+            .enumerate()
+            .map(|(i, bpp)| {
+                let (bytesperline, lines) = if i == 0 {
+                    (bytesperline, height)
+                } else {
+                    let derived = bytesperline * bpp / (first_bpp * self.hsub);
+                    let min = bits_per_line(width.div_ceil(self.hsub), *bpp);
+                    (derived.max(min), height.div_ceil(self.vsub))
+                };
+                PlanePixFormat {
+                    sizeimage: bytesperline * lines,
+                    bytesperline,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the minimum bytes per line and size of each memory plane of a
+    /// `width`x`height` image, e.g. to allocate `USERPTR` buffers. Returns an
+    /// empty vector for compressed formats.
+    pub fn plane_formats(&self, width: u32, height: u32) -> Vec<PlanePixFormat> {
+        self.merge_planes(self.color_plane_formats(width, height, 0))
+    }
+
+    /// Merge the color planes of a format that uses a single memory plane.
+    fn merge_planes(&self, color_planes: Vec<PlanePixFormat>) -> Vec<PlanePixFormat> {
+        if self.mem_planes != 1 || color_planes.len() <= 1 {
+            return color_planes;
+        }
+
+        vec![PlanePixFormat {
+            sizeimage: color_planes.iter().map(|plane| plane.sizeimage).sum(),
+            bytesperline: color_planes[0].bytesperline,
+        }]
+    }
+}
+
+const fn packed(fourcc: &[u8; 4], bits_per_pixel: &'static [u32]) -> PixelFormatInfo {
+    PixelFormatInfo {
+        pixelformat: PixelFormat::from_fourcc(fourcc),
+        mem_planes: 1,
+        bits_per_pixel,
+        hsub: 1,
+        vsub: 1,
+        compressed: false,
+    }
+}
+
+const fn yuv(
+    fourcc: &[u8; 4],
+    mem_planes: usize,
+    bits_per_pixel: &'static [u32],
+    hsub: u32,
+    vsub: u32,
+) -> PixelFormatInfo {
+    PixelFormatInfo {
+        pixelformat: PixelFormat::from_fourcc(fourcc),
+        mem_planes,
+        bits_per_pixel,
+        hsub,
+        vsub,
+        compressed: false,
+    }
+}
+
+const fn compressed(fourcc: &[u8; 4]) -> PixelFormatInfo {
+    PixelFormatInfo {
+        pixelformat: PixelFormat::from_fourcc(fourcc),
+        mem_planes: 1,
+        bits_per_pixel: &[],
+        hsub: 1,
+        vsub: 1,
+        compressed: true,
+    }
+}
+
+const PIXEL_FORMAT_INFOS: &[PixelFormatInfo] = &[
+    // Packed YUV. The chroma samples are shared by pairs of pixels.
+    yuv(b"YUYV", 1, &[16], 2, 1),
+    yuv(b"YVYU", 1, &[16], 2, 1),
+    yuv(b"UYVY", 1, &[16], 2, 1),
+    yuv(b"VYUY", 1, &[16], 2, 1),
+    // Semi-planar YUV.
+    yuv(b"NV12", 1, &[8, 16], 2, 2),
+    yuv(b"NV21", 1, &[8, 16], 2, 2),
+    yuv(b"NM12", 2, &[8, 16], 2, 2),
+    yuv(b"NM21", 2, &[8, 16], 2, 2),
+    yuv(b"NV16", 1, &[8, 16], 2, 1),
+    yuv(b"NV61", 1, &[8, 16], 2, 1),
+    yuv(b"NM16", 2, &[8, 16], 2, 1),
+    yuv(b"NM61", 2, &[8, 16], 2, 1),
+    yuv(b"NV24", 1, &[8, 16], 1, 1),
+    yuv(b"NV42", 1, &[8, 16], 1, 1),
+    yuv(b"P010", 1, &[16, 32], 2, 2),
+    // Planar YUV.
+    yuv(b"YU12", 1, &[8, 8, 8], 2, 2),
+    yuv(b"YV12", 1, &[8, 8, 8], 2, 2),
+    yuv(b"YM12", 3, &[8, 8, 8], 2, 2),
+    yuv(b"YM21", 3, &[8, 8, 8], 2, 2),
+    yuv(b"422P", 1, &[8, 8, 8], 2, 1),
+    yuv(b"YM16", 3, &[8, 8, 8], 2, 1),
+    yuv(b"YM24", 3, &[8, 8, 8], 1, 1),
+    // Greyscale.
+    packed(b"GREY", &[8]),
+    packed(b"Y10 ", &[16]),
+    packed(b"Y12 ", &[16]),
+    packed(b"Y16 ", &[16]),
+    // RGB.
+    packed(b"RGBP", &[16]),
+    packed(b"RGB3", &[24]),
+    packed(b"BGR3", &[24]),
+    packed(b"AR24", &[32]),
+    packed(b"XR24", &[32]),
+    packed(b"AB24", &[32]),
+    packed(b"XB24", &[32]),
+    packed(b"BA24", &[32]),
+    packed(b"BX24", &[32]),
+    packed(b"RA24", &[32]),
+    packed(b"RX24", &[32]),
+    // Bayer, with 10-bit samples stored in 16 bits.
+    packed(b"BA81", &[8]),
+    packed(b"GBRG", &[8]),
+    packed(b"GRBG", &[8]),
+    packed(b"RGGB", &[8]),
+    packed(b"BG10", &[16]),
+    packed(b"GB10", &[16]),
+    packed(b"BA10", &[16]),
+    packed(b"RG10", &[16]),
+    // Compressed.
+    compressed(b"MJPG"),
+    compressed(b"JPEG"),
+    compressed(b"MPG2"),
+    compressed(b"MG2S"),
+    compressed(b"MPG4"),
+    compressed(b"H264"),
+    compressed(b"S264"),
+    compressed(b"HEVC"),
+    compressed(b"S265"),
+    compressed(b"VP80"),
+    compressed(b"VP8F"),
+    compressed(b"VP90"),
+    compressed(b"VP9F"),
+    compressed(b"AV1F"),
+    compressed(b"FWHT"),
+    compressed(b"SFWH"),
+];
+
+/// Returns the layout of `format`, or `None` if it is not a known format.
+pub fn pixel_format_info(format: PixelFormat) -> Option<&'static PixelFormatInfo> {
+    PIXEL_FORMAT_INFOS
+        .iter()
+        .find(|info| info.pixelformat == format)
+}
+
+/// Returns a copy of `format` with one plane per memory plane of its pixel
+/// format, and the bytes per line and size of each plane raised to the
+/// minimum required by its resolution. Larger values, e.g. padded lines, are
+/// kept.
+///
+/// Returns `None` if the pixel format is unknown or compressed, in which
+/// case the plane sizes can only be decided by the driver.
+pub fn adjust_format(format: &Format) -> Option<Format> {
+    let info = pixel_format_info(format.pixelformat)?;
+    if info.compressed {
+        return None;
+    }
+
+    let bytesperline = format
+        .plane_fmt
+        .first()
+        .map(|plane| plane.bytesperline)
+        .unwrap_or_default();
+    let min_planes =
+        info.merge_planes(info.color_plane_formats(format.width, format.height, bytesperline));
+    let plane_fmt = min_planes
+        .into_iter()
+        .enumerate()
+        .map(|(i, min)| match format.plane_fmt.get(i) {
+            Some(plane) => {
+                let bytesperline = plane.bytesperline.max(min.bytesperline);
+                // Lines may have been padded by more than the minimum.
+                let padded = u64::from(min.sizeimage) * u64::from(bytesperline)
+                    / u64::from(min.bytesperline.max(1));
+                PlanePixFormat {
+                    sizeimage: plane.sizeimage.max(padded as u32),
+                    bytesperline,
+                }
+            }
+            None => min,
+        })
+        .collect();
+
+    Some(Format {
+        plane_fmt,
+        ..format.clone()
+    })
+}
+
+/// Check that `format` has as many planes as its pixel format has memory
+/// planes, and that they are large enough for its resolution.
+///
+/// Formats that are unknown or compressed are accepted as they are.
+pub fn check_format(format: &Format) -> Result<()> {
+    let adjusted = match adjust_format(format) {
+        Some(adjusted) => adjusted,
+        None => return Ok(()),
+    };
+
+    if format.plane_fmt.len() < adjusted.plane_fmt.len() {
+        Err(Error::NotEnoughPlanes)
+    } else if format.plane_fmt.len() > adjusted.plane_fmt.len() {
+        Err(Error::TooManyPlanes)
+    } else if format.plane_fmt != adjusted.plane_fmt {
+        Err(Error::InvalidFormat)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::PLANAR_LAYOUTS;
+    use super::*;
+
+    #[test]
+    fn format_info() {
+        let nv12 = pixel_format_info(PixelFormat::NV12).unwrap();
+        assert_eq!(nv12.color_planes(), 2);
+        assert_eq!(nv12.average_bits_per_pixel(), Some(12));
+        assert_eq!(
+            pixel_format_info(PixelFormat::YUYV)
+                .unwrap()
+                .average_bits_per_pixel(),
+            Some(16)
+        );
+
+        let h264 = pixel_format_info(PixelFormat::H264).unwrap();
+        assert!(h264.compressed);
+        assert_eq!(h264.average_bits_per_pixel(), None);
+        assert_eq!(pixel_format_info(b"ABCD".into()), None);
+
+        // The table must agree with the planar layouts.
+        for layout in PLANAR_LAYOUTS {
+            let contiguous = pixel_format_info(layout.contiguous.into()).unwrap();
+            let non_contiguous = pixel_format_info(layout.non_contiguous.into()).unwrap();
+            assert_eq!(contiguous.mem_planes, 1);
+            assert_eq!(contiguous.color_planes(), layout.planes.len());
+            assert_eq!(non_contiguous.mem_planes, layout.planes.len());
+        }
+    }
+
+    #[test]
+    fn plane_sizes() {
+        let plane = |bytesperline, sizeimage| PlanePixFormat {
+            sizeimage,
+            bytesperline,
+        };
+        let nv12 = pixel_format_info(PixelFormat::NV12).unwrap();
+        assert_eq!(nv12.plane_formats(640, 480), vec![plane(640, 640 * 720)]);
+        let nv12m = pixel_format_info(PixelFormat::NV12M).unwrap();
+        assert_eq!(
+            nv12m.plane_formats(641, 481),
+            vec![plane(641, 641 * 481), plane(642, 642 * 241)]
+        );
+        let yuv420 = pixel_format_info(PixelFormat::YUV420).unwrap();
+        assert_eq!(
+            yuv420.plane_formats(64, 32),
+            vec![plane(64, 64 * 32 + 2 * 32 * 16)]
+        );
+        assert_eq!(
+            pixel_format_info(PixelFormat::YUYV)
+                .unwrap()
+                .plane_formats(640, 480),
+            vec![plane(1280, 1280 * 480)]
+        );
+        assert!(pixel_format_info(PixelFormat::H264)
+            .unwrap()
+            .plane_formats(640, 480)
+            .is_empty());
+
+        // Missing planes are added, and padded lines kept.
+        let format = Format {
+            width: 640,
+            height: 480,
+            pixelformat: PixelFormat::YUV420M,
+            plane_fmt: vec![plane(768, 0)],
+            ..Default::default()
+        };
+        assert_eq!(check_format(&format), Err(Error::NotEnoughPlanes));
+        let adjusted = adjust_format(&format).unwrap();
+        assert_eq!(
+            adjusted.plane_fmt,
+            vec![
+                plane(768, 768 * 480),
+                plane(384, 384 * 240),
+                plane(384, 384 * 240)
+            ]
+        );
+        assert_eq!(check_format(&adjusted), Ok(()));
+
+        let format = Format {
+            pixelformat: PixelFormat::NV12,
+            plane_fmt: vec![plane(640, 640 * 480)],
+            ..adjusted
+        };
+        assert_eq!(check_format(&format), Err(Error::InvalidFormat));
+        assert_eq!(
+            check_format(&Format {
+                pixelformat: PixelFormat::MJPEG,
+                ..format
+            }),
+            Ok(())
+        );
+    }
+}
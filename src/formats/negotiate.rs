@@ -0,0 +1,232 @@
+//! Selection of a format and frame size among the ones supported by a
+//! device, given a list of preferences.
+//!
+//! Devices rarely support exactly the format an application asks for, so
+//! `negotiate` ranks what the device enumerates by how closely it matches
+//! each preference: the exact size first, then the closest larger size
+//! (which can be cropped or scaled down), then smaller sizes. Formats
+//! emulated in software (e.g. by libv4l) come after all the native ones.
+use crate::ioctl::{FmtDesc, FormatFlags, FrameSize};
+use crate::PixelFormat;
+
+/// A format and frame size the application would like to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preference {
+    pub pixelformat: PixelFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Preference {
+    pub fn new(pixelformat: impl Into<PixelFormat>, width: u32, height: u32) -> Self {
+        Preference {
+            pixelformat: pixelformat.into(),
+            width,
+            height,
+        }
+    }
+}
+
+/// How closely the size of a `Candidate` matches its preference, from best
+/// to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SizeMatch {
+    /// The preferred size is supported.
+    Exact,
+    /// The closest supported size that is at least as large as the preferred
+    /// one in both dimensions.
+    Larger,
+    /// The device does not enumerate its frame sizes, so the preferred size
+    /// is kept and left to the driver to adjust.
+    Unknown,
+    /// The supported size closest to the preferred one, which is smaller in
+    /// at least one dimension.
+    Smaller,
+}
+
+/// A format and frame size supported by the device, as returned by
+/// `negotiate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    pub pixelformat: PixelFormat,
+    pub width: u32,
+    pub height: u32,
+    pub size_match: SizeMatch,
+    /// Whether the format is emulated in software rather than supported by
+    /// the hardware.
+    pub emulated: bool,
+    /// Index of the preference this candidate was derived from.
+    pub preference: usize,
+}
+
+/// Returns the value closest to `value` within the range, preferring larger
+/// values.
+fn fit(value: u32, min: u32, max: u32, step: u32) -> u32 {
+    let step = step.max(1);
+    if value <= min {
+        return min;
+    }
+    // Last valid value of the range, as `max` may not be a valid step.
+    let last = min + (max.saturating_sub(min)) / step * step;
+    if value >= last {
+        return last;
+    }
+    min + (value - min).div_ceil(step) * step
+}
+
+/// Returns the size among `sizes` that best matches `width`x`height`.
+fn closest_size(sizes: &[FrameSize], width: u32, height: u32) -> (u32, u32, SizeMatch) {
+    let area = |(w, h): (u32, u32)| u64::from(w) * u64::from(h);
+    let mut larger: Option<(u32, u32)> = None;
+    let mut smaller: Option<(u32, u32)> = None;
+
+    for size in sizes {
+        let size = match size {
+            FrameSize::Discrete { width, height } => (*width, *height),
+            FrameSize::Stepwise(r) | FrameSize::Continuous(r) => (
+                fit(width, r.min_width, r.max_width, r.step_width),
+                fit(height, r.min_height, r.max_height, r.step_height),
+            ),
+        };
+
+        if size == (width, height) {
+            return (width, height, SizeMatch::Exact);
+        } else if size.0 >= width && size.1 >= height {
+            if larger.map_or(true, |larger| area(size) < area(larger)) {
+                larger = Some(size);
+            }
+        } else if smaller.map_or(true, |smaller| area(size) > area(smaller)) {
+            smaller = Some(size);
+        }
+    }
+
+    match (larger, smaller) {
+        (Some((w, h)), _) => (w, h, SizeMatch::Larger),
+        (None, Some((w, h))) => (w, h, SizeMatch::Smaller),
+        (None, None) => (width, height, SizeMatch::Unknown),
+    }
+}
+
+/// Rank the formats and frame sizes supported by a device against the
+/// `preferences` of the application, given in decreasing order of
+/// preference.
+///
+/// `available` contains the formats enumerated by the device (e.g. with
+/// `Queue::format_iter()`), each with the frame sizes enumerated for it
+/// (e.g. with `Queue::frame_size_iter()`), which can be empty.
+///
+/// One candidate is returned for each preference whose pixel format is
+/// available, using the supported size closest to the preferred one. The
+/// candidates are sorted with native formats first, then by `SizeMatch`,
+/// then by order of preference, so the first one is the best match. The
+/// list is empty if none of the preferred pixel formats are available.
+pub fn negotiate(
+    preferences: &[Preference],
+    available: &[(FmtDesc, Vec<FrameSize>)],
+) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = preferences
+        .iter()
+        .enumerate()
+        .filter_map(|(index, preference)| {
+            let (desc, sizes) = available
+                .iter()
+                .find(|(desc, _)| desc.pixelformat == preference.pixelformat)?;
+            let (width, height, size_match) =
+                closest_size(sizes, preference.width, preference.height);
+
+            Some(Candidate {
+                pixelformat: preference.pixelformat,
+                width,
+                height,
+                size_match,
+                emulated: desc.flags.contains(FormatFlags::EMULATED),
+                preference: index,
+            })
+        })
+        .collect();
+
+    candidates.sort_by_key(|c| (c.emulated, c.size_match, c.preference));
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ioctl::FrameSizeRange;
+    use crate::QueueType;
+
+    fn desc(pixelformat: &[u8; 4], flags: FormatFlags) -> FmtDesc {
+        FmtDesc {
+            queue: QueueType::VideoCapture,
+            flags,
+            description: String::new(),
+            pixelformat: pixelformat.into(),
+        }
+    }
+
+    #[test]
+    fn negotiate_formats() {
+        let range = FrameSizeRange {
+            min_width: 16,
+            max_width: 1920,
+            step_width: 16,
+            min_height: 16,
+            max_height: 1080,
+            step_height: 8,
+        };
+        let available = vec![
+            (
+                desc(b"YUYV", FormatFlags::empty()),
+                vec![
+                    FrameSize::Discrete {
+                        width: 640,
+                        height: 480,
+                    },
+                    FrameSize::Discrete {
+                        width: 1920,
+                        height: 1080,
+                    },
+                    FrameSize::Discrete {
+                        width: 1280,
+                        height: 720,
+                    },
+                ],
+            ),
+            (
+                desc(b"NV12", FormatFlags::empty()),
+                vec![FrameSize::Stepwise(range)],
+            ),
+            (desc(b"RGB3", FormatFlags::EMULATED), vec![]),
+        ];
+
+        let candidates = negotiate(
+            &[
+                Preference::new(b"RGB3", 640, 480),
+                Preference::new(b"YUYV", 800, 600),
+                Preference::new(b"MJPG", 640, 480),
+                Preference::new(b"NV12", 1000, 1000),
+                Preference::new(b"YUYV", 3840, 2160),
+            ],
+            &available,
+        );
+
+        let summary: Vec<_> = candidates
+            .iter()
+            .map(|c| (c.preference, c.width, c.height, c.size_match))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, 1280, 720, SizeMatch::Larger),
+                (3, 1008, 1000, SizeMatch::Larger),
+                (4, 1920, 1080, SizeMatch::Smaller),
+                (0, 640, 480, SizeMatch::Unknown),
+            ]
+        );
+        assert!(candidates[3].emulated);
+
+        let exact = negotiate(&[Preference::new(b"NV12", 1280, 720)], &available);
+        assert_eq!(exact[0].size_match, SizeMatch::Exact);
+        assert_eq!(fit(2000, 16, 1923, 16), 1920);
+    }
+}
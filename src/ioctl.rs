@@ -7,23 +7,63 @@
 //! argument, and only return the values written by the kernel. Therefore,
 //! although the return types look similar to the kernel structures, they are
 //! not strictly identical.
+mod create_bufs;
+mod cropcap;
+mod ctrl_value;
+mod custom;
+mod decoder_cmd;
 mod dqbuf;
+mod dqevent;
+mod encoder_cmd;
 mod enum_fmt;
+mod enum_frameintervals;
+mod enum_framesizes;
+mod ext_ctrls;
+mod expbuf;
+mod g_crop;
+mod g_ctrl;
 mod g_fmt;
+mod g_parm;
+mod g_selection;
+mod mmap;
+mod prepare_buf;
 mod qbuf;
 mod querybuf;
 mod querycap;
+mod queryctrl;
+mod querymenu;
 mod reqbufs;
 mod streamon;
+mod subscribe_event;
 
+pub use create_bufs::*;
+pub use cropcap::*;
+pub use ctrl_value::*;
+pub use custom::*;
+pub use decoder_cmd::*;
 pub use dqbuf::*;
+pub use dqevent::*;
+pub use encoder_cmd::*;
 pub use enum_fmt::*;
+pub use enum_frameintervals::*;
+pub use enum_framesizes::*;
+pub use ext_ctrls::*;
+pub use expbuf::*;
+pub use g_crop::*;
+pub use g_ctrl::*;
 pub use g_fmt::*;
+pub use g_parm::*;
+pub use g_selection::*;
+pub use mmap::*;
+pub use prepare_buf::*;
 pub use qbuf::*;
 pub use querybuf::*;
 pub use querycap::*;
+pub use queryctrl::*;
+pub use querymenu::*;
 pub use reqbufs::*;
 pub use streamon::*;
+pub use subscribe_event::*;
 
 use crate::bindings;
 use crate::QueueType;
@@ -83,11 +123,8 @@ mod tests {
 
 /// A memory area we can pass to ioctls in order to get/set plane information
 /// with the multi-planar API.
-type PlaneData = [bindings::v4l2_plane; bindings::VIDEO_MAX_PLANES as usize];
+pub type PlaneData = [bindings::v4l2_plane; bindings::VIDEO_MAX_PLANES as usize];
 
 fn is_multi_planar(queue: QueueType) -> bool {
-    match queue {
-        QueueType::VideoCaptureMplane | QueueType::VideoOutputMplane => true,
-        _ => false,
-    }
+    queue.is_multi_planar()
 }
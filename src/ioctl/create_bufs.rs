@@ -0,0 +1,63 @@
+//! Safe wrapper for the `VIDIOC_CREATE_BUFS` ioctl.
+use super::BufferCapabilities;
+use crate::bindings;
+use crate::memory::MemoryType;
+use crate::Format;
+use crate::QueueType;
+use crate::Result;
+use std::convert::TryInto;
+use std::mem;
+use std::ops::Range;
+use std::os::unix::io::AsRawFd;
+
+/// Result of the `create_bufs` ioctl.
+#[derive(Debug)]
+pub struct CreateBuffers {
+    /// Index of the first created buffer.
+    pub index: u32,
+    /// Number of buffers actually created, which can differ from the number
+    /// requested.
+    pub count: u32,
+    pub capabilities: BufferCapabilities,
+}
+
+impl CreateBuffers {
+    /// Returns the range of indices of the created buffers.
+    pub fn indices(&self) -> Range<usize> {
+        self.index as usize..(self.index + self.count) as usize
+    }
+}
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_create_buffers;
+    nix::ioctl_readwrite!(vidioc_create_bufs, b'V', 92, v4l2_create_buffers);
+}
+
+/// Safe wrapper around the `VIDIOC_CREATE_BUFS` ioctl. Creates `count`
+/// additional buffers of `memory` type, large enough to hold frames of
+/// `format`, which can differ from the format currently set on the queue.
+///
+/// Passing a `count` of 0 only fills the index of the next buffer to be
+/// created and the capabilities of the queue.
+pub fn create_bufs<F: AsRawFd>(
+    fd: &mut F,
+    queue: QueueType,
+    memory: MemoryType,
+    count: u32,
+    format: Format,
+) -> Result<CreateBuffers> {
+    let mut create_bufs = bindings::v4l2_create_buffers {
+        count,
+        memory: memory as u32,
+        format: (format, queue).try_into()?,
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe { ioctl::vidioc_create_bufs(fd.as_raw_fd(), &mut create_bufs) }?;
+
+    Ok(CreateBuffers {
+        index: create_bufs.index,
+        count: create_bufs.count,
+        capabilities: BufferCapabilities::from_bits_truncate(create_bufs.capabilities),
+    })
+}
@@ -0,0 +1,42 @@
+//! Safe wrapper for the `VIDIOC_CROPCAP` ioctl.
+use super::{Fraction, Rect};
+use crate::bindings;
+use crate::{QueueType, Result};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_cropcap;
+    nix::ioctl_readwrite!(vidioc_cropcap, b'V', 58, v4l2_cropcap);
+}
+
+/// Cropping capabilities of a queue. Safe variant of `struct v4l2_cropcap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropCap {
+    /// The limits within which the crop rectangle can be set.
+    pub bounds: Rect,
+    /// The default crop rectangle, covering the whole picture.
+    pub default_rect: Rect,
+    /// Aspect ratio (vertical/horizontal) of the pixels when no scaling is
+    /// applied.
+    pub pixel_aspect: Fraction,
+}
+
+/// Safe wrapper around the `VIDIOC_CROPCAP` ioctl.
+///
+/// This is part of the legacy crop API, which is superseded by
+/// `g_selection` but is the only one implemented by some older drivers.
+pub fn cropcap<F: AsRawFd>(fd: &F, queue: QueueType) -> Result<CropCap> {
+    let mut cropcap = bindings::v4l2_cropcap {
+        type_: queue as u32,
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe { ioctl::vidioc_cropcap(fd.as_raw_fd(), &mut cropcap) }?;
+
+    Ok(CropCap {
+        bounds: cropcap.bounds.into(),
+        default_rect: cropcap.defrect.into(),
+        pixel_aspect: cropcap.pixelaspect.into(),
+    })
+}
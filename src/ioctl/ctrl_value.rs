@@ -0,0 +1,454 @@
+//! Typed values of controls, and their conversion to and from the kernel
+//! representation used by the ext-ctrls ioctls.
+use super::{
+    g_ctrl, g_ext_ctrls, s_ext_ctrls, CtrlFlags, CtrlType, CtrlWhich, ExtControl, ExtControlValue,
+    QueryExtCtrl,
+};
+use crate::bindings;
+use crate::{CtrlId, Error, Result};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+/// Value of a control, typed according to the type of the control.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CtrlValue {
+    /// Value of `CtrlType::Integer`, `CtrlType::Bitmask` and
+    /// `CtrlType::Button` controls.
+    Integer(i32),
+    Integer64(i64),
+    Boolean(bool),
+    /// Index of the selected entry of `CtrlType::Menu` and
+    /// `CtrlType::IntegerMenu` controls.
+    Menu(u32),
+    String(String),
+    U8Array(Vec<u8>),
+    U16Array(Vec<u16>),
+    U32Array(Vec<u32>),
+    /// Raw payload of compound controls and arrays of other types, e.g. one
+    /// of the structures of the `controls` module.
+    Compound(Vec<u8>),
+}
+
+/// Value of a control as read from the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CtrlReading {
+    pub value: CtrlValue,
+    /// The control is volatile: its value is updated by the device itself,
+    /// e.g. the gain computed by the hardware while automatic gain is
+    /// enabled, so it may already be different from the one read.
+    pub volatile: bool,
+}
+
+/// Kinds of values, used to check that a value matches a control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Integer,
+    Integer64,
+    Boolean,
+    Menu,
+    String,
+    U8Array,
+    U16Array,
+    U32Array,
+    Compound,
+}
+
+impl ValueKind {
+    fn of_ctrl(ctrl: &QueryExtCtrl) -> Self {
+        match ctrl.type_ {
+            CtrlType::String => ValueKind::String,
+            CtrlType::U8 => ValueKind::U8Array,
+            CtrlType::U16 => ValueKind::U16Array,
+            CtrlType::U32 => ValueKind::U32Array,
+            // Arrays of simple types are passed as a payload.
+            _ if ctrl.flags.contains(CtrlFlags::HAS_PAYLOAD) => ValueKind::Compound,
+            CtrlType::Integer64 => ValueKind::Integer64,
+            CtrlType::Boolean => ValueKind::Boolean,
+            CtrlType::Menu | CtrlType::IntegerMenu => ValueKind::Menu,
+            _ => ValueKind::Integer,
+        }
+    }
+}
+
+/// Size of the payload of `ctrl`, in bytes.
+fn payload_size(ctrl: &QueryExtCtrl) -> usize {
+    ctrl.elem_size as usize * ctrl.elems as usize
+}
+
+/// Returns `Error::InvalidControlValue` if `len` elements of `elem_size` bytes
+/// do not make up the payload of `ctrl`.
+fn check_payload(ctrl: &QueryExtCtrl, len: usize, elem_size: usize) -> Result<()> {
+    if len * elem_size != payload_size(ctrl) {
+        return Err(Error::InvalidControlValue);
+    }
+
+    Ok(())
+}
+
+impl CtrlValue {
+    fn kind(&self) -> ValueKind {
+        match self {
+            CtrlValue::Integer(_) => ValueKind::Integer,
+            CtrlValue::Integer64(_) => ValueKind::Integer64,
+            CtrlValue::Boolean(_) => ValueKind::Boolean,
+            CtrlValue::Menu(_) => ValueKind::Menu,
+            CtrlValue::String(_) => ValueKind::String,
+            CtrlValue::U8Array(_) => ValueKind::U8Array,
+            CtrlValue::U16Array(_) => ValueKind::U16Array,
+            CtrlValue::U32Array(_) => ValueKind::U32Array,
+            CtrlValue::Compound(_) => ValueKind::Compound,
+        }
+    }
+
+    /// Convert this value into the kernel representation of control `ctrl`.
+    ///
+    /// Returns `Error::InvalidControlValue` if the value does not match the
+    /// type of `ctrl`, or if an array or compound value does not have the
+    /// size of its payload. Strings are checked against the maximum length of
+    /// the control.
+    pub fn to_ext_value(&self, ctrl: &QueryExtCtrl) -> Result<ExtControlValue> {
+        if self.kind() != ValueKind::of_ctrl(ctrl) {
+            return Err(Error::InvalidControlValue);
+        }
+
+        Ok(match self {
+            CtrlValue::Integer(value) => ExtControlValue::Value(*value),
+            CtrlValue::Integer64(value) => ExtControlValue::Value64(*value),
+            CtrlValue::Boolean(value) => ExtControlValue::Value(*value as i32),
+            CtrlValue::Menu(index) => ExtControlValue::Value(*index as i32),
+            CtrlValue::String(string) => {
+                // The payload must include the terminating nul.
+                if string.len() >= payload_size(ctrl) || string.contains('\0') {
+                    return Err(Error::InvalidControlValue);
+                }
+                let mut payload = string.clone().into_bytes();
+                payload.resize(payload_size(ctrl), 0);
+                ExtControlValue::Payload(payload)
+            }
+            CtrlValue::U8Array(array) => {
+                check_payload(ctrl, array.len(), mem::size_of::<u8>())?;
+                ExtControlValue::Payload(array.clone())
+            }
+            CtrlValue::U16Array(array) => {
+                check_payload(ctrl, array.len(), mem::size_of::<u16>())?;
+                ExtControlValue::Payload(
+                    array
+                        .iter()
+                        .flat_map(|v| v.to_ne_bytes().to_vec())
+                        .collect(),
+                )
+            }
+            CtrlValue::U32Array(array) => {
+                check_payload(ctrl, array.len(), mem::size_of::<u32>())?;
+                ExtControlValue::Payload(
+                    array
+                        .iter()
+                        .flat_map(|v| v.to_ne_bytes().to_vec())
+                        .collect(),
+                )
+            }
+            CtrlValue::Compound(payload) => {
+                check_payload(ctrl, payload.len(), 1)?;
+                ExtControlValue::Payload(payload.clone())
+            }
+        })
+    }
+
+    /// Convert `value`, the kernel representation of control `ctrl`, into a
+    /// typed value.
+    ///
+    /// Returns `Error::InvalidControlValue` if `value` does not match the
+    /// type of `ctrl`.
+    pub fn from_ext_value(value: &ExtControlValue, ctrl: &QueryExtCtrl) -> Result<CtrlValue> {
+        Ok(match (ValueKind::of_ctrl(ctrl), value) {
+            (ValueKind::Integer, ExtControlValue::Value(value)) => CtrlValue::Integer(*value),
+            (ValueKind::Integer64, ExtControlValue::Value64(value)) => CtrlValue::Integer64(*value),
+            (ValueKind::Boolean, ExtControlValue::Value(value)) => CtrlValue::Boolean(*value != 0),
+            (ValueKind::Menu, ExtControlValue::Value(index)) => CtrlValue::Menu(*index as u32),
+            (ValueKind::String, ExtControlValue::Payload(payload)) => {
+                let len = payload
+                    .iter()
+                    .position(|c| *c == 0)
+                    .unwrap_or(payload.len());
+                CtrlValue::String(String::from_utf8_lossy(&payload[..len]).into_owned())
+            }
+            (ValueKind::U8Array, ExtControlValue::Payload(payload)) => {
+                CtrlValue::U8Array(payload.clone())
+            }
+            (ValueKind::U16Array, ExtControlValue::Payload(payload)) => CtrlValue::U16Array(
+                payload
+                    .chunks_exact(mem::size_of::<u16>())
+                    .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+                    .collect(),
+            ),
+            (ValueKind::U32Array, ExtControlValue::Payload(payload)) => CtrlValue::U32Array(
+                payload
+                    .chunks_exact(mem::size_of::<u32>())
+                    .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect(),
+            ),
+            (ValueKind::Compound, ExtControlValue::Payload(payload)) => {
+                CtrlValue::Compound(payload.clone())
+            }
+            _ => return Err(Error::InvalidControlValue),
+        })
+    }
+}
+
+impl ExtControl {
+    /// Create a control to read the value of `ctrl` into, with a payload
+    /// buffer of the right size if needed.
+    pub fn for_ctrl(ctrl: &QueryExtCtrl) -> Self {
+        let value = match ValueKind::of_ctrl(ctrl) {
+            ValueKind::Integer | ValueKind::Boolean | ValueKind::Menu => ExtControlValue::Value(0),
+            ValueKind::Integer64 => ExtControlValue::Value64(0),
+            _ => ExtControlValue::Payload(vec![0u8; payload_size(ctrl)]),
+        };
+
+        ExtControl::new(ctrl.id, value)
+    }
+
+    /// Create a control setting `ctrl` to `value`.
+    pub fn from_value(ctrl: &QueryExtCtrl, value: &CtrlValue) -> Result<Self> {
+        Ok(ExtControl::new(ctrl.id, value.to_ext_value(ctrl)?))
+    }
+}
+
+/// Read the `which` value of all `ctrls` at once, as returned by
+/// `query_ext_ctrl`.
+pub fn g_ext_ctrl_values<F: AsRawFd>(
+    fd: &F,
+    which: CtrlWhich,
+    ctrls: &[QueryExtCtrl],
+) -> Result<Vec<CtrlReading>> {
+    let mut controls: Vec<_> = ctrls.iter().map(ExtControl::for_ctrl).collect();
+    g_ext_ctrls(fd, which, &mut controls)?;
+
+    controls
+        .iter()
+        .zip(ctrls.iter())
+        .map(|(control, ctrl)| {
+            Ok(CtrlReading {
+                value: CtrlValue::from_ext_value(&control.value, ctrl)?,
+                volatile: ctrl.flags.contains(CtrlFlags::VOLATILE),
+            })
+        })
+        .collect()
+}
+
+/// Auto-clusters of the standard controls: the control of the automatic mode,
+/// and the controls the device sets by itself while it is enabled.
+const AUTO_CLUSTERS: [(CtrlId, &[CtrlId]); 6] = [
+    (CtrlId::AUTOGAIN, &[CtrlId::GAIN]),
+    (CtrlId::AUTOBRIGHTNESS, &[CtrlId::BRIGHTNESS]),
+    (CtrlId::HUE_AUTO, &[CtrlId::HUE]),
+    (
+        CtrlId::AUTO_WHITE_BALANCE,
+        &[
+            CtrlId::RED_BALANCE,
+            CtrlId::BLUE_BALANCE,
+            CtrlId::WHITE_BALANCE_TEMPERATURE,
+        ],
+    ),
+    (
+        CtrlId::EXPOSURE_AUTO,
+        &[CtrlId::EXPOSURE, CtrlId::EXPOSURE_ABSOLUTE],
+    ),
+    (
+        CtrlId::FOCUS_AUTO,
+        &[CtrlId::FOCUS_ABSOLUTE, CtrlId::FOCUS_RELATIVE],
+    ),
+];
+
+/// Returns the control of the automatic mode of `id`, if `id` is one of the
+/// manual controls of a standard auto-cluster.
+pub fn auto_control(id: CtrlId) -> Option<CtrlId> {
+    AUTO_CLUSTERS
+        .iter()
+        .find(|(_, manual)| manual.contains(&id))
+        .map(|(auto, _)| *auto)
+}
+
+/// Returns true if auto control `auto` set to `value` lets the manual controls
+/// of its cluster be set.
+fn is_manual_mode(auto: CtrlId, value: i32) -> bool {
+    if auto == CtrlId::EXPOSURE_AUTO {
+        // The exposure time is also set manually in shutter priority mode.
+        value as u32 == bindings::v4l2_exposure_auto_type_V4L2_EXPOSURE_MANUAL
+            || value as u32 == bindings::v4l2_exposure_auto_type_V4L2_EXPOSURE_SHUTTER_PRIORITY
+    } else {
+        value == 0
+    }
+}
+
+/// Returns the auto control preventing one of `values` from being set, if
+/// any. `current` returns the current value of an auto control, or `None` if
+/// the device does not have it. Switching to manual mode as part of `values`
+/// is allowed.
+fn blocking_auto_control<C>(values: &[(&QueryExtCtrl, CtrlValue)], mut current: C) -> Option<CtrlId>
+where
+    C: FnMut(CtrlId) -> Option<i32>,
+{
+    values
+        .iter()
+        .filter_map(|(ctrl, _)| auto_control(ctrl.id))
+        .find(|&auto| {
+            let new_mode = values.iter().find(|(ctrl, _)| ctrl.id == auto).and_then(
+                |(_, value)| match value {
+                    CtrlValue::Integer(value) => Some(*value),
+                    CtrlValue::Boolean(value) => Some(*value as i32),
+                    CtrlValue::Menu(index) => Some(*index as i32),
+                    _ => None,
+                },
+            );
+            match new_mode.or_else(|| current(auto)) {
+                Some(mode) => !is_manual_mode(auto, mode),
+                None => false,
+            }
+        })
+}
+
+/// Set the `which` value of all the controls of `values` atomically, and
+/// return the values actually set.
+///
+/// Setting the current value of a manual control of an auto-cluster, e.g.
+/// `GAIN`, fails with `Error::AutoModeEnabled` while its automatic mode is
+/// enabled, as the device would override or ignore the value. The automatic
+/// mode can be disabled by setting it as part of `values`.
+pub fn s_ext_ctrl_values<F: AsRawFd>(
+    fd: &mut F,
+    which: CtrlWhich,
+    values: &[(&QueryExtCtrl, CtrlValue)],
+) -> Result<Vec<CtrlValue>> {
+    if which == CtrlWhich::Current {
+        let fd: &F = fd;
+        if let Some(auto) = blocking_auto_control(values, |auto| g_ctrl(fd, auto).ok()) {
+            return Err(Error::AutoModeEnabled { auto });
+        }
+    }
+
+    let mut controls = values
+        .iter()
+        .map(|(ctrl, value)| ExtControl::from_value(ctrl, value))
+        .collect::<Result<Vec<_>>>()?;
+    s_ext_ctrls(fd, which, &mut controls)?;
+
+    controls
+        .iter()
+        .zip(values.iter())
+        .map(|(control, (ctrl, _))| CtrlValue::from_ext_value(&control.value, ctrl))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CtrlId;
+
+    fn ctrl(type_: CtrlType, flags: CtrlFlags, elem_size: u32, elems: u32) -> QueryExtCtrl {
+        QueryExtCtrl {
+            id: CtrlId(0),
+            type_,
+            name: String::new(),
+            minimum: 0,
+            maximum: 0,
+            step: 1,
+            default_value: 0,
+            flags,
+            elem_size,
+            elems,
+            dims: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn auto_clusters() {
+        assert_eq!(auto_control(CtrlId::GAIN), Some(CtrlId::AUTOGAIN));
+        assert_eq!(
+            auto_control(CtrlId::EXPOSURE_ABSOLUTE),
+            Some(CtrlId::EXPOSURE_AUTO)
+        );
+        assert_eq!(auto_control(CtrlId::AUTOGAIN), None);
+        assert_eq!(auto_control(CtrlId::CONTRAST), None);
+
+        let mut gain = ctrl(CtrlType::Integer, CtrlFlags::VOLATILE, 4, 1);
+        gain.id = CtrlId::GAIN;
+        let mut autogain = ctrl(CtrlType::Boolean, CtrlFlags::UPDATE, 4, 1);
+        autogain.id = CtrlId::AUTOGAIN;
+        let set_gain = [(&gain, CtrlValue::Integer(10))];
+
+        assert_eq!(
+            blocking_auto_control(&set_gain, |_| Some(1)),
+            Some(CtrlId::AUTOGAIN)
+        );
+        assert_eq!(blocking_auto_control(&set_gain, |_| Some(0)), None);
+        // No automatic mode on this device.
+        assert_eq!(blocking_auto_control(&set_gain, |_| None), None);
+        // Disabling the automatic mode at the same time.
+        let set_both = [
+            (&autogain, CtrlValue::Boolean(false)),
+            (&gain, CtrlValue::Integer(10)),
+        ];
+        assert_eq!(blocking_auto_control(&set_both, |_| Some(1)), None);
+
+        let mut exposure = ctrl(CtrlType::Integer, CtrlFlags::empty(), 4, 1);
+        exposure.id = CtrlId::EXPOSURE_ABSOLUTE;
+        let set_exposure = [(&exposure, CtrlValue::Integer(100))];
+        // Aperture priority.
+        assert_eq!(
+            blocking_auto_control(&set_exposure, |_| Some(3)),
+            Some(CtrlId::EXPOSURE_AUTO)
+        );
+        // Shutter priority.
+        assert_eq!(blocking_auto_control(&set_exposure, |_| Some(2)), None);
+    }
+
+    #[test]
+    fn ctrl_value_conversion() {
+        let boolean = ctrl(CtrlType::Boolean, CtrlFlags::empty(), 4, 1);
+        let value = CtrlValue::Boolean(true).to_ext_value(&boolean).unwrap();
+        assert_eq!(value, ExtControlValue::Value(1));
+        assert_eq!(
+            CtrlValue::from_ext_value(&value, &boolean),
+            Ok(CtrlValue::Boolean(true))
+        );
+        assert_eq!(
+            CtrlValue::Integer(1).to_ext_value(&boolean),
+            Err(Error::InvalidControlValue)
+        );
+
+        let string = ctrl(CtrlType::String, CtrlFlags::HAS_PAYLOAD, 8, 1);
+        let value = CtrlValue::String("vivid".into())
+            .to_ext_value(&string)
+            .unwrap();
+        assert_eq!(value, ExtControlValue::Payload(b"vivid\0\0\0".to_vec()));
+        assert_eq!(
+            CtrlValue::from_ext_value(&value, &string),
+            Ok(CtrlValue::String("vivid".into()))
+        );
+        assert_eq!(
+            CtrlValue::String("too long".into()).to_ext_value(&string),
+            Err(Error::InvalidControlValue)
+        );
+
+        let u16_array = ctrl(CtrlType::U16, CtrlFlags::HAS_PAYLOAD, 2, 2);
+        let value = CtrlValue::U16Array(vec![1, 2])
+            .to_ext_value(&u16_array)
+            .unwrap();
+        assert_eq!(
+            CtrlValue::from_ext_value(&value, &u16_array),
+            Ok(CtrlValue::U16Array(vec![1, 2]))
+        );
+        assert_eq!(
+            CtrlValue::U16Array(vec![1]).to_ext_value(&u16_array),
+            Err(Error::InvalidControlValue)
+        );
+
+        // Arrays of integers are passed as compound payloads.
+        let int_array = ctrl(CtrlType::Integer, CtrlFlags::HAS_PAYLOAD, 4, 2);
+        match ExtControl::for_ctrl(&int_array).value {
+            ExtControlValue::Payload(payload) => assert_eq!(payload.len(), 8),
+            _ => panic!("Integer array without payload"),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Support for driver-private ioctls, for devices that extend V4L2 with their
+//! own commands.
+use crate::Result;
+use nix::sys::ioctl::ioctl_num_type;
+use std::os::unix::io::AsRawFd;
+
+/// A driver-private ioctl.
+///
+/// The request code is typically built with one of the `nix::request_code_*`
+/// macros, e.g. for a vendor ioctl reading and writing a `struct foo`:
+///
+/// ```ignore
+/// struct VidiocFoo;
+///
+/// unsafe impl CustomIoctl for VidiocFoo {
+///     const REQUEST: ioctl_num_type =
+///         nix::request_code_readwrite!(b'V', BASE_VIDIOC_PRIVATE, mem::size_of::<Foo>());
+///     type Arg = Foo;
+/// }
+/// ```
+///
+/// # Safety
+///
+/// `Arg` must match the layout of the argument expected by the kernel for
+/// `REQUEST`, and the kernel must not access memory beyond it.
+pub unsafe trait CustomIoctl {
+    /// Request code of the ioctl.
+    const REQUEST: ioctl_num_type;
+    /// Type of the argument passed to the ioctl.
+    type Arg;
+}
+
+/// Perform the ioctl with request code `request` on `fd`, passing `arg` as its
+/// argument. Returns the value returned by the ioctl.
+///
+/// # Safety
+///
+/// `arg` must point to memory that is valid for the ioctl to read and write
+/// according to `request`.
+pub unsafe fn ioctl_raw<T, F: AsRawFd>(
+    fd: &F,
+    request: ioctl_num_type,
+    arg: *mut T,
+) -> Result<i32> {
+    Ok(nix::errno::Errno::result(nix::libc::ioctl(
+        fd.as_raw_fd(),
+        request,
+        arg,
+    ))?)
+}
+
+/// Safe wrapper around the driver-private ioctl `I`.
+pub fn custom_ioctl<I: CustomIoctl, F: AsRawFd>(fd: &F, arg: &mut I::Arg) -> Result<i32> {
+    // Safe because implementors of `CustomIoctl` guarantee that `I::Arg`
+    // is the argument expected by the ioctl.
+    unsafe { ioctl_raw(fd, I::REQUEST, arg as *mut I::Arg) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::io::FromRawFd;
+
+    /// `FIONREAD` is not V4L2-specific, but works on any pipe.
+    struct FionRead;
+
+    unsafe impl CustomIoctl for FionRead {
+        const REQUEST: ioctl_num_type = nix::libc::FIONREAD as ioctl_num_type;
+        type Arg = nix::libc::c_int;
+    }
+
+    #[test]
+    fn custom_ioctl_on_pipe() {
+        let (read_fd, write_fd) = nix::unistd::pipe().unwrap();
+        let reader = unsafe { std::fs::File::from_raw_fd(read_fd) };
+        let mut writer = unsafe { std::fs::File::from_raw_fd(write_fd) };
+        writer.write_all(&[0u8; 5]).unwrap();
+
+        let mut available = 0;
+        assert_eq!(
+            custom_ioctl::<FionRead, _>(&reader, &mut available).unwrap(),
+            0
+        );
+        assert_eq!(available, 5);
+
+        // A regular file descriptor does not support this request.
+        let mut arg = 0u32;
+        assert!(unsafe { ioctl_raw(&reader, 0, &mut arg as *mut u32) }.is_err());
+    }
+}
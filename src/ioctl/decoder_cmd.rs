@@ -0,0 +1,122 @@
+//! Safe wrappers for the `VIDIOC_DECODER_CMD` and `VIDIOC_TRY_DECODER_CMD`
+//! ioctls.
+use crate::bindings;
+use crate::Result;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_decoder_cmd;
+    nix::ioctl_readwrite!(vidioc_decoder_cmd, b'V', 96, v4l2_decoder_cmd);
+    nix::ioctl_readwrite!(vidioc_try_decoder_cmd, b'V', 97, v4l2_decoder_cmd);
+}
+
+/// A command that can be sent to a decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecoderCommand {
+    /// Start decoding, or resume after a `Stop` command has completed. If
+    /// `mute_audio` is set, audio is muted when playing back at a non-standard
+    /// speed.
+    Start { mute_audio: bool },
+    /// Decode all the pending OUTPUT buffers, after which the last CAPTURE
+    /// buffer is marked with the `LAST` flag. If `immediately` is set,
+    /// decoding stops right away instead, and if `to_black` is set the
+    /// output shows a black picture rather than the last decoded frame.
+    Stop { to_black: bool, immediately: bool },
+    /// Pause decoding, keeping the pending data. If `to_black` is set, the
+    /// output shows a black picture rather than the last decoded frame.
+    Pause { to_black: bool },
+    /// Resume decoding after a `Pause` command.
+    Resume,
+}
+
+impl DecoderCommand {
+    /// Returns the `cmd` and `flags` members of `struct v4l2_decoder_cmd`
+    /// for this command.
+    fn to_v4l2(self) -> (u32, u32) {
+        let flag = |set: bool, flag: u32| if set { flag } else { 0 };
+
+        match self {
+            DecoderCommand::Start { mute_audio } => (
+                bindings::V4L2_DEC_CMD_START,
+                flag(mute_audio, bindings::V4L2_DEC_CMD_START_MUTE_AUDIO),
+            ),
+            DecoderCommand::Stop {
+                to_black,
+                immediately,
+            } => (
+                bindings::V4L2_DEC_CMD_STOP,
+                flag(to_black, bindings::V4L2_DEC_CMD_STOP_TO_BLACK)
+                    | flag(immediately, bindings::V4L2_DEC_CMD_STOP_IMMEDIATELY),
+            ),
+            DecoderCommand::Pause { to_black } => (
+                bindings::V4L2_DEC_CMD_PAUSE,
+                flag(to_black, bindings::V4L2_DEC_CMD_PAUSE_TO_BLACK),
+            ),
+            DecoderCommand::Resume => (bindings::V4L2_DEC_CMD_RESUME, 0),
+        }
+    }
+}
+
+type DecoderCmdIoctl =
+    unsafe fn(nix::libc::c_int, *mut bindings::v4l2_decoder_cmd) -> nix::Result<nix::libc::c_int>;
+
+fn run_decoder_cmd<F: AsRawFd>(
+    ioctl: DecoderCmdIoctl,
+    fd: &F,
+    command: DecoderCommand,
+) -> Result<()> {
+    let (cmd, flags) = command.to_v4l2();
+    let mut dec_cmd = bindings::v4l2_decoder_cmd {
+        cmd,
+        flags,
+        ..unsafe { mem::zeroed() }
+    };
+
+    unsafe { ioctl(fd.as_raw_fd(), &mut dec_cmd) }?;
+
+    Ok(())
+}
+
+/// Safe wrapper around the `VIDIOC_DECODER_CMD` ioctl.
+pub fn decoder_cmd<F: AsRawFd>(fd: &F, command: DecoderCommand) -> Result<()> {
+    run_decoder_cmd(ioctl::vidioc_decoder_cmd, fd, command)
+}
+
+/// Safe wrapper around the `VIDIOC_TRY_DECODER_CMD` ioctl. Checks whether
+/// the decoder supports `command`, without executing it.
+pub fn try_decoder_cmd<F: AsRawFd>(fd: &F, command: DecoderCommand) -> Result<()> {
+    run_decoder_cmd(ioctl::vidioc_try_decoder_cmd, fd, command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoder_command() {
+        assert_eq!(
+            DecoderCommand::Stop {
+                to_black: true,
+                immediately: true
+            }
+            .to_v4l2(),
+            (
+                bindings::V4L2_DEC_CMD_STOP,
+                bindings::V4L2_DEC_CMD_STOP_TO_BLACK | bindings::V4L2_DEC_CMD_STOP_IMMEDIATELY
+            )
+        );
+        assert_eq!(
+            DecoderCommand::Start { mute_audio: false }.to_v4l2(),
+            (bindings::V4L2_DEC_CMD_START, 0)
+        );
+        assert_eq!(
+            DecoderCommand::Pause { to_black: true }.to_v4l2(),
+            (
+                bindings::V4L2_DEC_CMD_PAUSE,
+                bindings::V4L2_DEC_CMD_PAUSE_TO_BLACK
+            )
+        );
+    }
+}
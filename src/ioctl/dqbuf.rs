@@ -1,10 +1,14 @@
 use super::{is_multi_planar, BufferFlags, PlaneData};
 use crate::bindings;
 use crate::QueueType;
-use crate::Result;
+use crate::{Error, Result};
+use arrayvec::ArrayVec;
+use nix::errno::Errno;
 
+use std::cmp::Ordering;
 use std::mem;
 use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
 
 /// Implementors can receive the result from the `dqbuf` ioctl.
 pub trait DQBuf: Sized {
@@ -39,6 +43,226 @@ impl DQBuf for u32 {
     }
 }
 
+/// Field order of a buffer, i.e. how the lines of the image contained in the
+/// buffer map to the fields of an interlaced frame. Safe variant of `enum
+/// v4l2_field`.
+///
+/// `Any` lets the driver choose the field order when setting a format, and
+/// is never returned by drivers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Field {
+    #[default]
+    Any = bindings::v4l2_field_V4L2_FIELD_ANY as isize,
+    None = bindings::v4l2_field_V4L2_FIELD_NONE as isize,
+    Top = bindings::v4l2_field_V4L2_FIELD_TOP as isize,
+    Bottom = bindings::v4l2_field_V4L2_FIELD_BOTTOM as isize,
+    Interlaced = bindings::v4l2_field_V4L2_FIELD_INTERLACED as isize,
+    SeqTB = bindings::v4l2_field_V4L2_FIELD_SEQ_TB as isize,
+    SeqBT = bindings::v4l2_field_V4L2_FIELD_SEQ_BT as isize,
+    Alternate = bindings::v4l2_field_V4L2_FIELD_ALTERNATE as isize,
+    InterlacedTB = bindings::v4l2_field_V4L2_FIELD_INTERLACED_TB as isize,
+    InterlacedBT = bindings::v4l2_field_V4L2_FIELD_INTERLACED_BT as isize,
+}
+
+impl Field {
+    /// Convert a `v4l2_field` value into the matching `Field`, if it is valid.
+    pub fn from_v4l2(field: u32) -> Option<Self> {
+        Some(match field {
+            bindings::v4l2_field_V4L2_FIELD_ANY => Field::Any,
+            bindings::v4l2_field_V4L2_FIELD_NONE => Field::None,
+            bindings::v4l2_field_V4L2_FIELD_TOP => Field::Top,
+            bindings::v4l2_field_V4L2_FIELD_BOTTOM => Field::Bottom,
+            bindings::v4l2_field_V4L2_FIELD_INTERLACED => Field::Interlaced,
+            bindings::v4l2_field_V4L2_FIELD_SEQ_TB => Field::SeqTB,
+            bindings::v4l2_field_V4L2_FIELD_SEQ_BT => Field::SeqBT,
+            bindings::v4l2_field_V4L2_FIELD_ALTERNATE => Field::Alternate,
+            bindings::v4l2_field_V4L2_FIELD_INTERLACED_TB => Field::InterlacedTB,
+            bindings::v4l2_field_V4L2_FIELD_INTERLACED_BT => Field::InterlacedBT,
+            _ => return None,
+        })
+    }
+
+    /// Returns true if a buffer with this field contains a single field of an
+    /// interlaced frame instead of a whole frame.
+    pub fn is_single_field(self) -> bool {
+        matches!(self, Field::Top | Field::Bottom)
+    }
+
+    /// Returns true if the content is interlaced, i.e. made of fields
+    /// captured at different times, whether the buffers contain single
+    /// fields or both of them.
+    pub fn is_interlaced(self) -> bool {
+        !matches!(self, Field::Any | Field::None)
+    }
+
+    /// Returns true if a buffer with this field contains a top field.
+    /// Equivalent of the `V4L2_FIELD_HAS_TOP` macro.
+    pub fn has_top(self) -> bool {
+        !matches!(
+            self,
+            Field::Any | Field::None | Field::Bottom | Field::Alternate
+        )
+    }
+
+    /// Returns true if a buffer with this field contains a bottom field.
+    /// Equivalent of the `V4L2_FIELD_HAS_BOTTOM` macro.
+    pub fn has_bottom(self) -> bool {
+        !matches!(
+            self,
+            Field::Any | Field::None | Field::Top | Field::Alternate
+        )
+    }
+
+    /// Returns true if a buffer with this field contains both fields of a
+    /// frame. Equivalent of the `V4L2_FIELD_HAS_BOTH` macro.
+    pub fn has_both(self) -> bool {
+        self.has_top() && self.has_bottom()
+    }
+}
+
+/// Clock used by the driver to produce the timestamp of a buffer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampType {
+    #[default]
+    Unknown,
+    /// Timestamps are taken from the `CLOCK_MONOTONIC` clock.
+    Monotonic,
+    /// Timestamps are copied from the matching OUTPUT buffer, as done by
+    /// memory-to-memory devices.
+    Copy,
+}
+
+/// Moment of the capture at which a `Monotonic` timestamp has been taken.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampSource {
+    #[default]
+    EndOfFrame,
+    StartOfExposure,
+}
+
+/// Timestamp of a dequeued buffer, along with the information about how to
+/// interpret it that the driver passes in the buffer flags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferTimestamp {
+    pub tv_sec: i64,
+    pub tv_usec: i64,
+    pub type_: TimestampType,
+    pub source: TimestampSource,
+}
+
+impl BufferTimestamp {
+    /// Build the timestamp from the `timestamp` and `flags` members of a
+    /// `struct v4l2_buffer`.
+    // The members of `timeval` are only 32-bit wide on some architectures.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn from_v4l2(timestamp: &bindings::timeval, flags: u32) -> Self {
+        let type_ = match flags & bindings::V4L2_BUF_FLAG_TIMESTAMP_MASK {
+            bindings::V4L2_BUF_FLAG_TIMESTAMP_MONOTONIC => TimestampType::Monotonic,
+            bindings::V4L2_BUF_FLAG_TIMESTAMP_COPY => TimestampType::Copy,
+            _ => TimestampType::Unknown,
+        };
+        let source = match flags & bindings::V4L2_BUF_FLAG_TSTAMP_SRC_MASK {
+            bindings::V4L2_BUF_FLAG_TSTAMP_SRC_SOE => TimestampSource::StartOfExposure,
+            _ => TimestampSource::EndOfFrame,
+        };
+
+        BufferTimestamp {
+            tv_sec: timestamp.tv_sec as i64,
+            tv_usec: timestamp.tv_usec as i64,
+            type_,
+            source,
+        }
+    }
+
+    /// Returns the timestamp as a duration, regardless of its clock. Negative
+    /// values are clamped to zero.
+    pub fn as_duration(&self) -> Duration {
+        let usecs = self
+            .tv_sec
+            .saturating_mul(1_000_000)
+            .saturating_add(self.tv_usec);
+        Duration::from_micros(usecs.max(0) as u64)
+    }
+
+    /// Converts a `Monotonic` timestamp into an `Instant`, which can then be
+    /// compared against the current time or the timestamps of other sources.
+    /// Returns `None` for other timestamp types, or if the timestamp cannot be
+    /// represented.
+    pub fn to_instant(&self) -> Option<Instant> {
+        if self.type_ != TimestampType::Monotonic {
+            return None;
+        }
+
+        // `Instant` uses `CLOCK_MONOTONIC` on Linux, but has no way to be built
+        // from a raw value, so go through the current time.
+        let mut now: nix::libc::timespec = unsafe { mem::zeroed() };
+        if unsafe { nix::libc::clock_gettime(nix::libc::CLOCK_MONOTONIC, &mut now) } != 0 {
+            return None;
+        }
+        let instant_now = Instant::now();
+        let clock_now = Duration::new(now.tv_sec as u64, now.tv_nsec as u32);
+        let timestamp = self.as_duration();
+
+        if timestamp <= clock_now {
+            instant_now.checked_sub(clock_now - timestamp)
+        } else {
+            instant_now.checked_add(timestamp - clock_now)
+        }
+    }
+}
+
+/// Sequence number of a frame, as counted by the driver.
+///
+/// Sequence numbers are 32-bit and wrap around on long sessions, so they
+/// cannot be compared directly. This type compares them using serial number
+/// arithmetic: a sequence number is after another if it is less than 2^31
+/// ahead of it. Two sequence numbers exactly 2^31 apart cannot be ordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FrameSequence(pub u32);
+
+impl FrameSequence {
+    /// Returns the sequence number following this one.
+    pub fn next(self) -> Self {
+        FrameSequence(self.0.wrapping_add(1))
+    }
+
+    /// Returns how far ahead of `earlier` this sequence number is, or `None`
+    /// if it is not after `earlier`.
+    pub fn distance_since(self, earlier: FrameSequence) -> Option<u32> {
+        match self.partial_cmp(&earlier) {
+            Some(Ordering::Greater) | Some(Ordering::Equal) => Some(self.0.wrapping_sub(earlier.0)),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of sequence numbers skipped between `previous`
+    /// and this one, i.e. the number of frames lost in between. Sequence
+    /// numbers going backwards (e.g. after a `streamoff`) count as no loss.
+    pub fn gap_since(self, previous: FrameSequence) -> u32 {
+        self.distance_since(previous)
+            .map_or(0, |distance| distance.saturating_sub(1))
+    }
+}
+
+impl From<u32> for FrameSequence {
+    fn from(sequence: u32) -> Self {
+        FrameSequence(sequence)
+    }
+}
+
+impl PartialOrd for FrameSequence {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        const HALF: u32 = 1 << 31;
+        match self.0.wrapping_sub(other.0) {
+            0 => Some(Ordering::Equal),
+            HALF => None,
+            distance if distance < HALF => Some(Ordering::Greater),
+            _ => Some(Ordering::Less),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DQBufPlane {
     pub length: u32,
@@ -46,6 +270,35 @@ pub struct DQBufPlane {
     pub data_offset: u32,
 }
 
+/// Type of an encoded frame, as reported by encoders in the flags of the
+/// buffers dequeued from their CAPTURE queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    /// Frame that can be decoded without reference to any other frame.
+    Key,
+    /// Frame predicted from previous frames only.
+    Predicted,
+    /// Frame predicted from both previous and following frames.
+    Bidirectional,
+}
+
+/// Information about an encoded frame, e.g. for adaptive streaming
+/// applications to decide where to start a new segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodedFrameInfo {
+    /// Type of the frame, or `None` if the encoder did not report it.
+    pub frame_type: Option<FrameType>,
+    /// Size of the encoded data, in bytes.
+    pub size: usize,
+    /// Timestamp of the frame, copied by the encoder from the OUTPUT buffer
+    /// the frame has been encoded from.
+    pub timestamp: BufferTimestamp,
+}
+
+/// Planes of a dequeued buffer. Stored inline so dequeuing a buffer does not
+/// allocate.
+pub type DQBufPlanes = ArrayVec<DQBufPlane, { bindings::VIDEO_MAX_PLANES as usize }>;
+
 /// Contains all the information from a dequeued buffer. Safe variant of
 /// `struct v4l2_buffer`.
 #[derive(Debug, Default)]
@@ -54,7 +307,94 @@ pub struct DQBuffer {
     pub flags: BufferFlags,
     pub field: u32,
     pub sequence: u32,
-    pub planes: Vec<DQBufPlane>,
+    pub timestamp: BufferTimestamp,
+    pub planes: DQBufPlanes,
+}
+
+impl DQBuffer {
+    /// Returns the field of this buffer, or `None` if the driver reported an
+    /// invalid value.
+    pub fn field(&self) -> Option<Field> {
+        Field::from_v4l2(self.field)
+    }
+
+    /// Returns true if this buffer only contains one field of a frame, which
+    /// happens when the `Alternate` field order is used.
+    pub fn is_single_field(&self) -> bool {
+        matches!(self.field(), Some(field) if field.is_single_field())
+    }
+
+    /// Returns true if the driver has not written any data into this buffer,
+    /// and the buffer is not the last one of a stream (buffers with the
+    /// `LAST` flag are often empty, but still carry this information).
+    pub fn is_empty(&self) -> bool {
+        !self.flags.contains(BufferFlags::LAST)
+            && self
+                .planes
+                .iter()
+                .all(|plane| plane.bytesused <= plane.data_offset)
+    }
+
+    /// Returns the number of the frame this buffer belongs to.
+    ///
+    /// In `Alternate` mode, the top and bottom fields of a frame are
+    /// dequeued in separate buffers that share the same sequence number, so
+    /// this number is not unique among dequeued buffers.
+    pub fn frame_number(&self) -> u32 {
+        self.sequence
+    }
+
+    /// Returns the sequence number of this buffer as a `FrameSequence`, which
+    /// can be safely compared against the ones of other buffers.
+    pub fn frame_sequence(&self) -> FrameSequence {
+        FrameSequence(self.sequence)
+    }
+
+    /// Returns the number of frames that have been dropped between `previous`
+    /// and this buffer, according to their sequence numbers.
+    ///
+    /// Two fields of the same frame share their sequence number, and are
+    /// thus not counted as a drop. Sequence numbers wrapping around are
+    /// handled, while sequence numbers going backwards (e.g. after a
+    /// `streamoff`) are considered as not having dropped any frame.
+    pub fn frames_dropped_since(&self, previous: &DQBuffer) -> u32 {
+        self.frame_sequence().gap_since(previous.frame_sequence())
+    }
+
+    /// Returns the type of encoded frame contained in this buffer, or `None`
+    /// if none of the `KEYFRAME`, `PFRAME` and `BFRAME` flags are set.
+    pub fn frame_type(&self) -> Option<FrameType> {
+        if self.flags.contains(BufferFlags::KEYFRAME) {
+            Some(FrameType::Key)
+        } else if self.flags.contains(BufferFlags::PFRAME) {
+            Some(FrameType::Predicted)
+        } else if self.flags.contains(BufferFlags::BFRAME) {
+            Some(FrameType::Bidirectional)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if this buffer contains a key frame, i.e. a frame that
+    /// can be decoded independently from the others.
+    pub fn is_keyframe(&self) -> bool {
+        self.frame_type() == Some(FrameType::Key)
+    }
+
+    /// Returns the information about the encoded frame contained in this
+    /// buffer, which is meaningful for buffers dequeued from the CAPTURE
+    /// queue of an encoder.
+    pub fn encoded_frame_info(&self) -> EncodedFrameInfo {
+        EncodedFrameInfo {
+            frame_type: self.frame_type(),
+            size: self
+                .planes
+                .iter()
+                .map(|plane| plane.bytesused.saturating_sub(plane.data_offset) as usize)
+                .sum(),
+            timestamp: self.timestamp,
+        }
+    }
 }
 
 impl DQBuf for DQBuffer {
@@ -63,11 +403,12 @@ impl DQBuf for DQBuffer {
         v4l2_planes: Option<&PlaneData>,
     ) -> Result<Self> {
         let planes = match v4l2_planes {
-            None => vec![DQBufPlane {
+            None => std::iter::once(DQBufPlane {
                 length: v4l2_buf.length,
                 bytesused: v4l2_buf.bytesused,
                 data_offset: 0,
-            }],
+            })
+            .collect(),
             Some(v4l2_planes) => v4l2_planes
                 .iter()
                 .take(v4l2_buf.length as usize)
@@ -84,6 +425,7 @@ impl DQBuf for DQBuffer {
             flags: BufferFlags::from_bits_truncate(v4l2_buf.flags),
             field: v4l2_buf.field,
             sequence: v4l2_buf.sequence,
+            timestamp: BufferTimestamp::from_v4l2(&v4l2_buf.timestamp, v4l2_buf.flags),
             planes,
         })
     }
@@ -97,20 +439,190 @@ mod ioctl {
 
 /// Safe wrapper around the `VIDIOC_DQBUF` ioctl.
 pub fn dqbuf<T: DQBuf, F: AsRawFd>(fd: &F, queue: QueueType) -> Result<T> {
+    if is_multi_planar(queue) {
+        dqbuf_with_planes(fd, queue, &mut Default::default())
+    } else {
+        dqbuf_single_planar(fd, queue)
+    }
+}
+
+/// Same as `dqbuf`, but uses `plane_data` to receive the planes of
+/// multi-planar buffers instead of initializing a new array for every call.
+/// Its previous content is irrelevant, so the same array can be reused to
+/// dequeue all the buffers of a stream.
+pub fn dqbuf_with_planes<T: DQBuf, F: AsRawFd>(
+    fd: &F,
+    queue: QueueType,
+    plane_data: &mut PlaneData,
+) -> Result<T> {
+    if !is_multi_planar(queue) {
+        return dqbuf_single_planar(fd, queue);
+    }
+
     let mut v4l2_buf = bindings::v4l2_buffer {
         type_: queue as u32,
         ..unsafe { mem::zeroed() }
     };
+    v4l2_buf.m.planes = plane_data.as_mut_ptr();
+    v4l2_buf.length = plane_data.len() as u32;
 
-    if is_multi_planar(queue) {
+    unsafe { ioctl::vidioc_dqbuf(fd.as_raw_fd(), &mut v4l2_buf) }?;
+    T::from_v4l2_buffer(&v4l2_buf, Some(plane_data))
+}
+
+fn dqbuf_single_planar<T: DQBuf, F: AsRawFd>(fd: &F, queue: QueueType) -> Result<T> {
+    let mut v4l2_buf = bindings::v4l2_buffer {
+        type_: queue as u32,
+        ..unsafe { mem::zeroed() }
+    };
+
+    unsafe { ioctl::vidioc_dqbuf(fd.as_raw_fd(), &mut v4l2_buf) }?;
+    T::from_v4l2_buffer(&v4l2_buf, None)
+}
+
+/// Same as `dqbuf`, but returns `Error::NotReady` instead of `EAGAIN` when
+/// no buffer is ready on a device opened with `O_NONBLOCK`.
+pub fn try_dqbuf<T: DQBuf, F: AsRawFd>(fd: &F, queue: QueueType) -> Result<T> {
+    dqbuf(fd, queue).map_err(|e| match e {
+        Error::Nix(nix::Error::Sys(Errno::EAGAIN)) => Error::NotReady,
+        e => e,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dqbuffer(field: Field, sequence: u32) -> DQBuffer {
+        DQBuffer {
+            field: field as u32,
+            sequence,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn frames_dropped_since() {
+        // Progressive frames.
+        let first = dqbuffer(Field::None, 10);
+        assert_eq!(dqbuffer(Field::None, 11).frames_dropped_since(&first), 0);
+        assert_eq!(dqbuffer(Field::None, 14).frames_dropped_since(&first), 3);
+
+        // Both fields of a frame share the same sequence number.
+        let top = dqbuffer(Field::Top, 10);
+        let bottom = dqbuffer(Field::Bottom, 10);
+        assert!(top.is_single_field() && bottom.is_single_field());
+        assert_eq!(bottom.frames_dropped_since(&top), 0);
+        assert_eq!(dqbuffer(Field::Top, 11).frames_dropped_since(&bottom), 0);
+
+        // Wrapping and reset sequence numbers.
+        let last = dqbuffer(Field::None, u32::MAX);
+        assert_eq!(dqbuffer(Field::None, 1).frames_dropped_since(&last), 1);
+        assert_eq!(first.frames_dropped_since(&dqbuffer(Field::None, 500)), 0);
+    }
+
+    #[test]
+    fn field_helpers() {
+        assert!(!Field::None.is_interlaced());
+        assert!(Field::Alternate.is_interlaced());
+        assert!(!Field::Alternate.has_top() && !Field::Alternate.has_bottom());
+        assert!(Field::Top.has_top() && !Field::Top.has_both());
+        assert!(Field::SeqBT.has_both());
+        assert!(Field::InterlacedTB.has_both());
+    }
+
+    #[test]
+    fn frame_sequence() {
+        let last = FrameSequence(u32::MAX);
+        assert!(last.next() > last);
+        assert_eq!(last.next(), FrameSequence(0));
+        assert!(FrameSequence(5) < FrameSequence(10));
+        assert!(FrameSequence(3) > FrameSequence(u32::MAX - 3));
+        assert_eq!(FrameSequence(1 << 31).partial_cmp(&FrameSequence(0)), None);
+
+        assert_eq!(FrameSequence(2).distance_since(last), Some(3));
+        assert_eq!(last.distance_since(FrameSequence(2)), None);
+        assert_eq!(FrameSequence(2).gap_since(last), 2);
+        assert_eq!(FrameSequence(7).gap_since(FrameSequence(7)), 0);
+        assert_eq!(FrameSequence(7).gap_since(FrameSequence(9)), 0);
+    }
+
+    #[test]
+    fn buffer_timestamp() {
+        let timeval = bindings::timeval {
+            tv_sec: 12,
+            tv_usec: 500_000,
+        };
+        let flags = bindings::V4L2_BUF_FLAG_TIMESTAMP_MONOTONIC
+            | bindings::V4L2_BUF_FLAG_TSTAMP_SRC_SOE
+            | bindings::V4L2_BUF_FLAG_DONE;
+        let timestamp = BufferTimestamp::from_v4l2(&timeval, flags);
+        assert_eq!(timestamp.type_, TimestampType::Monotonic);
+        assert_eq!(timestamp.source, TimestampSource::StartOfExposure);
+        assert_eq!(timestamp.as_duration(), Duration::from_millis(12_500));
+        assert!(timestamp.to_instant().is_some());
+
+        let timestamp =
+            BufferTimestamp::from_v4l2(&timeval, bindings::V4L2_BUF_FLAG_TIMESTAMP_COPY);
+        assert_eq!(timestamp.type_, TimestampType::Copy);
+        assert_eq!(timestamp.source, TimestampSource::EndOfFrame);
+        assert_eq!(timestamp.to_instant(), None);
+
+        // Out of range values are clamped instead of overflowing.
+        let timestamp = BufferTimestamp {
+            tv_sec: i64::MAX,
+            tv_usec: 999_999,
+            ..Default::default()
+        };
+        assert_eq!(
+            timestamp.as_duration(),
+            Duration::from_micros(i64::MAX as u64)
+        );
+    }
+
+    #[test]
+    fn dqbuffer_planes() {
+        let v4l2_buf = bindings::v4l2_buffer {
+            index: 3,
+            length: 2,
+            ..unsafe { mem::zeroed() }
+        };
         let mut plane_data: PlaneData = Default::default();
-        v4l2_buf.m.planes = plane_data.as_mut_ptr();
-        v4l2_buf.length = plane_data.len() as u32;
+        plane_data[0].bytesused = 100;
+        plane_data[1].bytesused = 50;
+        plane_data[2].bytesused = 25;
 
-        unsafe { ioctl::vidioc_dqbuf(fd.as_raw_fd(), &mut v4l2_buf) }?;
-        Ok(T::from_v4l2_buffer(&v4l2_buf, Some(&plane_data))?)
-    } else {
-        unsafe { ioctl::vidioc_dqbuf(fd.as_raw_fd(), &mut v4l2_buf) }?;
-        Ok(T::from_v4l2_buffer(&v4l2_buf, None)?)
+        let dqbuf = DQBuffer::from_v4l2_buffer(&v4l2_buf, Some(&plane_data)).unwrap();
+        assert_eq!(dqbuf.index, 3);
+        assert_eq!(dqbuf.planes.len(), 2);
+        assert_eq!(dqbuf.planes[1].bytesused, 50);
+
+        let dqbuf = DQBuffer::from_v4l2_buffer(&v4l2_buf, None).unwrap();
+        assert_eq!(dqbuf.planes.len(), 1);
+        assert_eq!(dqbuf.planes[0].length, 2);
+    }
+
+    #[test]
+    fn encoded_frame_info() {
+        let mut dqbuf = DQBuffer {
+            flags: BufferFlags::DONE | BufferFlags::KEYFRAME,
+            planes: std::iter::once(DQBufPlane {
+                length: 4096,
+                bytesused: 1200,
+                data_offset: 200,
+            })
+            .collect(),
+            ..Default::default()
+        };
+        assert!(dqbuf.is_keyframe());
+        let info = dqbuf.encoded_frame_info();
+        assert_eq!(info.frame_type, Some(FrameType::Key));
+        assert_eq!(info.size, 1000);
+
+        dqbuf.flags = BufferFlags::BFRAME;
+        assert_eq!(dqbuf.frame_type(), Some(FrameType::Bidirectional));
+        dqbuf.flags = BufferFlags::DONE;
+        assert_eq!(dqbuf.frame_type(), None);
+        assert!(!dqbuf.is_keyframe());
     }
 }
@@ -0,0 +1,199 @@
+//! Safe wrapper for the `VIDIOC_DQEVENT` ioctl.
+use super::{CtrlFlags, CtrlType};
+use crate::bindings;
+use crate::Result;
+use bitflags::bitflags;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_event;
+    nix::ioctl_read!(vidioc_dqevent, b'V', 89, v4l2_event);
+}
+
+bitflags! {
+    /// Flags corresponding to the `changes` field of `struct
+    /// v4l2_event_src_change`.
+    pub struct SrcChanges: u32 {
+        const RESOLUTION = bindings::V4L2_EVENT_SRC_CH_RESOLUTION;
+    }
+}
+
+bitflags! {
+    /// Flags corresponding to the `changes` field of `struct
+    /// v4l2_event_ctrl`.
+    pub struct CtrlChanges: u32 {
+        const VALUE = bindings::V4L2_EVENT_CTRL_CH_VALUE;
+        const FLAGS = bindings::V4L2_EVENT_CTRL_CH_FLAGS;
+        const RANGE = bindings::V4L2_EVENT_CTRL_CH_RANGE;
+    }
+}
+
+/// Payload of a control event. Safe variant of `struct v4l2_event_ctrl`.
+///
+/// All members are always valid and reflect the current state of the
+/// control, `changes` telling which ones have changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CtrlEvent {
+    pub changes: CtrlChanges,
+    pub type_: CtrlType,
+    /// Value of the control. Not valid for compound controls, which value
+    /// must be read with `g_ext_ctrls`.
+    pub value: i64,
+    pub flags: CtrlFlags,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub step: i32,
+    pub default_value: i32,
+}
+
+/// A V4L2 event, with its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Eos,
+    /// A control has changed. The id of the control is the `id` of the
+    /// `DQEvent`.
+    Ctrl(CtrlEvent),
+    /// A property of the source has changed. The pad or input concerned is
+    /// the `id` of the `DQEvent`.
+    SourceChange(SrcChanges),
+    /// An event of a type not handled by this crate, with its raw payload.
+    Raw {
+        type_: u32,
+        data: [u8; 64],
+    },
+}
+
+/// An event dequeued with `dqevent`, along with its metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DQEvent {
+    pub event: Event,
+    /// Id the event has been emitted for, e.g. a control id.
+    pub id: u32,
+    /// Number of events still pending after this one.
+    pub pending: u32,
+    /// Sequence number of the event. It is incremented for every event, so
+    /// gaps reveal events that have been dropped because the queue of the
+    /// subscription was full.
+    pub sequence: u32,
+    /// Time at which the event has been emitted, in the `CLOCK_MONOTONIC`
+    /// time base.
+    pub timestamp: Duration,
+}
+
+impl DQEvent {
+    // The members of `timespec` are only 32-bit wide on some architectures.
+    #[allow(clippy::unnecessary_cast)]
+    fn from_v4l2(event: &bindings::v4l2_event) -> Self {
+        // Safe because the type of the event tells us which member of the
+        // union is valid, and any bit pattern is valid for `data`.
+        let payload = match event.type_ {
+            bindings::V4L2_EVENT_EOS => Event::Eos,
+            bindings::V4L2_EVENT_CTRL => {
+                let ctrl = unsafe { &event.u.ctrl };
+                let type_ = CtrlType::from_v4l2(ctrl.type_);
+                let value = match type_ {
+                    CtrlType::Integer64 => unsafe { ctrl.__bindgen_anon_1.value64 },
+                    _ => i64::from(unsafe { ctrl.__bindgen_anon_1.value }),
+                };
+                Event::Ctrl(CtrlEvent {
+                    changes: CtrlChanges::from_bits_truncate(ctrl.changes),
+                    type_,
+                    value,
+                    flags: CtrlFlags::from_bits_truncate(ctrl.flags),
+                    minimum: ctrl.minimum,
+                    maximum: ctrl.maximum,
+                    step: ctrl.step,
+                    default_value: ctrl.default_value,
+                })
+            }
+            bindings::V4L2_EVENT_SOURCE_CHANGE => {
+                let changes = unsafe { event.u.src_change.changes };
+                Event::SourceChange(SrcChanges::from_bits_truncate(changes))
+            }
+            type_ => Event::Raw {
+                type_,
+                data: unsafe { event.u.data },
+            },
+        };
+
+        DQEvent {
+            event: payload,
+            id: event.id,
+            pending: event.pending,
+            sequence: event.sequence,
+            timestamp: Duration::new(
+                event.timestamp.tv_sec.max(0) as u64,
+                event.timestamp.tv_nsec.max(0) as u32,
+            ),
+        }
+    }
+}
+
+/// Safe wrapper around the `VIDIOC_DQEVENT` ioctl. Dequeue the next pending
+/// event, blocking until one is available unless `fd` is non-blocking.
+pub fn dqevent<F: AsRawFd>(fd: &F) -> Result<DQEvent> {
+    let mut event: bindings::v4l2_event = unsafe { mem::zeroed() };
+
+    unsafe { ioctl::vidioc_dqevent(fd.as_raw_fd(), &mut event) }?;
+
+    Ok(DQEvent::from_v4l2(&event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ctrl_event() {
+        let mut event: bindings::v4l2_event = unsafe { mem::zeroed() };
+        event.type_ = bindings::V4L2_EVENT_CTRL;
+        event.id = bindings::V4L2_CID_BRIGHTNESS;
+        event.sequence = 3;
+        event.timestamp.tv_sec = 2;
+        event.timestamp.tv_nsec = 500;
+        event.u.ctrl = bindings::v4l2_event_ctrl {
+            changes: bindings::V4L2_EVENT_CTRL_CH_VALUE,
+            type_: bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER,
+            __bindgen_anon_1: bindings::v4l2_event_ctrl__bindgen_ty_1 { value: -12 },
+            flags: 0,
+            minimum: -128,
+            maximum: 127,
+            step: 1,
+            default_value: 0,
+        };
+
+        let dqevent = DQEvent::from_v4l2(&event);
+        assert_eq!(dqevent.id, bindings::V4L2_CID_BRIGHTNESS);
+        assert_eq!(dqevent.sequence, 3);
+        assert_eq!(dqevent.timestamp, Duration::new(2, 500));
+        match dqevent.event {
+            Event::Ctrl(ctrl) => {
+                assert_eq!(ctrl.changes, CtrlChanges::VALUE);
+                assert_eq!(ctrl.type_, CtrlType::Integer);
+                assert_eq!(ctrl.value, -12);
+                assert_eq!((ctrl.minimum, ctrl.maximum), (-128, 127));
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn raw_event() {
+        let mut event: bindings::v4l2_event = unsafe { mem::zeroed() };
+        event.type_ = bindings::V4L2_EVENT_PRIVATE_START + 1;
+        let mut data = [0u8; 64];
+        data[0] = 0x42;
+        event.u.data = data;
+
+        match DQEvent::from_v4l2(&event).event {
+            Event::Raw { type_, data } => {
+                assert_eq!(type_, bindings::V4L2_EVENT_PRIVATE_START + 1);
+                assert_eq!(data[0], 0x42);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+}
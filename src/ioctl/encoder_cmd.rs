@@ -0,0 +1,103 @@
+//! Safe wrappers for the `VIDIOC_ENCODER_CMD` and `VIDIOC_TRY_ENCODER_CMD`
+//! ioctls.
+use crate::bindings;
+use crate::Result;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_encoder_cmd;
+    nix::ioctl_readwrite!(vidioc_encoder_cmd, b'V', 77, v4l2_encoder_cmd);
+    nix::ioctl_readwrite!(vidioc_try_encoder_cmd, b'V', 78, v4l2_encoder_cmd);
+}
+
+/// A command that can be sent to an encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncoderCommand {
+    /// Start encoding, or resume after a `Stop` command has completed.
+    Start,
+    /// Encode all the pending OUTPUT buffers, after which the last CAPTURE
+    /// buffer is marked with the `LAST` flag. If `at_gop_end` is set, the
+    /// encoder stops at the end of the current group of pictures instead.
+    Stop { at_gop_end: bool },
+    /// Pause encoding, keeping the pending data.
+    Pause,
+    /// Resume encoding after a `Pause` command.
+    Resume,
+}
+
+impl EncoderCommand {
+    /// Returns the `cmd` and `flags` members of `struct v4l2_encoder_cmd`
+    /// for this command.
+    fn to_v4l2(self) -> (u32, u32) {
+        match self {
+            EncoderCommand::Start => (bindings::V4L2_ENC_CMD_START, 0),
+            EncoderCommand::Stop { at_gop_end } => (
+                bindings::V4L2_ENC_CMD_STOP,
+                if at_gop_end {
+                    bindings::V4L2_ENC_CMD_STOP_AT_GOP_END
+                } else {
+                    0
+                },
+            ),
+            EncoderCommand::Pause => (bindings::V4L2_ENC_CMD_PAUSE, 0),
+            EncoderCommand::Resume => (bindings::V4L2_ENC_CMD_RESUME, 0),
+        }
+    }
+}
+
+type EncoderCmdIoctl =
+    unsafe fn(nix::libc::c_int, *mut bindings::v4l2_encoder_cmd) -> nix::Result<nix::libc::c_int>;
+
+fn run_encoder_cmd<F: AsRawFd>(
+    ioctl: EncoderCmdIoctl,
+    fd: &F,
+    command: EncoderCommand,
+) -> Result<()> {
+    let (cmd, flags) = command.to_v4l2();
+    let mut enc_cmd = bindings::v4l2_encoder_cmd {
+        cmd,
+        flags,
+        ..unsafe { mem::zeroed() }
+    };
+
+    unsafe { ioctl(fd.as_raw_fd(), &mut enc_cmd) }?;
+
+    Ok(())
+}
+
+/// Safe wrapper around the `VIDIOC_ENCODER_CMD` ioctl.
+pub fn encoder_cmd<F: AsRawFd>(fd: &F, command: EncoderCommand) -> Result<()> {
+    run_encoder_cmd(ioctl::vidioc_encoder_cmd, fd, command)
+}
+
+/// Safe wrapper around the `VIDIOC_TRY_ENCODER_CMD` ioctl. Checks whether
+/// the encoder supports `command`, without executing it.
+pub fn try_encoder_cmd<F: AsRawFd>(fd: &F, command: EncoderCommand) -> Result<()> {
+    run_encoder_cmd(ioctl::vidioc_try_encoder_cmd, fd, command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoder_command() {
+        assert_eq!(
+            EncoderCommand::Stop { at_gop_end: true }.to_v4l2(),
+            (
+                bindings::V4L2_ENC_CMD_STOP,
+                bindings::V4L2_ENC_CMD_STOP_AT_GOP_END
+            )
+        );
+        assert_eq!(
+            EncoderCommand::Stop { at_gop_end: false }.to_v4l2(),
+            (bindings::V4L2_ENC_CMD_STOP, 0)
+        );
+        assert_eq!(
+            EncoderCommand::Resume.to_v4l2(),
+            (bindings::V4L2_ENC_CMD_RESUME, 0)
+        );
+    }
+}
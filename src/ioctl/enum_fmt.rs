@@ -17,11 +17,34 @@ pub trait EnumFmt {
 bitflags! {
     /// Flags returned by the `VIDIOC_ENUM_FMT` ioctl into the `flags` field of
     /// `struct v4l2_fmtdesc`.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct FormatFlags: u32 {
         const COMPRESSED = bindings::V4L2_FMT_FLAG_COMPRESSED;
+        /// The format is converted in software, e.g. by libv4l, and is
+        /// likely to be slower than the native formats.
         const EMULATED = bindings::V4L2_FMT_FLAG_EMULATED;
+        /// The decoder accepts a continuous bytestream on its OUTPUT queue,
+        /// i.e. buffers do not need to contain exactly one frame. Only valid
+        /// for compressed formats of stateful decoders.
+        const CONTINUOUS_BYTESTREAM = bindings::V4L2_FMT_FLAG_CONTINUOUS_BYTESTREAM;
+        /// The resolution can change in the middle of the stream, in which
+        /// case the decoder emits a `SOURCE_CHANGE` event. Only valid for
+        /// compressed formats of stateful decoders.
+        const DYN_RESOLUTION = bindings::V4L2_FMT_FLAG_DYN_RESOLUTION;
+        /// The encoder supports setting the frame interval on its CAPTURE
+        /// queue independently of the OUTPUT one. Not defined in our
+        /// bindings yet.
+        const ENC_CAP_FRAME_INTERVAL = 0x0010;
+        /// The application can choose the colorspace of a CAPTURE queue when
+        /// setting its format. The other `CSC_` flags do the same for the
+        /// other colorimetry properties. Not defined in our bindings yet.
+        const CSC_COLORSPACE = 0x0020;
+        const CSC_XFER_FUNC = 0x0040;
+        const CSC_YCBCR_ENC = 0x0080;
+        const CSC_QUANTIZATION = 0x0100;
     }
 }
+
 /// Quickly get the Fourcc code of a format.
 impl EnumFmt for PixelFormat {
     fn from(fmtdesc: bindings::v4l2_fmtdesc) -> Self {
@@ -30,8 +53,15 @@ impl EnumFmt for PixelFormat {
 }
 
 /// Safe variant of the `v4l2_fmtdesc` struct, to be used with `enum_fmt`.
+///
+/// Despite its name, `pixelformat` contains a data format for SDR queues and
+/// a metadata format for meta queues. The `flags` are only meaningful for
+/// video queues.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FmtDesc {
+    /// The queue this format has been enumerated on.
+    pub queue: QueueType,
     pub flags: FormatFlags,
     pub description: String,
     pub pixelformat: PixelFormat,
@@ -39,6 +69,11 @@ pub struct FmtDesc {
 
 impl fmt::Display for FmtDesc {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.queue.is_sdr() {
+            write!(f, "SDR ")?;
+        } else if self.queue.is_meta() {
+            write!(f, "meta ")?;
+        }
         write!(
             f,
             "{}: {} {}",
@@ -53,9 +88,21 @@ impl fmt::Display for FmtDesc {
     }
 }
 
+impl FmtDesc {
+    /// Returns true if buffers of this format must each contain exactly one
+    /// frame, i.e. it is compressed but the device does not accept a
+    /// continuous bytestream.
+    pub fn requires_full_frames(&self) -> bool {
+        self.flags.contains(FormatFlags::COMPRESSED)
+            && !self.flags.contains(FormatFlags::CONTINUOUS_BYTESTREAM)
+    }
+}
+
 impl EnumFmt for FmtDesc {
     fn from(fmtdesc: bindings::v4l2_fmtdesc) -> Self {
         FmtDesc {
+            // `type_` is the queue type we passed to `enum_fmt`.
+            queue: QueueType::from_v4l2(fmtdesc.type_).expect("Invalid queue type"),
             flags: FormatFlags::from_bits_truncate(fmtdesc.flags),
             description: string_from_cstr(&fmtdesc.description).unwrap_or("".into()),
             pixelformat: fmtdesc.pixelformat.into(),
@@ -84,6 +131,10 @@ pub fn enum_fmt<T: EnumFmt, F: AsRawFd>(fd: &F, queue: QueueType, index: u32) ->
 /// Iterator over the formats of the given queue. This takes a reference to the
 /// device's file descriptor so no operation that could affect the format
 /// enumeration can take place while the iterator exists.
+///
+/// Any queue type supported by `VIDIOC_ENUM_FMT` can be used, including
+/// overlay, SDR and meta queues. The iterator is empty if the device does
+/// not support `queue`.
 pub struct FormatIterator<'a, F: AsRawFd> {
     fd: &'a F,
     queue: QueueType,
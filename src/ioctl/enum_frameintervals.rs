@@ -0,0 +1,234 @@
+//! Safe wrapper for the `VIDIOC_ENUM_FRAMEINTERVALS` ioctl.
+use super::Fraction;
+use crate::bindings;
+use crate::{Error, PixelFormat, Result};
+use nix::errno::Errno;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_frmivalenum;
+    nix::ioctl_readwrite!(vidioc_enum_frameintervals, b'V', 75, v4l2_frmivalenum);
+}
+
+/// Range of frame intervals, with the same layout as `struct
+/// v4l2_frmival_stepwise`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameIntervalRange {
+    pub min: Fraction,
+    pub max: Fraction,
+    pub step: Fraction,
+}
+
+impl FrameIntervalRange {
+    /// Returns true if `interval` is within the range and a valid step from
+    /// its minimum.
+    pub fn contains(&self, interval: Fraction) -> bool {
+        // All the fractions are compared as multiples of the product of their
+        // denominators, which cannot overflow a u128.
+        fn parts(fraction: Fraction) -> (u128, u128) {
+            (
+                u128::from(fraction.numerator),
+                u128::from(fraction.denominator),
+            )
+        }
+        let (n, d) = parts(interval);
+        let (min_n, min_d) = parts(self.min);
+        let (max_n, max_d) = parts(self.max);
+        let (step_n, step_d) = parts(self.step);
+        if d == 0 || min_d == 0 || max_d == 0 {
+            return false;
+        }
+        if n * min_d < min_n * d || n * max_d > max_n * d {
+            return false;
+        }
+        if step_n == 0 || step_d == 0 {
+            return true;
+        }
+
+        // (interval - min) / step must be an integer.
+        let offset = (n * min_d - min_n * d) * step_d;
+        let divisor = d * min_d * step_n;
+        offset / divisor * divisor == offset
+    }
+}
+
+impl From<bindings::v4l2_frmival_stepwise> for FrameIntervalRange {
+    fn from(stepwise: bindings::v4l2_frmival_stepwise) -> Self {
+        FrameIntervalRange {
+            min: stepwise.min.into(),
+            max: stepwise.max.into(),
+            step: stepwise.step.into(),
+        }
+    }
+}
+
+/// A frame interval supported by the device for a given pixel format and
+/// frame size, as returned by `enum_frameintervals`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameInterval {
+    /// A single supported interval. Devices using discrete intervals
+    /// enumerate all of them.
+    Discrete(Fraction),
+    /// All the intervals of the range are supported. This is the only
+    /// interval enumerated for the format.
+    Stepwise(FrameIntervalRange),
+    /// All the intervals between the minimum and maximum are supported. This
+    /// is the only interval enumerated for the format.
+    Continuous(FrameIntervalRange),
+}
+
+impl FrameInterval {
+    /// Returns true if `interval` matches this interval.
+    pub fn contains(&self, interval: Fraction) -> bool {
+        match self {
+            // Compare the values, as 2/60 is the same interval as 1/30.
+            FrameInterval::Discrete(discrete) => {
+                u64::from(discrete.numerator) * u64::from(interval.denominator)
+                    == u64::from(interval.numerator) * u64::from(discrete.denominator)
+                    && interval.denominator != 0
+            }
+            FrameInterval::Stepwise(range) => range.contains(interval),
+            // The step of continuous ranges is 1, which is meaningless.
+            FrameInterval::Continuous(range) => FrameIntervalRange {
+                step: Fraction::default(),
+                ..*range
+            }
+            .contains(interval),
+        }
+    }
+
+    fn from_v4l2(frmival: &bindings::v4l2_frmivalenum) -> Option<Self> {
+        // Safe because the active member of the union is given by `type_`.
+        unsafe {
+            match frmival.type_ {
+                bindings::v4l2_frmivaltypes_V4L2_FRMIVAL_TYPE_DISCRETE => Some(
+                    FrameInterval::Discrete(frmival.__bindgen_anon_1.discrete.into()),
+                ),
+                bindings::v4l2_frmivaltypes_V4L2_FRMIVAL_TYPE_STEPWISE => Some(
+                    FrameInterval::Stepwise(frmival.__bindgen_anon_1.stepwise.into()),
+                ),
+                bindings::v4l2_frmivaltypes_V4L2_FRMIVAL_TYPE_CONTINUOUS => Some(
+                    FrameInterval::Continuous(frmival.__bindgen_anon_1.stepwise.into()),
+                ),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Safe wrapper around the `VIDIOC_ENUM_FRAMEINTERVALS` ioctl.
+///
+/// Only discrete intervals have an `index` other than 0. `EINVAL` is
+/// returned once all the intervals have been enumerated, and `EIO` if the
+/// driver returns an unknown interval type.
+pub fn enum_frameintervals<F: AsRawFd>(
+    fd: &F,
+    pixel_format: PixelFormat,
+    width: u32,
+    height: u32,
+    index: u32,
+) -> Result<FrameInterval> {
+    let mut frmival = bindings::v4l2_frmivalenum {
+        index,
+        pixel_format: pixel_format.into(),
+        width,
+        height,
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe { ioctl::vidioc_enum_frameintervals(fd.as_raw_fd(), &mut frmival) }?;
+
+    FrameInterval::from_v4l2(&frmival).ok_or(Error::Nix(nix::Error::Sys(Errno::EIO)))
+}
+
+/// Iterator over the frame intervals supported for a pixel format and frame
+/// size. Like `FormatIterator`, it holds a reference to the device's file
+/// descriptor.
+///
+/// The iterator is empty if the device does not support the format, or does
+/// not implement `VIDIOC_ENUM_FRAMEINTERVALS`.
+pub struct FrameIntervalIterator<'a, F: AsRawFd> {
+    fd: &'a F,
+    pixel_format: PixelFormat,
+    width: u32,
+    height: u32,
+    index: u32,
+    done: bool,
+}
+
+impl<'a, F: AsRawFd> FrameIntervalIterator<'a, F> {
+    /// Create a new iterator listing the frame intervals supported for
+    /// `pixel_format` at a size of `width`x`height`.
+    pub fn new(fd: &'a F, pixel_format: PixelFormat, width: u32, height: u32) -> Self {
+        FrameIntervalIterator {
+            fd,
+            pixel_format,
+            width,
+            height,
+            index: 0,
+            done: false,
+        }
+    }
+}
+
+impl<'a, F: AsRawFd> Iterator for FrameIntervalIterator<'a, F> {
+    type Item = FrameInterval;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match enum_frameintervals(
+            self.fd,
+            self.pixel_format,
+            self.width,
+            self.height,
+            self.index,
+        ) {
+            Ok(interval) => {
+                self.index += 1;
+                // Ranges are always the only interval of the format.
+                self.done = !matches!(interval, FrameInterval::Discrete(_));
+                Some(interval)
+            }
+            // EINVAL means we have reached the last interval.
+            Err(Error::Nix(nix::Error::Sys(Errno::EINVAL))) => None,
+            // ENOTTY means the driver does not enumerate intervals.
+            Err(Error::Nix(nix::Error::Sys(Errno::ENOTTY))) => None,
+            _ => {
+                eprintln!("Unexpected return value for VIDIOC_ENUM_FRAMEINTERVALS!");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_intervals() {
+        let discrete = FrameInterval::Discrete(Fraction::new(1, 30));
+        assert!(discrete.contains(Fraction::new(2, 60)));
+        assert!(!discrete.contains(Fraction::new(1, 25)));
+        assert!(!discrete.contains(Fraction::new(0, 0)));
+
+        // From 1/30 to 1/10, in steps of 1/30.
+        let range = FrameIntervalRange {
+            min: Fraction::new(1, 30),
+            max: Fraction::new(1, 10),
+            step: Fraction::new(1, 30),
+        };
+        let stepwise = FrameInterval::Stepwise(range);
+        assert!(stepwise.contains(Fraction::new(1, 15)));
+        assert!(stepwise.contains(Fraction::new(1, 10)));
+        assert!(!stepwise.contains(Fraction::new(1, 20)));
+        assert!(!stepwise.contains(Fraction::new(1, 60)));
+        assert!(FrameInterval::Continuous(range).contains(Fraction::new(1, 20)));
+    }
+}
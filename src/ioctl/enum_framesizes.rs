@@ -0,0 +1,214 @@
+//! Safe wrapper for the `VIDIOC_ENUM_FRAMESIZES` ioctl.
+use crate::bindings;
+use crate::{Error, PixelFormat, Result};
+use nix::errno::Errno;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_frmsizeenum;
+    nix::ioctl_readwrite!(vidioc_enum_framesizes, b'V', 74, v4l2_frmsizeenum);
+}
+
+/// Range of frame sizes, with the same layout as `struct
+/// v4l2_frmsize_stepwise`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameSizeRange {
+    pub min_width: u32,
+    pub max_width: u32,
+    pub step_width: u32,
+    pub min_height: u32,
+    pub max_height: u32,
+    pub step_height: u32,
+}
+
+impl FrameSizeRange {
+    /// Returns true if `width`x`height` is within the range and a valid step
+    /// from its minimum.
+    pub fn contains(&self, width: u32, height: u32) -> bool {
+        fn in_range(value: u32, min: u32, max: u32, step: u32) -> bool {
+            let step = step.max(1);
+            value >= min && value <= max && (value - min) / step * step == value - min
+        }
+
+        in_range(width, self.min_width, self.max_width, self.step_width)
+            && in_range(height, self.min_height, self.max_height, self.step_height)
+    }
+}
+
+impl From<bindings::v4l2_frmsize_stepwise> for FrameSizeRange {
+    fn from(stepwise: bindings::v4l2_frmsize_stepwise) -> Self {
+        FrameSizeRange {
+            min_width: stepwise.min_width,
+            max_width: stepwise.max_width,
+            step_width: stepwise.step_width,
+            min_height: stepwise.min_height,
+            max_height: stepwise.max_height,
+            step_height: stepwise.step_height,
+        }
+    }
+}
+
+/// A frame size supported by the device for a given pixel format, as
+/// returned by `enum_framesizes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameSize {
+    /// A single supported size. Devices using discrete sizes enumerate all
+    /// of them.
+    Discrete { width: u32, height: u32 },
+    /// All the sizes of the range are supported. This is the only size
+    /// enumerated for the pixel format.
+    Stepwise(FrameSizeRange),
+    /// All the sizes between the minimum and maximum are supported, with a
+    /// step of 1 in both dimensions. This is the only size enumerated for
+    /// the pixel format.
+    Continuous(FrameSizeRange),
+}
+
+impl FrameSize {
+    /// Returns true if `width`x`height` matches this size.
+    pub fn contains(&self, width: u32, height: u32) -> bool {
+        match self {
+            FrameSize::Discrete {
+                width: w,
+                height: h,
+            } => (*w, *h) == (width, height),
+            FrameSize::Stepwise(range) | FrameSize::Continuous(range) => {
+                range.contains(width, height)
+            }
+        }
+    }
+
+    fn from_v4l2(frmsize: &bindings::v4l2_frmsizeenum) -> Option<Self> {
+        // Safe because the active member of the union is given by `type_`.
+        unsafe {
+            match frmsize.type_ {
+                bindings::v4l2_frmsizetypes_V4L2_FRMSIZE_TYPE_DISCRETE => {
+                    let discrete = frmsize.__bindgen_anon_1.discrete;
+                    Some(FrameSize::Discrete {
+                        width: discrete.width,
+                        height: discrete.height,
+                    })
+                }
+                bindings::v4l2_frmsizetypes_V4L2_FRMSIZE_TYPE_STEPWISE => Some(
+                    FrameSize::Stepwise(frmsize.__bindgen_anon_1.stepwise.into()),
+                ),
+                bindings::v4l2_frmsizetypes_V4L2_FRMSIZE_TYPE_CONTINUOUS => Some(
+                    FrameSize::Continuous(frmsize.__bindgen_anon_1.stepwise.into()),
+                ),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Safe wrapper around the `VIDIOC_ENUM_FRAMESIZES` ioctl.
+///
+/// Only discrete sizes have an `index` other than 0. `EINVAL` is returned
+/// once all the sizes have been enumerated, and `EIO` if the driver returns
+/// an unknown size type.
+pub fn enum_framesizes<F: AsRawFd>(
+    fd: &F,
+    pixel_format: PixelFormat,
+    index: u32,
+) -> Result<FrameSize> {
+    let mut frmsize = bindings::v4l2_frmsizeenum {
+        index,
+        pixel_format: pixel_format.into(),
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe { ioctl::vidioc_enum_framesizes(fd.as_raw_fd(), &mut frmsize) }?;
+
+    FrameSize::from_v4l2(&frmsize).ok_or(Error::Nix(nix::Error::Sys(Errno::EIO)))
+}
+
+/// Iterator over the frame sizes supported for a pixel format. Like
+/// `FormatIterator`, it holds a reference to the device's file descriptor.
+///
+/// The iterator is empty if the device does not support the pixel format,
+/// or does not implement `VIDIOC_ENUM_FRAMESIZES`.
+pub struct FrameSizeIterator<'a, F: AsRawFd> {
+    fd: &'a F,
+    pixel_format: PixelFormat,
+    index: u32,
+    done: bool,
+}
+
+impl<'a, F: AsRawFd> FrameSizeIterator<'a, F> {
+    /// Create a new iterator listing the frame sizes supported for
+    /// `pixel_format`.
+    pub fn new(fd: &'a F, pixel_format: PixelFormat) -> Self {
+        FrameSizeIterator {
+            fd,
+            pixel_format,
+            index: 0,
+            done: false,
+        }
+    }
+}
+
+impl<'a, F: AsRawFd> Iterator for FrameSizeIterator<'a, F> {
+    type Item = FrameSize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match enum_framesizes(self.fd, self.pixel_format, self.index) {
+            Ok(size) => {
+                self.index += 1;
+                // Ranges are always the only size of the pixel format.
+                self.done = !matches!(size, FrameSize::Discrete { .. });
+                Some(size)
+            }
+            // EINVAL means we have reached the last size.
+            Err(Error::Nix(nix::Error::Sys(Errno::EINVAL))) => None,
+            // ENOTTY means the driver does not enumerate sizes.
+            Err(Error::Nix(nix::Error::Sys(Errno::ENOTTY))) => None,
+            _ => {
+                eprintln!("Unexpected return value for VIDIOC_ENUM_FRAMESIZES!");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_sizes() {
+        let mut frmsize: bindings::v4l2_frmsizeenum = unsafe { mem::zeroed() };
+        frmsize.type_ = bindings::v4l2_frmsizetypes_V4L2_FRMSIZE_TYPE_DISCRETE;
+        frmsize.__bindgen_anon_1.discrete = bindings::v4l2_frmsize_discrete {
+            width: 640,
+            height: 480,
+        };
+        let discrete = FrameSize::from_v4l2(&frmsize).unwrap();
+        assert!(discrete.contains(640, 480));
+        assert!(!discrete.contains(640, 360));
+
+        frmsize.type_ = bindings::v4l2_frmsizetypes_V4L2_FRMSIZE_TYPE_STEPWISE;
+        frmsize.__bindgen_anon_1.stepwise = bindings::v4l2_frmsize_stepwise {
+            min_width: 16,
+            max_width: 1920,
+            step_width: 16,
+            min_height: 16,
+            max_height: 1088,
+            step_height: 16,
+        };
+        let stepwise = FrameSize::from_v4l2(&frmsize).unwrap();
+        assert!(matches!(stepwise, FrameSize::Stepwise(_)));
+        assert!(stepwise.contains(1280, 720));
+        assert!(!stepwise.contains(1280, 721));
+        assert!(!stepwise.contains(3840, 2160));
+
+        frmsize.type_ = 0;
+        assert_eq!(FrameSize::from_v4l2(&frmsize), None);
+    }
+}
@@ -0,0 +1,107 @@
+//! Safe wrapper for the `VIDIOC_EXPBUF` ioctl.
+use crate::bindings;
+use crate::QueueType;
+use crate::Result;
+use std::fs::File;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+
+/// Access mode of an exported DMABUF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportAccess {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+impl ExportAccess {
+    fn to_v4l2(self) -> u32 {
+        (match self {
+            ExportAccess::ReadOnly => nix::libc::O_RDONLY,
+            ExportAccess::WriteOnly => nix::libc::O_WRONLY,
+            ExportAccess::ReadWrite => nix::libc::O_RDWR,
+        }) as u32
+    }
+}
+
+/// Options for exporting a buffer plane as a DMABUF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportFlags {
+    pub access: ExportAccess,
+    /// Whether the DMABUF file descriptor is closed on `exec`.
+    pub cloexec: bool,
+}
+
+impl Default for ExportFlags {
+    /// Read-write access, closed on `exec`.
+    fn default() -> Self {
+        ExportFlags {
+            access: ExportAccess::ReadWrite,
+            cloexec: true,
+        }
+    }
+}
+
+impl ExportFlags {
+    fn to_v4l2(self) -> u32 {
+        let cloexec = match self.cloexec {
+            true => nix::libc::O_CLOEXEC as u32,
+            false => 0,
+        };
+
+        self.access.to_v4l2() | cloexec
+    }
+}
+
+/// A DMABUF exported from a buffer plane, along with the flags it has been
+/// exported with so importers know how they can access it. The file
+/// descriptor is closed when this object is dropped.
+#[derive(Debug)]
+pub struct ExportedBuffer {
+    pub file: File,
+    pub flags: ExportFlags,
+}
+
+impl AsRawFd for ExportedBuffer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for ExportedBuffer {
+    fn into_raw_fd(self) -> RawFd {
+        self.file.into_raw_fd()
+    }
+}
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_exportbuffer;
+    nix::ioctl_readwrite!(vidioc_expbuf, b'V', 16, v4l2_exportbuffer);
+}
+
+/// Safe wrapper around the `VIDIOC_EXPBUF` ioctl. Exports plane `plane` of
+/// MMAP buffer `index` as a DMABUF.
+pub fn expbuf<F: AsRawFd>(
+    fd: &F,
+    queue: QueueType,
+    index: usize,
+    plane: usize,
+    flags: ExportFlags,
+) -> Result<ExportedBuffer> {
+    let mut v4l2_expbuf = bindings::v4l2_exportbuffer {
+        type_: queue as u32,
+        index: index as u32,
+        plane: plane as u32,
+        flags: flags.to_v4l2(),
+        ..unsafe { mem::zeroed() }
+    };
+
+    unsafe { ioctl::vidioc_expbuf(fd.as_raw_fd(), &mut v4l2_expbuf) }?;
+
+    Ok(ExportedBuffer {
+        // Safe because the kernel has just created this fd for us.
+        file: unsafe { File::from_raw_fd(v4l2_expbuf.fd) },
+        flags,
+    })
+}
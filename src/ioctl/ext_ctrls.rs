@@ -0,0 +1,202 @@
+//! Safe wrappers for the `VIDIOC_G_EXT_CTRLS`, `VIDIOC_S_EXT_CTRLS` and
+//! `VIDIOC_TRY_EXT_CTRLS` ioctls.
+use crate::bindings;
+use crate::CtrlId;
+use crate::Result;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// Which value of the controls an ext-ctrls ioctl operates on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CtrlWhich {
+    /// The current value of the controls.
+    Current,
+    /// The default value of the controls. Can only be read.
+    Default,
+    /// The value of the controls stored in the media request `request_fd`,
+    /// which is applied when the request is queued.
+    Request(RawFd),
+}
+
+impl CtrlWhich {
+    fn which(self) -> u32 {
+        match self {
+            CtrlWhich::Current => bindings::V4L2_CTRL_WHICH_CUR_VAL,
+            CtrlWhich::Default => bindings::V4L2_CTRL_WHICH_DEF_VAL,
+            CtrlWhich::Request(_) => bindings::V4L2_CTRL_WHICH_REQUEST_VAL,
+        }
+    }
+
+    fn request_fd(self) -> RawFd {
+        match self {
+            CtrlWhich::Request(fd) => fd,
+            _ => 0,
+        }
+    }
+}
+
+/// Value of an extended control, in the form expected by the kernel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtControlValue {
+    /// Value of a control with a 32-bit or smaller type.
+    Value(i32),
+    /// Value of a `CtrlType::Integer64` control.
+    Value64(i64),
+    /// Payload of a control with `CtrlFlags::HAS_PAYLOAD`, i.e. strings,
+    /// arrays and compound controls. The buffer must be exactly as large as
+    /// the payload of the control, which is `elem_size * elems` as returned
+    /// by `query_ext_ctrl`.
+    ///
+    /// If the buffer is too small, the ioctl fails with `ENOSPC` and the
+    /// buffer is resized to the required size, so the ioctl can be retried.
+    Payload(Vec<u8>),
+}
+
+/// A control to read or write with the ext-ctrls ioctls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtControl {
+    pub id: CtrlId,
+    pub value: ExtControlValue,
+}
+
+impl ExtControl {
+    pub fn new(id: CtrlId, value: ExtControlValue) -> Self {
+        ExtControl { id, value }
+    }
+
+    /// Returns the kernel representation of this control. The returned
+    /// structure points to the payload of `self`, if any, and thus must not
+    /// outlive it.
+    fn as_raw(&mut self) -> bindings::v4l2_ext_control {
+        let mut raw = bindings::v4l2_ext_control {
+            id: self.id.into(),
+            ..unsafe { mem::zeroed() }
+        };
+        match &mut self.value {
+            ExtControlValue::Value(value) => raw.__bindgen_anon_1.value = *value,
+            ExtControlValue::Value64(value) => raw.__bindgen_anon_1.value64 = *value,
+            ExtControlValue::Payload(payload) => {
+                raw.size = payload.len() as u32;
+                raw.__bindgen_anon_1.ptr = payload.as_mut_ptr() as *mut _;
+            }
+        }
+
+        raw
+    }
+
+    /// Update this control with the values written by the kernel into `raw`.
+    fn update_from(&mut self, raw: &bindings::v4l2_ext_control) {
+        // Safe because we read the member of the union we have set in
+        // `as_raw`, and the kernel returns values of the same type.
+        match &mut self.value {
+            ExtControlValue::Value(value) => *value = unsafe { raw.__bindgen_anon_1.value },
+            ExtControlValue::Value64(value) => *value = unsafe { raw.__bindgen_anon_1.value64 },
+            ExtControlValue::Payload(payload) => {
+                let size = raw.size as usize;
+                if size > payload.len() {
+                    payload.resize(size, 0);
+                }
+            }
+        }
+    }
+}
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_ext_controls;
+    nix::ioctl_readwrite!(vidioc_g_ext_ctrls, b'V', 71, v4l2_ext_controls);
+    nix::ioctl_readwrite!(vidioc_s_ext_ctrls, b'V', 72, v4l2_ext_controls);
+    nix::ioctl_readwrite!(vidioc_try_ext_ctrls, b'V', 73, v4l2_ext_controls);
+}
+
+type ExtCtrlsIoctl =
+    unsafe fn(nix::libc::c_int, *mut bindings::v4l2_ext_controls) -> nix::Result<nix::libc::c_int>;
+
+fn ext_ctrls<F: AsRawFd>(
+    ioctl: ExtCtrlsIoctl,
+    fd: &F,
+    which: CtrlWhich,
+    controls: &mut [ExtControl],
+) -> Result<()> {
+    let mut raw_controls: Vec<_> = controls.iter_mut().map(ExtControl::as_raw).collect();
+    let mut ext_controls = bindings::v4l2_ext_controls {
+        __bindgen_anon_1: bindings::v4l2_ext_controls__bindgen_ty_1 {
+            which: which.which(),
+        },
+        count: raw_controls.len() as u32,
+        request_fd: which.request_fd(),
+        controls: raw_controls.as_mut_ptr(),
+        ..unsafe { mem::zeroed() }
+    };
+
+    // Safe because the payloads of `controls` outlive the ioctl, and the
+    // kernel won't write beyond the sizes we passed.
+    let res = unsafe { ioctl(fd.as_raw_fd(), &mut ext_controls) };
+
+    // The kernel may have updated the controls even if the ioctl failed, e.g.
+    // to report the required size of a payload.
+    for (control, raw) in controls.iter_mut().zip(raw_controls.iter()) {
+        control.update_from(raw);
+    }
+    res?;
+
+    Ok(())
+}
+
+/// Safe wrapper around the `VIDIOC_G_EXT_CTRLS` ioctl. Reads the `which`
+/// value of all `controls` at once.
+pub fn g_ext_ctrls<F: AsRawFd>(
+    fd: &F,
+    which: CtrlWhich,
+    controls: &mut [ExtControl],
+) -> Result<()> {
+    ext_ctrls(ioctl::vidioc_g_ext_ctrls, fd, which, controls)
+}
+
+/// Safe wrapper around the `VIDIOC_S_EXT_CTRLS` ioctl. Sets the `which` value
+/// of all `controls` atomically: if one of them is rejected, none is set.
+///
+/// The values actually set, which the driver may have adjusted, are written
+/// back into `controls`.
+pub fn s_ext_ctrls<F: AsRawFd>(
+    fd: &mut F,
+    which: CtrlWhich,
+    controls: &mut [ExtControl],
+) -> Result<()> {
+    ext_ctrls(ioctl::vidioc_s_ext_ctrls, fd, which, controls)
+}
+
+/// Safe wrapper around the `VIDIOC_TRY_EXT_CTRLS` ioctl. Checks whether
+/// `controls` would be accepted by `s_ext_ctrls`, without setting them.
+///
+/// The values that would be set are written back into `controls`.
+pub fn try_ext_ctrls<F: AsRawFd>(
+    fd: &F,
+    which: CtrlWhich,
+    controls: &mut [ExtControl],
+) -> Result<()> {
+    ext_ctrls(ioctl::vidioc_try_ext_ctrls, fd, which, controls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ext_control_raw() {
+        let mut control = ExtControl::new(CtrlId::GAIN, ExtControlValue::Payload(vec![0u8; 4]));
+        let mut raw = control.as_raw();
+        assert_eq!({ raw.size }, 4);
+
+        // Kernel reporting a larger payload size with ENOSPC.
+        raw.size = 16;
+        control.update_from(&raw);
+        assert_eq!(control.value, ExtControlValue::Payload(vec![0u8; 16]));
+
+        let mut control = ExtControl::new(CtrlId::VIDEO_BITRATE, ExtControlValue::Value(0));
+        let mut raw = control.as_raw();
+        raw.__bindgen_anon_1.value = 1_000_000;
+        control.update_from(&raw);
+        assert_eq!(control.value, ExtControlValue::Value(1_000_000));
+    }
+}
@@ -0,0 +1,42 @@
+//! Safe wrapper for the `VIDIOC_(G|S)_CROP` ioctls.
+//!
+//! These are part of the legacy crop API, which is superseded by the
+//! selection API but is the only one implemented by some older drivers. On
+//! OUTPUT queues, the legacy crop rectangle is the compose rectangle of the
+//! selection API.
+use super::Rect;
+use crate::bindings;
+use crate::{QueueType, Result};
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_crop;
+    nix::ioctl_readwrite!(vidioc_g_crop, b'V', 59, v4l2_crop);
+    nix::ioctl_write_ptr!(vidioc_s_crop, b'V', 60, v4l2_crop);
+}
+
+/// Safe wrapper around the `VIDIOC_G_CROP` ioctl.
+pub fn g_crop<F: AsRawFd>(fd: &F, queue: QueueType) -> Result<Rect> {
+    let mut crop = bindings::v4l2_crop {
+        type_: queue as u32,
+        c: Rect::default().into(),
+    };
+    unsafe { ioctl::vidioc_g_crop(fd.as_raw_fd(), &mut crop) }?;
+
+    Ok(crop.c.into())
+}
+
+/// Safe wrapper around the `VIDIOC_S_CROP` ioctl.
+///
+/// The driver may adjust `rect`, but does not report the rectangle actually
+/// applied, which must be read back with `g_crop`.
+pub fn s_crop<F: AsRawFd>(fd: &mut F, queue: QueueType, rect: Rect) -> Result<()> {
+    let crop = bindings::v4l2_crop {
+        type_: queue as u32,
+        c: rect.into(),
+    };
+    unsafe { ioctl::vidioc_s_crop(fd.as_raw_fd(), &crop) }?;
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+//! Safe wrappers for the `VIDIOC_G_CTRL` and `VIDIOC_S_CTRL` ioctls.
+use crate::bindings;
+use crate::CtrlId;
+use crate::Result;
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_control;
+    nix::ioctl_readwrite!(vidioc_g_ctrl, b'V', 27, v4l2_control);
+    nix::ioctl_readwrite!(vidioc_s_ctrl, b'V', 28, v4l2_control);
+}
+
+/// Safe wrapper around the `VIDIOC_G_CTRL` ioctl. Returns the current value
+/// of control `id`.
+///
+/// Only controls with a value that fits in 32 bits can be read this way.
+pub fn g_ctrl<F: AsRawFd>(fd: &F, id: CtrlId) -> Result<i32> {
+    let mut control = bindings::v4l2_control {
+        id: id.into(),
+        value: 0,
+    };
+
+    unsafe { ioctl::vidioc_g_ctrl(fd.as_raw_fd(), &mut control) }?;
+
+    Ok(control.value)
+}
+
+/// Safe wrapper around the `VIDIOC_S_CTRL` ioctl. Sets control `id` to
+/// `value`, and returns the value actually set, which the driver may have
+/// adjusted.
+pub fn s_ctrl<F: AsRawFd>(fd: &mut F, id: CtrlId, value: i32) -> Result<i32> {
+    let mut control = bindings::v4l2_control {
+        id: id.into(),
+        value,
+    };
+
+    unsafe { ioctl::vidioc_s_ctrl(fd.as_raw_fd(), &mut control) }?;
+
+    Ok(control.value)
+}
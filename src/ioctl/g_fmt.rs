@@ -1,5 +1,7 @@
 //! Safe wrapper for the `VIDIOC_(G|S|TRY)_FMT` ioctls.
+use super::Field;
 use crate::bindings;
+use crate::{Colorspace, Quantization, XferFunc, YCbCrEncoding};
 use crate::{Error, Result};
 use crate::{Format, PixelFormat, PlanePixFormat, QueueType};
 use std::convert::{From, Into, TryFrom, TryInto};
@@ -15,6 +17,11 @@ impl TryFrom<(Format, QueueType)> for bindings::v4l2_format {
     type Error = Error;
 
     fn try_from((format, queue): (Format, QueueType)) -> Result<Self> {
+        // Overlay, SDR and meta formats cannot be described by `Format`.
+        if !queue.is_video() {
+            return Err(Error::InvalidBufferType);
+        }
+
         Ok(bindings::v4l2_format {
             type_: queue as u32,
             fmt: match queue {
@@ -29,8 +36,15 @@ impl TryFrom<(Format, QueueType)> for bindings::v4l2_format {
                                 width: format.width,
                                 height: format.height,
                                 pixelformat: format.pixelformat.into(),
+                                field: format.field as u32,
+                                colorspace: format.colorspace.into(),
                                 num_planes: format.plane_fmt.len() as u8,
                                 plane_fmt: Default::default(),
+                                __bindgen_anon_1: bindings::v4l2_pix_format_mplane__bindgen_ty_1 {
+                                    ycbcr_enc: u32::from(format.ycbcr_enc) as u8,
+                                },
+                                quantization: u32::from(format.quantization) as u8,
+                                xfer_func: u32::from(format.xfer_func) as u8,
                                 ..unsafe { mem::zeroed() }
                             };
 
@@ -65,8 +79,18 @@ impl TryFrom<(Format, QueueType)> for bindings::v4l2_format {
                             width: format.width,
                             height: format.height,
                             pixelformat: format.pixelformat.into(),
+                            field: format.field as u32,
                             bytesperline,
                             sizeimage,
+                            colorspace: format.colorspace.into(),
+                            // Tells the kernel that the fields below are
+                            // valid.
+                            priv_: bindings::V4L2_PIX_FMT_PRIV_MAGIC,
+                            __bindgen_anon_1: bindings::v4l2_pix_format__bindgen_ty_1 {
+                                ycbcr_enc: format.ycbcr_enc.into(),
+                            },
+                            quantization: format.quantization.into(),
+                            xfer_func: format.xfer_func.into(),
                             ..unsafe { mem::zeroed() }
                         }
                     },
@@ -91,6 +115,12 @@ impl TryFrom<bindings::v4l2_format> for Format {
                         bytesperline: pix.bytesperline,
                         sizeimage: pix.sizeimage,
                     }],
+                    field: Field::from_v4l2(pix.field).unwrap_or_default(),
+                    colorspace: Colorspace::from_v4l2(pix.colorspace).unwrap_or_default(),
+                    xfer_func: XferFunc::from_v4l2(pix.xfer_func).unwrap_or_default(),
+                    ycbcr_enc: YCbCrEncoding::from_v4l2(unsafe { pix.__bindgen_anon_1.ycbcr_enc })
+                        .unwrap_or_default(),
+                    quantization: Quantization::from_v4l2(pix.quantization).unwrap_or_default(),
                 })
             }
             bindings::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE
@@ -116,6 +146,15 @@ impl TryFrom<bindings::v4l2_format> for Format {
                     height: pix_mp.height,
                     pixelformat: PixelFormat::from(pix_mp.pixelformat),
                     plane_fmt,
+                    field: Field::from_v4l2(pix_mp.field).unwrap_or_default(),
+                    colorspace: Colorspace::from_v4l2(pix_mp.colorspace).unwrap_or_default(),
+                    xfer_func: XferFunc::from_v4l2(pix_mp.xfer_func.into()).unwrap_or_default(),
+                    ycbcr_enc: YCbCrEncoding::from_v4l2(
+                        unsafe { pix_mp.__bindgen_anon_1.ycbcr_enc }.into(),
+                    )
+                    .unwrap_or_default(),
+                    quantization: Quantization::from_v4l2(pix_mp.quantization.into())
+                        .unwrap_or_default(),
                 })
             }
             _ => Err(Error::InvalidBufferType),
@@ -210,6 +249,11 @@ mod test {
                     bytesperline: 160,
                 },
             ],
+            field: Field::InterlacedTB,
+            colorspace: Colorspace::Rec709,
+            xfer_func: XferFunc::Rec709,
+            ycbcr_enc: YCbCrEncoding::Rec709,
+            quantization: Quantization::LimRange,
         };
         let v4l2_format = bindings::v4l2_format {
             ..(mplane.clone(), QueueType::VideoCaptureMplane)
@@ -232,6 +276,11 @@ mod test {
                 sizeimage: 307200,
                 bytesperline: 640,
             }],
+            field: Field::Alternate,
+            colorspace: Colorspace::Srgb,
+            xfer_func: XferFunc::Srgb,
+            ycbcr_enc: YCbCrEncoding::Bt601,
+            quantization: Quantization::FullRange,
         };
         // Conversion to/from single-planar format.
         let v4l2_format = bindings::v4l2_format {
@@ -263,10 +312,27 @@ mod test {
                     bytesperline: 160,
                 },
             ],
+            ..Default::default()
         };
         assert_eq!(
             TryInto::<bindings::v4l2_format>::try_into((mplane, QueueType::VideoCapture)).err(),
             Some(Error::TooManyPlanes)
         );
     }
+
+    #[test]
+    // Queues that do not use pixel formats are rejected.
+    fn non_video_to_v4l2_format() {
+        let format = Format::from((b"NV12", (640, 480)));
+        for &queue in &[
+            QueueType::MetaCapture,
+            QueueType::SdrCapture,
+            QueueType::VideoOverlay,
+        ] {
+            assert_eq!(
+                TryInto::<bindings::v4l2_format>::try_into((format.clone(), queue)).err(),
+                Some(Error::InvalidBufferType)
+            );
+        }
+    }
 }
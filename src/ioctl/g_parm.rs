@@ -0,0 +1,220 @@
+//! Safe wrapper for the `VIDIOC_(G|S)_PARM` ioctls.
+use crate::bindings;
+use crate::{QueueType, Result};
+use bitflags::bitflags;
+use std::fmt;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_streamparm;
+    nix::ioctl_readwrite!(vidioc_g_parm, b'V', 21, v4l2_streamparm);
+    nix::ioctl_readwrite!(vidioc_s_parm, b'V', 22, v4l2_streamparm);
+}
+
+/// A fraction, used for frame intervals. Safe variant of `struct v4l2_fract`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fraction {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl Fraction {
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        Fraction {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Returns the value of the fraction, or `None` if its denominator is 0.
+    pub fn as_f64(&self) -> Option<f64> {
+        if self.denominator == 0 {
+            None
+        } else {
+            Some(f64::from(self.numerator) / f64::from(self.denominator))
+        }
+    }
+}
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl From<bindings::v4l2_fract> for Fraction {
+    fn from(fract: bindings::v4l2_fract) -> Self {
+        Fraction::new(fract.numerator, fract.denominator)
+    }
+}
+
+impl From<Fraction> for bindings::v4l2_fract {
+    fn from(fraction: Fraction) -> Self {
+        bindings::v4l2_fract {
+            numerator: fraction.numerator,
+            denominator: fraction.denominator,
+        }
+    }
+}
+
+bitflags! {
+    /// Flags of the `capability` field of `struct v4l2_captureparm` and
+    /// `struct v4l2_outputparm`.
+    pub struct StreamParmCapabilities: u32 {
+        /// `time_per_frame` can be set.
+        const TIMEPERFRAME = bindings::V4L2_CAP_TIMEPERFRAME;
+    }
+}
+
+bitflags! {
+    /// Flags of the `capturemode` and `outputmode` fields of `struct
+    /// v4l2_captureparm` and `struct v4l2_outputparm`.
+    pub struct StreamParmModes: u32 {
+        const HIGHQUALITY = bindings::V4L2_MODE_HIGHQUALITY;
+    }
+}
+
+/// Streaming parameters of a queue. Safe variant of `struct
+/// v4l2_captureparm` and `struct v4l2_outputparm`, which have the same
+/// layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamParm {
+    /// Set by the driver, ignored by `s_parm`.
+    pub capabilities: StreamParmCapabilities,
+    pub mode: StreamParmModes,
+    /// Interval between frames, in seconds. A value of 0/0 keeps the current
+    /// interval when passed to `s_parm`.
+    pub time_per_frame: Fraction,
+    /// Driver-specific mode, should be 0.
+    pub extended_mode: u32,
+    /// Number of buffers used by the `read()` or `write()` I/O methods.
+    pub num_buffers: u32,
+}
+
+impl StreamParm {
+    /// Parameters that only change the interval between frames to
+    /// `time_per_frame`.
+    pub fn with_time_per_frame(time_per_frame: Fraction) -> Self {
+        StreamParm {
+            capabilities: StreamParmCapabilities::empty(),
+            mode: StreamParmModes::empty(),
+            time_per_frame,
+            extended_mode: 0,
+            num_buffers: 0,
+        }
+    }
+
+    fn from_v4l2(streamparm: &bindings::v4l2_streamparm, queue: QueueType) -> Self {
+        // Safe because the active member of the union is given by the queue
+        // type, and both members have the same layout anyway.
+        unsafe {
+            if queue.is_output() {
+                let output = &streamparm.parm.output;
+                StreamParm {
+                    capabilities: StreamParmCapabilities::from_bits_truncate(output.capability),
+                    mode: StreamParmModes::from_bits_truncate(output.outputmode),
+                    time_per_frame: output.timeperframe.into(),
+                    extended_mode: output.extendedmode,
+                    num_buffers: output.writebuffers,
+                }
+            } else {
+                let capture = &streamparm.parm.capture;
+                StreamParm {
+                    capabilities: StreamParmCapabilities::from_bits_truncate(capture.capability),
+                    mode: StreamParmModes::from_bits_truncate(capture.capturemode),
+                    time_per_frame: capture.timeperframe.into(),
+                    extended_mode: capture.extendedmode,
+                    num_buffers: capture.readbuffers,
+                }
+            }
+        }
+    }
+
+    fn to_v4l2(self, queue: QueueType) -> bindings::v4l2_streamparm {
+        let mut streamparm = bindings::v4l2_streamparm {
+            type_: queue as u32,
+            ..unsafe { mem::zeroed() }
+        };
+        if queue.is_output() {
+            streamparm.parm.output = bindings::v4l2_outputparm {
+                capability: self.capabilities.bits(),
+                outputmode: self.mode.bits(),
+                timeperframe: self.time_per_frame.into(),
+                extendedmode: self.extended_mode,
+                writebuffers: self.num_buffers,
+                ..unsafe { mem::zeroed() }
+            };
+        } else {
+            streamparm.parm.capture = bindings::v4l2_captureparm {
+                capability: self.capabilities.bits(),
+                capturemode: self.mode.bits(),
+                timeperframe: self.time_per_frame.into(),
+                extendedmode: self.extended_mode,
+                readbuffers: self.num_buffers,
+                ..unsafe { mem::zeroed() }
+            };
+        }
+
+        streamparm
+    }
+}
+
+/// Safe wrapper around the `VIDIOC_G_PARM` ioctl.
+pub fn g_parm<F: AsRawFd>(fd: &F, queue: QueueType) -> Result<StreamParm> {
+    let mut streamparm = bindings::v4l2_streamparm {
+        type_: queue as u32,
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe { ioctl::vidioc_g_parm(fd.as_raw_fd(), &mut streamparm) }?;
+
+    Ok(StreamParm::from_v4l2(&streamparm, queue))
+}
+
+/// Safe wrapper around the `VIDIOC_S_PARM` ioctl. Returns the parameters
+/// actually applied by the driver, which may have adjusted
+/// `time_per_frame` to the nearest supported value.
+pub fn s_parm<F: AsRawFd>(fd: &mut F, queue: QueueType, parm: StreamParm) -> Result<StreamParm> {
+    let mut streamparm = parm.to_v4l2(queue);
+    unsafe { ioctl::vidioc_s_parm(fd.as_raw_fd(), &mut streamparm) }?;
+
+    Ok(StreamParm::from_v4l2(&streamparm, queue))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_parm() {
+        let parm = StreamParm {
+            mode: StreamParmModes::HIGHQUALITY,
+            num_buffers: 2,
+            ..StreamParm::with_time_per_frame(Fraction::new(1, 30))
+        };
+
+        let streamparm = parm.to_v4l2(QueueType::VideoOutput);
+        let output = unsafe { streamparm.parm.output };
+        assert_eq!(streamparm.type_, QueueType::VideoOutput as u32);
+        assert_eq!(output.outputmode, bindings::V4L2_MODE_HIGHQUALITY);
+        assert_eq!(output.writebuffers, 2);
+        assert_eq!(
+            StreamParm::from_v4l2(&streamparm, QueueType::VideoOutput),
+            parm
+        );
+
+        let streamparm = parm.to_v4l2(QueueType::VideoCaptureMplane);
+        let capture = unsafe { streamparm.parm.capture };
+        assert_eq!(capture.timeperframe.denominator, 30);
+        assert_eq!(capture.readbuffers, 2);
+    }
+
+    #[test]
+    fn fraction() {
+        assert_eq!(Fraction::new(1, 4).as_f64(), Some(0.25));
+        assert_eq!(Fraction::new(1, 0).as_f64(), None);
+        assert_eq!(Fraction::new(1001, 30000).to_string(), "1001/30000");
+    }
+}
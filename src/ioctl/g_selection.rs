@@ -0,0 +1,129 @@
+//! Safe wrapper for the `VIDIOC_(G|S)_SELECTION` ioctls.
+use crate::bindings;
+use crate::{QueueType, Result};
+use bitflags::bitflags;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_selection;
+    nix::ioctl_readwrite!(vidioc_g_selection, b'V', 94, v4l2_selection);
+    nix::ioctl_readwrite!(vidioc_s_selection, b'V', 95, v4l2_selection);
+}
+
+/// A rectangle within a frame. Safe variant of `struct v4l2_rect`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(left: i32, top: i32, width: u32, height: u32) -> Self {
+        Rect {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+}
+
+impl From<bindings::v4l2_rect> for Rect {
+    fn from(rect: bindings::v4l2_rect) -> Self {
+        Rect::new(rect.left, rect.top, rect.width, rect.height)
+    }
+}
+
+impl From<Rect> for bindings::v4l2_rect {
+    fn from(rect: Rect) -> Self {
+        bindings::v4l2_rect {
+            left: rect.left,
+            top: rect.top,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+/// Rectangles that can be read or set with the selection ioctls.
+///
+/// The crop targets apply to the source of the data (e.g. the sensor of a
+/// capture device, or the frames of an OUTPUT queue), and the compose targets
+/// to its destination (e.g. the buffers of a CAPTURE queue).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SelectionTarget {
+    /// The area of the source that is used.
+    Crop = bindings::V4L2_SEL_TGT_CROP,
+    /// The default crop rectangle, covering the whole picture.
+    CropDefault = bindings::V4L2_SEL_TGT_CROP_DEFAULT,
+    /// The limits within which the crop rectangle can be set.
+    CropBounds = bindings::V4L2_SEL_TGT_CROP_BOUNDS,
+    /// The native size of the source, e.g. of the sensor.
+    NativeSize = bindings::V4L2_SEL_TGT_NATIVE_SIZE,
+    /// The area of the destination the data is written to. For decoders,
+    /// this is the visible part of the decoded frames.
+    Compose = bindings::V4L2_SEL_TGT_COMPOSE,
+    /// The default compose rectangle.
+    ComposeDefault = bindings::V4L2_SEL_TGT_COMPOSE_DEFAULT,
+    /// The limits within which the compose rectangle can be set.
+    ComposeBounds = bindings::V4L2_SEL_TGT_COMPOSE_BOUNDS,
+    /// The compose rectangle, plus the padding pixels written by the
+    /// hardware around it.
+    ComposePadded = bindings::V4L2_SEL_TGT_COMPOSE_PADDED,
+}
+
+bitflags! {
+    /// Constraints on how the driver may adjust the rectangle passed to
+    /// `s_selection`.
+    pub struct SelectionFlags: u32 {
+        /// The rectangle may only grow.
+        const GE = bindings::V4L2_SEL_FLAG_GE;
+        /// The rectangle may only shrink.
+        const LE = bindings::V4L2_SEL_FLAG_LE;
+        /// Do not change the configuration of the other targets. Mostly
+        /// meant for subdevices.
+        const KEEP_CONFIG = bindings::V4L2_SEL_FLAG_KEEP_CONFIG;
+    }
+}
+
+/// Safe wrapper around the `VIDIOC_G_SELECTION` ioctl.
+///
+/// As per the V4L2 specification, `queue` should not be a multi-planar
+/// type, although recent kernels accept these as well.
+pub fn g_selection<F: AsRawFd>(fd: &F, queue: QueueType, target: SelectionTarget) -> Result<Rect> {
+    let mut selection = bindings::v4l2_selection {
+        type_: queue as u32,
+        target: target as u32,
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe { ioctl::vidioc_g_selection(fd.as_raw_fd(), &mut selection) }?;
+
+    Ok(selection.r.into())
+}
+
+/// Safe wrapper around the `VIDIOC_S_SELECTION` ioctl. Returns the rectangle
+/// actually applied by the driver, which may have adjusted `rect` within the
+/// constraints given by `flags`.
+pub fn s_selection<F: AsRawFd>(
+    fd: &mut F,
+    queue: QueueType,
+    target: SelectionTarget,
+    rect: Rect,
+    flags: SelectionFlags,
+) -> Result<Rect> {
+    let mut selection = bindings::v4l2_selection {
+        type_: queue as u32,
+        target: target as u32,
+        flags: flags.bits(),
+        r: rect.into(),
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe { ioctl::vidioc_s_selection(fd.as_raw_fd(), &mut selection) }?;
+
+    Ok(selection.r.into())
+}
@@ -0,0 +1,138 @@
+//! Safe wrappers for mapping MMAP buffer planes into memory.
+use crate::Result;
+use nix::sys::mman::{self, MapFlags, ProtFlags};
+use std::fmt::{self, Debug};
+use std::os::unix::io::AsRawFd;
+
+/// A mapping of a MMAP buffer plane, which is unmapped when dropped.
+///
+/// The content of the plane is only meaningful while the buffer is not
+/// queued, i.e. before queueing an OUTPUT buffer or after dequeueing a
+/// CAPTURE one.
+pub struct PlaneMapping {
+    data: *mut u8,
+    length: usize,
+}
+
+// The mapping is owned by this object and only accessed through it.
+unsafe impl Send for PlaneMapping {}
+unsafe impl Sync for PlaneMapping {}
+
+impl PlaneMapping {
+    /// Returns the size of the mapping, in bytes.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Returns true if the mapping is empty.
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Returns the content of the plane.
+    pub fn as_slice(&self) -> &[u8] {
+        // Safe because the mapping is valid for `length` bytes until we drop.
+        unsafe { std::slice::from_raw_parts(self.data, self.length) }
+    }
+
+    /// Returns the content of the plane for writing.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // Safe because the mapping is valid for `length` bytes until we drop,
+        // and we are borrowed mutably.
+        unsafe { std::slice::from_raw_parts_mut(self.data, self.length) }
+    }
+}
+
+impl AsRef<[u8]> for PlaneMapping {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsMut<[u8]> for PlaneMapping {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+impl Debug for PlaneMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PlaneMapping")
+            .field("data", &self.data)
+            .field("length", &self.length)
+            .finish()
+    }
+}
+
+impl Drop for PlaneMapping {
+    fn drop(&mut self) {
+        // Safe because we are unmapping exactly what we have mapped. Nothing
+        // sensible can be done if this fails.
+        let _ = unsafe { mman::munmap(self.data as *mut _, self.length) };
+    }
+}
+
+/// Map `length` bytes of the buffer plane located at `mem_offset` of `fd`,
+/// as returned by `querybuf` into a `QueryBufferMMAP`. The plane is mapped
+/// for reading and writing.
+pub fn mmap<F: AsRawFd>(fd: &F, mem_offset: u32, length: u32) -> Result<PlaneMapping> {
+    let length = length as usize;
+    // Safe because we let the kernel choose the address of the mapping, which
+    // is only accessed within the bounds we have been given.
+    let data = unsafe {
+        mman::mmap(
+            std::ptr::null_mut(),
+            length,
+            ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+            MapFlags::MAP_SHARED,
+            fd.as_raw_fd(),
+            mem_offset as nix::libc::off_t,
+        )
+    }?;
+
+    Ok(PlaneMapping {
+        data: data as *mut u8,
+        length,
+    })
+}
+
+/// Unmap `mapping`, reporting any error. Dropping the mapping also unmaps it,
+/// but silently ignores errors.
+pub fn munmap(mapping: PlaneMapping) -> Result<()> {
+    let mapping = std::mem::ManuallyDrop::new(mapping);
+    // Safe because we are unmapping exactly what has been mapped, and the
+    // mapping won't be accessed or unmapped again.
+    unsafe { mman::munmap(mapping.data as *mut _, mapping.length) }?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::io::Read;
+
+    #[test]
+    fn map_file() {
+        let path = std::env::temp_dir().join(format!("v4l2-mmap-{}", std::process::id()));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(8192).unwrap();
+
+        let mut mapping = mmap(&file, 4096, 16).unwrap();
+        assert_eq!(mapping.len(), 16);
+        mapping.as_mut_slice()[..4].copy_from_slice(b"V4L2");
+        assert_eq!(&mapping.as_slice()[..4], b"V4L2");
+        munmap(mapping).unwrap();
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).unwrap();
+        assert_eq!(&content[4096..4100], b"V4L2");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+//! Safe wrapper for the `VIDIOC_PREPARE_BUF` ioctl.
+use super::{is_multi_planar, PlaneData, QBuf, QBufTemplate};
+use crate::bindings;
+use crate::memory::PlaneHandle;
+use crate::QueueType;
+use crate::Result;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_buffer;
+    nix::ioctl_readwrite!(vidioc_prepare_buf, b'V', 93, v4l2_buffer);
+}
+
+/// Safe wrapper around the `VIDIOC_PREPARE_BUF` ioctl.
+///
+/// Lets the driver perform the preparation steps of queueing buffer `index`
+/// with `buf_data` (e.g. validating it, pinning or mapping its memory, cache
+/// management) ahead of time, so the subsequent `qbuf` of the same buffer
+/// is faster. The same data must then be passed to `qbuf`.
+pub fn prepare_buf<T: QBuf, F: AsRawFd>(
+    fd: &F,
+    queue: QueueType,
+    index: usize,
+    buf_data: T,
+) -> Result<()> {
+    let mut v4l2_buf = bindings::v4l2_buffer {
+        index: index as u32,
+        type_: queue as u32,
+        ..unsafe { mem::zeroed() }
+    };
+
+    if is_multi_planar(queue) {
+        let mut plane_data: PlaneData = Default::default();
+        v4l2_buf.m.planes = plane_data.as_mut_ptr();
+
+        buf_data.fill_mplane_v4l2_buffer(&mut v4l2_buf, &mut plane_data)?;
+        unsafe { ioctl::vidioc_prepare_buf(fd.as_raw_fd(), &mut v4l2_buf) }?;
+        Ok(())
+    } else {
+        buf_data.fill_splane_v4l2_buffer(&mut v4l2_buf)?;
+        unsafe { ioctl::vidioc_prepare_buf(fd.as_raw_fd(), &mut v4l2_buf) }?;
+        Ok(())
+    }
+}
+
+/// Same as `prepare_buf`, but prepares the buffer described by `template`,
+/// which must then be passed to `qbuf_template`.
+pub fn prepare_buf_template<H: PlaneHandle, F: AsRawFd>(
+    fd: &F,
+    template: &QBufTemplate<H>,
+) -> Result<()> {
+    template.with_v4l2_buffer(|v4l2_buf| unsafe {
+        ioctl::vidioc_prepare_buf(fd.as_raw_fd(), v4l2_buf)
+    })?;
+    Ok(())
+}
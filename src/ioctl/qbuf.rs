@@ -6,8 +6,10 @@ use crate::{bindings, Error, QueueType, Result};
 use bitflags::bitflags;
 use std::cmp::Ordering;
 use std::fmt::Debug;
+use std::marker::PhantomData;
 use std::mem;
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
 
 /// For simple initialization of `PlaneData`.
 impl Default for bindings::v4l2_plane {
@@ -26,6 +28,11 @@ bitflags! {
         const QUEUED = bindings::V4L2_BUF_FLAG_QUEUED;
         const DONE = bindings::V4L2_BUF_FLAG_DONE;
         const ERROR = bindings::V4L2_BUF_FLAG_ERROR;
+        const KEYFRAME = bindings::V4L2_BUF_FLAG_KEYFRAME;
+        const PFRAME = bindings::V4L2_BUF_FLAG_PFRAME;
+        const BFRAME = bindings::V4L2_BUF_FLAG_BFRAME;
+        const PREPARED = bindings::V4L2_BUF_FLAG_PREPARED;
+        const REQUEST_FD = bindings::V4L2_BUF_FLAG_REQUEST_FD;
 
         const LAST = bindings::V4L2_BUF_FLAG_LAST;
     }
@@ -71,6 +78,12 @@ pub struct QBuffer<H: PlaneHandle> {
     pub flags: BufferFlags,
     pub field: u32,
     pub sequence: u32,
+    /// Media request to queue the buffer into. Only used if `flags` contains
+    /// `REQUEST_FD`.
+    pub request_fd: RawFd,
+    /// Timestamp of the buffer. Only meaningful for OUTPUT buffers, whose
+    /// timestamp is copied to the CAPTURE buffers produced from them.
+    pub timestamp: Duration,
     pub planes: Vec<QBufPlane<H>>,
 }
 
@@ -80,12 +93,28 @@ impl<H: PlaneHandle> Default for QBuffer<H> {
             flags: Default::default(),
             field: Default::default(),
             sequence: Default::default(),
+            request_fd: Default::default(),
+            timestamp: Default::default(),
             planes: Vec::new(),
         }
     }
 }
 
-impl<H: PlaneHandle> QBuf for QBuffer<H> {
+impl<H: PlaneHandle> QBuffer<H> {
+    /// Fill the members of `v4l2_buf` that do not depend on the planes.
+    fn fill_v4l2_buffer_common(&self, v4l2_buf: &mut bindings::v4l2_buffer) {
+        v4l2_buf.flags = self.flags.bits();
+        v4l2_buf.timestamp.tv_sec = self.timestamp.as_secs() as _;
+        v4l2_buf.timestamp.tv_usec = self.timestamp.subsec_micros() as _;
+        if self.flags.contains(BufferFlags::REQUEST_FD) {
+            v4l2_buf.__bindgen_anon_1.request_fd = self.request_fd;
+        }
+    }
+}
+
+/// Borrowing variant, for passing the same buffer data to several ioctls, e.g.
+/// `prepare_buf` and `qbuf`.
+impl<H: PlaneHandle> QBuf for &QBuffer<H> {
     fn fill_splane_v4l2_buffer(self, v4l2_buf: &mut bindings::v4l2_buffer) -> Result<()> {
         match self.planes.len().cmp(&1) {
             Ordering::Less => return Err(Error::NotEnoughPlanes),
@@ -97,6 +126,7 @@ impl<H: PlaneHandle> QBuf for QBuffer<H> {
         if plane.data_offset != 0 {
             return Err(Error::DataOffsetNotSupported);
         }
+        self.fill_v4l2_buffer_common(v4l2_buf);
         v4l2_buf.memory = H::MEMORY_TYPE as u32;
         v4l2_buf.bytesused = plane.bytesused;
         H::fill_v4l2_buffer(&plane.handle, v4l2_buf);
@@ -116,11 +146,12 @@ impl<H: PlaneHandle> QBuf for QBuffer<H> {
             return Err(Error::TooManyPlanes);
         }
 
+        self.fill_v4l2_buffer_common(v4l2_buf);
         v4l2_buf.memory = H::MEMORY_TYPE as u32;
         v4l2_buf.length = self.planes.len() as u32;
         v4l2_planes
             .iter_mut()
-            .zip(self.planes.into_iter())
+            .zip(self.planes.iter())
             .for_each(|(v4l2_plane, plane)| {
                 v4l2_plane.bytesused = plane.bytesused;
                 v4l2_plane.data_offset = plane.data_offset;
@@ -131,6 +162,172 @@ impl<H: PlaneHandle> QBuf for QBuffer<H> {
     }
 }
 
+impl<H: PlaneHandle> QBuf for QBuffer<H> {
+    fn fill_splane_v4l2_buffer(self, v4l2_buf: &mut bindings::v4l2_buffer) -> Result<()> {
+        (&self).fill_splane_v4l2_buffer(v4l2_buf)
+    }
+
+    fn fill_mplane_v4l2_buffer(
+        self,
+        v4l2_buf: &mut bindings::v4l2_buffer,
+        v4l2_planes: &mut PlaneData,
+    ) -> Result<()> {
+        (&self).fill_mplane_v4l2_buffer(v4l2_buf, v4l2_planes)
+    }
+}
+
+/// Pre-filled buffer information for queueing the same buffer repeatedly
+/// with `qbuf_template`.
+///
+/// The members that do not change between frames (index, queue type, memory
+/// type and number of planes) are set once when the template is created, so
+/// only the bytes used and memory handle of each plane, and possibly the
+/// flags, field, timestamp and request, need to be updated before each
+/// queueing. Create one template per buffer index and keep it for the whole
+/// stream.
+pub struct QBufTemplate<H: PlaneHandle> {
+    v4l2_buf: bindings::v4l2_buffer,
+    v4l2_planes: PlaneData,
+    multi_planar: bool,
+    _handle: PhantomData<H>,
+}
+
+// `v4l2_buffer` is not `Send` because of the pointer to the planes of
+// multi-planar buffers, which is only set on the copies passed to the ioctls.
+unsafe impl<H: PlaneHandle + Send> Send for QBufTemplate<H> {}
+
+impl<H: PlaneHandle> QBufTemplate<H> {
+    /// Create a template for buffer `index` of `queue`, which has
+    /// `num_planes` planes. Single-planar queues only support one plane.
+    pub fn new(queue: QueueType, index: usize, num_planes: usize) -> Result<Self> {
+        let multi_planar = is_multi_planar(queue);
+        let max_planes = if multi_planar {
+            bindings::VIDEO_MAX_PLANES as usize
+        } else {
+            1
+        };
+        if num_planes == 0 {
+            return Err(Error::NotEnoughPlanes);
+        } else if num_planes > max_planes {
+            return Err(Error::TooManyPlanes);
+        }
+
+        let mut v4l2_buf = bindings::v4l2_buffer {
+            index: index as u32,
+            type_: queue as u32,
+            memory: H::MEMORY_TYPE as u32,
+            ..unsafe { mem::zeroed() }
+        };
+        if multi_planar {
+            v4l2_buf.length = num_planes as u32;
+        }
+
+        Ok(QBufTemplate {
+            v4l2_buf,
+            v4l2_planes: Default::default(),
+            multi_planar,
+            _handle: PhantomData,
+        })
+    }
+
+    /// Returns the index of the buffer this template queues.
+    pub fn index(&self) -> usize {
+        self.v4l2_buf.index as usize
+    }
+
+    /// Returns the number of planes of the buffer.
+    pub fn num_planes(&self) -> usize {
+        if self.multi_planar {
+            self.v4l2_buf.length as usize
+        } else {
+            1
+        }
+    }
+
+    /// Set the number of bytes used and the memory handle of plane `plane`
+    /// for the next queueing.
+    pub fn set_plane(&mut self, plane: usize, bytesused: u32, handle: &H) -> Result<()> {
+        if plane >= self.num_planes() {
+            return Err(Error::TooManyPlanes);
+        }
+
+        if self.multi_planar {
+            let v4l2_plane = &mut self.v4l2_planes[plane];
+            v4l2_plane.bytesused = bytesused;
+            handle.fill_v4l2_plane(v4l2_plane);
+        } else {
+            self.v4l2_buf.bytesused = bytesused;
+            handle.fill_v4l2_buffer(&mut self.v4l2_buf);
+        }
+
+        Ok(())
+    }
+
+    /// Set the offset at which the data of plane `plane` starts. Only
+    /// multi-planar buffers support a non-zero offset.
+    pub fn set_data_offset(&mut self, plane: usize, data_offset: u32) -> Result<()> {
+        if plane >= self.num_planes() {
+            return Err(Error::TooManyPlanes);
+        }
+
+        if self.multi_planar {
+            self.v4l2_planes[plane].data_offset = data_offset;
+        } else if data_offset != 0 {
+            return Err(Error::DataOffsetNotSupported);
+        }
+
+        Ok(())
+    }
+
+    /// Set the flags to queue the buffer with.
+    pub fn set_flags(&mut self, flags: BufferFlags) {
+        self.v4l2_buf.flags = flags.bits();
+    }
+
+    /// Set the field contained in the buffer.
+    pub fn set_field(&mut self, field: u32) {
+        self.v4l2_buf.field = field;
+    }
+
+    /// Set the timestamp of the buffer. Only meaningful for OUTPUT buffers.
+    pub fn set_timestamp(&mut self, timestamp: Duration) {
+        self.v4l2_buf.timestamp.tv_sec = timestamp.as_secs() as _;
+        self.v4l2_buf.timestamp.tv_usec = timestamp.subsec_micros() as _;
+    }
+
+    /// Queue the buffer as part of the media request `request_fd`, or
+    /// immediately if `None`.
+    pub fn set_request(&mut self, request_fd: Option<RawFd>) {
+        let mut flags = BufferFlags::from_bits_truncate(self.v4l2_buf.flags);
+        flags.set(BufferFlags::REQUEST_FD, request_fd.is_some());
+        self.v4l2_buf.flags = flags.bits();
+        self.v4l2_buf.__bindgen_anon_1.request_fd = request_fd.unwrap_or(0);
+    }
+
+    /// Reset the flags, field, timestamp and request of the buffer, before
+    /// reusing the template for another frame. The planes are left as they
+    /// are.
+    pub fn clear(&mut self) {
+        self.v4l2_buf.flags = 0;
+        self.v4l2_buf.field = 0;
+        self.set_timestamp(Duration::default());
+        self.v4l2_buf.__bindgen_anon_1.request_fd = 0;
+    }
+
+    /// Invoke `f` with a copy of the buffer information that can be passed
+    /// to an ioctl. The driver writes back into the buffer and planes, so
+    /// the template itself is left untouched.
+    pub(super) fn with_v4l2_buffer<R>(&self, f: impl FnOnce(&mut bindings::v4l2_buffer) -> R) -> R {
+        let mut v4l2_buf = self.v4l2_buf;
+        let mut plane_data = self.v4l2_planes;
+        if self.multi_planar {
+            v4l2_buf.m.planes = plane_data.as_mut_ptr();
+        }
+
+        f(&mut v4l2_buf)
+    }
+}
+
 #[doc(hidden)]
 mod ioctl {
     use crate::bindings::v4l2_buffer;
@@ -178,3 +375,66 @@ pub fn qbuf<T: QBuf, F: AsRawFd>(
         Ok(())
     }
 }
+
+/// Same as `qbuf`, but queues the buffer described by `template`. The
+/// template is left untouched, so it can be queued again after only updating
+/// what changed.
+///
+/// The same invariants as for `qbuf` must be guaranteed by the caller.
+pub fn qbuf_template<H: PlaneHandle, F: AsRawFd>(fd: &F, template: &QBufTemplate<H>) -> Result<()> {
+    template
+        .with_v4l2_buffer(|v4l2_buf| unsafe { ioctl::vidioc_qbuf(fd.as_raw_fd(), v4l2_buf) })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{DMABufHandle, MMAPHandle, MemoryType};
+
+    #[test]
+    fn qbuf_template() {
+        assert_eq!(
+            QBufTemplate::<MMAPHandle>::new(QueueType::VideoCapture, 0, 2).err(),
+            Some(Error::TooManyPlanes)
+        );
+        assert_eq!(
+            QBufTemplate::<MMAPHandle>::new(QueueType::VideoCaptureMplane, 0, 0).err(),
+            Some(Error::NotEnoughPlanes)
+        );
+
+        let mut template =
+            QBufTemplate::<MMAPHandle>::new(QueueType::VideoOutputMplane, 3, 2).unwrap();
+        assert_eq!(template.index(), 3);
+        assert_eq!(template.num_planes(), 2);
+        template.set_plane(1, 1000, &MMAPHandle::default()).unwrap();
+        assert_eq!(template.v4l2_planes[1].bytesused, 1000);
+        assert_eq!(
+            template.set_plane(2, 1000, &MMAPHandle::default()),
+            Err(Error::TooManyPlanes)
+        );
+        template.set_data_offset(1, 64).unwrap();
+        assert_eq!(template.v4l2_planes[1].data_offset, 64);
+        template.set_timestamp(Duration::from_micros(1_500_000));
+        assert_eq!(template.v4l2_buf.timestamp.tv_sec, 1);
+        assert_eq!(template.v4l2_buf.timestamp.tv_usec, 500_000);
+        template.set_request(Some(7));
+        assert_eq!(template.v4l2_buf.flags, BufferFlags::REQUEST_FD.bits());
+        assert_eq!(unsafe { template.v4l2_buf.__bindgen_anon_1.request_fd }, 7);
+        template.clear();
+        assert_eq!(template.v4l2_buf.flags, 0);
+        assert_eq!(template.v4l2_buf.timestamp.tv_sec, 0);
+        assert_eq!(template.v4l2_planes[1].bytesused, 1000);
+
+        let mut template = QBufTemplate::new(QueueType::VideoCapture, 1, 1).unwrap();
+        template
+            .set_plane(0, 0, &unsafe { DMABufHandle::new(5) })
+            .unwrap();
+        assert_eq!(template.v4l2_buf.memory, MemoryType::DMABuf as u32);
+        assert_eq!(unsafe { template.v4l2_buf.m.fd }, 5);
+        assert_eq!(
+            template.set_data_offset(0, 64),
+            Err(Error::DataOffsetNotSupported)
+        );
+    }
+}
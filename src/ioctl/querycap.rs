@@ -15,6 +15,7 @@ pub trait QueryCap {
 bitflags! {
     /// Flags returned by the `VIDIOC_QUERYCAP` ioctl into the `capabilities`
     /// or `device_capabilities` field of `v4l2_capability`.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Capabilities: u32 {
         const VIDEO_CAPTURE = bindings::V4L2_CAP_VIDEO_CAPTURE;
         const VIDEO_OUTPUT = bindings::V4L2_CAP_VIDEO_OUTPUT;
@@ -69,6 +70,7 @@ impl QueryCap for Capabilities {
 
 /// Safe variant of the `v4l2_capability` struct, to be used with `querycap`.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Capability {
     pub driver: String,
     pub card: String,
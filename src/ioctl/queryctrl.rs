@@ -0,0 +1,257 @@
+//! Safe wrappers for the `VIDIOC_QUERYCTRL` and `VIDIOC_QUERY_EXT_CTRL`
+//! ioctls.
+use super::string_from_cstr;
+use crate::bindings;
+use crate::{CtrlClass, CtrlId};
+use crate::{Error, Result};
+use bitflags::bitflags;
+use nix::errno::Errno;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+/// Type of the value of a control.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CtrlType {
+    Integer,
+    Boolean,
+    Menu,
+    Button,
+    Integer64,
+    /// Not an actual control, but the description of a class of controls.
+    CtrlClass,
+    String,
+    Bitmask,
+    IntegerMenu,
+    U8,
+    U16,
+    U32,
+    /// Any other type, typically a compound control carrying a C structure.
+    Other(u32),
+}
+
+impl CtrlType {
+    pub(super) fn from_v4l2(type_: u32) -> Self {
+        match type_ {
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER => CtrlType::Integer,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_BOOLEAN => CtrlType::Boolean,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_MENU => CtrlType::Menu,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_BUTTON => CtrlType::Button,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER64 => CtrlType::Integer64,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_CTRL_CLASS => CtrlType::CtrlClass,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_STRING => CtrlType::String,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_BITMASK => CtrlType::Bitmask,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_INTEGER_MENU => CtrlType::IntegerMenu,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_U8 => CtrlType::U8,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_U16 => CtrlType::U16,
+            bindings::v4l2_ctrl_type_V4L2_CTRL_TYPE_U32 => CtrlType::U32,
+            type_ => CtrlType::Other(type_),
+        }
+    }
+
+    /// Returns true if controls of this type carry their value as a payload
+    /// rather than as a single integer.
+    pub fn is_compound(self) -> bool {
+        match self {
+            CtrlType::String | CtrlType::U8 | CtrlType::U16 | CtrlType::U32 => true,
+            CtrlType::Other(type_) => type_ >= bindings::v4l2_ctrl_type_V4L2_CTRL_COMPOUND_TYPES,
+            _ => false,
+        }
+    }
+}
+
+bitflags! {
+    /// Flags corresponding to the `flags` field of `struct v4l2_queryctrl`.
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct CtrlFlags: u32 {
+        const DISABLED = bindings::V4L2_CTRL_FLAG_DISABLED;
+        const GRABBED = bindings::V4L2_CTRL_FLAG_GRABBED;
+        const READ_ONLY = bindings::V4L2_CTRL_FLAG_READ_ONLY;
+        const UPDATE = bindings::V4L2_CTRL_FLAG_UPDATE;
+        const INACTIVE = bindings::V4L2_CTRL_FLAG_INACTIVE;
+        const SLIDER = bindings::V4L2_CTRL_FLAG_SLIDER;
+        const WRITE_ONLY = bindings::V4L2_CTRL_FLAG_WRITE_ONLY;
+        const VOLATILE = bindings::V4L2_CTRL_FLAG_VOLATILE;
+        const HAS_PAYLOAD = bindings::V4L2_CTRL_FLAG_HAS_PAYLOAD;
+        const EXECUTE_ON_WRITE = bindings::V4L2_CTRL_FLAG_EXECUTE_ON_WRITE;
+        const MODIFY_LAYOUT = bindings::V4L2_CTRL_FLAG_MODIFY_LAYOUT;
+        /// The number of elements of the array can change with each set.
+        /// Not defined in our bindings yet.
+        const DYNAMIC_ARRAY = 0x0800;
+    }
+}
+
+/// Description of a control, as returned by `queryctrl`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueryCtrl {
+    pub id: CtrlId,
+    pub type_: CtrlType,
+    pub name: String,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub step: i32,
+    pub default_value: i32,
+    pub flags: CtrlFlags,
+}
+
+/// Description of a control, as returned by `query_ext_ctrl`. Contrary to
+/// `QueryCtrl`, 64-bit ranges and the layout of compound controls are
+/// supported.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueryExtCtrl {
+    pub id: CtrlId,
+    pub type_: CtrlType,
+    pub name: String,
+    pub minimum: i64,
+    pub maximum: i64,
+    pub step: u64,
+    pub default_value: i64,
+    pub flags: CtrlFlags,
+    /// Size in bytes of a single element of the control.
+    pub elem_size: u32,
+    /// Total number of elements of the control, i.e. the product of `dims`.
+    pub elems: u32,
+    /// Size of each dimension of array controls. Empty for controls that are
+    /// not arrays.
+    pub dims: Vec<u32>,
+}
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::{v4l2_query_ext_ctrl, v4l2_queryctrl};
+    nix::ioctl_readwrite!(vidioc_queryctrl, b'V', 36, v4l2_queryctrl);
+    nix::ioctl_readwrite!(vidioc_query_ext_ctrl, b'V', 103, v4l2_query_ext_ctrl);
+}
+
+/// Safe wrapper around the `VIDIOC_QUERYCTRL` ioctl. Returns the description
+/// of control `id`.
+pub fn queryctrl<F: AsRawFd>(fd: &F, id: CtrlId) -> Result<QueryCtrl> {
+    let mut queryctrl = bindings::v4l2_queryctrl {
+        id: id.into(),
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe { ioctl::vidioc_queryctrl(fd.as_raw_fd(), &mut queryctrl) }?;
+
+    Ok(QueryCtrl {
+        id: CtrlId(queryctrl.id),
+        type_: CtrlType::from_v4l2(queryctrl.type_),
+        name: string_from_cstr(&queryctrl.name).unwrap_or("".into()),
+        minimum: queryctrl.minimum,
+        maximum: queryctrl.maximum,
+        step: queryctrl.step,
+        default_value: queryctrl.default_value,
+        flags: CtrlFlags::from_bits_truncate(queryctrl.flags),
+    })
+}
+
+/// Safe wrapper around the `VIDIOC_QUERY_EXT_CTRL` ioctl. Returns the
+/// description of control `id`.
+///
+/// `id` can be or-ed with `V4L2_CTRL_FLAG_NEXT_CTRL` and
+/// `V4L2_CTRL_FLAG_NEXT_COMPOUND` to obtain the control following `id`
+/// instead, but `CtrlIterator` is more convenient for this.
+pub fn query_ext_ctrl<F: AsRawFd>(fd: &F, id: CtrlId) -> Result<QueryExtCtrl> {
+    let mut query = bindings::v4l2_query_ext_ctrl {
+        id: id.into(),
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe { ioctl::vidioc_query_ext_ctrl(fd.as_raw_fd(), &mut query) }?;
+
+    let name: Vec<u8> = query.name.iter().map(|&c| c as u8).collect();
+    let nr_of_dims = (query.nr_of_dims as usize).min(query.dims.len());
+
+    Ok(QueryExtCtrl {
+        id: CtrlId(query.id),
+        type_: CtrlType::from_v4l2(query.type_),
+        name: string_from_cstr(&name).unwrap_or("".into()),
+        minimum: query.minimum,
+        maximum: query.maximum,
+        step: query.step,
+        default_value: query.default_value,
+        flags: CtrlFlags::from_bits_truncate(query.flags),
+        elem_size: query.elem_size,
+        elems: query.elems,
+        dims: query.dims[..nr_of_dims].to_vec(),
+    })
+}
+
+/// Iterator over the controls of a device, in the order of their
+/// identifiers. Control classes are returned as controls of type
+/// `CtrlType::CtrlClass`, before the controls of the class.
+pub struct CtrlIterator<'a, F: AsRawFd> {
+    fd: &'a F,
+    /// Identifier of the last returned control.
+    id: u32,
+    /// Flags to add to `id` to get the next control.
+    next_flags: u32,
+    /// If set, iteration stops at the first control outside of this class.
+    class: Option<CtrlClass>,
+}
+
+impl<'a, F: AsRawFd> CtrlIterator<'a, F> {
+    /// Create a new iterator listing all the controls of `fd`, including
+    /// compound ones.
+    pub fn new(fd: &'a F) -> Self {
+        CtrlIterator {
+            fd,
+            id: 0,
+            next_flags: bindings::V4L2_CTRL_FLAG_NEXT_CTRL | bindings::V4L2_CTRL_FLAG_NEXT_COMPOUND,
+            class: None,
+        }
+    }
+
+    /// Skip compound controls.
+    pub fn without_compound(mut self) -> Self {
+        self.next_flags &= !bindings::V4L2_CTRL_FLAG_NEXT_COMPOUND;
+        self
+    }
+
+    /// Only list the controls of `class`, starting with the class control
+    /// itself if the driver reports it. Controls are ordered by identifier,
+    /// so this only queries the controls of the class.
+    pub fn in_class(mut self, class: CtrlClass) -> Self {
+        self.id = class as u32;
+        self.class = Some(class);
+        self
+    }
+}
+
+impl<'a, F: AsRawFd> Iterator for CtrlIterator<'a, F> {
+    type Item = QueryExtCtrl;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match query_ext_ctrl(self.fd, CtrlId(self.id | self.next_flags)) {
+            Ok(query) => {
+                self.id = query.id.into();
+                match self.class {
+                    Some(class) if !class.contains(self.id) => None,
+                    _ => Some(query),
+                }
+            }
+            // EINVAL means we have reached the last control.
+            Err(Error::Nix(nix::Error::Sys(Errno::EINVAL))) => None,
+            _ => {
+                eprintln!("Unexpected return value for VIDIOC_QUERY_EXT_CTRL!");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ctrl_type() {
+        assert_eq!(CtrlType::from_v4l2(1), CtrlType::Integer);
+        assert_eq!(CtrlType::from_v4l2(0x101), CtrlType::U16);
+        assert!(!CtrlType::Menu.is_compound());
+        assert!(CtrlType::String.is_compound());
+        // V4L2_CTRL_TYPE_AREA
+        assert!(CtrlType::from_v4l2(0x106).is_compound());
+    }
+}
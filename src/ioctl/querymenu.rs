@@ -0,0 +1,109 @@
+//! Safe wrapper for the `VIDIOC_QUERYMENU` ioctl.
+use super::{queryctrl, string_from_cstr, CtrlType};
+use crate::bindings;
+use crate::CtrlId;
+use crate::{Error, Result};
+use nix::errno::Errno;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+/// An entry of a menu control, as returned by `querymenu`.
+///
+/// Whether the entry is described by a name or a value depends on the type
+/// of the control, which `querymenu` does not know about. Use `name` for
+/// `CtrlType::Menu` controls and `value` for `CtrlType::IntegerMenu` ones.
+#[derive(Clone, Copy)]
+pub struct QueryMenu {
+    pub id: CtrlId,
+    pub index: u32,
+    item: bindings::v4l2_querymenu__bindgen_ty_1,
+}
+
+impl QueryMenu {
+    /// Returns the name of the entry of a `CtrlType::Menu` control.
+    pub fn name(&self) -> String {
+        // Safe because any byte sequence is a valid name.
+        string_from_cstr(unsafe { &self.item.name }).unwrap_or("".into())
+    }
+
+    /// Returns the value of the entry of a `CtrlType::IntegerMenu` control.
+    pub fn value(&self) -> i64 {
+        // Safe because any 64-bit value is a valid integer.
+        unsafe { self.item.value }
+    }
+}
+
+/// Safe wrapper around the `VIDIOC_QUERYMENU` ioctl. Returns the entry at
+/// `index` of menu control `id`.
+///
+/// Menus can have holes, in which case this returns `EINVAL` for the missing
+/// indices.
+pub fn querymenu<F: AsRawFd>(fd: &F, id: CtrlId, index: u32) -> Result<QueryMenu> {
+    let mut querymenu = bindings::v4l2_querymenu {
+        id: id.into(),
+        index,
+        ..unsafe { mem::zeroed() }
+    };
+    unsafe { ioctl::vidioc_querymenu(fd.as_raw_fd(), &mut querymenu) }?;
+
+    Ok(QueryMenu {
+        id: CtrlId(querymenu.id),
+        index: querymenu.index,
+        item: querymenu.__bindgen_anon_1,
+    })
+}
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_querymenu;
+    nix::ioctl_readwrite!(vidioc_querymenu, b'V', 37, v4l2_querymenu);
+}
+
+/// Content of a menu entry, depending on the type of the menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuItem {
+    /// Entry of a `CtrlType::Menu` control.
+    Name(String),
+    /// Entry of a `CtrlType::IntegerMenu` control.
+    Value(i64),
+}
+
+/// A valid entry of a menu control, as returned by `menu_entries`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuEntry {
+    /// Index of the entry, i.e. the value to set the control to in order to
+    /// select this entry.
+    pub index: u32,
+    pub item: MenuItem,
+}
+
+/// Returns all the valid entries of menu control `id`, skipping the holes of
+/// the menu. An empty list is returned if `id` is not a menu control.
+pub fn menu_entries<F: AsRawFd>(fd: &F, id: CtrlId) -> Result<Vec<MenuEntry>> {
+    let ctrl = queryctrl(fd, id)?;
+    let integer_menu = match ctrl.type_ {
+        CtrlType::Menu => false,
+        CtrlType::IntegerMenu => true,
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut entries = Vec::new();
+    for index in ctrl.minimum..=ctrl.maximum {
+        let entry = match querymenu(fd, ctrl.id, index as u32) {
+            Ok(entry) => entry,
+            // EINVAL means this index is a hole in the menu.
+            Err(Error::Nix(nix::Error::Sys(Errno::EINVAL))) => continue,
+            Err(e) => return Err(e),
+        };
+        entries.push(MenuEntry {
+            index: entry.index,
+            item: if integer_menu {
+                MenuItem::Value(entry.value())
+            } else {
+                MenuItem::Name(entry.name())
+            },
+        });
+    }
+
+    Ok(entries)
+}
@@ -25,6 +25,25 @@ bitflags! {
     }
 }
 
+impl BufferCapabilities {
+    /// Returns whether these capabilities advertise support for the `memory`
+    /// type.
+    ///
+    /// Kernels older than 5.0 do not report any capability, in which case
+    /// this method always returns `true`.
+    pub fn supports_memory(&self, memory: MemoryType) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        self.contains(match memory {
+            MemoryType::MMAP => BufferCapabilities::SUPPORTS_MMAP,
+            MemoryType::UserPtr => BufferCapabilities::SUPPORTS_USERPTR,
+            MemoryType::DMABuf => BufferCapabilities::SUPPORTS_DMABUF,
+        })
+    }
+}
+
 impl ReqBufs for () {
     fn from(_reqbufs: bindings::v4l2_requestbuffers) -> Self {
         ()
@@ -0,0 +1,101 @@
+//! Safe wrappers for the `VIDIOC_SUBSCRIBE_EVENT` and
+//! `VIDIOC_UNSUBSCRIBE_EVENT` ioctls.
+use crate::bindings;
+use crate::Result;
+use bitflags::bitflags;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+#[doc(hidden)]
+mod ioctl {
+    use crate::bindings::v4l2_event_subscription;
+    nix::ioctl_write_ptr!(vidioc_subscribe_event, b'V', 90, v4l2_event_subscription);
+    nix::ioctl_write_ptr!(vidioc_unsubscribe_event, b'V', 91, v4l2_event_subscription);
+}
+
+/// Type of the events that can be subscribed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    /// All the event types. Only valid for `unsubscribe_event`, to cancel
+    /// all the subscriptions at once.
+    All,
+    /// A vertical sync has occurred.
+    Vsync,
+    /// The last frame of the stream has been decoded.
+    Eos,
+    /// The value, flags or range of a control have changed. The id of the
+    /// subscription is the id of the control to watch.
+    Ctrl,
+    /// The reception of a frame has started.
+    FrameSync,
+    /// A property of the source, e.g. the resolution of the stream being
+    /// decoded, has changed. The id of the subscription is the pad or input
+    /// to watch.
+    SourceChange,
+    /// Motion has been detected.
+    MotionDet,
+    /// A driver-specific event, which type must be at least
+    /// `V4L2_EVENT_PRIVATE_START`.
+    Private(u32),
+}
+
+impl EventType {
+    fn type_(self) -> u32 {
+        match self {
+            EventType::All => bindings::V4L2_EVENT_ALL,
+            EventType::Vsync => bindings::V4L2_EVENT_VSYNC,
+            EventType::Eos => bindings::V4L2_EVENT_EOS,
+            EventType::Ctrl => bindings::V4L2_EVENT_CTRL,
+            EventType::FrameSync => bindings::V4L2_EVENT_FRAME_SYNC,
+            EventType::SourceChange => bindings::V4L2_EVENT_SOURCE_CHANGE,
+            EventType::MotionDet => bindings::V4L2_EVENT_MOTION_DET,
+            EventType::Private(type_) => type_,
+        }
+    }
+}
+
+bitflags! {
+    /// Flags corresponding to the `flags` field of `struct
+    /// v4l2_event_subscription`.
+    #[derive(Default)]
+    pub struct SubscribeEventFlags: u32 {
+        const SEND_INITIAL = bindings::V4L2_EVENT_SUB_FL_SEND_INITIAL;
+        const ALLOW_FEEDBACK = bindings::V4L2_EVENT_SUB_FL_ALLOW_FEEDBACK;
+    }
+}
+
+/// Safe wrapper around the `VIDIOC_SUBSCRIBE_EVENT` ioctl. Subscribe to the
+/// events of type `event` emitted for `id`, which are then obtained with
+/// `dqevent`. Pending events are signaled by `POLLPRI` when polling `fd`.
+pub fn subscribe_event<F: AsRawFd>(
+    fd: &F,
+    event: EventType,
+    id: u32,
+    flags: SubscribeEventFlags,
+) -> Result<()> {
+    let subscription = bindings::v4l2_event_subscription {
+        type_: event.type_(),
+        id,
+        flags: flags.bits(),
+        ..unsafe { mem::zeroed() }
+    };
+
+    unsafe { ioctl::vidioc_subscribe_event(fd.as_raw_fd(), &subscription) }?;
+
+    Ok(())
+}
+
+/// Safe wrapper around the `VIDIOC_UNSUBSCRIBE_EVENT` ioctl. Cancel the
+/// subscription to the events of type `event` emitted for `id`, or all the
+/// subscriptions if `event` is `EventType::All`.
+pub fn unsubscribe_event<F: AsRawFd>(fd: &F, event: EventType, id: u32) -> Result<()> {
+    let subscription = bindings::v4l2_event_subscription {
+        type_: event.type_(),
+        id,
+        ..unsafe { mem::zeroed() }
+    };
+
+    unsafe { ioctl::vidioc_unsubscribe_event(fd.as_raw_fd(), &subscription) }?;
+
+    Ok(())
+}
@@ -18,9 +18,15 @@
 //! (camera, decoder/encoder, etc).
 //!
 mod bindings;
+mod colorimetry;
+pub mod controls;
 pub mod device;
+pub mod formats;
 pub mod ioctl;
 pub mod memory;
+pub mod request;
+pub mod splitter;
+pub mod testpattern;
 
 use std::ffi;
 use std::fmt;
@@ -53,6 +59,40 @@ pub enum Error {
     /// not exist, or we try to submit a buffer that has been deleted while we
     /// were preparing it.
     InvalidBuffer,
+    /// The queue cannot be reconfigured in its current state, e.g. because it
+    /// has buffers allocated or is streaming.
+    Busy {
+        streaming: bool,
+    },
+    /// The queue does not support the requested memory type.
+    UnsupportedMemoryType,
+    /// The driver rejected the format for this queue.
+    InvalidFormat,
+    /// The queue has been paused, so buffers cannot be dequeued until it is
+    /// resumed.
+    Paused,
+    /// A thread panicked while updating the state of the queue, which cannot
+    /// be trusted anymore. The queue and all its buffers must be dropped, and
+    /// the queue obtained again from the device to resume streaming.
+    Poisoned,
+    /// A control value does not match the type or the size of the control it
+    /// is meant for.
+    InvalidControlValue,
+    /// The device does not support the requested frame interval for the
+    /// current format of the queue.
+    InvalidFrameInterval,
+    /// A control cannot be set manually while `auto`, the control of the
+    /// automatic mode of its cluster (e.g. `AUTOGAIN` for `GAIN`), is
+    /// enabled.
+    AutoModeEnabled {
+        auto: CtrlId,
+    },
+    /// No buffer became available within the requested timeout.
+    Timeout,
+    /// No buffer is ready to be dequeued yet.
+    NotReady,
+    /// The wait has been interrupted by a `Waker`.
+    Woken,
     Nix(nix::Error),
     FfiNul(ffi::NulError),
     FfiInvalidString(ffi::FromBytesWithNulError),
@@ -68,6 +108,20 @@ impl Display for Error {
             Error::TooManyPlanes => write!(f, "Too many planes specified"),
             Error::DataOffsetNotSupported => write!(f, "Data offset not supported"),
             Error::InvalidBuffer => write!(f, "Invalid buffer"),
+            Error::Busy { streaming: true } => write!(f, "Queue is busy (streaming)"),
+            Error::Busy { streaming: false } => write!(f, "Queue is busy"),
+            Error::UnsupportedMemoryType => write!(f, "Memory type not supported"),
+            Error::InvalidFormat => write!(f, "Invalid format"),
+            Error::Paused => write!(f, "Queue is paused"),
+            Error::Poisoned => write!(f, "Queue state is poisoned"),
+            Error::InvalidControlValue => write!(f, "Invalid control value"),
+            Error::InvalidFrameInterval => write!(f, "Invalid frame interval"),
+            Error::AutoModeEnabled { auto } => {
+                write!(f, "Control is in automatic mode (set by {:#x})", auto.0)
+            }
+            Error::Timeout => write!(f, "Timed out"),
+            Error::NotReady => write!(f, "No buffer ready"),
+            Error::Woken => write!(f, "Woken up"),
             Error::Nix(e) => Debug::fmt(e, f),
             Error::FfiNul(e) => Debug::fmt(e, f),
             Error::FfiInvalidString(e) => Debug::fmt(e, f),
@@ -99,15 +153,272 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Types of queues currently supported by this library.
 #[allow(unused)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum QueueType {
     VideoCapture = bindings::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE as isize,
     VideoOutput = bindings::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT as isize,
     VideoCaptureMplane = bindings::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE as isize,
     VideoOutputMplane = bindings::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT_MPLANE as isize,
+    VideoOverlay = bindings::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OVERLAY as isize,
+    VideoOutputOverlay = bindings::v4l2_buf_type_V4L2_BUF_TYPE_VIDEO_OUTPUT_OVERLAY as isize,
+    SdrCapture = bindings::v4l2_buf_type_V4L2_BUF_TYPE_SDR_CAPTURE as isize,
+    SdrOutput = bindings::v4l2_buf_type_V4L2_BUF_TYPE_SDR_OUTPUT as isize,
+    MetaCapture = bindings::v4l2_buf_type_V4L2_BUF_TYPE_META_CAPTURE as isize,
+    MetaOutput = bindings::v4l2_buf_type_V4L2_BUF_TYPE_META_OUTPUT as isize,
+}
+
+impl QueueType {
+    /// Returns the queue type matching the `type` member of a V4L2 structure,
+    /// if it is supported.
+    pub fn from_v4l2(type_: u32) -> Option<Self> {
+        use QueueType::*;
+        [
+            VideoCapture,
+            VideoOutput,
+            VideoCaptureMplane,
+            VideoOutputMplane,
+            VideoOverlay,
+            VideoOutputOverlay,
+            SdrCapture,
+            SdrOutput,
+            MetaCapture,
+            MetaOutput,
+        ]
+        .iter()
+        .copied()
+        .find(|queue| *queue as u32 == type_)
+    }
+
+    /// Returns true if this queue carries video frames, i.e. its formats are
+    /// pixel formats described by `Format`.
+    pub fn is_video(self) -> bool {
+        use QueueType::*;
+        matches!(
+            self,
+            VideoCapture | VideoOutput | VideoCaptureMplane | VideoOutputMplane
+        )
+    }
+
+    /// Returns true if this queue uses the multi-planar API.
+    pub fn is_multi_planar(self) -> bool {
+        matches!(
+            self,
+            QueueType::VideoCaptureMplane | QueueType::VideoOutputMplane
+        )
+    }
+
+    /// Returns true if buffers of this queue are filled by the application
+    /// and consumed by the device.
+    pub fn is_output(self) -> bool {
+        use QueueType::*;
+        matches!(
+            self,
+            VideoOutput | VideoOutputMplane | VideoOutputOverlay | SdrOutput | MetaOutput
+        )
+    }
+
+    /// Returns true if the formats of this queue are SDR data formats.
+    pub fn is_sdr(self) -> bool {
+        matches!(self, QueueType::SdrCapture | QueueType::SdrOutput)
+    }
+
+    /// Returns true if the formats of this queue are metadata formats.
+    pub fn is_meta(self) -> bool {
+        matches!(self, QueueType::MetaCapture | QueueType::MetaOutput)
+    }
+}
+
+/// Classes of V4L2 controls. Each control belongs to exactly one class, which
+/// can be used to group controls into meaningful sections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CtrlClass {
+    User = bindings::V4L2_CTRL_CLASS_USER as isize,
+    /// Codec controls. Called `MPEG` in the V4L2 headers for historical
+    /// reasons.
+    Codec = bindings::V4L2_CTRL_CLASS_MPEG as isize,
+    Camera = bindings::V4L2_CTRL_CLASS_CAMERA as isize,
+    FmTx = bindings::V4L2_CTRL_CLASS_FM_TX as isize,
+    Flash = bindings::V4L2_CTRL_CLASS_FLASH as isize,
+    Jpeg = bindings::V4L2_CTRL_CLASS_JPEG as isize,
+    ImageSource = bindings::V4L2_CTRL_CLASS_IMAGE_SOURCE as isize,
+    ImageProc = bindings::V4L2_CTRL_CLASS_IMAGE_PROC as isize,
+    Dv = bindings::V4L2_CTRL_CLASS_DV as isize,
+    FmRx = bindings::V4L2_CTRL_CLASS_FM_RX as isize,
+    RfTuner = bindings::V4L2_CTRL_CLASS_RF_TUNER as isize,
+    Detect = bindings::V4L2_CTRL_CLASS_DETECT as isize,
+    /// Controls of stateless codecs. Not defined in our bindings yet.
+    CodecStateless = 0x00a4_0000,
+    /// Not defined in our bindings yet.
+    Colorimetry = 0x00a5_0000,
+}
+
+impl CtrlClass {
+    /// All the control classes, in the order of their identifiers.
+    pub const ALL: [CtrlClass; 14] = [
+        CtrlClass::User,
+        CtrlClass::Codec,
+        CtrlClass::Camera,
+        CtrlClass::FmTx,
+        CtrlClass::Flash,
+        CtrlClass::Jpeg,
+        CtrlClass::ImageSource,
+        CtrlClass::ImageProc,
+        CtrlClass::Dv,
+        CtrlClass::FmRx,
+        CtrlClass::RfTuner,
+        CtrlClass::Detect,
+        CtrlClass::CodecStateless,
+        CtrlClass::Colorimetry,
+    ];
+
+    /// Returns the class control `ctrl_id` belongs to, or `None` if it is not
+    /// part of a known class.
+    pub fn from_ctrl_id(ctrl_id: u32) -> Option<Self> {
+        // Same as the V4L2_CTRL_ID2CLASS macro.
+        let class = ctrl_id & 0x0fff_0000;
+        CtrlClass::ALL.iter().copied().find(|c| *c as u32 == class)
+    }
+
+    /// Returns the identifier of the class control of this class, i.e. the
+    /// control that describes the class itself. Enumerating controls from
+    /// this identifier returns the controls of this class first.
+    pub fn ctrl_id(self) -> u32 {
+        self as u32 | 1
+    }
+
+    /// Returns true if control `ctrl_id` belongs to this class.
+    pub fn contains(self, ctrl_id: u32) -> bool {
+        CtrlClass::from_ctrl_id(ctrl_id) == Some(self)
+    }
+}
+
+impl Display for CtrlClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CtrlClass::User => "User Controls",
+            CtrlClass::Codec => "Codec Controls",
+            CtrlClass::Camera => "Camera Controls",
+            CtrlClass::FmTx => "FM Transmitter Controls",
+            CtrlClass::Flash => "Flash Controls",
+            CtrlClass::Jpeg => "JPEG Compression Controls",
+            CtrlClass::ImageSource => "Image Source Controls",
+            CtrlClass::ImageProc => "Image Processing Controls",
+            CtrlClass::Dv => "Digital Video Controls",
+            CtrlClass::FmRx => "FM Receiver Controls",
+            CtrlClass::RfTuner => "RF Tuner Controls",
+            CtrlClass::Detect => "Detection Controls",
+            CtrlClass::CodecStateless => "Stateless Codec Controls",
+            CtrlClass::Colorimetry => "Colorimetry Controls",
+        })
+    }
+}
+
+/// Identifier of a V4L2 control. Constants are provided for common controls,
+/// but any identifier can be used, e.g. for driver-specific controls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CtrlId(pub u32);
+
+impl CtrlId {
+    // User controls.
+    pub const BRIGHTNESS: CtrlId = CtrlId(bindings::V4L2_CID_BRIGHTNESS);
+    pub const CONTRAST: CtrlId = CtrlId(bindings::V4L2_CID_CONTRAST);
+    pub const SATURATION: CtrlId = CtrlId(bindings::V4L2_CID_SATURATION);
+    pub const HUE: CtrlId = CtrlId(bindings::V4L2_CID_HUE);
+    pub const AUTO_WHITE_BALANCE: CtrlId = CtrlId(bindings::V4L2_CID_AUTO_WHITE_BALANCE);
+    pub const RED_BALANCE: CtrlId = CtrlId(bindings::V4L2_CID_RED_BALANCE);
+    pub const BLUE_BALANCE: CtrlId = CtrlId(bindings::V4L2_CID_BLUE_BALANCE);
+    pub const EXPOSURE: CtrlId = CtrlId(bindings::V4L2_CID_EXPOSURE);
+    pub const AUTOGAIN: CtrlId = CtrlId(bindings::V4L2_CID_AUTOGAIN);
+    pub const GAIN: CtrlId = CtrlId(bindings::V4L2_CID_GAIN);
+    pub const HUE_AUTO: CtrlId = CtrlId(bindings::V4L2_CID_HUE_AUTO);
+    pub const WHITE_BALANCE_TEMPERATURE: CtrlId =
+        CtrlId(bindings::V4L2_CID_WHITE_BALANCE_TEMPERATURE);
+    pub const AUTOBRIGHTNESS: CtrlId = CtrlId(bindings::V4L2_CID_AUTOBRIGHTNESS);
+    pub const HFLIP: CtrlId = CtrlId(bindings::V4L2_CID_HFLIP);
+    pub const VFLIP: CtrlId = CtrlId(bindings::V4L2_CID_VFLIP);
+    pub const POWER_LINE_FREQUENCY: CtrlId = CtrlId(bindings::V4L2_CID_POWER_LINE_FREQUENCY);
+    pub const SHARPNESS: CtrlId = CtrlId(bindings::V4L2_CID_SHARPNESS);
+    pub const ROTATE: CtrlId = CtrlId(bindings::V4L2_CID_ROTATE);
+    pub const MIN_BUFFERS_FOR_CAPTURE: CtrlId = CtrlId(bindings::V4L2_CID_MIN_BUFFERS_FOR_CAPTURE);
+    pub const MIN_BUFFERS_FOR_OUTPUT: CtrlId = CtrlId(bindings::V4L2_CID_MIN_BUFFERS_FOR_OUTPUT);
+
+    // Codec controls. Called `MPEG` in the V4L2 headers for historical
+    // reasons.
+    pub const VIDEO_BITRATE_MODE: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_BITRATE_MODE);
+    pub const VIDEO_BITRATE: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_BITRATE);
+    pub const VIDEO_BITRATE_PEAK: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_BITRATE_PEAK);
+    pub const VIDEO_FRAME_RC_ENABLE: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_FRAME_RC_ENABLE);
+    pub const VIDEO_GOP_SIZE: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_GOP_SIZE);
+    pub const VIDEO_B_FRAMES: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_B_FRAMES);
+    pub const VIDEO_FORCE_KEY_FRAME: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_FORCE_KEY_FRAME);
+    pub const VIDEO_H264_PROFILE: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_H264_PROFILE);
+    pub const VIDEO_H264_LEVEL: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_H264_LEVEL);
+    pub const VIDEO_H264_I_PERIOD: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_H264_I_PERIOD);
+    pub const VIDEO_H264_MIN_QP: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_H264_MIN_QP);
+    pub const VIDEO_H264_MAX_QP: CtrlId = CtrlId(bindings::V4L2_CID_MPEG_VIDEO_H264_MAX_QP);
+
+    // Camera controls.
+    pub const EXPOSURE_AUTO: CtrlId = CtrlId(bindings::V4L2_CID_EXPOSURE_AUTO);
+    pub const EXPOSURE_ABSOLUTE: CtrlId = CtrlId(bindings::V4L2_CID_EXPOSURE_ABSOLUTE);
+    pub const FOCUS_ABSOLUTE: CtrlId = CtrlId(bindings::V4L2_CID_FOCUS_ABSOLUTE);
+    pub const FOCUS_RELATIVE: CtrlId = CtrlId(bindings::V4L2_CID_FOCUS_RELATIVE);
+    pub const FOCUS_AUTO: CtrlId = CtrlId(bindings::V4L2_CID_FOCUS_AUTO);
+    pub const ZOOM_ABSOLUTE: CtrlId = CtrlId(bindings::V4L2_CID_ZOOM_ABSOLUTE);
+
+    /// Returns the class this control belongs to, or `None` if it is not part
+    /// of a known class.
+    pub fn class(self) -> Option<CtrlClass> {
+        CtrlClass::from_ctrl_id(self.0)
+    }
+}
+
+impl From<u32> for CtrlId {
+    fn from(id: u32) -> Self {
+        CtrlId(id)
+    }
+}
+
+impl From<CtrlId> for u32 {
+    fn from(id: CtrlId) -> Self {
+        id.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CtrlClass, CtrlId};
+
+    #[test]
+    fn ctrl_class_from_ctrl_id() {
+        // V4L2_CID_BRIGHTNESS
+        assert_eq!(CtrlClass::from_ctrl_id(0x0098_0900), Some(CtrlClass::User));
+        // V4L2_CID_MPEG_VIDEO_BITRATE
+        assert_eq!(CtrlClass::from_ctrl_id(0x0099_09cf), Some(CtrlClass::Codec));
+        // V4L2_CID_EXPOSURE_AUTO
+        assert!(CtrlClass::Camera.contains(0x009a_0901));
+        // Flags in the upper bits are ignored.
+        assert_eq!(
+            CtrlClass::from_ctrl_id(0x8000_0000 | CtrlClass::Flash.ctrl_id()),
+            Some(CtrlClass::Flash)
+        );
+        assert_eq!(CtrlClass::from_ctrl_id(0x00ff_0000), None);
+    }
+
+    #[test]
+    fn ctrl_id() {
+        assert_eq!(CtrlId::BRIGHTNESS, CtrlId(0x0098_0900));
+        assert_eq!(CtrlId::BRIGHTNESS.class(), Some(CtrlClass::User));
+        assert_eq!(CtrlId::VIDEO_BITRATE.class(), Some(CtrlClass::Codec));
+        assert_eq!(CtrlId::EXPOSURE_AUTO.class(), Some(CtrlClass::Camera));
+        assert_eq!(u32::from(CtrlId::from(0x0098_0901)), 0x0098_0901);
+    }
 }
 
 mod pixel_format {
+    use crate::{formats, Error};
     use std::fmt;
+    use std::str::FromStr;
 
     /// A Fourcc pixel format, used to pass formats to V4L2. It can be converted
     /// back and forth from a 32-bit integer, or a 4-bytes string.
@@ -129,13 +440,78 @@ mod pixel_format {
         }
     }
 
+    impl PixelFormat {
+        pub const YUYV: PixelFormat = PixelFormat::from_fourcc(b"YUYV");
+        pub const UYVY: PixelFormat = PixelFormat::from_fourcc(b"UYVY");
+        pub const NV12: PixelFormat = PixelFormat::from_fourcc(b"NV12");
+        pub const NV21: PixelFormat = PixelFormat::from_fourcc(b"NV21");
+        pub const NV16: PixelFormat = PixelFormat::from_fourcc(b"NV16");
+        pub const NV61: PixelFormat = PixelFormat::from_fourcc(b"NV61");
+        pub const NV12M: PixelFormat = PixelFormat::from_fourcc(b"NM12");
+        pub const NV21M: PixelFormat = PixelFormat::from_fourcc(b"NM21");
+        pub const YUV420: PixelFormat = PixelFormat::from_fourcc(b"YU12");
+        pub const YVU420: PixelFormat = PixelFormat::from_fourcc(b"YV12");
+        pub const YUV420M: PixelFormat = PixelFormat::from_fourcc(b"YM12");
+        pub const YUV422P: PixelFormat = PixelFormat::from_fourcc(b"422P");
+        pub const GREY: PixelFormat = PixelFormat::from_fourcc(b"GREY");
+        pub const RGB565: PixelFormat = PixelFormat::from_fourcc(b"RGBP");
+        pub const RGB24: PixelFormat = PixelFormat::from_fourcc(b"RGB3");
+        pub const BGR24: PixelFormat = PixelFormat::from_fourcc(b"BGR3");
+        pub const ABGR32: PixelFormat = PixelFormat::from_fourcc(b"AR24");
+        pub const XBGR32: PixelFormat = PixelFormat::from_fourcc(b"XR24");
+        pub const MJPEG: PixelFormat = PixelFormat::from_fourcc(b"MJPG");
+        pub const JPEG: PixelFormat = PixelFormat::from_fourcc(b"JPEG");
+        pub const H264: PixelFormat = PixelFormat::from_fourcc(b"H264");
+        pub const H264_SLICE: PixelFormat = PixelFormat::from_fourcc(b"S264");
+        pub const HEVC: PixelFormat = PixelFormat::from_fourcc(b"HEVC");
+        pub const VP8: PixelFormat = PixelFormat::from_fourcc(b"VP80");
+        pub const VP9: PixelFormat = PixelFormat::from_fourcc(b"VP90");
+        pub const FWHT: PixelFormat = PixelFormat::from_fourcc(b"FWHT");
+        pub const FWHT_STATELESS: PixelFormat = PixelFormat::from_fourcc(b"SFWH");
+
+        /// Same as the `From<&[u8; 4]>` implementation, but usable in
+        /// constants.
+        pub const fn from_fourcc(n: &[u8; 4]) -> Self {
+            PixelFormat(
+                n[0] as u32 | (n[1] as u32) << 8 | (n[2] as u32) << 16 | (n[3] as u32) << 24,
+            )
+        }
+
+        /// Returns true if this format uses one memory plane per color plane
+        /// (e.g. `NV12M`), and thus can only be used with the multi-planar
+        /// API.
+        pub fn is_multiplanar(self) -> bool {
+            formats::is_non_contiguous(self)
+        }
+
+        /// Returns the layout of this format, if it is a known one.
+        pub fn info(self) -> Option<&'static formats::PixelFormatInfo> {
+            formats::pixel_format_info(self)
+        }
+    }
+
     /// Simple way to convert a string litteral (e.g. b"NV12") into a pixel
     /// format that can be passed to V4L2.
     impl From<&[u8; 4]> for PixelFormat {
         fn from(n: &[u8; 4]) -> Self {
-            PixelFormat(
-                n[0] as u32 | (n[1] as u32) << 8 | (n[2] as u32) << 16 | (n[3] as u32) << 24,
-            )
+            PixelFormat::from_fourcc(n)
+        }
+    }
+
+    /// Parse a 4-character string (e.g. "NV12") into a pixel format. Shorter
+    /// codes are padded with spaces, as V4L2 does for e.g. "Y10 ".
+    impl FromStr for PixelFormat {
+        type Err = Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let bytes = s.as_bytes();
+            if bytes.is_empty() || bytes.len() > 4 || !s.is_ascii() {
+                return Err(Error::InvalidFormat);
+            }
+
+            let mut fourcc = [b' '; 4];
+            fourcc[..bytes.len()].copy_from_slice(bytes);
+            Ok(PixelFormat::from_fourcc(&fourcc))
         }
     }
 
@@ -164,6 +540,50 @@ mod pixel_format {
         }
     }
 
+    /// Pixel formats are serialized as their 4-character code when it is
+    /// printable, and as their numerical value otherwise (e.g. for formats
+    /// with the big-endian flag set).
+    #[cfg(feature = "serde")]
+    impl serde::Serialize for PixelFormat {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let fourcc: [u8; 4] = (*self).into();
+            if fourcc.iter().all(|c| c.is_ascii_graphic() || *c == b' ') {
+                serializer.serialize_str(&self.to_string())
+            } else {
+                serializer.serialize_u32(self.0)
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    impl<'de> serde::Deserialize<'de> for PixelFormat {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct Visitor;
+
+            impl<'de> serde::de::Visitor<'de> for Visitor {
+                type Value = PixelFormat;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a 4-character code or a 32-bit integer")
+                }
+
+                fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<PixelFormat, E> {
+                    v.parse()
+                        .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(v), &self))
+                }
+
+                fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<PixelFormat, E> {
+                    use std::convert::TryFrom;
+                    u32::try_from(v)
+                        .map(PixelFormat::from)
+                        .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(v), &self))
+                }
+            }
+
+            deserializer.deserialize_any(Visitor)
+        }
+    }
+
     #[cfg(test)]
     mod tests {
         use super::PixelFormat;
@@ -184,6 +604,47 @@ mod pixel_format {
             assert_eq!(to_string, NV12_STRING);
         }
 
+        #[test]
+        fn pixelformat_from_str() {
+            let format: PixelFormat = NV12_STRING.parse().unwrap();
+            assert_eq!(format, PixelFormat::NV12);
+            assert_eq!("Y10".parse::<PixelFormat>().unwrap(), b"Y10 ".into());
+            assert!("NV12M".parse::<PixelFormat>().is_err());
+            assert!("".parse::<PixelFormat>().is_err());
+            assert!(PixelFormat::NV12M.is_multiplanar());
+            assert!(!PixelFormat::NV12.is_multiplanar());
+        }
+
+        #[cfg(feature = "serde")]
+        #[test]
+        fn pixelformat_serde() {
+            use crate::{Format, PlanePixFormat};
+
+            let json = serde_json::to_string(&PixelFormat::NV12).unwrap();
+            assert_eq!(json, "\"NV12\"");
+            let big_endian = PixelFormat::from(0x8000_0000 | NV12_U32);
+            let json = serde_json::to_string(&big_endian).unwrap();
+            assert_eq!(json, (0x8000_0000 | NV12_U32).to_string());
+            assert_eq!(
+                serde_json::from_str::<PixelFormat>(&json).unwrap(),
+                big_endian
+            );
+            assert!(serde_json::from_str::<PixelFormat>("\"NV12M\"").is_err());
+
+            let format = Format {
+                width: 640,
+                height: 480,
+                pixelformat: PixelFormat::NV12,
+                plane_fmt: vec![PlanePixFormat {
+                    sizeimage: 640 * 720,
+                    bytesperline: 640,
+                }],
+                ..Default::default()
+            };
+            let json = serde_json::to_string(&format).unwrap();
+            assert_eq!(serde_json::from_str::<Format>(&json).unwrap(), format);
+        }
+
         #[test]
         fn pixelformat_from_u8s() {
             let format = PixelFormat::from(NV12_U8S);
@@ -199,9 +660,12 @@ mod pixel_format {
 pub use pixel_format::*;
 
 mod format {
-    use super::PixelFormat;
+    use super::{formats, Error, PixelFormat, QueueType, Result};
+    use super::{Colorspace, Quantization, XferFunc, YCbCrEncoding};
+    use crate::ioctl::Field;
 
     #[derive(Debug, PartialEq, Clone, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct PlanePixFormat {
         pub sizeimage: u32,
         pub bytesperline: u32,
@@ -212,11 +676,19 @@ mod format {
     /// one plane shall be used - attempts to have more will be rejected by the
     /// ioctl wrappers.
     #[derive(Debug, PartialEq, Clone, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Format {
         pub width: u32,
         pub height: u32,
         pub pixelformat: PixelFormat,
         pub plane_fmt: Vec<PlanePixFormat>,
+        /// Field order of the frames, which tells whether they are
+        /// interlaced.
+        pub field: Field,
+        pub colorspace: Colorspace,
+        pub xfer_func: XferFunc,
+        pub ycbcr_enc: YCbCrEncoding,
+        pub quantization: Quantization,
     }
 
     /// Quickly build a usable `Format` from a pixel format and resolution.
@@ -230,5 +702,154 @@ mod format {
             }
         }
     }
+
+    impl Format {
+        /// Returns a builder for a new format, which checks the planes of
+        /// the format against its pixel format.
+        pub fn builder() -> PixFormatBuilder {
+            PixFormatBuilder::default()
+        }
+    }
+
+    /// Builder for `Format`, obtained with `Format::builder()`.
+    ///
+    /// Planes do not need to be specified, in which case the driver computes
+    /// their layout when the format is set. The same builder can produce
+    /// both the single-planar and multi-planar representations of a format.
+    #[derive(Debug, Clone, Default)]
+    pub struct PixFormatBuilder {
+        format: Format,
+    }
+
+    impl PixFormatBuilder {
+        pub fn size(mut self, width: u32, height: u32) -> Self {
+            self.format.width = width;
+            self.format.height = height;
+            self
+        }
+
+        pub fn pixelformat(mut self, pixelformat: impl Into<PixelFormat>) -> Self {
+            self.format.pixelformat = pixelformat.into();
+            self
+        }
+
+        pub fn field(mut self, field: Field) -> Self {
+            self.format.field = field;
+            self
+        }
+
+        pub fn colorspace(mut self, colorspace: Colorspace) -> Self {
+            self.format.colorspace = colorspace;
+            self
+        }
+
+        pub fn xfer_func(mut self, xfer_func: XferFunc) -> Self {
+            self.format.xfer_func = xfer_func;
+            self
+        }
+
+        pub fn ycbcr_enc(mut self, ycbcr_enc: YCbCrEncoding) -> Self {
+            self.format.ycbcr_enc = ycbcr_enc;
+            self
+        }
+
+        pub fn quantization(mut self, quantization: Quantization) -> Self {
+            self.format.quantization = quantization;
+            self
+        }
+
+        /// Add a memory plane, with the given bytes per line and size.
+        pub fn plane(mut self, bytesperline: u32, sizeimage: u32) -> Self {
+            self.format.plane_fmt.push(PlanePixFormat {
+                sizeimage,
+                bytesperline,
+            });
+            self
+        }
+
+        /// Build the format for the single-planar API, which can only
+        /// describe formats using a single memory plane.
+        ///
+        /// `Error::InvalidFormat` is returned for non-contiguous pixel formats
+        /// (e.g. `NM12`), and `Error::TooManyPlanes` if more than one plane
+        /// has been added.
+        pub fn build_single_planar(self) -> Result<Format> {
+            if formats::is_non_contiguous(self.format.pixelformat) {
+                return Err(Error::InvalidFormat);
+            }
+            if self.format.plane_fmt.len() > 1 {
+                return Err(Error::TooManyPlanes);
+            }
+
+            Ok(self.format)
+        }
+
+        /// Build the format for the multi-planar API, with one memory plane
+        /// per color plane for non-contiguous pixel formats, and a single
+        /// one otherwise.
+        ///
+        /// If planes have been added, `Error::NotEnoughPlanes` or
+        /// `Error::TooManyPlanes` is returned if their number does not match
+        /// the pixel format. Otherwise, empty planes are added.
+        pub fn build_multi_planar(mut self) -> Result<Format> {
+            let pixelformat = self.format.pixelformat;
+            let num_planes = if formats::is_non_contiguous(pixelformat) {
+                formats::num_color_planes(pixelformat).unwrap_or(1)
+            } else {
+                1
+            };
+
+            let planes = &mut self.format.plane_fmt;
+            if planes.is_empty() {
+                planes.resize(num_planes, Default::default());
+            } else if planes.len() < num_planes {
+                return Err(Error::NotEnoughPlanes);
+            } else if planes.len() > num_planes {
+                return Err(Error::TooManyPlanes);
+            }
+
+            Ok(self.format)
+        }
+
+        /// Build the format for the API used by `queue`.
+        pub fn build_for(self, queue: QueueType) -> Result<Format> {
+            if queue.is_multi_planar() {
+                self.build_multi_planar()
+            } else {
+                self.build_single_planar()
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn format_builder() {
+            let builder = Format::builder().size(640, 480).pixelformat(b"NM12");
+            assert!(matches!(
+                builder.clone().build_single_planar(),
+                Err(Error::InvalidFormat)
+            ));
+            let format = builder.clone().build_multi_planar().unwrap();
+            assert_eq!((format.width, format.height), (640, 480));
+            assert_eq!(format.plane_fmt.len(), 2);
+            assert!(matches!(
+                builder.plane(640, 640 * 480).build_multi_planar(),
+                Err(Error::NotEnoughPlanes)
+            ));
+
+            let builder = Format::builder()
+                .size(640, 480)
+                .pixelformat(b"NV12")
+                .plane(640, 640 * 480 * 3 / 2);
+            let single = builder.clone().build_single_planar().unwrap();
+            let multi = builder.build_for(QueueType::VideoCaptureMplane).unwrap();
+            assert_eq!(single, multi);
+            assert_eq!(single.plane_fmt[0].bytesperline, 640);
+        }
+    }
 }
+pub use colorimetry::*;
 pub use format::*;
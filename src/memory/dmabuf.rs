@@ -13,9 +13,15 @@ pub struct DMABufHandle(RawFd);
 impl DMABufHandle {
     /// Create a new DMABUF handle.
     ///
-    /// This method is unsafe. The caller must ensure that `fd` will not be closed
-    /// at least until the buffer using this handle is queued.
-    unsafe fn new(fd: RawFd) -> Self {
+    /// Users of the `device` module don't need to call this: `DMABuf` builds
+    /// handles from the `File` passed when queueing, which it keeps until the
+    /// buffer is dequeued.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `fd` will not be closed at least until the
+    /// buffer using this handle is queued.
+    pub unsafe fn new(fd: RawFd) -> Self {
         DMABufHandle(fd)
     }
 }
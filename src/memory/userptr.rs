@@ -16,6 +16,12 @@ pub struct UserPtrHandle {
     length: u32,
 }
 
+// The pointer is never dereferenced on our side, only passed to the kernel,
+// and the memory it points to is owned by the `QBufType` of `UserPtr`, which
+// is required to be `Send`.
+unsafe impl Send for UserPtrHandle {}
+unsafe impl Sync for UserPtrHandle {}
+
 impl UserPtrHandle {
     /// Create a new handle from anything that references bytes.
     ///
@@ -0,0 +1,123 @@
+//! Support for the media request API.
+//!
+//! A request bundles buffers and control values that the driver applies
+//! together when the request is queued. Stateless codecs require it, as
+//! every frame must be submitted along with the codec parameters needed to
+//! decode it.
+//!
+//! Requests are allocated from the media device the video device belongs to,
+//! e.g. `/dev/media0`, and are used through their own file descriptor.
+use crate::ioctl::{
+    g_ext_ctrl_values, g_ext_ctrls, s_ext_ctrls, CtrlReading, CtrlWhich, ExtControl, QueryExtCtrl,
+};
+use crate::Result;
+use nix::poll::{poll, PollFd, PollFlags};
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::time::Duration;
+
+#[doc(hidden)]
+mod ioctl {
+    nix::ioctl_read!(media_ioc_request_alloc, b'|', 0x05, nix::libc::c_int);
+    nix::ioctl_none!(media_request_ioc_queue, b'|', 0x80);
+    nix::ioctl_none!(media_request_ioc_reinit, b'|', 0x81);
+}
+
+/// An owned media request, which is freed when dropped.
+#[derive(Debug)]
+pub struct Request {
+    file: File,
+}
+
+impl Request {
+    /// Allocate a new request from `media`, an opened media device. Wraps the
+    /// `MEDIA_IOC_REQUEST_ALLOC` ioctl.
+    pub fn alloc<F: AsRawFd>(media: &F) -> Result<Self> {
+        let mut request_fd: nix::libc::c_int = 0;
+        unsafe { ioctl::media_ioc_request_alloc(media.as_raw_fd(), &mut request_fd) }?;
+
+        Ok(Request {
+            // Safe because the kernel has just created this fd for us.
+            file: unsafe { File::from_raw_fd(request_fd) },
+        })
+    }
+
+    /// Queue the request, i.e. submit the buffers and controls associated
+    /// with it to the driver. Wraps the `MEDIA_REQUEST_IOC_QUEUE` ioctl.
+    ///
+    /// Once queued, the request cannot be modified until it has completed,
+    /// which is signaled by an exceptional condition (`POLLPRI`) on its file
+    /// descriptor.
+    pub fn queue(&self) -> Result<()> {
+        unsafe { ioctl::media_request_ioc_queue(self.file.as_raw_fd()) }?;
+
+        Ok(())
+    }
+
+    /// Wait for the request to complete, for at most `timeout` if specified.
+    /// Returns `false` if the timeout expired before the request completed.
+    ///
+    /// `wait_async()` does the same without blocking the calling thread.
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<bool> {
+        let timeout = match timeout {
+            Some(timeout) => timeout.as_millis().min(i32::MAX as u128) as i32,
+            None => -1,
+        };
+        let mut fds = [PollFd::new(self.file.as_raw_fd(), PollFlags::POLLPRI)];
+
+        Ok(poll(&mut fds, timeout)? > 0)
+    }
+
+    /// Reinitialize a completed request so it can be reused, which is cheaper
+    /// than allocating a new one. Wraps the `MEDIA_REQUEST_IOC_REINIT` ioctl.
+    pub fn reinit(&self) -> Result<()> {
+        unsafe { ioctl::media_request_ioc_reinit(self.file.as_raw_fd()) }?;
+
+        Ok(())
+    }
+
+    /// Store the values of `controls` into the request, so they are applied
+    /// to `device` along with the buffers of the request when it is queued.
+    ///
+    /// The values are checked by the driver at this point, so errors are
+    /// reported the same way as when setting controls directly.
+    pub fn set_ctrls<F: AsRawFd>(&self, device: &mut F, controls: &mut [ExtControl]) -> Result<()> {
+        s_ext_ctrls(device, self.into(), controls)
+    }
+
+    /// Read the values of `controls` from the request. Once the request has
+    /// completed, these are the values the driver used to process it.
+    pub fn get_ctrls<F: AsRawFd>(&self, device: &F, controls: &mut [ExtControl]) -> Result<()> {
+        g_ext_ctrls(device, self.into(), controls)
+    }
+
+    /// Same as `get_ctrls()`, but reads the controls described by `ctrls`
+    /// and returns their typed values, e.g. to check the status reported by
+    /// the driver once the request has completed.
+    pub fn get_ctrl_values<F: AsRawFd>(
+        &self,
+        device: &F,
+        ctrls: &[QueryExtCtrl],
+    ) -> Result<Vec<CtrlReading>> {
+        g_ext_ctrl_values(device, self.into(), ctrls)
+    }
+}
+
+/// Allows any control API taking a `CtrlWhich` to operate on a request.
+impl From<&Request> for CtrlWhich {
+    fn from(request: &Request) -> Self {
+        CtrlWhich::Request(request.as_raw_fd())
+    }
+}
+
+impl AsRawFd for Request {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for Request {
+    fn into_raw_fd(self) -> RawFd {
+        self.file.into_raw_fd()
+    }
+}
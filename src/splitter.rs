@@ -0,0 +1,86 @@
+//! Helpers to split a continuous compressed stream into the chunks to submit
+//! to the OUTPUT queue of a decoder.
+//!
+//! Most decoders expect exactly one frame per OUTPUT buffer. The splitters of
+//! this module are iterators over the frames of a stream held in memory, for
+//! the most common container-less formats.
+//!
+//! Decoders which advertise the `CONTINUOUS_BYTESTREAM` format flag accept
+//! arbitrary chunks instead, in which case `Coalesce` can be used to fill
+//! OUTPUT buffers as much as possible and reduce the number of submissions.
+mod annexb;
+mod fwht;
+mod ivf;
+
+pub use annexb::*;
+pub use fwht::*;
+pub use ivf::*;
+
+/// Iterator adapter turning frames into chunks of at most `max_size` bytes,
+/// by concatenating consecutive frames and splitting the ones that do not fit
+/// into a single chunk. Only suitable for decoders accepting a continuous
+/// bytestream.
+pub struct Coalesce<'a, I: Iterator<Item = &'a [u8]>> {
+    frames: I,
+    max_size: usize,
+    /// Part of the last frame that has not been returned yet.
+    remainder: &'a [u8],
+}
+
+impl<'a, I: Iterator<Item = &'a [u8]>> Coalesce<'a, I> {
+    /// Coalesce `frames` into chunks of at most `max_size` bytes, typically the
+    /// size of the OUTPUT buffers. A `max_size` of 0 is treated as 1.
+    pub fn new(frames: I, max_size: usize) -> Self {
+        Coalesce {
+            frames,
+            max_size: max_size.max(1),
+            remainder: &[],
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = &'a [u8]>> Iterator for Coalesce<'a, I> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::with_capacity(self.max_size);
+
+        loop {
+            if self.remainder.is_empty() {
+                match self.frames.next() {
+                    Some(frame) => self.remainder = frame,
+                    None if chunk.is_empty() => return None,
+                    None => return Some(chunk),
+                }
+            }
+
+            let len = self.remainder.len().min(self.max_size - chunk.len());
+            let (data, remainder) = self.remainder.split_at(len);
+            chunk.extend_from_slice(data);
+            self.remainder = remainder;
+
+            if chunk.len() == self.max_size {
+                return Some(chunk);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Coalesce;
+
+    #[test]
+    fn coalesce() {
+        let frames: [&[u8]; 4] = [&[1, 2, 3], &[4], &[5, 6, 7, 8, 9, 10, 11], &[12]];
+        let chunks: Vec<Vec<u8>> = Coalesce::new(frames.iter().copied(), 4).collect();
+        assert_eq!(
+            chunks,
+            vec![vec![1, 2, 3, 4], vec![5, 6, 7, 8], vec![9, 10, 11, 12]]
+        );
+
+        let chunks: Vec<Vec<u8>> = Coalesce::new(frames.iter().copied(), 5).collect();
+        assert_eq!(chunks.last(), Some(&vec![11, 12]));
+        assert_eq!(Coalesce::new(std::iter::empty(), 4).next(), None);
+    }
+}
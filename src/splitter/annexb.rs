@@ -0,0 +1,146 @@
+//! Splitting of H.264 and HEVC streams in Annex B format into access units.
+//!
+//! NAL units are delimited by start codes (`00 00 01`, optionally preceded by
+//! additional zero bytes). An access unit starts at the first NAL unit that
+//! cannot belong to the current picture once it contains a slice, or at the
+//! first slice of a new picture.
+
+/// Codecs supported by `AnnexBSplitter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnexBCodec {
+    H264,
+    Hevc,
+}
+
+impl AnnexBCodec {
+    /// Returns whether `nal` (without its start code) is a slice, and whether
+    /// it starts a new access unit if the current one already contains a
+    /// slice.
+    fn classify(self, nal: &[u8]) -> (bool, bool) {
+        match self {
+            AnnexBCodec::H264 => {
+                let nal_type = nal[0] & 0x1f;
+                match nal_type {
+                    // Slices. The first one of a picture has first_mb_in_slice
+                    // set to 0, which is encoded as a single 1 bit.
+                    1 | 5 => (true, matches!(nal.get(1), Some(b) if b & 0x80 != 0)),
+                    // AUD, SPS, PPS, SEI, prefix NAL, subset SPS and reserved.
+                    6..=9 | 14..=18 => (false, true),
+                    _ => (false, false),
+                }
+            }
+            AnnexBCodec::Hevc => {
+                let nal_type = (nal[0] >> 1) & 0x3f;
+                match nal_type {
+                    // Slices, with first_slice_segment_in_pic_flag as the
+                    // first bit after the 2-byte NAL header.
+                    0..=31 => (true, matches!(nal.get(2), Some(b) if b & 0x80 != 0)),
+                    // VPS, SPS, PPS, AUD, prefix SEI and reserved.
+                    32..=35 | 39 | 41..=44 | 48..=55 => (false, true),
+                    _ => (false, false),
+                }
+            }
+        }
+    }
+}
+
+/// Returns the position of the next start code of `data` at or after `from`,
+/// as the range covering the start code including its leading zero bytes.
+fn find_start_code(data: &[u8], from: usize) -> Option<(usize, usize)> {
+    let pos = data.get(from..)?.windows(3).position(|w| w == [0, 0, 1])? + from;
+    // Include the extra leading zero of 4-byte start codes.
+    let start = if pos > from && data[pos - 1] == 0 {
+        pos - 1
+    } else {
+        pos
+    };
+    Some((start, pos + 3))
+}
+
+/// Iterator over the access units of an Annex B H.264 or HEVC stream, start
+/// codes included. Any data preceding the first start code is ignored.
+pub struct AnnexBSplitter<'a> {
+    codec: AnnexBCodec,
+    stream: &'a [u8],
+}
+
+impl<'a> AnnexBSplitter<'a> {
+    pub fn new(codec: AnnexBCodec, stream: &'a [u8]) -> Self {
+        let stream = match find_start_code(stream, 0) {
+            Some((start, _)) => &stream[start..],
+            None => &[],
+        };
+
+        AnnexBSplitter { codec, stream }
+    }
+}
+
+impl<'a> Iterator for AnnexBSplitter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut next = find_start_code(self.stream, 0);
+        let mut has_slice = false;
+
+        while let Some((start_code, nal_start)) = next {
+            next = find_start_code(self.stream, nal_start);
+            let nal_end = next.map_or(self.stream.len(), |(start, _)| start);
+            let nal = &self.stream[nal_start..nal_end];
+            if nal.is_empty() {
+                continue;
+            }
+
+            let (is_slice, starts_au) = self.codec.classify(nal);
+            if has_slice && starts_au {
+                // The access unit ends right before this NAL's start code.
+                let (au, rest) = self.stream.split_at(start_code);
+                self.stream = rest;
+                return Some(au);
+            }
+            has_slice |= is_slice;
+        }
+
+        match self.stream {
+            [] => None,
+            au => {
+                self.stream = &[];
+                Some(au)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_h264() {
+        let sps_pps: &[u8] = &[0, 0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 3];
+        let idr: &[u8] = &[0, 0, 1, 0x65, 0x88, 4];
+        // Second slice of the same picture (first_mb_in_slice != 0).
+        let idr_slice: &[u8] = &[0, 0, 1, 0x65, 0x40, 5];
+        let p: &[u8] = &[0, 0, 0, 1, 0x41, 0x9a, 6];
+        let aud_p: &[u8] = &[0, 0, 0, 1, 0x09, 0xf0, 0, 0, 1, 0x41, 0x9a, 7];
+        let stream = [&[0xffu8][..], sps_pps, idr, idr_slice, p, aud_p].concat();
+
+        let aus: Vec<&[u8]> = AnnexBSplitter::new(AnnexBCodec::H264, &stream).collect();
+        assert_eq!(aus, vec![&[sps_pps, idr, idr_slice].concat()[..], p, aud_p]);
+    }
+
+    #[test]
+    fn split_hevc() {
+        let vps: &[u8] = &[0, 0, 0, 1, 0x40, 0x01, 1];
+        let idr: &[u8] = &[0, 0, 1, 0x26, 0x01, 0xaf, 2];
+        let idr_slice: &[u8] = &[0, 0, 1, 0x26, 0x01, 0x20, 3];
+        let trail: &[u8] = &[0, 0, 1, 0x02, 0x01, 0xd0, 4];
+        let stream = [vps, idr, idr_slice, trail].concat();
+
+        let aus: Vec<&[u8]> = AnnexBSplitter::new(AnnexBCodec::Hevc, &stream).collect();
+        assert_eq!(aus, vec![&[vps, idr, idr_slice].concat()[..], trail]);
+        assert_eq!(
+            AnnexBSplitter::new(AnnexBCodec::Hevc, &[1, 2, 3]).next(),
+            None
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Splitting of the FWHT streams produced by the `vicodec` encoder.
+//!
+//! Every encoded frame starts with a `struct fwht_cframe_hdr` header, which
+//! contains the resolution of the frame as well as the size of the compressed
+//! data that follows it.
+use std::convert::TryInto;
+
+/// Magic values that start every FWHT frame.
+const FWHT_MAGIC1: u32 = 0x4f4f_4f4f;
+const FWHT_MAGIC2: u32 = 0xffff_ffff;
+/// Size of `struct fwht_cframe_hdr`.
+pub const FWHT_HEADER_SIZE: usize = 11 * 4;
+
+/// The fields of `struct fwht_cframe_hdr` that are relevant to users.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FwhtHeader {
+    pub version: u32,
+    pub width: u32,
+    pub height: u32,
+    pub flags: u32,
+    pub colorspace: u32,
+    pub xfer_func: u32,
+    pub ycbcr_enc: u32,
+    pub quantization: u32,
+    /// Size of the compressed data following the header.
+    pub size: u32,
+}
+
+impl FwhtHeader {
+    /// Parse the header at the beginning of `data`. Returns `None` if `data`
+    /// does not start with a valid FWHT header.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < FWHT_HEADER_SIZE {
+            return None;
+        }
+
+        // Magic values are written in CPU order, the rest is big-endian.
+        let field = |i: usize| data[i * 4..(i + 1) * 4].try_into().unwrap();
+        if u32::from_ne_bytes(field(0)) != FWHT_MAGIC1
+            || u32::from_ne_bytes(field(1)) != FWHT_MAGIC2
+        {
+            return None;
+        }
+
+        Some(FwhtHeader {
+            version: u32::from_be_bytes(field(2)),
+            width: u32::from_be_bytes(field(3)),
+            height: u32::from_be_bytes(field(4)),
+            flags: u32::from_be_bytes(field(5)),
+            colorspace: u32::from_be_bytes(field(6)),
+            xfer_func: u32::from_be_bytes(field(7)),
+            ycbcr_enc: u32::from_be_bytes(field(8)),
+            quantization: u32::from_be_bytes(field(9)),
+            size: u32::from_be_bytes(field(10)),
+        })
+    }
+
+    /// Total size of the frame, header included.
+    pub fn frame_size(&self) -> usize {
+        FWHT_HEADER_SIZE + self.size as usize
+    }
+}
+
+/// Iterator over the frames of a FWHT stream, headers included. Iteration
+/// stops at the end of the stream, or at the first invalid or truncated
+/// frame.
+pub struct FwhtSplitter<'a> {
+    stream: &'a [u8],
+}
+
+impl<'a> FwhtSplitter<'a> {
+    pub fn new(stream: &'a [u8]) -> Self {
+        FwhtSplitter { stream }
+    }
+}
+
+impl<'a> Iterator for FwhtSplitter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = FwhtHeader::parse(self.stream)?;
+        if header.frame_size() > self.stream.len() {
+            return None;
+        }
+
+        let (frame, rest) = self.stream.split_at(header.frame_size());
+        self.stream = rest;
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fwht_frame(width: u32, height: u32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&FWHT_MAGIC1.to_ne_bytes());
+        frame.extend_from_slice(&FWHT_MAGIC2.to_ne_bytes());
+        for field in &[3, width, height, 0, 0, 0, 0, 0, payload.len() as u32] {
+            frame.extend_from_slice(&field.to_be_bytes());
+        }
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn split_fwht() {
+        let first = fwht_frame(640, 480, &[1, 2, 3]);
+        let second = fwht_frame(320, 240, &[4]);
+        let stream = [&first[..], &second[..], &[0u8; 4][..]].concat();
+
+        let frames: Vec<&[u8]> = FwhtSplitter::new(&stream).collect();
+        assert_eq!(frames, vec![&first[..], &second[..]]);
+
+        let header = FwhtHeader::parse(frames[1]).unwrap();
+        assert_eq!((header.width, header.height, header.size), (320, 240, 1));
+    }
+}
@@ -0,0 +1,119 @@
+//! Splitting of IVF files, the simple container commonly used for VP8 and VP9
+//! streams.
+use std::convert::TryInto;
+
+/// Size of the header at the start of an IVF file.
+const IVF_FILE_HEADER_SIZE: usize = 32;
+/// Size of the header preceding every frame.
+const IVF_FRAME_HEADER_SIZE: usize = 12;
+
+/// The information contained in the header of an IVF file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IvfHeader {
+    /// Codec of the stream, e.g. `VP90`.
+    pub fourcc: [u8; 4],
+    pub width: u16,
+    pub height: u16,
+    pub frame_rate: u32,
+    pub time_scale: u32,
+    pub num_frames: u32,
+}
+
+/// Iterator over the frames of an IVF file, without their IVF frame header.
+/// Iteration stops at the end of the file, or at the first truncated frame.
+pub struct IvfSplitter<'a> {
+    header: IvfHeader,
+    stream: &'a [u8],
+}
+
+impl<'a> IvfSplitter<'a> {
+    /// Create a splitter over the IVF file `file`. Returns `None` if `file`
+    /// does not start with a valid IVF header.
+    pub fn new(file: &'a [u8]) -> Option<Self> {
+        if file.len() < IVF_FILE_HEADER_SIZE || &file[0..4] != b"DKIF" {
+            return None;
+        }
+
+        let le16 = |i: usize| u16::from_le_bytes(file[i..i + 2].try_into().unwrap());
+        let le32 = |i: usize| u32::from_le_bytes(file[i..i + 4].try_into().unwrap());
+        let header_size = le16(6) as usize;
+        if header_size < IVF_FILE_HEADER_SIZE || header_size > file.len() {
+            return None;
+        }
+
+        Some(IvfSplitter {
+            header: IvfHeader {
+                fourcc: file[8..12].try_into().unwrap(),
+                width: le16(12),
+                height: le16(14),
+                frame_rate: le32(16),
+                time_scale: le32(20),
+                num_frames: le32(24),
+            },
+            stream: &file[header_size..],
+        })
+    }
+
+    /// Returns the header of the IVF file.
+    pub fn header(&self) -> &IvfHeader {
+        &self.header
+    }
+}
+
+impl<'a> Iterator for IvfSplitter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stream.len() < IVF_FRAME_HEADER_SIZE {
+            return None;
+        }
+
+        let frame_size = u32::from_le_bytes(self.stream[0..4].try_into().unwrap()) as usize;
+        let frame_end = IVF_FRAME_HEADER_SIZE + frame_size;
+        if frame_end > self.stream.len() {
+            return None;
+        }
+
+        let frame = &self.stream[IVF_FRAME_HEADER_SIZE..frame_end];
+        self.stream = &self.stream[frame_end..];
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_ivf() {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"DKIF");
+        file.extend_from_slice(&0u16.to_le_bytes());
+        file.extend_from_slice(&32u16.to_le_bytes());
+        file.extend_from_slice(b"VP90");
+        file.extend_from_slice(&320u16.to_le_bytes());
+        file.extend_from_slice(&240u16.to_le_bytes());
+        file.extend_from_slice(&[30, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+        for (i, frame) in [&[1u8, 2, 3][..], &[4u8][..]].iter().enumerate() {
+            file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(i as u64).to_le_bytes());
+            file.extend_from_slice(frame);
+        }
+        // Truncated frame.
+        file.extend_from_slice(&[10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5]);
+
+        let splitter = IvfSplitter::new(&file).unwrap();
+        assert_eq!(&splitter.header().fourcc, b"VP90");
+        assert_eq!(
+            (splitter.header().width, splitter.header().height),
+            (320, 240)
+        );
+        assert_eq!(splitter.header().num_frames, 2);
+        assert_eq!(
+            splitter.collect::<Vec<_>>(),
+            vec![&[1u8, 2, 3][..], &[4u8][..]]
+        );
+
+        assert!(IvfSplitter::new(b"RIFF").is_none());
+    }
+}
@@ -0,0 +1,218 @@
+//! Generation of animated test patterns, to feed OUTPUT queues with valid
+//! frames for any of the supported formats.
+//!
+//! The pattern is an RGB XOR pattern shifted by a seed, inspired by
+//! <http://cliffle.com/blog/bare-metal-wasm/>. YUV formats get the same
+//! pattern, converted using BT.601 limited range coefficients. The stride of
+//! each plane is taken from the format, and the padding at the end of lines
+//! is left untouched.
+use crate::formats;
+use crate::{Error, Format, PixelFormat, Result};
+
+/// Pixel formats supported by the test pattern generator.
+pub const SUPPORTED_FORMATS: [&[u8; 4]; 4] = [b"RGB3", b"YUYV", b"NV12", b"NM12"];
+
+/// Returns true if `format` is supported by the test pattern generator.
+pub fn is_supported(format: PixelFormat) -> bool {
+    SUPPORTED_FORMATS.iter().any(|&f| format == f.into())
+}
+
+/// Returns the RGB color of pixel (`x`, `y`) for `seed`.
+fn rgb(x: usize, y: usize, seed: u32) -> [u8; 3] {
+    let rgba = seed.wrapping_add((x ^ y) as u32).to_le_bytes();
+    [rgba[0], rgba[1], rgba[2]]
+}
+
+/// Convert an RGB color to YUV, using the BT.601 limited range coefficients.
+fn rgb_to_yuv([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    [y as u8, u as u8, v as u8]
+}
+
+/// Returns the lines of `plane`, with `stride` bytes between the start of
+/// consecutive lines and only the first `line_len` bytes of each line.
+fn lines(
+    plane: &mut [u8],
+    stride: usize,
+    line_len: usize,
+    height: usize,
+) -> Result<impl Iterator<Item = &mut [u8]>> {
+    if stride < line_len || (height > 0 && plane.len() < stride * (height - 1) + line_len) {
+        return Err(Error::InvalidBuffer);
+    }
+
+    Ok(plane
+        .chunks_mut(stride)
+        .take(height)
+        .map(move |line| &mut line[..line_len]))
+}
+
+/// Fill the color planes of an NV12 frame, given as its luma and chroma
+/// planes with their respective strides.
+fn fill_nv12(
+    (luma, luma_stride): (&mut [u8], usize),
+    (chroma, chroma_stride): (&mut [u8], usize),
+    width: usize,
+    height: usize,
+    seed: u32,
+) -> Result<()> {
+    for (y, line) in lines(luma, luma_stride, width, height)?.enumerate() {
+        for (x, pixel) in line.iter_mut().enumerate() {
+            *pixel = rgb_to_yuv(rgb(x, y, seed))[0];
+        }
+    }
+
+    let chroma_width = width.div_ceil(2) * 2;
+    let chroma_height = height.div_ceil(2);
+    for (y, line) in lines(chroma, chroma_stride, chroma_width, chroma_height)?.enumerate() {
+        for (x, pixel) in line.chunks_mut(2).enumerate() {
+            let [_, u, v] = rgb_to_yuv(rgb(x * 2, y * 2, seed));
+            pixel[0] = u;
+            pixel[1] = v;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fill `planes` with the test pattern for `seed`, according to `format`.
+/// `seed` can be increased over consecutive calls to animate the pattern.
+///
+/// `planes` must contain one memory plane per plane of `format`, e.g. a
+/// single one for `NV12` and two for `NM12`. `Error::InvalidFormat` is
+/// returned if the pixel format is not supported, `Error::NotEnoughPlanes` if
+/// planes are missing, and `Error::InvalidBuffer` if a plane is too small.
+pub fn fill_planes(planes: &mut [&mut [u8]], format: &Format, seed: u32) -> Result<()> {
+    if !is_supported(format.pixelformat) {
+        return Err(Error::InvalidFormat);
+    }
+    if planes.len() < format.plane_fmt.len() || format.plane_fmt.is_empty() {
+        return Err(Error::NotEnoughPlanes);
+    }
+
+    let width = format.width as usize;
+    let height = format.height as usize;
+    let stride = |i: usize| format.plane_fmt[i].bytesperline as usize;
+    let fourcc: [u8; 4] = format.pixelformat.into();
+
+    match &fourcc {
+        b"RGB3" => {
+            for (y, line) in lines(planes[0], stride(0), width * 3, height)?.enumerate() {
+                for (x, pixel) in line.chunks_mut(3).enumerate() {
+                    pixel.copy_from_slice(&rgb(x, y, seed));
+                }
+            }
+        }
+        b"YUYV" => {
+            let line_len = width.div_ceil(2) * 4;
+            for (y, line) in lines(planes[0], stride(0), line_len, height)?.enumerate() {
+                for (x, pixels) in line.chunks_mut(4).enumerate() {
+                    let [y0, u, v] = rgb_to_yuv(rgb(x * 2, y, seed));
+                    let [y1, _, _] = rgb_to_yuv(rgb(x * 2 + 1, y, seed));
+                    pixels.copy_from_slice(&[y0, u, y1, v]);
+                }
+            }
+        }
+        b"NV12" => {
+            // Both color planes share the same memory plane and stride.
+            let offsets = formats::contiguous_plane_offsets(format).ok_or(Error::InvalidFormat)?;
+            if planes[0].len() < offsets[1] {
+                return Err(Error::InvalidBuffer);
+            }
+            let (luma, chroma) = planes[0].split_at_mut(offsets[1]);
+            fill_nv12((luma, stride(0)), (chroma, stride(0)), width, height, seed)?;
+        }
+        b"NM12" => {
+            if format.plane_fmt.len() < 2 {
+                return Err(Error::NotEnoughPlanes);
+            }
+            let (luma, chroma) = planes.split_at_mut(1);
+            fill_nv12(
+                (luma[0], stride(0)),
+                (chroma[0], stride(1)),
+                width,
+                height,
+                seed,
+            )?;
+        }
+        _ => unreachable!("unsupported format {}", format.pixelformat),
+    }
+
+    Ok(())
+}
+
+/// Fill `frame` with the test pattern for `seed`, for single-plane formats.
+/// See `fill_planes()`.
+pub fn fill(frame: &mut [u8], format: &Format, seed: u32) -> Result<()> {
+    fill_planes(&mut [frame], format, seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PlanePixFormat;
+
+    fn format(fourcc: &[u8; 4], planes: &[(u32, u32)]) -> Format {
+        Format {
+            width: 4,
+            height: 2,
+            pixelformat: fourcc.into(),
+            plane_fmt: planes
+                .iter()
+                .map(|&(bytesperline, sizeimage)| PlanePixFormat {
+                    bytesperline,
+                    sizeimage,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rgb3_with_padding() {
+        let mut frame = [0xffu8; 32];
+        fill(&mut frame, &format(b"RGB3", &[(16, 32)]), 0).unwrap();
+        assert_eq!(&frame[..12], &[0, 0, 0, 1, 0, 0, 2, 0, 0, 3, 0, 0]);
+        // Padding is untouched.
+        assert_eq!(&frame[12..16], &[0xff; 4]);
+        assert_eq!(&frame[16..19], &[1, 0, 0]);
+
+        assert_eq!(
+            fill(&mut frame[..20], &format(b"RGB3", &[(16, 32)]), 0),
+            Err(Error::InvalidBuffer)
+        );
+        assert_eq!(
+            fill(&mut frame, &format(b"MJPG", &[(16, 32)]), 0),
+            Err(Error::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn yuv_formats() {
+        let mut yuyv = [0u8; 16];
+        fill(&mut yuyv, &format(b"YUYV", &[(8, 16)]), 0).unwrap();
+        // Black in limited range.
+        assert_eq!(&yuyv[..4], &[16, 128, 16, 128]);
+
+        let mut nv12 = [0u8; 12];
+        fill(&mut nv12, &format(b"NV12", &[(4, 12)]), 0).unwrap();
+
+        let mut luma = [0u8; 8];
+        let mut chroma = [0u8; 4];
+        fill_planes(
+            &mut [&mut luma, &mut chroma],
+            &format(b"NM12", &[(4, 8), (4, 4)]),
+            0,
+        )
+        .unwrap();
+        assert_eq!(&nv12[..], &[&luma[..], &chroma[..]].concat()[..]);
+
+        assert_eq!(
+            fill(&mut luma, &format(b"NM12", &[(4, 8), (4, 4)]), 0),
+            Err(Error::NotEnoughPlanes)
+        );
+    }
+}
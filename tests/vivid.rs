@@ -0,0 +1,711 @@
+//! Tests of the `device` API against the single-planar capture node of the
+//! `vivid` virtual driver, loaded with `modprobe vivid multiplanar=1`.
+//!
+//! These tests need access to the device, and are thus ignored by default.
+//! Run them with:
+//!
+//! ```sh
+//! VIVID_DEVICE=/dev/videoN cargo test --test vivid -- --ignored
+//! ```
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use v4l2::device::queue::*;
+use v4l2::device::*;
+use v4l2::ioctl::{
+    self, CtrlChanges, CtrlType, CtrlValue, CtrlWhich, Event, EventType, ExportAccess, ExportFlags,
+    Fraction, FrameInterval, MenuItem, SubscribeEventFlags,
+};
+use v4l2::memory::{DMABuf, MMAP};
+use v4l2::{CtrlClass, CtrlId, Error, QueueType};
+
+fn open_vivid() -> Arc<Mutex<Device>> {
+    let path = PathBuf::from(
+        std::env::var("VIVID_DEVICE").expect("VIVID_DEVICE must point to the vivid node"),
+    );
+    let device = Device::open(&path, DeviceConfig::new()).expect("Failed to open device");
+    assert_eq!(device.capability.driver, "vivid");
+
+    Arc::new(Mutex::new(device))
+}
+
+#[test]
+#[ignore]
+fn single_planar_capture() {
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    assert_eq!(queue.get_type(), QueueType::VideoCapture);
+    assert!(!queue.is_multi_planar());
+
+    // Non-contiguous formats are converted to their contiguous variant.
+    let format = queue
+        .set_format((b"NM12", (640, 480)).into())
+        .expect("Failed to set format");
+    assert_eq!(format.pixelformat, b"NV12".into());
+    assert_eq!(queue.num_planes(), 1);
+    let image_size = queue.plane_sizes()[0];
+    assert!(image_size >= 640 * 480 * 3 / 2);
+
+    let mut queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate buffers");
+    for index in 0..queue.num_buffers() {
+        let querybuf = queue
+            .query_mmap_buffer(index)
+            .expect("Failed to query buffer");
+        assert_eq!(querybuf.planes.len(), 1);
+        assert!(querybuf.planes[0].length as usize >= image_size);
+
+        let flags = ExportFlags {
+            access: ExportAccess::ReadOnly,
+            cloexec: true,
+        };
+        let exported = queue
+            .export_buffer(index, 0, flags)
+            .expect("Failed to export buffer");
+        assert_eq!(exported.flags, flags);
+
+        // DMABUFs exported through the queue are kept and reused.
+        let dmabufs = queue
+            .export_dmabufs(index, flags)
+            .expect("Failed to export buffer");
+        assert_eq!(dmabufs.len(), 1);
+        let dmabuf = queue
+            .export_dmabuf(index, 0, flags)
+            .expect("Failed to export buffer");
+        assert!(Arc::ptr_eq(&dmabufs[0], &dmabuf));
+    }
+
+    // Buffers can be added after the initial allocation.
+    let num_buffers = queue.num_buffers();
+    let added = queue.add_buffers(1).expect("Failed to add buffers");
+    assert_eq!(added, num_buffers..num_buffers + 1);
+    assert_eq!(queue.num_buffers(), num_buffers + 1);
+
+    while let Ok(buffer) = queue.get_free_buffer() {
+        assert_eq!(buffer.num_expected_planes(), 1);
+        let buffer = buffer.add_plane(qbuf::Plane::cap(()));
+        buffer.prepare().expect("Failed to prepare buffer");
+        buffer.queue().expect("Failed to queue buffer");
+    }
+    queue.streamon().expect("Failed to start streaming");
+
+    // Buffers stay queued while the queue is paused.
+    queue.pause().expect("Failed to pause queue");
+    assert_eq!(queue.dequeue().err(), Some(v4l2::Error::Paused));
+    assert_eq!(queue.num_queued_buffers(), queue.num_buffers());
+    queue.resume().expect("Failed to resume queue");
+
+    let dqbuf = queue.dequeue().expect("Failed to dequeue buffer");
+    assert_eq!(dqbuf.data.planes.len(), 1);
+    assert!(dqbuf.data.planes[0].bytesused > 0);
+    let mapping = queue
+        .map_plane(dqbuf.data.index as usize, 0)
+        .expect("Failed to map buffer");
+    assert!(mapping.len() >= image_size);
+    drop(dqbuf);
+
+    queue.streamoff().expect("Failed to stop streaming");
+    // Buffers cannot be freed while they are mapped.
+    drop(mapping);
+    queue.free_buffers().expect("Failed to free buffers");
+}
+
+#[test]
+#[ignore]
+fn dmabuf_capture() {
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+
+    // Obtain DMABUFs by exporting MMAP buffers, which remain valid after the
+    // MMAP buffers are freed.
+    let mmap_queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate MMAP buffers");
+    let mut dmabufs = (0..mmap_queue.num_buffers())
+        .map(|index| {
+            mmap_queue
+                .export_buffer(index, 0, Default::default())
+                .expect("Failed to export buffer")
+                .file
+        })
+        .collect::<Vec<_>>();
+    let queue = mmap_queue
+        .free_buffers()
+        .expect("Failed to free MMAP buffers");
+
+    let queue = queue
+        .request_buffers::<DMABuf>(dmabufs.len() as u32)
+        .expect("Failed to allocate DMABUF buffers");
+    while let Some(dmabuf) = dmabufs.pop() {
+        queue
+            .get_free_buffer()
+            .expect("Failed to obtain buffer")
+            .add_plane(qbuf::Plane::cap(dmabuf))
+            .queue()
+            .expect("Failed to queue buffer");
+    }
+    queue.streamon().expect("Failed to start streaming");
+
+    // The DMABUF is given back along with the dequeued buffer.
+    let mut dqbuf = queue.dequeue().expect("Failed to dequeue buffer");
+    assert_eq!(dqbuf.plane_handles.len(), 1);
+    assert!(dqbuf.data.planes[0].bytesused > 0);
+    dmabufs.push(dqbuf.plane_handles.remove(0));
+    drop(dqbuf);
+
+    // And so are the ones of buffers still queued when streaming stops.
+    let canceled = queue.streamoff().expect("Failed to stop streaming");
+    dmabufs.extend(
+        canceled
+            .into_iter()
+            .flat_map(|buffer| buffer.plane_handles.into_iter()),
+    );
+    assert_eq!(dmabufs.len(), queue.num_buffers());
+    queue.free_buffers().expect("Failed to free buffers");
+}
+
+#[test]
+#[ignore]
+fn controls() {
+    let device = open_vivid();
+    let device = device.lock().unwrap();
+
+    let controls: Vec<_> = ioctl::CtrlIterator::new(&*device).collect();
+    assert!(controls
+        .iter()
+        .any(|ctrl| ctrl.id == CtrlId::BRIGHTNESS && ctrl.type_ == CtrlType::Integer));
+
+    // vivid exposes both kinds of menus in its test controls class.
+    for ctrl in controls
+        .iter()
+        .filter(|ctrl| matches!(ctrl.type_, CtrlType::Menu | CtrlType::IntegerMenu))
+    {
+        let entries = ioctl::menu_entries(&*device, ctrl.id).expect("Failed to query menu");
+        assert!(!entries.is_empty());
+        for entry in entries {
+            match (ctrl.type_, entry.item) {
+                (CtrlType::Menu, MenuItem::Name(_)) => (),
+                (CtrlType::IntegerMenu, MenuItem::Value(_)) => (),
+                _ => panic!("Menu entry does not match the control type"),
+            }
+        }
+    }
+}
+
+#[test]
+#[ignore]
+fn control_events() {
+    let device = open_vivid();
+    let mut device = device.lock().unwrap();
+    let id = u32::from(CtrlId::BRIGHTNESS);
+
+    ioctl::subscribe_event(
+        &*device,
+        EventType::Ctrl,
+        id,
+        SubscribeEventFlags::SEND_INITIAL,
+    )
+    .expect("Failed to subscribe to control events");
+
+    // The initial event reports the current state of the control.
+    let value = ioctl::g_ctrl(&*device, CtrlId::BRIGHTNESS).expect("Failed to read control");
+    let initial = ioctl::dqevent(&*device).expect("Failed to dequeue event");
+    assert_eq!(initial.id, id);
+    match initial.event {
+        Event::Ctrl(ctrl) => assert_eq!(ctrl.value, i64::from(value)),
+        event => panic!("Unexpected event {:?}", event),
+    }
+
+    let new_value = ioctl::s_ctrl(&mut *device, CtrlId::BRIGHTNESS, (value + 1) % 256)
+        .expect("Failed to set control");
+    let change = ioctl::dqevent(&*device).expect("Failed to dequeue event");
+    assert!(change.sequence > initial.sequence);
+    match change.event {
+        Event::Ctrl(ctrl) => {
+            assert!(ctrl.changes.contains(CtrlChanges::VALUE));
+            assert_eq!(ctrl.value, i64::from(new_value));
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+
+    ioctl::unsubscribe_event(&*device, EventType::All, 0).expect("Failed to unsubscribe");
+}
+
+#[test]
+#[ignore]
+fn controls_in_class() {
+    let device = open_vivid();
+    let device = device.lock().unwrap();
+
+    let user_controls = device.controls_in_class(CtrlClass::User);
+    assert!(user_controls
+        .iter()
+        .any(|ctrl| ctrl.id == CtrlId::BRIGHTNESS));
+    for ctrl in &user_controls {
+        assert!(CtrlClass::User.contains(ctrl.id.into()));
+        assert_ne!(ctrl.type_, CtrlType::CtrlClass);
+    }
+
+    // The class control comes first when iterating.
+    let first = ioctl::CtrlIterator::new(&*device)
+        .in_class(CtrlClass::User)
+        .next()
+        .expect("No user control");
+    assert_eq!(u32::from(first.id), CtrlClass::User.ctrl_id());
+    assert_eq!(first.type_, CtrlType::CtrlClass);
+
+    // vivid has no flash controls.
+    assert!(device.controls_in_class(CtrlClass::Flash).is_empty());
+}
+
+#[test]
+#[ignore]
+fn reset_controls() {
+    let device = open_vivid();
+    let mut device = device.lock().unwrap();
+    let brightness =
+        ioctl::query_ext_ctrl(&*device, CtrlId::BRIGHTNESS).expect("Failed to query control");
+
+    let value = (brightness.default_value as i32 + 1) % 256;
+    ioctl::s_ctrl(&mut *device, CtrlId::BRIGHTNESS, value).expect("Failed to set control");
+    assert!(device
+        .reset_control(CtrlId::BRIGHTNESS)
+        .expect("Failed to reset control"));
+    assert_eq!(
+        ioctl::g_ctrl(&*device, CtrlId::BRIGHTNESS).expect("Failed to read control"),
+        brightness.default_value as i32
+    );
+
+    // Buttons have no value to reset.
+    let button = device
+        .controls_in_class(CtrlClass::User)
+        .into_iter()
+        .find(|ctrl| ctrl.type_ == CtrlType::Button)
+        .expect("No button control");
+    assert!(!device
+        .reset_control(button.id)
+        .expect("Failed to reset button"));
+
+    ioctl::s_ctrl(&mut *device, CtrlId::BRIGHTNESS, value).expect("Failed to set control");
+    assert!(
+        device
+            .reset_all_controls()
+            .expect("Failed to reset controls")
+            > 0
+    );
+    assert_eq!(
+        ioctl::g_ctrl(&*device, CtrlId::BRIGHTNESS).expect("Failed to read control"),
+        brightness.default_value as i32
+    );
+}
+
+#[test]
+#[ignore]
+fn auto_cluster() {
+    let device = open_vivid();
+    let mut device = device.lock().unwrap();
+    let autogain =
+        ioctl::query_ext_ctrl(&*device, CtrlId::AUTOGAIN).expect("Failed to query control");
+    let gain = ioctl::query_ext_ctrl(&*device, CtrlId::GAIN).expect("Failed to query control");
+
+    ioctl::s_ctrl(&mut *device, CtrlId::AUTOGAIN, 1).expect("Failed to enable autogain");
+    let readings =
+        ioctl::g_ext_ctrl_values(&*device, CtrlWhich::Current, std::slice::from_ref(&gain))
+            .expect("Failed to read gain");
+    assert!(readings[0].volatile);
+
+    // The gain cannot be set while autogain is enabled...
+    assert_eq!(
+        ioctl::s_ext_ctrl_values(
+            &mut *device,
+            CtrlWhich::Current,
+            &[(&gain, CtrlValue::Integer(10))]
+        ),
+        Err(Error::AutoModeEnabled {
+            auto: CtrlId::AUTOGAIN
+        })
+    );
+    // ... unless it is disabled at the same time.
+    ioctl::s_ext_ctrl_values(
+        &mut *device,
+        CtrlWhich::Current,
+        &[
+            (&autogain, CtrlValue::Boolean(false)),
+            (&gain, CtrlValue::Integer(10)),
+        ],
+    )
+    .expect("Failed to set gain manually");
+}
+
+#[test]
+#[ignore]
+fn dequeue_timeout() {
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+    let queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate buffers");
+    queue.streamon().expect("Failed to start streaming");
+
+    // No buffer is queued, so none can ever be dequeued.
+    assert!(matches!(
+        queue.dequeue_timeout(Duration::from_millis(100)),
+        Err(Error::Timeout)
+    ));
+    assert!(matches!(queue.try_dequeue(), Err(Error::NotReady)));
+
+    while let Ok(buffer) = queue.get_free_buffer() {
+        let buffer = buffer.add_plane(qbuf::Plane::cap(()));
+        buffer.queue().expect("Failed to queue buffer");
+    }
+    let dqbuf = queue
+        .dequeue_timeout(Duration::from_secs(2))
+        .expect("Failed to dequeue buffer");
+    assert!(dqbuf.data.planes[0].bytesused > 0);
+    drop(dqbuf);
+
+    queue.streamoff().expect("Failed to stop streaming");
+}
+
+#[test]
+#[ignore]
+fn dequeue_all_ready() {
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+    let queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate buffers");
+    queue.streamon().expect("Failed to start streaming");
+
+    while let Ok(buffer) = queue.get_free_buffer() {
+        let buffer = buffer.add_plane(qbuf::Plane::cap(()));
+        buffer.queue().expect("Failed to queue buffer");
+    }
+    // Leave enough time for both buffers to be filled.
+    std::thread::sleep(Duration::from_millis(500));
+    let buffers = queue
+        .dequeue_all_ready()
+        .expect("Failed to dequeue buffers");
+    assert_eq!(buffers.len(), 2);
+    assert!(buffers
+        .iter()
+        .all(|buffer| buffer.data.planes[0].bytesused > 0));
+
+    // Nothing is queued anymore.
+    assert!(queue
+        .dequeue_all_ready()
+        .expect("Failed to dequeue buffers")
+        .is_empty());
+    drop(buffers);
+
+    queue.streamoff().expect("Failed to stop streaming");
+}
+
+#[test]
+#[ignore]
+fn dequeue_or_wake() {
+    use v4l2::device::poller::Waker;
+
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+    let queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate buffers");
+    queue.streamon().expect("Failed to start streaming");
+    let waker = Waker::new().expect("Failed to create waker");
+
+    // No buffer is queued, so none can ever be dequeued.
+    assert!(matches!(
+        queue.dequeue_or_wake(&waker),
+        Err(Error::NotReady)
+    ));
+
+    while let Ok(buffer) = queue.get_free_buffer() {
+        let buffer = buffer.add_plane(qbuf::Plane::cap(()));
+        buffer.queue().expect("Failed to queue buffer");
+    }
+    // A pending wake-up interrupts the wait, and is consumed.
+    waker.wake().expect("Failed to wake");
+    assert!(matches!(queue.dequeue_or_wake(&waker), Err(Error::Woken)));
+    let dqbuf = queue
+        .dequeue_or_wake(&waker)
+        .expect("Failed to dequeue buffer");
+    assert!(dqbuf.data.planes[0].bytesused > 0);
+    drop(dqbuf);
+
+    queue.streamoff().expect("Failed to stop streaming");
+}
+
+#[cfg(feature = "mio")]
+#[test]
+#[ignore]
+fn mio_event_source() {
+    use mio::{Events, Interest, Poll, Token};
+
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+    let mut queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate buffers");
+
+    let mut poll = Poll::new().expect("Failed to create poll instance");
+    poll.registry()
+        .register(&mut queue, Token(0), Interest::READABLE)
+        .expect("Failed to register queue");
+
+    while let Ok(buffer) = queue.get_free_buffer() {
+        let buffer = buffer.add_plane(qbuf::Plane::cap(()));
+        buffer.queue().expect("Failed to queue buffer");
+    }
+    queue.streamon().expect("Failed to start streaming");
+
+    let mut events = Events::with_capacity(4);
+    poll.poll(&mut events, Some(Duration::from_secs(2)))
+        .expect("Failed to poll");
+    assert!(events
+        .iter()
+        .any(|event| event.token() == Token(0) && event.is_readable()));
+    drop(queue.try_dequeue().expect("Failed to dequeue buffer"));
+
+    poll.registry()
+        .deregister(&mut queue)
+        .expect("Failed to deregister queue");
+    queue.streamoff().expect("Failed to stop streaming");
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[ignore]
+fn dequeue_async() {
+    use v4l2::device::fd_waiter::TokioWaiter;
+
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+    let queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate buffers");
+
+    while let Ok(buffer) = queue.get_free_buffer() {
+        let buffer = buffer.add_plane(qbuf::Plane::cap(()));
+        buffer.queue().expect("Failed to queue buffer");
+    }
+    queue.streamon().expect("Failed to start streaming");
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .expect("Failed to create runtime");
+    runtime.block_on(async {
+        for _ in 0..4 {
+            let buffer = queue
+                .dequeue_async::<TokioWaiter>()
+                .await
+                .expect("Failed to dequeue buffer");
+            assert!(buffer.data.planes[0].bytesused > 0);
+            drop(buffer);
+            let buffer = queue.get_free_buffer().expect("No free buffer");
+            let buffer = buffer.add_plane(qbuf::Plane::cap(()));
+            buffer.queue().expect("Failed to queue buffer");
+        }
+    });
+
+    queue.streamoff().expect("Failed to stop streaming");
+}
+
+#[test]
+#[ignore]
+fn capture_thread() {
+    use std::sync::mpsc;
+    use v4l2::device::capture_thread::CaptureThread;
+
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+    let queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate buffers");
+
+    let (sender, receiver) = mpsc::channel();
+    let capture = CaptureThread::start(queue, move |frame| {
+        let _ = sender.send(frame.data.planes[0].bytesused);
+    })
+    .expect("Failed to start capture thread");
+    // More frames than buffers, so they must be requeued.
+    for _ in 0..4 {
+        let bytesused = receiver
+            .recv_timeout(Duration::from_secs(2))
+            .expect("No frame captured");
+        assert!(bytesused > 0);
+    }
+
+    let queue = capture.stop().expect("Capture failed");
+    assert!(!queue.is_streaming());
+}
+
+#[test]
+#[ignore]
+fn frame_iterator() {
+    use v4l2::device::frame_iter::FrameIterator;
+
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+    let queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate buffers");
+    queue.streamon().expect("Failed to start streaming");
+
+    // More frames than buffers, so they must be requeued.
+    for frame in FrameIterator::new(&queue).take(4) {
+        let frame = frame.expect("Failed to capture frame");
+        assert!(frame.data.planes[0].bytesused > 0);
+    }
+
+    queue.streamoff().expect("Failed to stop streaming");
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[ignore]
+fn frame_stream() {
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use v4l2::device::fd_waiter::TokioWaiter;
+    use v4l2::device::frame_stream::FrameStream;
+
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+    let queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate buffers");
+    queue.streamon().expect("Failed to start streaming");
+
+    let mut stream = FrameStream::<TokioWaiter>::new(Arc::new(queue));
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .expect("Failed to create runtime");
+    runtime.block_on(async {
+        // More frames than buffers, so they must be requeued.
+        for _ in 0..4 {
+            let frame = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx))
+                .await
+                .expect("Stream ended");
+            assert!(frame.data.planes[0].bytesused > 0);
+        }
+    });
+
+    stream
+        .queue()
+        .streamoff()
+        .expect("Failed to stop streaming");
+    assert!(stream.take_error().is_none());
+}
+
+#[test]
+#[ignore]
+fn frame_interval() {
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    let format = queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+    assert!(queue
+        .frame_size_iter(format.pixelformat)
+        .any(|size| size.contains(format.width, format.height)));
+
+    // The webcam input of vivid only has discrete intervals.
+    let intervals: Vec<_> = queue
+        .frame_interval_iter(format.pixelformat, format.width, format.height)
+        .collect();
+    let interval = match intervals.last() {
+        Some(FrameInterval::Discrete(interval)) => *interval,
+        interval => panic!("Unexpected frame interval {:?}", interval),
+    };
+    let applied = queue
+        .set_frame_interval(interval)
+        .expect("Failed to set frame interval");
+    assert!(FrameInterval::Discrete(interval).contains(applied));
+    assert_eq!(queue.get_frame_interval().unwrap(), applied);
+
+    match queue.set_frame_interval(Fraction::new(1, 7)) {
+        Err(Error::InvalidFrameInterval) => (),
+        result => panic!("Unexpected result {:?}", result),
+    }
+}
+
+#[test]
+#[ignore]
+fn buffer_wipe() {
+    let device = open_vivid();
+    let mut queue =
+        Queue::get_capture_queue(Arc::clone(&device)).expect("Failed to obtain capture queue");
+    queue
+        .set_format((b"YUYV", (640, 480)).into())
+        .expect("Failed to set format");
+    let queue = queue
+        .request_buffers::<MMAP>(2)
+        .expect("Failed to allocate buffers");
+    queue
+        .set_buffer_wipe(Some(wipe::BufferWipe::Pattern(0x5a)))
+        .expect("Failed to enable buffer wiping");
+    let is_wiped = |index: usize| {
+        let mapping = queue.map_plane(index, 0).expect("Failed to map buffer");
+        mapping.as_slice().iter().all(|&b| b == 0x5a)
+    };
+    assert!(is_wiped(0) && is_wiped(1));
+
+    queue.streamon().expect("Failed to start streaming");
+    queue.queue_free_buffers().expect("Failed to queue buffers");
+    let dqbuf = queue
+        .dequeue_timeout(Duration::from_secs(2))
+        .expect("Failed to dequeue buffer");
+    let index = dqbuf.data.index as usize;
+    assert!(!is_wiped(index));
+    drop(dqbuf);
+    assert!(is_wiped(index));
+
+    queue.streamoff().expect("Failed to stop streaming");
+    queue
+        .set_buffer_wipe(None)
+        .expect("Failed to disable buffer wiping");
+    queue.free_buffers().expect("Failed to free buffers");
+}